@@ -0,0 +1,591 @@
+//! Async BME68X driver built on `embedded-hal-async`.
+//!
+//! The register layout, compensation, and heater calculations are shared with
+//! the blocking [`crate::BME68xDev`]; only the bus and delay accesses differ.
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::i2c::I2c;
+
+use crate::{
+    calc_meas_dur, calc_sequential_dur, calc_shared_heatr_dur, conf_from_regs, conf_to_regs,
+    heatr_ctrl_regs, parse_all_fields, parse_field_status, sort_fields, BME68xAddr,
+    BME68xCalibData, BME68xConf, BME68xData, BME68xError, BME68xHeatrConf, BME68xHeatrRegs,
+    BME68xOpMode, BME68xRegister, BME68xVariant, BME68X_CHIP_ID, BME68X_CONF_REGS,
+    BME68X_LEN_COEFF1, BME68X_LEN_COEFF2, BME68X_LEN_COEFF3, BME68X_LEN_COEFF_ALL,
+    BME68X_LEN_CONFIG, BME68X_LEN_FIELD, BME68X_LEN_FIELD_OFFSET, BME68X_LEN_INTERLEAVE_BUFF,
+    BME68X_MODE_MSK, BME68X_NEW_DATA_MSK, BME68X_PERIOD_POLL, BME68X_PERIOD_RESET,
+    BME68X_SOFT_RESET_CMD, MAX_PROFILE_LEN,
+};
+
+/// Async BME68X driver for sensors connected over I2C
+pub struct BME68xDevAsync<I2C, D> {
+    /// Concrete I2C Implementation
+    i2c: I2C,
+
+    /// The I2C Address
+    address: BME68xAddr,
+
+    /// Chip ID
+    chip_id: u8,
+
+    /// Variant ID.
+    variant_id: BME68xVariant,
+
+    /// Ambient Temperature in degrees C
+    amb_temp: i8,
+
+    /// Sensor Calibration Data
+    calib: BME68xCalibData,
+
+    /// Delay implementation
+    delay: D,
+}
+
+impl<I2C: I2c, D: DelayNs> BME68xDevAsync<I2C, D> {
+    /// Create a new instance of the sensor
+    ///
+    /// # Arguments
+    /// * `i2c`: The I2C bus the sensor is attached to
+    /// * `address`: The address of the sensor on the bus
+    /// * `amb_temp`: Ambient temperature to use for compensation, in degrees C. 25 is a safe value for this
+    /// * `delay`: Delay implementation
+    ///
+    /// # Returns
+    /// A new instance of the sensor structure
+    pub fn new(i2c: I2C, address: BME68xAddr, amb_temp: i8, delay: D) -> Self {
+        Self {
+            i2c,
+            address,
+            chip_id: 0,
+            variant_id: BME68xVariant::GasLow,
+            amb_temp,
+            calib: BME68xCalibData::new(),
+            delay,
+        }
+    }
+
+    /// Destroy the driver and return the I2C bus and delay
+    ///
+    /// # Returns
+    /// The I2C bus and delay
+    pub fn destroy(self) -> (I2C, D) {
+        (self.i2c, self.delay)
+    }
+
+    /// Set the ambient temperature used to calculate the heater resistance
+    ///
+    /// The heater needs less power to reach its target temperature in warmer
+    /// surroundings. The new value is used the next time the heater is configured.
+    ///
+    /// # Arguments
+    /// * `amb_temp`: Ambient temperature in degrees C
+    pub fn set_amb_temp(&mut self, amb_temp: i8) {
+        self.amb_temp = amb_temp;
+    }
+
+    /// Get the ambient temperature used to calculate the heater resistance
+    ///
+    /// # Returns
+    /// The ambient temperature in degrees C
+    #[must_use]
+    pub fn get_amb_temp(&self) -> i8 {
+        self.amb_temp
+    }
+
+    /// Initialize the sensor.
+    ///
+    /// Reads the Chip ID and calibrates the sensor.
+    /// This should be called before all other functions.
+    ///
+    /// # Errors
+    /// Returns an error if the initialzation is unsuccessful.
+    pub async fn init(&mut self) -> Result<(), BME68xError> {
+        self.soft_reset().await?;
+
+        self.chip_id = self.get_reg(BME68xRegister::ChipId).await?;
+        if self.chip_id == BME68X_CHIP_ID {
+            let variant = self.get_reg(BME68xRegister::VariantId).await?;
+            self.variant_id = BME68xVariant::from(variant);
+            self.get_calib_data().await
+        } else {
+            Err(BME68xError::DevNotFound)
+        }
+    }
+
+    /// Write the given data to the registers of the sensor.
+    ///
+    /// # Arguments
+    /// * `reg_addr`: Register addresess to write data to
+    /// * `reg_data`: Data to write to the registers
+    ///
+    /// # Errors
+    /// Errors if failing to write to the registers.
+    async fn set_regs(&mut self, reg_addr: &[u8], reg_data: &[u8]) -> Result<(), BME68xError> {
+        let len = reg_addr.len();
+        if reg_addr.len() != reg_data.len() {
+            Err(BME68xError::InvalidLength)
+        } else if (len > 0) && (len <= (BME68X_LEN_INTERLEAVE_BUFF / 2)) {
+            let mut tmp_buff = [0; BME68X_LEN_INTERLEAVE_BUFF];
+            for (index, (addr, data)) in reg_addr.iter().zip(reg_data).enumerate() {
+                tmp_buff[2 * index] = *addr;
+                tmp_buff[(2 * index) + 1] = *data;
+            }
+            self.i2c
+                .write(self.address.into(), &tmp_buff[0..(2 * len)])
+                .await
+                .map_err(|_| BME68xError::ComFail)
+        } else {
+            Err(BME68xError::InvalidLength)
+        }
+    }
+
+    /// Write a byte to the given register.
+    ///
+    /// # Arguments
+    /// * `reg_addr`: The register to write data to
+    /// * `data`: The byte of data to write.
+    ///
+    /// # Errors
+    /// Errors if writing to the register failed
+    async fn set_reg(&mut self, reg_addr: BME68xRegister, data: u8) -> Result<(), BME68xError> {
+        self.set_regs(&[reg_addr.into()], &[data]).await
+    }
+
+    /// Read data from the given registers
+    ///
+    /// # Arguments
+    /// * `reg_addr`: Register addresses to read data from
+    /// * `data`: The buffer to place the read data in.
+    ///
+    /// # Errors
+    /// Errors if failing to read from the registers.
+    async fn get_regs(&mut self, reg_addr: u8, data: &mut [u8]) -> Result<(), BME68xError> {
+        self.i2c
+            .write_read(self.address.into(), &[reg_addr], data)
+            .await
+            .map_err(|_| BME68xError::ComFail)
+    }
+
+    /// Read data from a single register
+    ///
+    /// # Arguments
+    /// * `reg_addr`: The address of the register to read data from
+    ///
+    /// # Returns
+    /// The byte read from the register
+    ///
+    /// # Errors
+    /// Errors if reading from the register failed.
+    async fn get_reg(&mut self, reg_addr: BME68xRegister) -> Result<u8, BME68xError> {
+        let mut data = [0];
+        self.get_regs(reg_addr.into(), &mut data).await?;
+        Ok(data[0])
+    }
+
+    /// Soft-Reset the sensor
+    ///
+    /// # Errors
+    /// Returns an error if soft-resetting the sensor failed.
+    pub async fn soft_reset(&mut self) -> Result<(), BME68xError> {
+        self.set_reg(BME68xRegister::SoftReset, BME68X_SOFT_RESET_CMD)
+            .await?;
+        self.delay.delay_us(BME68X_PERIOD_RESET).await;
+        Ok(())
+    }
+
+    /// Set the operation mode of the sensor
+    ///
+    /// # Arguments
+    /// * `op_mode`: The desired operation mode
+    ///
+    /// # Errors
+    /// Returns an error if setting the operation mode fails.
+    pub async fn set_op_mode(&mut self, op_mode: BME68xOpMode) -> Result<(), BME68xError> {
+        let mut tmp_pow_mode;
+        loop {
+            tmp_pow_mode = self.get_reg(BME68xRegister::CtrlMeas).await?;
+            let pow_mode: BME68xOpMode = (tmp_pow_mode & BME68X_MODE_MSK).into();
+
+            if !matches!(pow_mode, BME68xOpMode::SleepMode) {
+                tmp_pow_mode &= !BME68X_MODE_MSK; /* Set to sleep */
+                self.set_reg(BME68xRegister::CtrlMeas, tmp_pow_mode).await?;
+                self.delay.delay_us(BME68X_PERIOD_POLL).await;
+            } else {
+                break;
+            }
+        }
+        /* Already in sleep */
+        if !matches!(op_mode, BME68xOpMode::SleepMode) {
+            tmp_pow_mode = (tmp_pow_mode & !BME68X_MODE_MSK) | (op_mode as u8 & BME68X_MODE_MSK);
+            self.set_reg(BME68xRegister::CtrlMeas, tmp_pow_mode).await?;
+        }
+        Ok(())
+    }
+
+    /// Get the operation mode of the sensor
+    ///
+    /// # Returns
+    /// The current operation mode of the sensor
+    ///
+    /// # Errors
+    /// Returns an error if getting the operation mode fails
+    pub async fn get_op_mode(&mut self) -> Result<BME68xOpMode, BME68xError> {
+        let output = self.get_reg(BME68xRegister::CtrlMeas).await?;
+        Ok(BME68xOpMode::from(output & BME68X_MODE_MSK))
+    }
+
+    /// Get the remaining duration that can be used for heating
+    ///
+    /// # Arguments
+    /// * `op_mode`: The operation mode of the sensor
+    /// * `conf`: The sensor configuration.
+    ///
+    /// # Returns
+    /// The measurement duration that can be used for heating
+    pub fn get_meas_dur(&self, op_mode: BME68xOpMode, conf: &BME68xConf) -> u32 {
+        calc_meas_dur(op_mode, *conf)
+    }
+
+    /// Get the duration of one full cycle through a sequential heater profile
+    ///
+    /// # Arguments
+    /// * `conf`: The sensor configuration.
+    /// * `heatr_conf`: The sequential heater configuration
+    ///
+    /// # Returns
+    /// The duration of a full sequential cycle in microseconds
+    pub fn get_sequential_dur(&self, conf: &BME68xConf, heatr_conf: &BME68xHeatrConf) -> u32 {
+        calc_sequential_dur(*conf, heatr_conf)
+    }
+
+    /// Read the pressure, temperature, humidity, and gas data from the sensor
+    /// Then apply compensation to the data.
+    ///
+    /// # Arguments
+    /// * `op_mode`: The operation mode of the sensor
+    ///
+    /// # Returns
+    /// Tuple wheter the first element is the sensor data, and the second is the
+    /// number of read elements
+    ///
+    /// # Errors
+    /// Returns an error if reading the sensor data fails
+    pub async fn get_data(
+        &mut self,
+        op_mode: BME68xOpMode,
+    ) -> Result<([BME68xData; 3], u8), BME68xError> {
+        let new_fields;
+        let mut data = [BME68xData::new(); 3];
+        match op_mode {
+            BME68xOpMode::ForcedMode => {
+                data[0] = self.read_field_data(0).await?;
+                new_fields = 1;
+            }
+            BME68xOpMode::ParallelMode | BME68xOpMode::SequentialMode => {
+                let mut buff = [0; BME68X_LEN_FIELD * 3];
+                let mut set_val = [0; 30];
+                self.get_regs(BME68xRegister::Field0.into(), &mut buff)
+                    .await?;
+                self.get_regs(BME68xRegister::IdacHeat0.into(), &mut set_val)
+                    .await?;
+                data = parse_all_fields(&mut self.calib, self.variant_id, &buff, &set_val)?;
+                new_fields = sort_fields(&mut data);
+            }
+            BME68xOpMode::SleepMode => return Err(BME68xError::DefineOpMode),
+        }
+        if new_fields == 0 {
+            Err(BME68xError::NoNewData)
+        } else {
+            Ok((data, new_fields))
+        }
+    }
+
+    /// Set the oversampling, filter, and odr configuration
+    ///
+    /// Arguments
+    /// * `conf`: Sensor Configuration.
+    ///
+    /// # Errors
+    /// Returns an error if setting the configration failed.
+    pub async fn set_config(&mut self, conf: &BME68xConf) -> Result<(), BME68xError> {
+        let mut data_array = [0; BME68X_LEN_CONFIG];
+        let current_op_mode = self.get_op_mode().await?;
+
+        // Configure only in sleep mode
+        self.set_op_mode(BME68xOpMode::SleepMode).await?;
+
+        self.get_regs(BME68xRegister::CtrlGas1.into(), &mut data_array)
+            .await?;
+
+        self.set_regs(&BME68X_CONF_REGS, &conf_to_regs(*conf, data_array))
+            .await?;
+        self.set_op_mode(current_op_mode).await
+    }
+
+    /// Get the oversampleing, filter, and odr configuration
+    ///
+    /// # Returns
+    /// The current sensor configuration.
+    ///
+    /// # Errors
+    /// Returns an error if getting the configuration failed.
+    pub async fn get_config(&mut self) -> Result<BME68xConf, BME68xError> {
+        let mut data_array = [0; BME68X_LEN_CONFIG];
+        self.get_regs(BME68xRegister::CtrlGas1.into(), &mut data_array)
+            .await?;
+        Ok(conf_from_regs(data_array))
+    }
+
+    /// Set the gas configuration of the sensor
+    ///
+    /// # Arguments
+    /// * `op_mode` Expected operation mode of the sensor
+    /// * `conf`: Desired heating configuration.
+    ///
+    /// # Errors
+    /// Returns an error if seting the heater configuration failed
+    pub async fn set_heatr_conf(
+        &mut self,
+        op_mode: BME68xOpMode,
+        conf: &BME68xHeatrConf,
+    ) -> Result<(), BME68xError> {
+        self.set_op_mode(BME68xOpMode::SleepMode).await?;
+        let mut ctrl_gas_data = [0; 2];
+        let ctrl_gas_addr = [
+            BME68xRegister::CtrlGas0.into(),
+            BME68xRegister::CtrlGas1.into(),
+        ];
+
+        let regs = BME68xHeatrRegs::new(conf, op_mode, &self.calib, self.amb_temp)?;
+        if let Some(shared_dur) = regs.shared_dur {
+            self.set_reg(BME68xRegister::ShdHeatrDur, shared_dur)
+                .await?;
+        }
+        self.set_regs(regs.rh_reg_addr(), regs.rh_reg_data())
+            .await?;
+        self.set_regs(regs.gw_reg_addr(), regs.gw_reg_data())
+            .await?;
+
+        self.get_regs(BME68xRegister::CtrlGas0.into(), &mut ctrl_gas_data)
+            .await?;
+        let ctrl_gas_data = heatr_ctrl_regs(conf, self.variant_id, regs.nb_conv, ctrl_gas_data);
+
+        self.set_regs(&ctrl_gas_addr, &ctrl_gas_data).await
+    }
+
+    /// Set the heater configuration to be disabled.
+    ///
+    /// # Arguments
+    /// * `op_mode`: The Operating mode to use.
+    ///
+    /// # Errors
+    /// Returns an error if disabling fails.
+    pub async fn set_heatr_conf_disabled(
+        &mut self,
+        op_mode: BME68xOpMode,
+    ) -> Result<(), BME68xError> {
+        self.set_heatr_conf(op_mode, &BME68xHeatrConf::new()).await
+    }
+
+    /// Set the heater configuration for a forced measurement
+    ///
+    /// # Arguments
+    /// * `temperature`: The temperature to set the heater to
+    /// * `duration`: The duration to runt the heater for
+    ///
+    /// # Errors
+    /// Returns an error if setting the heater configuration fails.
+    pub async fn set_heatr_conf_forced(
+        &mut self,
+        temperature: u16,
+        duration: u16,
+    ) -> Result<(), BME68xError> {
+        self.set_heatr_conf(
+            BME68xOpMode::ForcedMode,
+            &BME68xHeatrConf::new_forced(temperature, duration),
+        )
+        .await
+    }
+
+    /// Set the heater configuration for parallel measurement
+    ///
+    /// Profile lengths must be equal, and less than or equal to 10
+    /// steps
+    ///
+    /// # Arguments
+    /// * `temp_profile`: The temperature profile to use
+    /// * `duration_profile`: The duration profile to use
+    ///
+    /// # Errors
+    /// Returns ane error if setting the heater configuraiton fails.
+    pub async fn set_heatr_conf_parallel(
+        &mut self,
+        temp_profile: &[u16],
+        duration_profile: &[u16],
+    ) -> Result<(), BME68xError> {
+        let current_config = self.get_config().await?;
+        let shared_duration = calc_shared_heatr_dur(current_config)?;
+        self.set_heatr_conf(
+            BME68xOpMode::ParallelMode,
+            &BME68xHeatrConf::new_parallel(temp_profile, duration_profile, shared_duration)?,
+        )
+        .await
+    }
+
+    /// Set the heater configuration for sequential measurement
+    ///
+    /// Profile lengths must be equal, and less than or equal to 10
+    /// steps
+    ///
+    /// # Arguments
+    /// * `temp_profile`: The temperature profile to use
+    /// * `duration_profile`: The duration profile to use
+    ///
+    /// # Errors
+    /// Returns ane error if setting the heater configuraiton fails.
+    pub async fn set_heatr_conf_sequential(
+        &mut self,
+        temp_profile: &[u16],
+        duration_profile: &[u16],
+    ) -> Result<(), BME68xError> {
+        self.set_heatr_conf(
+            BME68xOpMode::SequentialMode,
+            &BME68xHeatrConf::new_sequential(temp_profile, duration_profile)?,
+        )
+        .await
+    }
+
+    /// Get the heater configuration of the sensor
+    ///
+    /// # Returns
+    /// The current heater configuration of the sensor
+    ///
+    /// # Errors
+    /// Returns an error if reading the heater configuration failed.
+    pub async fn get_heatr_conf(&mut self) -> Result<BME68xHeatrConf, BME68xError> {
+        let mut conf = BME68xHeatrConf::new();
+        let mut data = [0; MAX_PROFILE_LEN];
+
+        self.get_regs(BME68xRegister::ResHeat0.into(), &mut data)
+            .await?;
+        conf.heatr_temp_prof = data.map(core::convert::Into::into);
+
+        self.get_regs(BME68xRegister::GasWait0.into(), &mut data)
+            .await?;
+        conf.heatr_dur_prof = data.map(core::convert::Into::into);
+
+        Ok(conf)
+    }
+
+    /// Perform a forced measurement
+    ///
+    /// # Arguments
+    /// * `temperature`: The temperature to heat the heater to
+    /// * `duration`: The duration to heat the heater for
+    ///
+    /// # Returns
+    /// The measured data.
+    ///
+    /// # Errors
+    /// Returns an error if performing the measurement failed.
+    pub async fn forced_measurent(
+        &mut self,
+        temperature: u16,
+        duration: u16,
+    ) -> Result<BME68xData, BME68xError> {
+        self.set_heatr_conf_forced(temperature, duration).await?;
+        let config = self.get_config().await?;
+        let delay_period =
+            self.get_meas_dur(BME68xOpMode::ForcedMode, &config) + (u32::from(duration) * 1000);
+
+        // Start measurement and wait for it to finish
+        self.set_op_mode(BME68xOpMode::ForcedMode).await?;
+        self.delay.delay_us(delay_period).await;
+
+        // Get the data
+        let (data, _) = self.get_data(BME68xOpMode::ForcedMode).await?;
+        Ok(data[0])
+    }
+
+    /// Read the calibration data from the sensor
+    ///
+    /// # Errors
+    /// Errors if reading the calibration data failed.
+    async fn get_calib_data(&mut self) -> Result<(), BME68xError> {
+        let mut coeff_array = [0; BME68X_LEN_COEFF_ALL];
+
+        self.get_regs(
+            BME68xRegister::Coeff1.into(),
+            &mut coeff_array[0..BME68X_LEN_COEFF1],
+        )
+        .await?;
+        self.get_regs(
+            BME68xRegister::Coeff2.into(),
+            &mut coeff_array[BME68X_LEN_COEFF1..(BME68X_LEN_COEFF1 + BME68X_LEN_COEFF2)],
+        )
+        .await?;
+        self.get_regs(
+            BME68xRegister::Coeff3.into(),
+            &mut coeff_array[(BME68X_LEN_COEFF1 + BME68X_LEN_COEFF2)
+                ..(BME68X_LEN_COEFF1 + BME68X_LEN_COEFF2 + BME68X_LEN_COEFF3)],
+        )
+        .await?;
+
+        self.calib = BME68xCalibData::from_coeff_array(&coeff_array);
+        Ok(())
+    }
+
+    /// Read a single data field from the sensor
+    ///
+    /// # Arguments
+    /// * `index`: The index of the field to read
+    ///
+    /// # Returns
+    /// The data read from the sensor
+    ///
+    /// # Errors
+    /// Returns an error if reading the field failed.
+    async fn read_field_data(&mut self, index: u8) -> Result<BME68xData, BME68xError> {
+        let mut tries = 5;
+        let mut data = BME68xData::new();
+        while tries > 0 {
+            let mut buff = [0; BME68X_LEN_FIELD];
+            let reg_addr: u8 =
+                (u8::from(BME68xRegister::Field0)) + (index * BME68X_LEN_FIELD_OFFSET);
+            self.get_regs(reg_addr, &mut buff).await?;
+
+            data = parse_field_status(&buff, self.variant_id);
+
+            if (data.status & BME68X_NEW_DATA_MSK) != 0 {
+                let mut set_val = [0; 1];
+                self.get_regs(
+                    u8::from(BME68xRegister::ResHeat0) + data.gas_index,
+                    &mut set_val,
+                )
+                .await?;
+                data.res_heat = set_val[0];
+                self.get_regs(
+                    u8::from(BME68xRegister::IdacHeat0) + data.gas_index,
+                    &mut set_val,
+                )
+                .await?;
+                data.idac = set_val[0];
+                self.get_regs(
+                    u8::from(BME68xRegister::GasWait0) + data.gas_index,
+                    &mut set_val,
+                )
+                .await?;
+                data.gas_wait = set_val[0];
+                self.calib
+                    .compensate_field(&buff, self.variant_id, &mut data)?;
+                break;
+            }
+            self.delay.delay_us(BME68X_PERIOD_POLL).await;
+            tries -= 1;
+        }
+        if (tries == 0) && (data == BME68xData::new()) {
+            Err(BME68xError::NoNewData)
+        } else {
+            Ok(data)
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Communication interfaces (I2C and SPI) for the BME68X sensor.
+use embedded_hal::i2c::I2c;
+use embedded_hal::spi::{Operation, SpiDevice};
+
+use crate::{BME68xAddr, BME68xError, BME68xIntf, BME68xRegister};
+
+/// Length of the interleaved (register, data) write buffer
+pub(crate) const BME68X_LEN_INTERLEAVE_BUFF: usize = 20;
+
+/// Mask for SPI memory page
+pub(crate) const BME68X_MEM_PAGE_MSK: u8 = 0x10;
+
+/// Mask for reading a register in SPI
+pub(crate) const BME68X_SPI_RD_MSK: u8 = 0x80;
+
+/// Mask for writing a register in SPI
+pub(crate) const BME68X_SPI_WR_MSK: u8 = 0x7f;
+
+/// Enumeration of the memory pages for SPI mode
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq)]
+enum BME68xMemPage {
+    /// SPI Memory Page 0
+    Page0 = 0x10,
+
+    /// SPI Memory Page 1
+    Page1 = 0x00,
+}
+
+impl BME68xMemPage {
+    /// Get the memory page that holds the given register
+    ///
+    /// # Arguments
+    /// * `reg_addr`: The address of the register
+    ///
+    /// # Returns
+    /// The memory page that the register lives in.
+    fn for_register(reg_addr: u8) -> Self {
+        if reg_addr > 0x7f {
+            Self::Page1
+        } else {
+            Self::Page0
+        }
+    }
+}
+
+impl From<u8> for BME68xMemPage {
+    fn from(value: u8) -> Self {
+        if (value & BME68X_MEM_PAGE_MSK) == 0 {
+            Self::Page1
+        } else {
+            Self::Page0
+        }
+    }
+}
+
+impl From<BME68xMemPage> for u8 {
+    fn from(value: BME68xMemPage) -> Self {
+        value as u8
+    }
+}
+
+/// Trait for the bus that is used to talk to the sensor.
+///
+/// Implementations take care of any bus specific details (addressing,
+/// read/write masks, SPI memory pages), so the driver can work purely in
+/// terms of register addresses.
+pub trait BME68xInterface {
+    /// The type of interface that is implemented
+    const INTF: BME68xIntf;
+
+    /// Read a block of consecutive registers
+    ///
+    /// # Arguments
+    /// * `reg_addr`: The address of the first register to read
+    /// * `data`: Buffer to place the read data in.
+    ///
+    /// # Errors
+    /// Returns `BME68xError::ComFail` if the bus transaction failed.
+    fn read_regs(&mut self, reg_addr: u8, data: &mut [u8]) -> Result<(), BME68xError>;
+
+    /// Write data to a set of registers
+    ///
+    /// # Arguments
+    /// * `reg_addr`: The registers to write to
+    /// * `reg_data`: The data to write. Must be the same length as `reg_addr`
+    ///
+    /// # Errors
+    /// Returns `BME68xError::ComFail` if the bus transaction failed.
+    fn write_regs(&mut self, reg_addr: &[u8], reg_data: &[u8]) -> Result<(), BME68xError>;
+
+    /// Synchronize any cached interface state with the sensor.
+    ///
+    /// The sensor can change its side of the state on its own (for example, a
+    /// soft reset puts the SPI memory page back to its default), so this is
+    /// called around those operations.
+    ///
+    /// # Errors
+    /// Returns `BME68xError::ComFail` if the bus transaction failed.
+    fn sync(&mut self) -> Result<(), BME68xError> {
+        Ok(())
+    }
+}
+
+/// I2C interface to the sensor
+pub struct I2cInterface<I2C> {
+    /// Concrete I2C Implementation
+    i2c: I2C,
+
+    /// The I2C Address
+    address: BME68xAddr,
+}
+
+impl<I2C: I2c> I2cInterface<I2C> {
+    /// Create a new I2C interface
+    ///
+    /// # Arguments
+    /// * `i2c`: The I2C bus the sensor is attached to
+    /// * `address`: The address of the sensor on the bus
+    ///
+    /// # Returns
+    /// The new interface
+    pub fn new(i2c: I2C, address: BME68xAddr) -> Self {
+        Self { i2c, address }
+    }
+
+    /// Destroy the interface and return the I2C bus
+    ///
+    /// # Returns
+    /// The I2C Bus.
+    pub fn destroy(self) -> I2C {
+        self.i2c
+    }
+}
+
+impl<I2C: I2c> BME68xInterface for I2cInterface<I2C> {
+    const INTF: BME68xIntf = BME68xIntf::I2CIntf;
+
+    fn read_regs(&mut self, reg_addr: u8, data: &mut [u8]) -> Result<(), BME68xError> {
+        self.i2c
+            .write_read(self.address.into(), &[reg_addr], data)
+            .map_err(|_| BME68xError::ComFail)
+    }
+
+    fn write_regs(&mut self, reg_addr: &[u8], reg_data: &[u8]) -> Result<(), BME68xError> {
+        let mut tmp_buff = [0; BME68X_LEN_INTERLEAVE_BUFF];
+        let len = interleave(reg_addr, reg_data, &mut tmp_buff);
+        self.i2c
+            .write(self.address.into(), &tmp_buff[0..len])
+            .map_err(|_| BME68xError::ComFail)
+    }
+}
+
+/// SPI interface to the sensor
+///
+/// In SPI mode the register map is split over two memory pages. This
+/// interface keeps track of the currently selected page and switches pages
+/// as needed before accessing a register.
+pub struct SpiInterface<SPI> {
+    /// Concrete SPI Implementation
+    spi: SPI,
+
+    /// Memory page currently selected on the sensor
+    mem_page: BME68xMemPage,
+}
+
+impl<SPI: SpiDevice> SpiInterface<SPI> {
+    /// Create a new SPI interface
+    ///
+    /// # Arguments
+    /// * `spi`: The SPI device for the sensor (including chip select)
+    ///
+    /// # Returns
+    /// The new interface
+    pub fn new(spi: SPI) -> Self {
+        Self {
+            spi,
+            mem_page: BME68xMemPage::Page0,
+        }
+    }
+
+    /// Destroy the interface and return the SPI device
+    ///
+    /// # Returns
+    /// The SPI device.
+    pub fn destroy(self) -> SPI {
+        self.spi
+    }
+
+    /// Read the memory page register.
+    ///
+    /// # Returns
+    /// The raw value of the memory page register.
+    ///
+    /// # Errors
+    /// Returns an error if reading the register failed.
+    fn read_mem_page_reg(&mut self) -> Result<u8, BME68xError> {
+        let mut read_buffer = [0];
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[u8::from(BME68xRegister::MemPage) | BME68X_SPI_RD_MSK]),
+                Operation::Read(&mut read_buffer),
+            ])
+            .map_err(|_| BME68xError::ComFail)?;
+        Ok(read_buffer[0])
+    }
+
+    /// Switch to the memory page holding the given register, if needed.
+    ///
+    /// # Arguments
+    /// * `reg_addr`: The address of the register to read/write from.
+    ///
+    /// # Errors
+    /// Returns an error if switching memory pages fails.
+    fn set_mem_page(&mut self, reg_addr: u8) -> Result<(), BME68xError> {
+        let mem_page = BME68xMemPage::for_register(reg_addr);
+
+        if mem_page != self.mem_page {
+            let reg = self.read_mem_page_reg()? & (!BME68X_MEM_PAGE_MSK);
+            let reg = reg | (u8::from(mem_page) & BME68X_MEM_PAGE_MSK);
+
+            self.spi
+                .write(&[u8::from(BME68xRegister::MemPage) & BME68X_SPI_WR_MSK, reg])
+                .map_err(|_| BME68xError::ComFail)?;
+            self.mem_page = mem_page;
+        }
+        Ok(())
+    }
+}
+
+impl<SPI: SpiDevice> BME68xInterface for SpiInterface<SPI> {
+    const INTF: BME68xIntf = BME68xIntf::SPIIntf;
+
+    fn read_regs(&mut self, reg_addr: u8, data: &mut [u8]) -> Result<(), BME68xError> {
+        self.set_mem_page(reg_addr)?;
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[reg_addr | BME68X_SPI_RD_MSK]),
+                Operation::Read(data),
+            ])
+            .map_err(|_| BME68xError::ComFail)
+    }
+
+    fn write_regs(&mut self, reg_addr: &[u8], reg_data: &[u8]) -> Result<(), BME68xError> {
+        // Registers are written in bursts of (reg, data) pairs. A burst can
+        // only address a single memory page, so split it wherever the page changes.
+        let mut start = 0;
+        while start < reg_addr.len() {
+            let page = BME68xMemPage::for_register(reg_addr[start]);
+            let end = reg_addr[start..]
+                .iter()
+                .position(|reg| BME68xMemPage::for_register(*reg) != page)
+                .map_or(reg_addr.len(), |offset| start + offset);
+
+            self.set_mem_page(reg_addr[start])?;
+
+            let mut tmp_buff = [0; BME68X_LEN_INTERLEAVE_BUFF];
+            let len = interleave(&reg_addr[start..end], &reg_data[start..end], &mut tmp_buff);
+            for reg in tmp_buff[0..len].iter_mut().step_by(2) {
+                *reg &= BME68X_SPI_WR_MSK;
+            }
+            self.spi
+                .write(&tmp_buff[0..len])
+                .map_err(|_| BME68xError::ComFail)?;
+            start = end;
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<(), BME68xError> {
+        self.mem_page = BME68xMemPage::from(self.read_mem_page_reg()?);
+        Ok(())
+    }
+}
+
+/// Interleave register addresses and data into a single buffer
+///
+/// Data is interwoven in the form (reg, data, reg, data, reg, data, ...)
+///
+/// # Arguments
+/// * `reg_addr`: The register addresses
+/// * `reg_data`: The data for each register
+/// * `buffer`: The buffer to write the interleaved data to.
+///
+/// # Returns
+/// The number of bytes of the buffer that were used.
+fn interleave(reg_addr: &[u8], reg_data: &[u8], buffer: &mut [u8]) -> usize {
+    for (index, (addr, data)) in reg_addr.iter().zip(reg_data).enumerate() {
+        buffer[2 * index] = *addr;
+        buffer[(2 * index) + 1] = *data;
+    }
+    2 * reg_addr.len()
+}
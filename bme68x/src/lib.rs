@@ -1,4 +1,4 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 //! BME68X Driver Implementation in pure rust.
 // TODO: Conditional FPU support?
 // TODO: Break this up into a few files to make it easier to read.
@@ -6,8 +6,18 @@
 // FIXME: Get this moved into cargo.toml. IDK why it is nt working there
 #![allow(clippy::unreadable_literal)]
 use core::num::TryFromIntError;
+use embedded_hal::delay::DelayNs;
 
-use embedded_hal::i2c::I2c;
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod interface;
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
+
+#[cfg(feature = "async")]
+pub use asynchronous::BME68xDevAsync;
+use interface::BME68X_LEN_INTERLEAVE_BUFF;
+pub use interface::{BME68xInterface, I2cInterface, SpiInterface};
 
 // Other stuff
 /// Max profile length
@@ -119,15 +129,6 @@ const BME68X_GASM_VALID_MSK: u8 = 0x20;
 /// Mask for heater stability
 const BME68X_HEAT_STAB_MSK: u8 = 0x10;
 
-/// Mask for SPI memory page
-const BME68X_MEM_PAGE_MSK: u8 = 0x10;
-
-/// Mask for reading a register in SPI
-const BME68X_SPI_RD_MSK: u8 = 0x80;
-
-/// Mask for writing a register in SPI
-const BME68X_SPI_WR_MSK: u8 = 0x7f;
-
 /// Mask for the H1 calibration coefficient
 const BME68X_BIT_H1_DATA_MSK: u16 = 0x0f;
 
@@ -154,9 +155,6 @@ const BME68X_LEN_FIELD_OFFSET: u8 = 17;
 ///  Length of the configuration register
 const BME68X_LEN_CONFIG: usize = 5;
 
-///  Length of the interleaved buffer
-const BME68X_LEN_INTERLEAVE_BUFF: usize = 20;
-
 //  Coefficient index macros
 
 ///  Coefficient T2 LSB position
@@ -302,33 +300,6 @@ pub enum BME68xAddr {
     HIGH = 0x77,
 }
 
-/// Enumeration of the memory pages for SPI mode
-#[repr(u8)]
-#[derive(Clone, Copy)]
-enum BME68xMemPage {
-    /// SPI Memory Page 0
-    Page0 = 0x10,
-
-    /// SPI Memory Page 1
-    Page1 = 0x00,
-}
-
-impl From<u8> for BME68xMemPage {
-    fn from(value: u8) -> Self {
-        match value {
-            0x10 => Self::Page0,
-            0x00 => Self::Page1,
-            _ => panic!("Cannot convert {value} to a BME68xMemPage"),
-        }
-    }
-}
-
-impl From<BME68xMemPage> for u8 {
-    fn from(value: BME68xMemPage) -> Self {
-        value as u8
-    }
-}
-
 /// Control to enable or disable the heater.
 enum BME68xHeaterEnable {
     /// Disable the heater
@@ -676,7 +647,7 @@ pub enum BME68xIntf {
 
 /// BME68X Operating Modes
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum BME68xOpMode {
     /// Sleep Mode
     SleepMode = 0,
@@ -712,7 +683,7 @@ impl From<u8> for BME68xOpMode {
 /// Sensor Field Data Structure
 #[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct BME68xData {
-    /// Sensor Status (new_data, gasm_valid, heat_stab)
+    /// Sensor Status (`new_data`, `gasm_valid`, `heat_stab`)
     pub status: u8,
 
     /// Index of the heater profile in use
@@ -731,16 +702,36 @@ pub struct BME68xData {
     pub gas_wait: u8,
 
     /// Temperature in degrees celsius
+    #[cfg(not(feature = "fixed-point"))]
     pub temperature: f32,
 
+    /// Temperature in degrees celsius x100
+    #[cfg(feature = "fixed-point")]
+    pub temperature: i16,
+
     /// Pressure in Pascal
+    #[cfg(not(feature = "fixed-point"))]
     pub pressure: f32,
 
-    /// Humidity in % relative humidity x1000
+    /// Pressure in Pascal
+    #[cfg(feature = "fixed-point")]
+    pub pressure: u32,
+
+    /// Humidity in % relative humidity
+    #[cfg(not(feature = "fixed-point"))]
     pub humidity: f32,
 
+    /// Humidity in % relative humidity x1000
+    #[cfg(feature = "fixed-point")]
+    pub humidity: u32,
+
     /// Gas Resistance in Ohms
+    #[cfg(not(feature = "fixed-point"))]
     pub gas_resistance: f32,
+
+    /// Gas Resistance in Ohms
+    #[cfg(feature = "fixed-point")]
+    pub gas_resistance: u32,
 }
 
 impl BME68xData {
@@ -754,12 +745,158 @@ impl BME68xData {
             res_heat: 0,
             idac: 0,
             gas_wait: 0,
+            #[cfg(not(feature = "fixed-point"))]
             temperature: 0.0,
+            #[cfg(not(feature = "fixed-point"))]
             pressure: 0.0,
+            #[cfg(not(feature = "fixed-point"))]
             humidity: 0.0,
+            #[cfg(not(feature = "fixed-point"))]
             gas_resistance: 0.0,
+            #[cfg(feature = "fixed-point")]
+            temperature: 0,
+            #[cfg(feature = "fixed-point")]
+            pressure: 0,
+            #[cfg(feature = "fixed-point")]
+            humidity: 0,
+            #[cfg(feature = "fixed-point")]
+            gas_resistance: 0,
+        }
+    }
+}
+
+#[cfg(not(feature = "fixed-point"))]
+impl BME68xData {
+    /// Create a new instance from readings, regardless of the compensation mode
+    ///
+    /// # Arguments
+    /// * `temperature`: Temperature in degrees celsius
+    /// * `pressure`: Pressure in Pascal
+    /// * `humidity`: Humidity in % relative humidity
+    /// * `gas_resistance`: Gas resistance in Ohms
+    ///
+    /// # Returns
+    /// The new instance, with all other members set to 0
+    #[must_use]
+    pub fn from_readings(
+        temperature: f32,
+        pressure: f32,
+        humidity: f32,
+        gas_resistance: f32,
+    ) -> Self {
+        Self {
+            temperature,
+            pressure,
+            humidity,
+            gas_resistance,
+            ..Self::new()
+        }
+    }
+
+    /// Get the temperature as a float, regardless of the compensation mode
+    ///
+    /// # Returns
+    /// Temperature in degrees celsius
+    #[must_use]
+    pub fn temperature_degc(&self) -> f32 {
+        self.temperature
+    }
+
+    /// Get the pressure as a float, regardless of the compensation mode
+    ///
+    /// # Returns
+    /// Pressure in Pascal
+    #[must_use]
+    pub fn pressure_pa(&self) -> f32 {
+        self.pressure
+    }
+
+    /// Get the humidity as a float, regardless of the compensation mode
+    ///
+    /// # Returns
+    /// Humidity in % relative humidity
+    #[must_use]
+    pub fn humidity_percent(&self) -> f32 {
+        self.humidity
+    }
+
+    /// Get the gas resistance as a float, regardless of the compensation mode
+    ///
+    /// # Returns
+    /// Gas resistance in Ohms
+    #[must_use]
+    pub fn gas_resistance_ohm(&self) -> f32 {
+        self.gas_resistance
+    }
+}
+
+#[cfg(feature = "fixed-point")]
+impl BME68xData {
+    /// Create a new instance from readings, regardless of the compensation mode
+    ///
+    /// The readings are rounded to the resolution of the fixed-point members.
+    ///
+    /// # Arguments
+    /// * `temperature`: Temperature in degrees celsius
+    /// * `pressure`: Pressure in Pascal
+    /// * `humidity`: Humidity in % relative humidity
+    /// * `gas_resistance`: Gas resistance in Ohms
+    ///
+    /// # Returns
+    /// The new instance, with all other members set to 0
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn from_readings(
+        temperature: f32,
+        pressure: f32,
+        humidity: f32,
+        gas_resistance: f32,
+    ) -> Self {
+        Self {
+            // Round half away from zero, as `f32::round` needs std
+            temperature: (temperature * 100.0 + 0.5f32.copysign(temperature)) as i16,
+            pressure: (pressure + 0.5) as u32,
+            humidity: (humidity * 1000.0 + 0.5) as u32,
+            gas_resistance: (gas_resistance + 0.5) as u32,
+            ..Self::new()
         }
     }
+
+    /// Get the temperature as a float, regardless of the compensation mode
+    ///
+    /// # Returns
+    /// Temperature in degrees celsius
+    #[must_use]
+    pub fn temperature_degc(&self) -> f32 {
+        f32::from(self.temperature) / 100.0
+    }
+
+    /// Get the pressure as a float, regardless of the compensation mode
+    ///
+    /// # Returns
+    /// Pressure in Pascal
+    #[must_use]
+    pub fn pressure_pa(&self) -> f32 {
+        cast_u2f32(self.pressure)
+    }
+
+    /// Get the humidity as a float, regardless of the compensation mode
+    ///
+    /// # Returns
+    /// Humidity in % relative humidity
+    #[must_use]
+    pub fn humidity_percent(&self) -> f32 {
+        cast_u2f32(self.humidity) / 1000.0
+    }
+
+    /// Get the gas resistance as a float, regardless of the compensation mode
+    ///
+    /// # Returns
+    /// Gas resistance in Ohms
+    #[must_use]
+    pub fn gas_resistance_ohm(&self) -> f32 {
+        cast_u2f32(self.gas_resistance)
+    }
 }
 
 /// Calibration Coefficent Data Structurre
@@ -835,8 +972,13 @@ struct BME68xCalibData {
     par_p10: u8,
 
     /// Variable to store the intermediate temperature coefficient
+    #[cfg(not(feature = "fixed-point"))]
     t_fine: f32,
 
+    /// Variable to store the intermediate temperature coefficient
+    #[cfg(feature = "fixed-point")]
+    t_fine: i32,
+
     /// Heater resistance range coefficient
     res_heat_range: u8,
 
@@ -877,111 +1019,584 @@ impl BME68xCalibData {
             range_sw_err: 0,
             res_heat_range: 0,
             res_heat_val: 0,
+            #[cfg(not(feature = "fixed-point"))]
             t_fine: 0.0,
+            #[cfg(feature = "fixed-point")]
+            t_fine: 0,
         }
     }
-}
 
-/// BME68X sensor settings structure which comprises of ODR, over-sampling and filter settings.
-#[derive(Clone, Copy, Debug)]
-pub struct BME68xConf {
-    /// Humidity overrsampling
-    pub os_hum: BME68xOs,
+    /// Parse the calibration coefficents read from the sensor
+    ///
+    /// # Arguments
+    /// * `coeff_array`: The raw coefficent registers, in the order they are read
+    ///
+    /// # Returns
+    /// The parsed calibration data
+    fn from_coeff_array(coeff_array: &[u8; BME68X_LEN_COEFF_ALL]) -> Self {
+        let mut calib = Self::new();
 
-    /// Temperature Oversampling
-    pub os_temp: BME68xOs,
+        // Copy data over
+        /* Temperature related coefficients */
+        calib.par_t1 = concat_bytes(
+            coeff_array[BME68X_IDX_T1_MSB],
+            coeff_array[BME68X_IDX_T1_LSB],
+        );
+        calib.par_t2 = wrap_u2i16(concat_bytes(
+            coeff_array[BME68X_IDX_T2_MSB],
+            coeff_array[BME68X_IDX_T2_LSB],
+        ));
+        calib.par_t3 = wrap_u2i8(coeff_array[BME68X_IDX_T3]);
 
-    /// Pressure Oversampling
-    pub os_pres: BME68xOs,
+        /* Pressure related coefficients */
+        calib.par_p1 = concat_bytes(
+            coeff_array[BME68X_IDX_P1_MSB],
+            coeff_array[BME68X_IDX_P1_LSB],
+        );
+        calib.par_p2 = wrap_u2i16(concat_bytes(
+            coeff_array[BME68X_IDX_P2_MSB],
+            coeff_array[BME68X_IDX_P2_LSB],
+        ));
+        calib.par_p3 = wrap_u2i8(coeff_array[BME68X_IDX_P3]);
+        calib.par_p4 = wrap_u2i16(concat_bytes(
+            coeff_array[BME68X_IDX_P4_MSB],
+            coeff_array[BME68X_IDX_P4_LSB],
+        ));
+        calib.par_p5 = wrap_u2i16(concat_bytes(
+            coeff_array[BME68X_IDX_P5_MSB],
+            coeff_array[BME68X_IDX_P5_LSB],
+        ));
+        calib.par_p6 = wrap_u2i8(coeff_array[BME68X_IDX_P6]);
+        calib.par_p7 = wrap_u2i8(coeff_array[BME68X_IDX_P7]);
+        calib.par_p8 = wrap_u2i16(concat_bytes(
+            coeff_array[BME68X_IDX_P8_MSB],
+            coeff_array[BME68X_IDX_P8_LSB],
+        ));
+        calib.par_p9 = wrap_u2i16(concat_bytes(
+            coeff_array[BME68X_IDX_P9_MSB],
+            coeff_array[BME68X_IDX_P9_LSB],
+        ));
+        calib.par_p10 = coeff_array[BME68X_IDX_P10];
 
-    /// Filter Coefficent
-    pub filter: BME68xFilter,
+        /* Humidity related coefficients */
+        calib.par_h1 = (u16::from(coeff_array[BME68X_IDX_H1_MSB]) << 4)
+            | (u16::from(coeff_array[BME68X_IDX_H1_LSB]) & BME68X_BIT_H1_DATA_MSK);
+        calib.par_h2 = (u16::from(coeff_array[BME68X_IDX_H2_MSB]) << 4)
+            | (u16::from(coeff_array[BME68X_IDX_H2_LSB]) >> 4);
+        calib.par_h3 = wrap_u2i8(coeff_array[BME68X_IDX_H3]);
+        calib.par_h4 = wrap_u2i8(coeff_array[BME68X_IDX_H4]);
+        calib.par_h5 = wrap_u2i8(coeff_array[BME68X_IDX_H5]);
+        calib.par_h6 = coeff_array[BME68X_IDX_H6];
+        calib.par_h7 = wrap_u2i8(coeff_array[BME68X_IDX_H7]);
 
-    /// Standby time between sequential mode measurement profiles
-    pub odr: BME68xODR,
-}
+        /* Gas heater related coefficients */
+        calib.par_gh1 = wrap_u2i8(coeff_array[BME68X_IDX_GH1]);
+        calib.par_gh2 = wrap_u2i16(concat_bytes(
+            coeff_array[BME68X_IDX_GH2_MSB],
+            coeff_array[BME68X_IDX_GH2_LSB],
+        ));
+        calib.par_gh3 = wrap_u2i8(coeff_array[BME68X_IDX_GH3]);
 
-/// Gas Heater Configuration
-#[derive(Debug, Clone, Copy, Default)]
-pub struct BME68xHeatrConf {
-    /// Enable gas measurement
-    pub enable: bool,
+        /* Other coefficients */
+        calib.res_heat_range = (coeff_array[BME68X_IDX_RES_HEAT_RANGE] & BME68X_RHRANGE_MSK) / 16;
+        calib.res_heat_val = wrap_u2i8(coeff_array[BME68X_IDX_RES_HEAT_VAL]);
+        calib.range_sw_err =
+            (wrap_u2i8(coeff_array[BME68X_IDX_RANGE_SW_ERR] & BME68X_RSERROR_MSK)) / 16;
 
-    /// Store the heater temperature for forced mode degree Celsius
-    pub heatr_temp: u16,
+        calib
+    }
 
-    /// Store the heating duration for forced mode in milliseconds
-    pub heatr_dur: u16,
+    /// Compensate the raw measurements of a data field
+    ///
+    /// # Arguments
+    /// * `buff`: The raw field data
+    /// * `variant_id`: The variant of the sensor
+    /// * `data`: The data to write the compensated measurements to
+    ///
+    /// # Errors
+    /// Returns an error if the raw gas data is out of range.
+    fn compensate_field(
+        &mut self,
+        buff: &[u8],
+        variant_id: BME68xVariant,
+        data: &mut BME68xData,
+    ) -> Result<(), BME68xError> {
+        let adc_pres =
+            (u32::from(buff[2]) * 4096) | (u32::from(buff[3]) * 16) | (u32::from(buff[4]) / 16);
+        let adc_temp =
+            (u32::from(buff[5]) * 4096) | (u32::from(buff[6]) * 16) | (u32::from(buff[7]) / 16);
+        let adc_hum = (u32::from(buff[8]) * 256) | u32::from(buff[9]);
+        let adc_gas_res_low = (u32::from(buff[13]) * 4) | ((u32::from(buff[14])) / 64);
+        let adc_gas_res_high = (u32::from(buff[15]) * 4) | ((u32::from(buff[16])) / 64);
+        let gas_range_l = buff[14] & BME68X_GAS_RANGE_MSK;
+        let gas_range_h = buff[16] & BME68X_GAS_RANGE_MSK;
+
+        data.temperature = self.calc_temperature(adc_temp);
+        data.pressure = self.calc_pressure(adc_pres);
+        data.humidity = self.calc_humidity(adc_hum);
+        if matches!(variant_id, BME68xVariant::GasHigh) {
+            data.gas_resistance = calc_gas_resistance_high(
+                // Checked mathmatically. Should never go out of bounds
+                u16::try_from(adc_gas_res_high)?,
+                gas_range_h,
+            );
+        } else {
+            data.gas_resistance = self.calc_gas_resistance_low(
+                // Checked mathmatically. Should never go out of bounds
+                u16::try_from(adc_gas_res_low)?,
+                gas_range_l,
+            );
+        }
+        Ok(())
+    }
 
-    /// Store the heater temperature profile in degree Celsius, Max of 10
-    pub heatr_temp_prof: [u16; MAX_PROFILE_LEN],
+    /// Calcualte the temperature as a float
+    ///
+    /// # Arguments
+    /// * `temp_adc`: The raw ADC Temperature
+    ///
+    /// Returns
+    /// The temperature in degrees celsius as a float
+    #[cfg(not(feature = "fixed-point"))]
+    fn calc_temperature(&mut self, temp_adc: u32) -> f32 {
+        let par_t1_f32 = f32::from(self.par_t1);
+        let par_t3_f32 = f32::from(self.par_t3);
+        let temp_f32 = cast_u2f32(temp_adc);
 
-    /// Store the heating duration profile in milliseconds. Max of 10
-    pub heatr_dur_prof: [u16; MAX_PROFILE_LEN],
+        let var1 = ((temp_f32 / 16384.0) - (par_t1_f32 / 1024.0)) * f32::from(self.par_t2);
 
-    /// Variable to store the length of the heating profile
-    pub profile_len: u8,
+        let var2 = (((temp_f32 / 131072.0) - (par_t1_f32 / 8192.0))
+            * ((temp_f32 / 131072.0) - (par_t1_f32 / 8192.0)))
+            * (par_t3_f32 * 16.0);
 
-    /// Variable to store heating duration for parallel mode in milliseconds
-    pub shared_heatr_dur: u16,
-}
+        self.t_fine = var1 + var2;
 
-impl BME68xHeatrConf {
-    /// Create a new empty instance.
-    #[must_use]
-    pub fn new() -> Self {
-        Self {
-            enable: false,
-            heatr_temp: 0,
-            heatr_dur: 0,
-            heatr_temp_prof: [0; MAX_PROFILE_LEN],
-            heatr_dur_prof: [0; MAX_PROFILE_LEN],
-            profile_len: 0,
-            shared_heatr_dur: 0,
-        }
+        self.t_fine / 5120.0
     }
 
-    /// Create a new instance for a forced measurement
+    /// Calcualte the pressure value as a float
     ///
     /// # Arguments
-    /// * `temperature`: The temperature to run the heater at
-    /// * `duration`: The duration to run the heater for.
+    /// * `pres_adc`: Raw pressure ADC value
     ///
     /// # Returns
-    /// New configuration instance for a forced measurement
-    #[must_use]
-    pub fn new_forced(temperature: u16, duration: u16) -> Self {
-        Self {
-            enable: true,
-            heatr_temp: temperature,
-            heatr_dur: duration,
-            heatr_temp_prof: [0; MAX_PROFILE_LEN],
-            heatr_dur_prof: [0; MAX_PROFILE_LEN],
-            profile_len: 0,
-            shared_heatr_dur: 0,
+    /// Pressure in pascals as a float
+    #[cfg(not(feature = "fixed-point"))]
+    fn calc_pressure(&self, pres_adc: u32) -> f32 {
+        let var1 = (self.t_fine / 2.0) - 64000.0;
+        let var2 = var1 * var1 * (f32::from(self.par_p6) / 131072.0);
+        let var2 = var2 + (var1 * (f32::from(self.par_p5) * 2.0));
+        let var2 = (var2 / 4.0) + ((f32::from(self.par_p4)) * 65536.0);
+        let var1 = (((f32::from(self.par_p3) * var1 * var1) / 16384.0)
+            + (f32::from(self.par_p2) * var1))
+            / 524288.0;
+        let var1 = (1.0 + (var1 / 32768.0)) * (f32::from(self.par_p1));
+
+        let calc_pres = 1048576.0 - cast_u2f32(pres_adc);
+
+        if var1 != 0.0 {
+            let calc_pres = ((calc_pres - (var2 / 4096.0)) * 6250.0) / var1;
+            let var1 = (f32::from(self.par_p9) * calc_pres * calc_pres) / 2147483648.0;
+            let var2 = calc_pres * (f32::from(self.par_p8) / 32768.0);
+            let var3 = (calc_pres / 256.0)
+                * (calc_pres / 256.0)
+                * (calc_pres / 256.0)
+                * (f32::from(self.par_p10) / 131072.0);
+            calc_pres + (var1 + var2 + var3 + (f32::from(self.par_p7) * 128.0)) / 16.0
+        } else {
+            0.0
         }
     }
 
-    /// Create a new instance for a parallel measurement
-    ///
-    /// A profile can be defined of up to 10 steps. Both the heater and
-    /// temperatuer profile must have the same number of steps
+    /// Calcualte the humidity value as a float
     ///
     /// # Arguments
-    /// * `temp_profile`: The Temperature profile to run the heater for
-    /// * `duration_profile`: Heater Duration Profile to use
-    /// * `shared_heatr_dur`: Shared heating duration time
+    /// * `hum_adc`: Raw humidty ADC value
     ///
     /// # Returns
-    /// Created instance for a parallel measurement
-    ///
-    /// # Errors
-    /// Will Return a `BME68xError::InvalidLength` If the profiles are longer
-    /// than 10 steps, or the profiles have differnet lengths.
-    pub fn new_parallel(
-        temp_profile: &[u16],
-        duration_profile: &[u16],
-        shared_duration: u16,
+    /// The percent humidity as a floating point.
+    #[cfg(not(feature = "fixed-point"))]
+    fn calc_humidity(&self, hum_adc: u32) -> f32 {
+        let temp_comp = (self.t_fine) / 5120.0;
+
+        let var1 = cast_u2f32(hum_adc)
+            - ((f32::from(self.par_h1) * 16.0) + ((f32::from(self.par_h3) / 2.0) * temp_comp));
+        let var2 = var1
+            * ((f32::from(self.par_h2) / 262144.0)
+                * (1.0
+                    + ((f32::from(self.par_h4) / 16384.0) * temp_comp)
+                    + ((f32::from(self.par_h5) / 1048576.0) * temp_comp * temp_comp)));
+        let var3 = f32::from(self.par_h6) / 16384.0;
+        let var4 = f32::from(self.par_h7) / 2097152.0;
+        let calc_hum = var2 + ((var3 + (var4 * temp_comp)) * var2 * var2);
+
+        calc_hum.clamp(0.0, 100.0)
+    }
+
+    /// Calculate gas resistance low value as a float
+    ///
+    /// # Arguments:
+    /// * `gas_res_adc`: Raw ADC gas resistance value
+    /// * `gas_range`: The gas range to use for the calculation
+    ///
+    /// # Returns
+    /// The low gas resistance as a float
+    #[cfg(not(feature = "fixed-point"))]
+    fn calc_gas_resistance_low(&self, gas_res_adc: u16, gas_range: u8) -> f32 {
+        let gas_res_f = f32::from(gas_res_adc);
+        let gas_range_f = cast_i2f32(1 << gas_range);
+        let lookup_k1_range = [
+            0.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, -0.8, 0.0, 0.0, -0.2, -0.5, 0.0, -1.0, 0.0, 0.0,
+        ];
+        let lookup_k2_range = [
+            0.0, 0.0, 0.0, 0.0, 0.1, 0.7, 0.0, -0.8, -0.1, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+        ];
+
+        let var1 = 1340.0 + (5.0 * f32::from(self.range_sw_err));
+        let var2 = (var1) * (1.0 + lookup_k1_range[gas_range as usize] / 100.0);
+        let var3 = 1.0 + (lookup_k2_range[gas_range as usize] / 100.0);
+
+        1.0 / (var3 * (0.000000125) * gas_range_f * (((gas_res_f - 512.0) / var2) + 1.0))
+    }
+
+    /// Calculate the heater resistance using float
+    ///
+    /// # Arguments
+    ///  * `temp`: The temperature
+    ///  * `amb_temp`: The ambient temperature in degrees C
+    ///
+    /// # Returns
+    /// Heater resistance value to set into the integer
+    #[cfg(not(feature = "fixed-point"))]
+    fn calc_res_heat(&self, temp: u16, amb_temp: i8) -> u8 {
+        let temp = if temp > 400 { 400.0 } else { f32::from(temp) };
+
+        let var1 = (f32::from(self.par_gh1) / (16.0)) + 49.0;
+        let var2 = ((f32::from(self.par_gh2) / (32768.0)) * (0.0005)) + 0.00235;
+        let var3 = f32::from(self.par_gh3) / (1024.0);
+        let var4 = var1 * (1.0 + (var2 * temp));
+        let var5 = var4 + (var3 * f32::from(amb_temp));
+
+        // Casting to u8 is deliberate here. The original library also does it.
+        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+        let result = (3.4
+            * ((var5
+                * (4.0 / (4.0 + f32::from(self.res_heat_range)))
+                * (1.0 / (1.0 + (f32::from(self.res_heat_val) * 0.002))))
+                - 25.0)) as u8;
+        result
+    }
+
+    /// Calculate the temperature value using integer math
+    ///
+    /// # Arguments
+    /// * `temp_adc`: Raw temperature ADC value
+    ///
+    /// # Returns
+    /// Temperature in degrees celsius x100
+    #[cfg(feature = "fixed-point")]
+    fn calc_temperature(&mut self, temp_adc: u32) -> i16 {
+        // ADC values are 20 bits, so these will never go out of bounds
+        let var1 = i64::from(temp_adc >> 3) - (i64::from(self.par_t1) << 1);
+        let var2 = (var1 * i64::from(self.par_t2)) >> 11;
+        let var3 = ((var1 >> 1) * (var1 >> 1)) >> 12;
+        let var3 = (var3 * (i64::from(self.par_t3) << 4)) >> 14;
+
+        // Truncation is deliberate here. The original library also does it.
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            self.t_fine = (var2 + var3) as i32;
+            (((self.t_fine * 5) + 128) >> 8) as i16
+        }
+    }
+
+    /// Calculate the pressure value using integer math
+    ///
+    /// # Arguments
+    /// * `pres_adc`: Raw pressure ADC value
+    ///
+    /// # Returns
+    /// Pressure in pascals
+    #[cfg(feature = "fixed-point")]
+    fn calc_pressure(&self, pres_adc: u32) -> u32 {
+        /// Threshold above which the pressure is divided before shifting, to prevent overflow
+        const PRES_OVF_CHECK: i32 = 0x40000000;
+
+        let var1 = (self.t_fine >> 1) - 64000;
+        let var2 = ((((var1 >> 2) * (var1 >> 2)) >> 11) * i32::from(self.par_p6)) >> 2;
+        let var2 = var2 + ((var1 * i32::from(self.par_p5)) << 1);
+        let var2 = (var2 >> 2) + (i32::from(self.par_p4) << 16);
+        let var1 = (((((var1 >> 2) * (var1 >> 2)) >> 13) * (i32::from(self.par_p3) << 5)) >> 3)
+            + ((i32::from(self.par_p2) * var1) >> 1);
+        let var1 = var1 >> 18;
+        let var1 = ((32768 + var1) * i32::from(self.par_p1)) >> 15;
+
+        // ADC values are 20 bits, so this will never go out of bounds
+        let pressure_comp = 1048576 - i32::try_from(pres_adc).unwrap_or(0);
+        // The original library relies on this multiplication wrapping.
+        let pressure_comp = (pressure_comp - (var2 >> 12)).wrapping_mul(3125);
+        if var1 == 0 {
+            return 0;
+        }
+        let pressure_comp = if pressure_comp >= PRES_OVF_CHECK {
+            (pressure_comp / var1) << 1
+        } else {
+            (pressure_comp << 1) / var1
+        };
+
+        let var1 =
+            (i32::from(self.par_p9) * (((pressure_comp >> 3) * (pressure_comp >> 3)) >> 13)) >> 12;
+        let var2 = ((pressure_comp >> 2) * i32::from(self.par_p8)) >> 13;
+        // Done in 64 bit, as the product of the three terms can exceed 32 bits.
+        let var3 = (i64::from(pressure_comp >> 8)
+            * i64::from(pressure_comp >> 8)
+            * i64::from(pressure_comp >> 8)
+            * i64::from(self.par_p10))
+            >> 17;
+
+        // Truncation is deliberate here. The original library also does it.
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let result = (pressure_comp
+            + ((var1 + var2 + (var3 as i32) + (i32::from(self.par_p7) << 7)) >> 4))
+            as u32;
+        result
+    }
+
+    /// Calculate the humidity value using integer math
+    ///
+    /// # Arguments
+    /// * `hum_adc`: Raw humidty ADC value
+    ///
+    /// # Returns
+    /// Humidity in % relative humidity x1000
+    #[cfg(feature = "fixed-point")]
+    fn calc_humidity(&self, hum_adc: u32) -> u32 {
+        let temp_scaled = ((self.t_fine * 5) + 128) >> 8;
+        // ADC values are 16 bits, so this will never go out of bounds
+        let var1 = (i32::try_from(hum_adc).unwrap_or(0) - (i32::from(self.par_h1) * 16))
+            - (((temp_scaled * i32::from(self.par_h3)) / 100) >> 1);
+        let var2 = (i32::from(self.par_h2)
+            * (((temp_scaled * i32::from(self.par_h4)) / 100)
+                + (((temp_scaled * ((temp_scaled * i32::from(self.par_h5)) / 100)) >> 6) / 100)
+                + (1 << 14)))
+            >> 10;
+        let var3 = var1 * var2;
+        let var4 = i32::from(self.par_h6) << 7;
+        let var4 = (var4 + ((temp_scaled * i32::from(self.par_h7)) / 100)) >> 4;
+        let var5 = ((var3 >> 14) * (var3 >> 14)) >> 10;
+        let var6 = (var4 * var5) >> 1;
+        let calc_hum = (((var3 + var6) >> 10) * 1000) >> 12;
+
+        // Clamped to be positive, so the sign can never be lost.
+        #[allow(clippy::cast_sign_loss)]
+        let result = calc_hum.clamp(0, 100000) as u32;
+        result
+    }
+
+    /// Calculate gas resistance low value using integer math
+    ///
+    /// # Arguments:
+    /// * `gas_res_adc`: Raw ADC gas resistance value
+    /// * `gas_range`: The gas range to use for the calculation
+    ///
+    /// # Returns
+    /// The low gas resistance in Ohms
+    #[cfg(feature = "fixed-point")]
+    fn calc_gas_resistance_low(&self, gas_res_adc: u16, gas_range: u8) -> u32 {
+        let lookup_table1: [u32; 16] = [
+            2147483647, 2147483647, 2147483647, 2147483647, 2147483647, 2126008810, 2147483647,
+            2130303777, 2147483647, 2147483647, 2143188679, 2136746228, 2147483647, 2126008810,
+            2147483647, 2147483647,
+        ];
+        let lookup_table2: [u32; 16] = [
+            4096000000, 2048000000, 1024000000, 512000000, 255744255, 127110228, 64000000,
+            32258064, 16016016, 8000000, 4000000, 2000000, 1000000, 500000, 250000, 125000,
+        ];
+        let gas_range = usize::from(gas_range);
+
+        let var1 = ((1340 + (5 * i64::from(self.range_sw_err)))
+            * i64::from(lookup_table1[gas_range]))
+            >> 16;
+        let var2 = ((i64::from(gas_res_adc) << 15) - 16777216) + var1;
+        let var3 = (i64::from(lookup_table2[gas_range]) * var1) >> 9;
+
+        // Truncation is deliberate here. The original library also does it.
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let result = ((var3 + (var2 >> 1)) / var2) as u32;
+        result
+    }
+
+    /// Calculate the heater resistance using integer math
+    ///
+    /// # Arguments
+    ///  * `temp`: The temperature
+    ///  * `amb_temp`: The ambient temperature in degrees C
+    ///
+    /// # Returns
+    /// Heater resistance value to set into the integer
+    #[cfg(feature = "fixed-point")]
+    fn calc_res_heat(&self, temp: u16, amb_temp: i8) -> u8 {
+        let temp = i32::from(temp.min(400));
+
+        let var1 = ((i32::from(amb_temp) * i32::from(self.par_gh3)) / 1000) * 256;
+        let var2 = (i32::from(self.par_gh1) + 784)
+            * (((((i32::from(self.par_gh2) + 154009) * temp * 5) / 100) + 3276800) / 10);
+        let var3 = var1 + (var2 / 2);
+        let var4 = var3 / (i32::from(self.res_heat_range) + 4);
+        let var5 = (131 * i32::from(self.res_heat_val)) + 65536;
+        let heatr_res_x100 = ((var4 / var5) - 250) * 34;
+
+        // Casting to u8 is deliberate here. The original library also does it.
+        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+        let result = ((heatr_res_x100 + 50) / 100) as u8;
+        result
+    }
+}
+
+/// BME68X sensor settings structure which comprises of ODR, over-sampling and filter settings.
+#[derive(Clone, Copy, Debug)]
+pub struct BME68xConf {
+    /// Humidity overrsampling
+    pub os_hum: BME68xOs,
+
+    /// Temperature Oversampling
+    pub os_temp: BME68xOs,
+
+    /// Pressure Oversampling
+    pub os_pres: BME68xOs,
+
+    /// Filter Coefficent
+    pub filter: BME68xFilter,
+
+    /// Standby time between sequential mode measurement profiles
+    pub odr: BME68xODR,
+}
+
+/// Gas Heater Configuration
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BME68xHeatrConf {
+    /// Enable gas measurement
+    pub enable: bool,
+
+    /// Store the heater temperature for forced mode degree Celsius
+    pub heatr_temp: u16,
+
+    /// Store the heating duration for forced mode in milliseconds
+    pub heatr_dur: u16,
+
+    /// Store the heater temperature profile in degree Celsius, Max of 10
+    pub heatr_temp_prof: [u16; MAX_PROFILE_LEN],
+
+    /// Store the heating duration profile in milliseconds. Max of 10
+    pub heatr_dur_prof: [u16; MAX_PROFILE_LEN],
+
+    /// Variable to store the length of the heating profile
+    pub profile_len: u8,
+
+    /// Variable to store heating duration for parallel mode in milliseconds
+    pub shared_heatr_dur: u16,
+}
+
+impl BME68xHeatrConf {
+    /// Create a new empty instance.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            enable: false,
+            heatr_temp: 0,
+            heatr_dur: 0,
+            heatr_temp_prof: [0; MAX_PROFILE_LEN],
+            heatr_dur_prof: [0; MAX_PROFILE_LEN],
+            profile_len: 0,
+            shared_heatr_dur: 0,
+        }
+    }
+
+    /// Create a new instance for a forced measurement
+    ///
+    /// # Arguments
+    /// * `temperature`: The temperature to run the heater at
+    /// * `duration`: The duration to run the heater for.
+    ///
+    /// # Returns
+    /// New configuration instance for a forced measurement
+    #[must_use]
+    pub fn new_forced(temperature: u16, duration: u16) -> Self {
+        Self {
+            enable: true,
+            heatr_temp: temperature,
+            heatr_dur: duration,
+            heatr_temp_prof: [0; MAX_PROFILE_LEN],
+            heatr_dur_prof: [0; MAX_PROFILE_LEN],
+            profile_len: 0,
+            shared_heatr_dur: 0,
+        }
+    }
+
+    /// Create a new instance for a parallel measurement
+    ///
+    /// A profile can be defined of up to 10 steps. Both the heater and
+    /// temperatuer profile must have the same number of steps
+    ///
+    /// # Arguments
+    /// * `temp_profile`: The Temperature profile to run the heater for
+    /// * `duration_profile`: Heater Duration Profile to use
+    /// * `shared_heatr_dur`: Shared heating duration time
+    ///
+    /// # Returns
+    /// Created instance for a parallel measurement
+    ///
+    /// # Errors
+    /// Will Return a `BME68xError::InvalidLength` If the profiles are longer
+    /// than 10 steps, or the profiles have differnet lengths.
+    pub fn new_parallel(
+        temp_profile: &[u16],
+        duration_profile: &[u16],
+        shared_duration: u16,
+    ) -> Result<Self, BME68xError> {
+        Self::from_profiles(temp_profile, duration_profile, shared_duration)
+    }
+
+    /// Create a new instance for a sequential measurement
+    ///
+    /// A profile can be defined of up to 10 steps. Both the heater and
+    /// temperatuer profile must have the same number of steps. Each step
+    /// heats for its own duration (up to 4032 ms), and produces one data field.
+    ///
+    /// # Arguments
+    /// * `temp_profile`: The Temperature profile to run the heater for
+    /// * `duration_profile`: Heater Duration Profile to use, in milliseconds
+    ///
+    /// # Returns
+    /// Created instance for a sequential measurement
+    ///
+    /// # Errors
+    /// Will Return a `BME68xError::InvalidLength` If the profiles are longer
+    /// than 10 steps, or the profiles have differnet lengths.
+    pub fn new_sequential(
+        temp_profile: &[u16],
+        duration_profile: &[u16],
+    ) -> Result<Self, BME68xError> {
+        Self::from_profiles(temp_profile, duration_profile, 0)
+    }
+
+    /// Create a new instance from a heater profile
+    ///
+    /// # Arguments
+    /// * `temp_profile`: The Temperature profile to run the heater for
+    /// * `duration_profile`: Heater Duration Profile to use
+    /// * `shared_heatr_dur`: Shared heating duration time
+    ///
+    /// # Returns
+    /// Created instance using the heater profile
+    ///
+    /// # Errors
+    /// Will Return a `BME68xError::InvalidLength` If the profiles are longer
+    /// than 10 steps, or the profiles have differnet lengths.
+    fn from_profiles(
+        temp_profile: &[u16],
+        duration_profile: &[u16],
+        shared_duration: u16,
     ) -> Result<Self, BME68xError> {
         if temp_profile.len() != duration_profile.len() || temp_profile.len() > MAX_PROFILE_LEN {
             Err(BME68xError::InvalidLength)
@@ -1007,13 +1622,9 @@ impl BME68xHeatrConf {
 }
 
 /// BME68X Device Structure
-pub struct BME68xDev<'a, I2C> {
-    // FIXME: Instead need to support I2C or SPI
-    /// Concrete I2C Implementation
-    i2c: I2C,
-
-    /// The I2C Address
-    address: BME68xAddr,
+pub struct BME68xDev<INTF, D> {
+    /// Interface (I2C or SPI) used to talk to the sensor
+    intf: INTF,
 
     /// Chip ID
     chip_id: u8,
@@ -1021,12 +1632,6 @@ pub struct BME68xDev<'a, I2C> {
     /// Variant ID.
     variant_id: BME68xVariant,
 
-    /// SPI/I2C Interface
-    intf: BME68xIntf,
-
-    /// Memory page used
-    mem_page: BME68xMemPage,
-
     /// Ambient Temperature in degrees C
     amb_temp: i8,
 
@@ -1039,8 +1644,8 @@ pub struct BME68xDev<'a, I2C> {
     /// Store info messages
     info_msg: BME68xError,
 
-    /// Function to delay by a specific numebr of microseconds
-    delay_us: &'a dyn Fn(u32),
+    /// Delay implementation
+    delay: D,
 }
 
 impl From<BME68xAddr> for u8 {
@@ -1049,40 +1654,57 @@ impl From<BME68xAddr> for u8 {
     }
 }
 
-impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
+impl<INTF: BME68xInterface, D: DelayNs> BME68xDev<INTF, D> {
     /// Create a new instance of the sensor
     ///
     /// # Arguments
-    /// * `bus`: The communication bus to use for talking with the sensor
-    /// * `address`: The address to use for talking to the sensor
+    /// * `intf`: The interface (`I2cInterface` or `SpiInterface`) to use for talking with the sensor
     /// * `amb_temp`: Ambient temperature to use for compensation, in degrees C. 25 is a safe value for this
-    /// * `intf`: The interface type to use for communication
-    /// * `delay_us`: Function to use for performing delay in microseconds.
+    /// * `delay`: Delay implementation
     ///
     /// # Returns
     /// A new instance of the sensor structure
-    pub fn new(
-        bus: I2C,
-        address: BME68xAddr,
-        amb_temp: i8,
-        intf: BME68xIntf,
-        delay_us: &'a dyn Fn(u32),
-    ) -> Self {
+    pub fn new(intf: INTF, amb_temp: i8, delay: D) -> Self {
         Self {
-            address,
-            i2c: bus,
+            intf,
             chip_id: 0,
             amb_temp,
             variant_id: BME68xVariant::GasLow,
-            intf,
-            mem_page: BME68xMemPage::Page0,
             calib: BME68xCalibData::new(),
             intf_rslt: BME68xError::Ok,
             info_msg: BME68xError::Ok,
-            delay_us,
+            delay,
         }
     }
 
+    /// Destroy the driver and return the interface and delay
+    ///
+    /// # Returns
+    /// The interface and delay used by the driver
+    pub fn destroy(self) -> (INTF, D) {
+        (self.intf, self.delay)
+    }
+
+    /// Set the ambient temperature used to calculate the heater resistance
+    ///
+    /// The heater needs less power to reach its target temperature in warmer
+    /// surroundings. The new value is used the next time the heater is configured.
+    ///
+    /// # Arguments
+    /// * `amb_temp`: Ambient temperature in degrees C
+    pub fn set_amb_temp(&mut self, amb_temp: i8) {
+        self.amb_temp = amb_temp;
+    }
+
+    /// Get the ambient temperature used to calculate the heater resistance
+    ///
+    /// # Returns
+    /// The ambient temperature in degrees C
+    #[must_use]
+    pub fn get_amb_temp(&self) -> i8 {
+        self.amb_temp
+    }
+
     /// Initialize the sensor.
     ///
     /// Reads the Chip ID and calibrates the sensor.
@@ -1107,42 +1729,17 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
     /// # Arguments
     /// * `reg_addr`: Register addresess to write data to
     /// * `reg_data`: Data to write to the registers
-    /// * `len`: Number of bytes of data to write.
     ///
     /// # Errors
     /// Errors if failing to write to the registers.
-    fn set_regs(
-        &mut self,
-        reg_addr: &[u8],
-        reg_data: &[u8],
-        // len: usize,
-    ) -> Result<(), BME68xError> {
-        // FIXME: Proper spi support
+    fn set_regs(&mut self, reg_addr: &[u8], reg_data: &[u8]) -> Result<(), BME68xError> {
         let len = reg_addr.len();
         if reg_addr.len() != reg_data.len() {
             Err(BME68xError::InvalidLength)
         } else if (len > 0) && (len <= (BME68X_LEN_INTERLEAVE_BUFF / 2)) {
-            let mut tmp_buff = [0; BME68X_LEN_INTERLEAVE_BUFF];
-            // Data is interwoven in the form (reg, data, reg, data, reg, data, ...)
-            // FIXME: Re-Write to not require fixed length array? use iter?
-            for index in 0..len {
-                if matches!(self.intf, BME68xIntf::SPIIntf) {
-                    self.set_mem_page(reg_addr[index])?;
-                    tmp_buff[2 * index] = reg_addr[index] & BME68X_SPI_WR_MSK;
-                } else {
-                    tmp_buff[2 * index] = reg_addr[index];
-                }
-                tmp_buff[(2 * index) + 1] = reg_data[index];
-            }
-
-            let result = self.i2c.write(self.address.into(), &tmp_buff[0..(2 * len)]);
-            if result.is_ok() {
-                self.intf_rslt = BME68xError::Ok;
-                Ok(())
-            } else {
-                self.intf_rslt = BME68xError::ComFail;
-                Err(BME68xError::ComFail)
-            }
+            let result = self.intf.write_regs(reg_addr, reg_data);
+            self.intf_rslt = result.err().unwrap_or(BME68xError::Ok);
+            result
         } else {
             Err(BME68xError::InvalidLength)
         }
@@ -1157,24 +1754,7 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
     /// # Errors
     /// Errors if writing to the register failed
     fn set_reg(&mut self, reg_addr: BME68xRegister, data: u8) -> Result<(), BME68xError> {
-        // Convert register to u8
-        let mut reg_addr = u8::from(reg_addr);
-        // FIXME: Proper SPI SUpport
-        if matches!(self.intf, BME68xIntf::SPIIntf) {
-            self.set_mem_page(reg_addr)?;
-            reg_addr &= BME68X_SPI_WR_MSK;
-        }
-
-        let buff = [reg_addr, data];
-
-        let result = self.i2c.write(self.address.into(), &buff);
-        if result.is_ok() {
-            self.intf_rslt = BME68xError::Ok;
-            Ok(())
-        } else {
-            self.intf_rslt = BME68xError::ComFail;
-            Err(BME68xError::ComFail)
-        }
+        self.set_regs(&[reg_addr.into()], &[data])
     }
 
     /// Read data from the given registers
@@ -1188,21 +1768,10 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
     ///
     /// # Errors
     /// Errors if failing to read from the registers.
-    fn get_regs(&mut self, mut reg_addr: u8, data: &mut [u8]) -> Result<(), BME68xError> {
-        // FIXME: Proper SPI support
-        if matches!(self.intf, BME68xIntf::SPIIntf) {
-            self.set_mem_page(reg_addr)?;
-            reg_addr |= BME68X_SPI_RD_MSK;
-        }
-        let result = self.i2c.write_read(self.address.into(), &[reg_addr], data);
-
-        if result.is_ok() {
-            self.intf_rslt = BME68xError::Ok;
-            Ok(())
-        } else {
-            self.intf_rslt = BME68xError::ComFail;
-            Err(BME68xError::ComFail)
-        }
+    fn get_regs(&mut self, reg_addr: u8, data: &mut [u8]) -> Result<(), BME68xError> {
+        let result = self.intf.read_regs(reg_addr, data);
+        self.intf_rslt = result.err().unwrap_or(BME68xError::Ok);
+        result
     }
 
     /// Read data from a single register
@@ -1229,17 +1798,13 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
     /// # Errors
     /// Returns an error if soft-resetting the sensor failed.
     pub fn soft_reset(&mut self) -> Result<(), BME68xError> {
-        if matches!(self.intf, BME68xIntf::SPIIntf) {
-            self.get_mem_page()?;
-        }
+        self.intf.sync()?;
         self.set_reg(BME68xRegister::SoftReset, BME68X_SOFT_RESET_CMD)?;
 
-        (self.delay_us)(BME68X_PERIOD_RESET);
-        if matches!(self.intf, BME68xIntf::SPIIntf) {
-            self.get_mem_page()?;
-        }
+        self.delay.delay_us(BME68X_PERIOD_RESET);
 
-        Ok(())
+        // The reset puts the sensor back on its default memory page.
+        self.intf.sync()
     }
 
     /// Set the operation mode of the sensor
@@ -1259,7 +1824,7 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
                 // In rust ! is bitwise not
                 tmp_pow_mode &= !BME68X_MODE_MSK; /* Set to sleep */
                 self.set_reg(BME68xRegister::CtrlMeas, tmp_pow_mode)?;
-                (self.delay_us)(BME68X_PERIOD_POLL);
+                self.delay.delay_us(BME68X_PERIOD_POLL);
             } else {
                 break;
             }
@@ -1293,22 +1858,19 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
     /// # Returns
     /// The measurement duration that can be used for heating
     pub fn get_meas_dur(&self, op_mode: BME68xOpMode, conf: &BME68xConf) -> u32 {
-        let mut meas_dur;
-
-        let meas_cycles = conf.os_temp.get_meas_cycles()
-            + conf.os_pres.get_meas_cycles()
-            + conf.os_hum.get_meas_cycles();
-
-        // TPH Measurement Duration
-        meas_dur = meas_cycles * 1963;
-        meas_dur += 477 * 4; // TPH Switching Duration
-        meas_dur += 477 * 5; // Gas measurement duration
-
-        if matches!(op_mode, BME68xOpMode::ParallelMode) {
-            meas_dur += 1000; // Wake up diration of 1 ms
-        }
+        calc_meas_dur(op_mode, *conf)
+    }
 
-        meas_dur
+    /// Get the duration of one full cycle through a sequential heater profile
+    ///
+    /// # Arguments
+    /// * `conf`: The sensor configuration.
+    /// * `heatr_conf`: The sequential heater configuration
+    ///
+    /// # Returns
+    /// The duration of a full sequential cycle in microseconds
+    pub fn get_sequential_dur(&self, conf: &BME68xConf, heatr_conf: &BME68xHeatrConf) -> u32 {
+        calc_sequential_dur(*conf, heatr_conf)
     }
 
     /// Read the pressure, temperature, humidity, and gas data from the sensor
@@ -1327,7 +1889,7 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
         &mut self,
         op_mode: BME68xOpMode,
     ) -> Result<([BME68xData; 3], u8), BME68xError> {
-        let mut new_fields = 0;
+        let new_fields;
         let mut data = [BME68xData::new(); 3];
         match op_mode {
             BME68xOpMode::ForcedMode => {
@@ -1336,19 +1898,7 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
             }
             BME68xOpMode::ParallelMode | BME68xOpMode::SequentialMode => {
                 data = self.read_all_field_data()?;
-
-                // TODO: Check over this. Probably a way to do it properly in rust.
-                // Sort sensor data
-                for i in 0..3 {
-                    if (data[i].status & BME68X_NEW_DATA_MSK) != 0 {
-                        new_fields += 1;
-                        for i in 0..2 {
-                            for j in i + 1..3 {
-                                sort_sensor_data(i, j, &mut data);
-                            }
-                        }
-                    }
-                }
+                new_fields = sort_fields(&mut data);
             }
             BME68xOpMode::SleepMode => return Err(BME68xError::DefineOpMode),
         }
@@ -1367,9 +1917,6 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
     /// # Errors
     /// Returns an error if setting the configration failed.
     pub fn set_config(&mut self, conf: &BME68xConf) -> Result<(), BME68xError> {
-        let mut odr20 = 0;
-        let mut odr3 = 1;
-        let reg_array = [0x71, 0x72, 0x73, 0x74, 0x75];
         let mut data_array = [0; BME68X_LEN_CONFIG];
         let current_op_mode = self.get_op_mode()?;
 
@@ -1378,33 +1925,8 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
 
         self.get_regs(BME68xRegister::CtrlGas1.into(), &mut data_array)?;
         self.info_msg = BME68xError::Ok;
-        data_array[4] = set_bits(
-            data_array[4],
-            BME68X_FILTER_MSK,
-            BME68X_FILTER_POS,
-            conf.filter.into(),
-        );
-        data_array[3] = set_bits(
-            data_array[3],
-            BME68X_OST_MSK,
-            BME68X_OST_POS,
-            conf.os_temp.into(),
-        );
-        data_array[3] = set_bits(
-            data_array[3],
-            BME68X_OSP_MSK,
-            BME68X_OSP_POS,
-            conf.os_pres.into(),
-        );
-        data_array[1] = set_bits_pos_0(data_array[1], BME68X_OSH_MSK, conf.os_hum.into());
-        if !matches!(conf.odr, BME68xODR::ODRNone) {
-            odr20 = conf.odr.into();
-            odr3 = 0;
-        }
-        data_array[4] = set_bits(data_array[4], BME68X_ODR20_MSK, BME68X_ODR20_POS, odr20);
-        data_array[0] = set_bits(data_array[0], BME68X_ODR3_MSK, BME68X_ODR3_POS, odr3);
 
-        self.set_regs(&reg_array, &data_array)?;
+        self.set_regs(&BME68X_CONF_REGS, &conf_to_regs(*conf, data_array))?;
         self.set_op_mode(current_op_mode)
     }
 
@@ -1416,23 +1938,9 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
     /// # Errors
     /// Returns an error if getting the configuration failed.
     pub fn get_config(&mut self) -> Result<BME68xConf, BME68xError> {
-        let mut data_array = [0; 5];
+        let mut data_array = [0; BME68X_LEN_CONFIG];
         self.get_regs(BME68xRegister::CtrlGas1.into(), &mut data_array)?;
-        Ok(BME68xConf {
-            os_hum: BME68xOs::from(data_array[1] & BME68X_OSH_MSK),
-            filter: BME68xFilter::from(get_bits(
-                data_array[4],
-                BME68X_FILTER_MSK,
-                BME68X_FILTER_POS,
-            )),
-            os_temp: BME68xOs::from(get_bits(data_array[3], BME68X_OST_MSK, BME68X_OST_POS)),
-            os_pres: BME68xOs::from(get_bits(data_array[3], BME68X_OSP_MSK, BME68X_OSP_POS)),
-            odr: if get_bits(data_array[0], BME68X_ODR3_MSK, BME68X_ODR3_POS) == 0 {
-                BME68xODR::ODRNone
-            } else {
-                BME68xODR::from(get_bits(data_array[4], BME68X_ODR20_MSK, BME68X_ODR20_POS))
-            },
-        })
+        Ok(conf_from_regs(data_array))
     }
 
     /// Set the gas configuration of the sensor
@@ -1457,32 +1965,7 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
 
         let nb_conv = self.set_conf(conf, op_mode)?;
         self.get_regs(BME68xRegister::CtrlGas0.into(), &mut ctrl_gas_data)?;
-
-        let (hctrl, run_gas) = match (conf.enable, self.variant_id) {
-            (true, BME68xVariant::GasHigh) => {
-                (BME68xHeaterEnable::Enable, BME68xGasEnable::EnableHigh)
-            }
-
-            (true, BME68xVariant::GasLow) => {
-                (BME68xHeaterEnable::Enable, BME68xGasEnable::EnableLow)
-            }
-
-            (false, _) => (BME68xHeaterEnable::Disable, BME68xGasEnable::Disable),
-        };
-
-        ctrl_gas_data[0] = set_bits(
-            ctrl_gas_data[0],
-            BME68X_HCTRL_MSK,
-            BME68X_HCTRL_POS,
-            hctrl.into(),
-        );
-        ctrl_gas_data[1] = set_bits_pos_0(ctrl_gas_data[1], BME68X_NBCONV_MSK, nb_conv);
-        ctrl_gas_data[1] = set_bits(
-            ctrl_gas_data[1],
-            BME68X_RUN_GAS_MSK,
-            BME68X_RUN_GAS_POS,
-            run_gas.into(),
-        );
+        let ctrl_gas_data = heatr_ctrl_regs(conf, self.variant_id, nb_conv, ctrl_gas_data);
 
         self.set_regs(&ctrl_gas_addr, &ctrl_gas_data)
     }
@@ -1534,16 +2017,35 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
         duration_profile: &[u16],
     ) -> Result<(), BME68xError> {
         let current_config = self.get_config()?;
-        // FIXME: Find out where the 140 comes from
-        let shared_duration = u16::try_from(
-            140 - (self.get_meas_dur(BME68xOpMode::ParallelMode, &current_config) / 1000),
-        )?;
+        let shared_duration = calc_shared_heatr_dur(current_config)?;
         self.set_heatr_conf(
             BME68xOpMode::ParallelMode,
             &BME68xHeatrConf::new_parallel(temp_profile, duration_profile, shared_duration)?,
         )
     }
 
+    /// Set the heater configuration for sequential measurement
+    ///
+    /// Profile lengths must be equal, and less than or equal to 10
+    /// steps
+    ///
+    /// # Arguments
+    /// * `temp_profile`: The temperature profile to use
+    /// * `duration_profile`: The duration profile to use
+    ///
+    /// # Errors
+    /// Returns ane error if setting the heater configuraiton fails.
+    pub fn set_heatr_conf_sequential(
+        &mut self,
+        temp_profile: &[u16],
+        duration_profile: &[u16],
+    ) -> Result<(), BME68xError> {
+        self.set_heatr_conf(
+            BME68xOpMode::SequentialMode,
+            &BME68xHeatrConf::new_sequential(temp_profile, duration_profile)?,
+        )
+    }
+
     /// Get the heater configuration of the sensor
     ///
     ///
@@ -1590,7 +2092,7 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
 
         // Start measurement and wait for it to finish
         self.set_op_mode(BME68xOpMode::ForcedMode)?;
-        (self.delay_us)(delay_period);
+        self.delay.delay_us(delay_period);
 
         // Get the data
         let (data, _) = self.get_data(BME68xOpMode::ForcedMode)?;
@@ -1626,7 +2128,7 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
         self.set_op_mode(BME68xOpMode::ForcedMode)?;
 
         // Wait for measurement to complete
-        (self.delay_us)(BME68X_HEATR_DUR1_DELAY);
+        self.delay.delay_us(BME68X_HEATR_DUR1_DELAY);
         let (data, _) = self.get_data(BME68xOpMode::ForcedMode)?;
 
         if (data[0].idac != 0x00)
@@ -1653,7 +2155,7 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
             self.set_op_mode(BME68xOpMode::ForcedMode)?;
 
             // Wait for measurement to complete
-            (self.delay_us)(BME68X_HEATR_DUR2_DELAY);
+            self.delay.delay_us(BME68X_HEATR_DUR2_DELAY);
             let (samples, _) = self.get_data(BME68xOpMode::ForcedMode)?;
             data[i] = samples[0];
             i += 1;
@@ -1677,233 +2179,29 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
         // Read second chunk of coefficents.
         self.get_regs(
             BME68xRegister::Coeff2.into(),
-            &mut coeff_array[BME68X_LEN_COEFF1..(BME68X_LEN_COEFF1 + BME68X_LEN_COEFF2)],
-        )?;
-
-        // Read the third chunk of coefficents
-        self.get_regs(
-            BME68xRegister::Coeff3.into(),
-            &mut coeff_array[(BME68X_LEN_COEFF1 + BME68X_LEN_COEFF2)
-                ..(BME68X_LEN_COEFF1 + BME68X_LEN_COEFF2 + BME68X_LEN_COEFF3)],
-        )?;
-
-        // Copy data over
-        /* Temperature related coefficients */
-        self.calib.par_t1 = concat_bytes(
-            coeff_array[BME68X_IDX_T1_MSB],
-            coeff_array[BME68X_IDX_T1_LSB],
-        );
-        self.calib.par_t2 = wrap_u2i16(concat_bytes(
-            coeff_array[BME68X_IDX_T2_MSB],
-            coeff_array[BME68X_IDX_T2_LSB],
-        ));
-        self.calib.par_t3 = wrap_u2i8(coeff_array[BME68X_IDX_T3]);
-
-        /* Pressure related coefficients */
-        self.calib.par_p1 = concat_bytes(
-            coeff_array[BME68X_IDX_P1_MSB],
-            coeff_array[BME68X_IDX_P1_LSB],
-        );
-        self.calib.par_p2 = wrap_u2i16(concat_bytes(
-            coeff_array[BME68X_IDX_P2_MSB],
-            coeff_array[BME68X_IDX_P2_LSB],
-        ));
-        self.calib.par_p3 = wrap_u2i8(coeff_array[BME68X_IDX_P3]);
-        self.calib.par_p4 = wrap_u2i16(concat_bytes(
-            coeff_array[BME68X_IDX_P4_MSB],
-            coeff_array[BME68X_IDX_P4_LSB],
-        ));
-        self.calib.par_p5 = wrap_u2i16(concat_bytes(
-            coeff_array[BME68X_IDX_P5_MSB],
-            coeff_array[BME68X_IDX_P5_LSB],
-        ));
-        self.calib.par_p6 = wrap_u2i8(coeff_array[BME68X_IDX_P6]);
-        self.calib.par_p7 = wrap_u2i8(coeff_array[BME68X_IDX_P7]);
-        self.calib.par_p8 = wrap_u2i16(concat_bytes(
-            coeff_array[BME68X_IDX_P8_MSB],
-            coeff_array[BME68X_IDX_P8_LSB],
-        ));
-        self.calib.par_p9 = wrap_u2i16(concat_bytes(
-            coeff_array[BME68X_IDX_P9_MSB],
-            coeff_array[BME68X_IDX_P9_LSB],
-        ));
-        self.calib.par_p10 = coeff_array[BME68X_IDX_P10];
-
-        /* Humidity related coefficients */
-        self.calib.par_h1 = (u16::from(coeff_array[BME68X_IDX_H1_MSB]) << 4)
-            | (u16::from(coeff_array[BME68X_IDX_H1_LSB]) & BME68X_BIT_H1_DATA_MSK);
-        self.calib.par_h2 = (u16::from(coeff_array[BME68X_IDX_H2_MSB]) << 4)
-            | (u16::from(coeff_array[BME68X_IDX_H2_LSB]) >> 4);
-        self.calib.par_h3 = wrap_u2i8(coeff_array[BME68X_IDX_H3]);
-        self.calib.par_h4 = wrap_u2i8(coeff_array[BME68X_IDX_H4]);
-        self.calib.par_h5 = wrap_u2i8(coeff_array[BME68X_IDX_H5]);
-        self.calib.par_h6 = coeff_array[BME68X_IDX_H6];
-        self.calib.par_h7 = wrap_u2i8(coeff_array[BME68X_IDX_H7]);
-
-        /* Gas heater related coefficients */
-        self.calib.par_gh1 = wrap_u2i8(coeff_array[BME68X_IDX_GH1]);
-        self.calib.par_gh2 = wrap_u2i16(concat_bytes(
-            coeff_array[BME68X_IDX_GH2_MSB],
-            coeff_array[BME68X_IDX_GH2_LSB],
-        ));
-        self.calib.par_gh3 = wrap_u2i8(coeff_array[BME68X_IDX_GH3]);
-
-        /* Other coefficients */
-        self.calib.res_heat_range =
-            (coeff_array[BME68X_IDX_RES_HEAT_RANGE] & BME68X_RHRANGE_MSK) / 16;
-        self.calib.res_heat_val = wrap_u2i8(coeff_array[BME68X_IDX_RES_HEAT_VAL]);
-        self.calib.range_sw_err =
-            (wrap_u2i8(coeff_array[BME68X_IDX_RANGE_SW_ERR] & BME68X_RSERROR_MSK)) / 16;
-
-        Ok(())
-    }
-
-    /// Read the variant ID information register status
-    ///
-    /// # Errors
-    /// Errors if reading the register failed
-    fn read_variant_id(&mut self) -> Result<(), BME68xError> {
-        let data = self.get_reg(BME68xRegister::VariantId)?;
-        self.variant_id = BME68xVariant::from(data);
-        Ok(())
-    }
-
-    /// Calcualte the temperature as a float
-    ///
-    /// # Arguments
-    /// * `temp_adc`: The raw ADC Temperature
-    ///
-    /// Returns
-    /// The temperature in degrees celsius as a float
-    fn calc_temperature(&mut self, temp_adc: u32) -> f32 {
-        let par_t1_f32 = f32::from(self.calib.par_t1);
-        let par_t3_f32 = f32::from(self.calib.par_t3);
-        let temp_f32 = cast_u2f32(temp_adc);
-
-        let var1 = ((temp_f32 / 16384.0) - (par_t1_f32 / 1024.0)) * (par_t1_f32);
-
-        let var2 = (((temp_f32 / 131072.0) - (par_t1_f32 / 8192.0))
-            * ((temp_f32 / 131072.0) - (par_t1_f32 / 8192.0)))
-            * (par_t3_f32 * 16.0);
-
-        self.calib.t_fine = var1 + var2;
-
-        self.calib.t_fine / 5120.0
-    }
-
-    /// Calcualte the pressure value as a float
-    ///
-    /// # Arguments
-    /// * `pres_adc`: Raw pressure ADC value
-    ///
-    /// # Returns
-    /// Pressure in pascals as a float
-    fn calc_pressure(&self, pres_adc: u32) -> f32 {
-        let var1 = (self.calib.t_fine / 2.0) - 64000.0;
-        let var2 = var1 * var1 * (f32::from(self.calib.par_p6) / 131072.0);
-        let var2 = var2 + (var1 * (f32::from(self.calib.par_p5) * 2.0));
-        let var2 = (var2 / 4.0) + ((f32::from(self.calib.par_p4)) * 65536.0);
-        let var1 = (((f32::from(self.calib.par_p3) * var1 * var1) / 16384.0)
-            + (f32::from(self.calib.par_p2) * var1))
-            / 524288.0;
-        let var1 = (1.0 + (var1 / 32768.0)) * (f32::from(self.calib.par_p1));
-
-        let calc_pres = 1048576.0 - cast_u2f32(pres_adc);
-
-        if var1 != 0.0 {
-            let calc_pres = ((calc_pres - (var2 / 4096.0)) * 6250.0) / var1;
-            let var1 = (f32::from(self.calib.par_p9) * calc_pres * calc_pres) / 2147483648.0;
-            let var2 = calc_pres * (f32::from(self.calib.par_p8) / 32768.0);
-            let var3 = (calc_pres / 256.0)
-                * (calc_pres / 256.0)
-                * (calc_pres / 256.0)
-                * (f32::from(self.calib.par_p10) / 131072.0);
-            calc_pres + (var1 + var2 + var3 + (f32::from(self.calib.par_p7) * 128.0)) / 16.0
-        } else {
-            0.0
-        }
-    }
-
-    /// Calcualte the humidity value as a float
-    ///
-    /// # Arguments
-    /// * `hum_adc`: Raw humidty ADC value
-    ///
-    /// # Returns
-    /// The percent humidity as a floating point.
-    fn calc_humidity(&self, hum_adc: u32) -> f32 {
-        let temp_comp = (self.calib.t_fine) / 5120.0;
-
-        let var1 = cast_u2f32(hum_adc)
-            - ((f32::from(self.calib.par_h1) * 16.0)
-                + ((f32::from(self.calib.par_h3) / 2.0) * temp_comp));
-        let var2 = var1
-            * ((f32::from(self.calib.par_h2) / 262144.0)
-                * (1.0
-                    + ((f32::from(self.calib.par_h4) / 16384.0) * temp_comp)
-                    + ((f32::from(self.calib.par_h5) / 1048576.0) * temp_comp * temp_comp)));
-        let var3 = f32::from(self.calib.par_h6) / 16384.0;
-        let var4 = f32::from(self.calib.par_h7) / 2097152.0;
-        let calc_hum = var2 + ((var3 + (var4 * temp_comp)) * var2 * var2);
-
-        if calc_hum > 100.0 {
-            100.0
-        } else if calc_hum < 0.0 {
-            0.0
-        } else {
-            calc_hum
-        }
-    }
-
-    /// Calculate gas resistance low value as a float
-    ///
-    /// # Arguments:
-    /// * `gas_res_adc`: Raw ADC gas resistance value
-    /// * `gas_range`: The gas range to use for the calculation
-    ///
-    /// # Returns
-    /// The low gas resistance as a float
-    fn calc_gas_resistance_low(&self, gas_res_adc: u16, gas_range: u8) -> f32 {
-        let gas_res_f = f32::from(gas_res_adc);
-        let gas_range_f = cast_i2f32(1 << gas_range);
-        let lookup_k1_range = [
-            0.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, -0.8, 0.0, 0.0, -0.2, -0.5, 0.0, -1.0, 0.0, 0.0,
-        ];
-        let lookup_k2_range = [
-            0.0, 0.0, 0.0, 0.0, 0.1, 0.7, 0.0, -0.8, -0.1, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
-        ];
+            &mut coeff_array[BME68X_LEN_COEFF1..(BME68X_LEN_COEFF1 + BME68X_LEN_COEFF2)],
+        )?;
 
-        let var1 = 1340.0 + (5.0 * f32::from(self.calib.range_sw_err));
-        let var2 = (var1) * (1.0 + lookup_k1_range[gas_range as usize] / 100.0);
-        let var3 = 1.0 + (lookup_k2_range[gas_range as usize] / 100.0);
+        // Read the third chunk of coefficents
+        self.get_regs(
+            BME68xRegister::Coeff3.into(),
+            &mut coeff_array[(BME68X_LEN_COEFF1 + BME68X_LEN_COEFF2)
+                ..(BME68X_LEN_COEFF1 + BME68X_LEN_COEFF2 + BME68X_LEN_COEFF3)],
+        )?;
 
-        1.0 / (var3 * (0.000000125) * gas_range_f * (((gas_res_f - 512.0) / var2) + 1.0))
+        self.calib = BME68xCalibData::from_coeff_array(&coeff_array);
+
+        Ok(())
     }
 
-    /// Calculate the heater resistance using float
-    ///
-    /// # Arguments
-    ///  * `temp`: The temperature
+    /// Read the variant ID information register status
     ///
-    /// # Returns
-    /// Heater resistance value to set into the integer
-    fn calc_res_heat(&self, temp: u16) -> u8 {
-        let temp = if temp > 400 { 400.0 } else { f32::from(temp) };
-
-        let var1 = (f32::from(self.calib.par_gh1) / (16.0)) + 49.0;
-        let var2 = ((f32::from(self.calib.par_gh2) / (32768.0)) * (0.0005)) + 0.00235;
-        let var3 = f32::from(self.calib.par_gh3) / (1024.0);
-        let var4 = var1 * (1.0 + (var2 * temp));
-        let var5 = var4 + (var3 * f32::from(self.amb_temp));
-
-        // Casting to u8 is deliberate here. The original library also does it.
-        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
-        let result = (3.4
-            * ((var5
-                * (4.0 / (4.0 + f32::from(self.calib.res_heat_range)))
-                * (1.0 / (1.0 + (f32::from(self.calib.res_heat_val) * 0.002))))
-                - 25.0)) as u8;
-        result
+    /// # Errors
+    /// Errors if reading the register failed
+    fn read_variant_id(&mut self) -> Result<(), BME68xError> {
+        let data = self.get_reg(BME68xRegister::VariantId)?;
+        self.variant_id = BME68xVariant::from(data);
+        Ok(())
     }
 
     /// Read a single data from the senssor
@@ -1925,67 +2223,17 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
                 (u8::from(BME68xRegister::Field0)) + (index * BME68X_LEN_FIELD_OFFSET);
             self.get_regs(reg_addr, &mut buff)?;
 
-            data.status = buff[0] & BME68X_NEW_DATA_MSK;
-            data.gas_index = buff[0] & BME68X_GAS_INDEX_MSK;
-            data.meas_index = buff[1];
-
-            let adc_pres =
-                (u32::from(buff[2]) * 4096) | (u32::from(buff[3]) * 16) | (u32::from(buff[4]) / 16);
-            let adc_temp =
-                (u32::from(buff[5]) * 4096) | (u32::from(buff[6]) * 16) | (u32::from(buff[7]) / 16);
-            let adc_hum = (u32::from(buff[8]) * 256) | u32::from(buff[9]);
-            let adc_gas_res_low = (u32::from(buff[13]) * 4) | ((u32::from(buff[14])) / 64);
-            let adc_gas_res_high = (u32::from(buff[15]) * 4) | ((u32::from(buff[16])) / 64);
-            let gas_range_l = buff[14] & BME68X_GAS_RANGE_MSK;
-            let gas_range_h = buff[16] & BME68X_GAS_RANGE_MSK;
-            if matches!(self.variant_id, BME68xVariant::GasHigh) {
-                data.status |= buff[16] & BME68X_GASM_VALID_MSK;
-                data.status |= buff[16] & BME68X_HEAT_STAB_MSK;
-            } else {
-                data.status |= buff[14] & BME68X_GASM_VALID_MSK;
-                data.status |= buff[14] & BME68X_HEAT_STAB_MSK;
-            }
+            data = parse_field_status(&buff, self.variant_id);
 
             if (data.status & BME68X_NEW_DATA_MSK) != 0 {
-                let mut reg = [0; 1];
-                // FIXME: FIgure out how to use get_Reg instead of get_Regs here.
-                self.get_regs(
-                    (u8::from(BME68xRegister::ResHeat0)) + data.gas_index,
-                    &mut reg,
-                )?;
-                data.res_heat = reg[0];
-
-                self.get_regs(
-                    (u8::from(BME68xRegister::IdacHeat0)) + data.gas_index,
-                    &mut reg,
-                )?;
-                data.idac = reg[0];
-
-                self.get_regs(
-                    (u8::from(BME68xRegister::GasWait0)) + data.gas_index,
-                    &mut reg,
-                )?;
-                data.gas_wait = reg[0];
-
-                data.temperature = self.calc_temperature(adc_temp);
-                data.pressure = self.calc_pressure(adc_pres);
-                data.humidity = self.calc_humidity(adc_hum);
-                if matches!(self.variant_id, BME68xVariant::GasHigh) {
-                    data.gas_resistance = calc_gas_resistance_high(
-                        // Checked mathmatically. Should never go out of bounds
-                        u16::try_from(adc_gas_res_high)?,
-                        gas_range_h,
-                    );
-                } else {
-                    data.gas_resistance = self.calc_gas_resistance_low(
-                        // Checked mathmatically. Should never go out of bounds
-                        u16::try_from(adc_gas_res_low)?,
-                        gas_range_l,
-                    );
-                }
+                data.res_heat = self.get_regs_at(BME68xRegister::ResHeat0, data.gas_index)?;
+                data.idac = self.get_regs_at(BME68xRegister::IdacHeat0, data.gas_index)?;
+                data.gas_wait = self.get_regs_at(BME68xRegister::GasWait0, data.gas_index)?;
+                self.calib
+                    .compensate_field(&buff, self.variant_id, &mut data)?;
                 break;
             }
-            (self.delay_us)(BME68X_PERIOD_POLL);
+            self.delay.delay_us(BME68X_PERIOD_POLL);
             tries -= 1;
         }
         if (tries == 0) && (data == BME68xData::new()) {
@@ -1995,10 +2243,24 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
         }
     }
 
-    /// Read all data fields of the sensor
+    /// Read a single register at an offset from a base register
     ///
     /// # Arguments
-    /// * `data`: Buffer to populate with the read data
+    /// * `base`: The base register
+    /// * `offset`: The offset from the base register to read.
+    ///
+    /// # Returns
+    /// The byte read from the register
+    ///
+    /// # Errors
+    /// Errors if reading from the register failed.
+    fn get_regs_at(&mut self, base: BME68xRegister, offset: u8) -> Result<u8, BME68xError> {
+        let mut reg = [0; 1];
+        self.get_regs(u8::from(base) + offset, &mut reg)?;
+        Ok(reg[0])
+    }
+
+    /// Read all data fields of the sensor
     ///
     /// # Returns
     /// The data read from the fields.
@@ -2008,197 +2270,402 @@ impl<'a, I2C: I2c> BME68xDev<'a, I2C> {
     fn read_all_field_data(&mut self) -> Result<[BME68xData; 3], BME68xError> {
         let mut buff = [0; BME68X_LEN_FIELD * 3];
         let mut set_val = [0; 30];
-        let mut data = [BME68xData::new(); 3];
         self.get_regs(BME68xRegister::Field0.into(), &mut buff)?;
 
         self.get_regs(BME68xRegister::IdacHeat0.into(), &mut set_val)?;
 
-        for i in 0..3 {
-            let off = i * BME68X_LEN_FIELD;
-            data[i].status = buff[off] & BME68X_NEW_DATA_MSK;
-            data[i].gas_index = buff[off] & BME68X_GAS_INDEX_MSK;
-            data[i].meas_index = buff[off + 1];
-            let adc_pres = (u32::from(buff[off + 2]) * 4096)
-                | (u32::from(buff[off + 3]) * 16)
-                | (u32::from(buff[off + 4]) / 16);
-            let adc_temp = (u32::from(buff[off + 5]) * 4096)
-                | (u32::from(buff[off + 6]) * 16)
-                | (u32::from(buff[off + 7]) / 16);
-            let adc_hum = (u32::from(buff[off + 8]) * 256) | u32::from(buff[off + 9]);
-            let adc_gas_res_low =
-                (u32::from(buff[off + 13]) * 4) | ((u32::from(buff[off + 14])) / 64);
-            let adc_gas_res_high =
-                (u32::from(buff[off + 15]) * 4) | ((u32::from(buff[off + 16])) / 64);
-            let gas_range_l = buff[off + 14] & BME68X_GAS_RANGE_MSK;
-            let gas_range_h = buff[off + 16] & BME68X_GAS_RANGE_MSK;
-            if matches!(self.variant_id, BME68xVariant::GasHigh) {
-                data[i].status |= buff[off + 16] & BME68X_GASM_VALID_MSK;
-                data[i].status |= buff[off + 16] & BME68X_HEAT_STAB_MSK;
-            } else {
-                data[i].status |= buff[off + 14] & BME68X_GASM_VALID_MSK;
-                data[i].status |= buff[off + 14] & BME68X_HEAT_STAB_MSK;
-            }
-            data[i].idac = set_val[usize::from(data[i].gas_index)];
-            data[i].res_heat = set_val[usize::from(10 + data[i].gas_index)];
-            data[i].gas_wait = set_val[usize::from(20 + data[i].gas_index)];
-            data[i].temperature = self.calc_temperature(adc_temp);
-            data[i].pressure = self.calc_pressure(adc_pres);
-            data[i].humidity = self.calc_humidity(adc_hum);
-            if matches!(self.variant_id, BME68xVariant::GasHigh) {
-                data[i].gas_resistance = calc_gas_resistance_high(
-                    // Checked mathmatically. Should never go out of bounds
-                    u16::try_from(adc_gas_res_high)?,
-                    gas_range_h,
-                );
-            } else {
-                data[i].gas_resistance = self.calc_gas_resistance_low(
-                    // Checked mathmatically. Should never go out of bounds
-                    u16::try_from(adc_gas_res_low)?,
-                    gas_range_l,
-                );
-            }
-        }
-
-        Ok(data)
+        parse_all_fields(&mut self.calib, self.variant_id, &buff, &set_val)
     }
 
-    /// Switch between SPI memory pages
+    /// Set heater configuration
     ///
     /// # Arguments
-    /// * `reg_addr`: The address of the register to read/write from.
+    /// * `conf`: The heater configuration to set.
+    /// * `op_mode`: The sensor operating mode to use
+    ///
+    /// # Returns
+    /// The number of conversions that will be performed for the given sensor
+    /// configuration.
     ///
     /// # Errors
-    /// Returns an error if switchign memeory pages fails.
-    fn set_mem_page(&mut self, reg_addr: u8) -> Result<(), BME68xError> {
-        let mem_page = if reg_addr > 0x7f {
-            BME68xMemPage::Page1
-        } else {
-            BME68xMemPage::Page0
-        };
+    /// Returns an error if setting the heater configuration fails
+    fn set_conf(
+        &mut self,
+        conf: &BME68xHeatrConf,
+        op_mode: BME68xOpMode,
+    ) -> Result<u8, BME68xError> {
+        let regs = BME68xHeatrRegs::new(conf, op_mode, &self.calib, self.amb_temp)?;
 
-        // IDK why it is complaining here, I'm passing it in.
-        #[allow(unused_variables)]
-        let page_match = matches!(self.mem_page, mem_page);
-
-        if !page_match {
-            self.mem_page = mem_page;
-            let write_buffer = [u8::from(BME68xRegister::MemPage) | BME68X_SPI_RD_MSK];
-            let mut read_buffer = [0];
-            let result = self
-                .i2c
-                .write_read(self.address.into(), &write_buffer, &mut read_buffer);
-            if result.is_ok() {
-                let reg = read_buffer[0] & (!BME68X_MEM_PAGE_MSK);
-                let reg = reg | (u8::from(self.mem_page) & BME68X_MEM_PAGE_MSK);
-
-                let write_buffer = [u8::from(BME68xRegister::MemPage) & BME68X_SPI_WR_MSK, reg];
-                let result = self.i2c.write(self.address.into(), &write_buffer);
-                if result.is_ok() {
-                    self.intf_rslt = BME68xError::Ok;
-                    Ok(())
-                } else {
-                    self.intf_rslt = BME68xError::ComFail;
-                    Err(BME68xError::ComFail)
-                }
-            } else {
-                self.intf_rslt = BME68xError::ComFail;
-                Err(BME68xError::ComFail)
-            }
-        } else {
-            Ok(())
+        if let Some(shared_dur) = regs.shared_dur {
+            self.set_reg(BME68xRegister::ShdHeatrDur, shared_dur)?;
         }
+        self.set_regs(regs.rh_reg_addr(), regs.rh_reg_data())?;
+        self.set_regs(regs.gw_reg_addr(), regs.gw_reg_data())?;
+
+        Ok(regs.nb_conv)
     }
+}
 
-    /// Get The current SPI memory page and update the internal structure
-    ///
-    /// # Errors
-    /// Returns an error if reading the current memory page failed.
-    fn get_mem_page(&mut self) -> Result<(), BME68xError> {
-        let mut read_buffer = [0];
-        let result = self.i2c.write_read(
-            self.address.into(),
-            &[u8::from(BME68xRegister::MemPage) | BME68X_SPI_RD_MSK],
-            &mut read_buffer,
-        );
-        if result.is_ok() {
-            self.mem_page = BME68xMemPage::from(read_buffer[0] & BME68X_MEM_PAGE_MSK);
-            Ok(())
+/// Registers holding the oversampling, filter and ODR configuration
+const BME68X_CONF_REGS: [u8; BME68X_LEN_CONFIG] = [0x71, 0x72, 0x73, 0x74, 0x75];
+
+/// Apply a sensor configuration to the configuration register values
+///
+/// # Arguments
+/// * `conf`: The sensor configuration to apply
+/// * `data_array`: The current values of the configuration registers
+///
+/// # Returns
+/// The new values for the configuration registers.
+fn conf_to_regs(
+    conf: BME68xConf,
+    mut data_array: [u8; BME68X_LEN_CONFIG],
+) -> [u8; BME68X_LEN_CONFIG] {
+    let mut odr20 = 0;
+    let mut odr3 = 1;
+    data_array[4] = set_bits(
+        data_array[4],
+        BME68X_FILTER_MSK,
+        BME68X_FILTER_POS,
+        conf.filter.into(),
+    );
+    data_array[3] = set_bits(
+        data_array[3],
+        BME68X_OST_MSK,
+        BME68X_OST_POS,
+        conf.os_temp.into(),
+    );
+    data_array[3] = set_bits(
+        data_array[3],
+        BME68X_OSP_MSK,
+        BME68X_OSP_POS,
+        conf.os_pres.into(),
+    );
+    data_array[1] = set_bits_pos_0(data_array[1], BME68X_OSH_MSK, conf.os_hum.into());
+    if !matches!(conf.odr, BME68xODR::ODRNone) {
+        odr20 = conf.odr.into();
+        odr3 = 0;
+    }
+    data_array[4] = set_bits(data_array[4], BME68X_ODR20_MSK, BME68X_ODR20_POS, odr20);
+    data_array[0] = set_bits(data_array[0], BME68X_ODR3_MSK, BME68X_ODR3_POS, odr3);
+    data_array
+}
+
+/// Decode the sensor configuration from the configuration register values
+///
+/// # Arguments
+/// * `data_array`: The values of the configuration registers
+///
+/// # Returns
+/// The sensor configuration.
+fn conf_from_regs(data_array: [u8; BME68X_LEN_CONFIG]) -> BME68xConf {
+    BME68xConf {
+        os_hum: BME68xOs::from(data_array[1] & BME68X_OSH_MSK),
+        filter: BME68xFilter::from(get_bits(
+            data_array[4],
+            BME68X_FILTER_MSK,
+            BME68X_FILTER_POS,
+        )),
+        os_temp: BME68xOs::from(get_bits(data_array[3], BME68X_OST_MSK, BME68X_OST_POS)),
+        os_pres: BME68xOs::from(get_bits(data_array[3], BME68X_OSP_MSK, BME68X_OSP_POS)),
+        odr: if get_bits(data_array[0], BME68X_ODR3_MSK, BME68X_ODR3_POS) != 0 {
+            BME68xODR::ODRNone
         } else {
-            Err(BME68xError::ComFail)
-        }
+            BME68xODR::from(get_bits(data_array[4], BME68X_ODR20_MSK, BME68X_ODR20_POS))
+        },
     }
+}
 
-    /// Set heater configuration
+/// Apply a heater configuration to the gas control register values
+///
+/// # Arguments
+/// * `conf`: The heater configuration
+/// * `variant_id`: The variant of the sensor
+/// * `nb_conv`: The number of conversions to perform
+/// * `ctrl_gas_data`: The current values of the `CtrlGas0` and `CtrlGas1` registers
+///
+/// # Returns
+/// The new values for the `CtrlGas0` and `CtrlGas1` registers
+fn heatr_ctrl_regs(
+    conf: &BME68xHeatrConf,
+    variant_id: BME68xVariant,
+    nb_conv: u8,
+    mut ctrl_gas_data: [u8; 2],
+) -> [u8; 2] {
+    let (hctrl, run_gas) = match (conf.enable, variant_id) {
+        (true, BME68xVariant::GasHigh) => (BME68xHeaterEnable::Enable, BME68xGasEnable::EnableHigh),
+
+        (true, BME68xVariant::GasLow) => (BME68xHeaterEnable::Enable, BME68xGasEnable::EnableLow),
+
+        (false, _) => (BME68xHeaterEnable::Disable, BME68xGasEnable::Disable),
+    };
+
+    ctrl_gas_data[0] = set_bits(
+        ctrl_gas_data[0],
+        BME68X_HCTRL_MSK,
+        BME68X_HCTRL_POS,
+        hctrl.into(),
+    );
+    ctrl_gas_data[1] = set_bits_pos_0(ctrl_gas_data[1], BME68X_NBCONV_MSK, nb_conv);
+    ctrl_gas_data[1] = set_bits(
+        ctrl_gas_data[1],
+        BME68X_RUN_GAS_MSK,
+        BME68X_RUN_GAS_POS,
+        run_gas.into(),
+    );
+    ctrl_gas_data
+}
+
+/// Register values for a heater profile
+struct BME68xHeatrRegs {
+    /// Register value for the shared heater duration, if it needs to be set.
+    shared_dur: Option<u8>,
+
+    /// Addresses of the heater resistance registers
+    rh_reg_addr: [u8; MAX_PROFILE_LEN],
+
+    /// Values for the heater resistance registers
+    rh_reg_data: [u8; MAX_PROFILE_LEN],
+
+    /// Addresses of the gas wait registers
+    gw_reg_addr: [u8; MAX_PROFILE_LEN],
+
+    /// Values for the gas wait registers
+    gw_reg_data: [u8; MAX_PROFILE_LEN],
+
+    /// Number of registers of each type to write
+    write_len: usize,
+
+    /// Number of conversions that will be performed
+    nb_conv: u8,
+}
+
+impl BME68xHeatrRegs {
+    /// Calculate the register values for a heater configuration
     ///
     /// # Arguments
     /// * `conf`: The heater configuration to set.
     /// * `op_mode`: The sensor operating mode to use
+    /// * `calib`: The calibration data of the sensor
+    /// * `amb_temp`: The ambient temperature
     ///
     /// # Returns
-    /// The number of conversions that will be performed for the given sensor
-    /// configuration.
+    /// The register values to write to the sensor
     ///
     /// # Errors
-    /// Returns an error if setting the heater configuration fails
-    fn set_conf(
-        &mut self,
+    /// Returns an error if the heater configuration is not valid for the
+    /// operating mode.
+    fn new(
         conf: &BME68xHeatrConf,
         op_mode: BME68xOpMode,
-    ) -> Result<u8, BME68xError> {
-        let mut nb_conv = 0;
-        let mut write_len = 0;
-        let mut rh_reg_addr = [0; MAX_PROFILE_LEN];
-        let mut rh_reg_data = [0; MAX_PROFILE_LEN];
-        let mut gw_reg_addr = [0; MAX_PROFILE_LEN];
-        let mut gw_reg_data = [0; MAX_PROFILE_LEN];
+        calib: &BME68xCalibData,
+        amb_temp: i8,
+    ) -> Result<Self, BME68xError> {
+        let mut regs = Self {
+            shared_dur: None,
+            rh_reg_addr: [0; MAX_PROFILE_LEN],
+            rh_reg_data: [0; MAX_PROFILE_LEN],
+            gw_reg_addr: [0; MAX_PROFILE_LEN],
+            gw_reg_data: [0; MAX_PROFILE_LEN],
+            write_len: 0,
+            nb_conv: 0,
+        };
 
         match op_mode {
             BME68xOpMode::ForcedMode => {
-                rh_reg_addr[0] = BME68xRegister::ResHeat0.into();
-                rh_reg_data[0] = self.calc_res_heat(conf.heatr_temp);
-                gw_reg_addr[0] = BME68xRegister::GasWait0.into();
-                gw_reg_data[0] = calc_gas_wait(conf.heatr_dur);
-                nb_conv = 0;
-                write_len = 1;
-            }
-            BME68xOpMode::SequentialMode => {
-                for i in 0..conf.profile_len {
-                    let index: usize = i.into();
-                    rh_reg_addr[index] = u8::from(BME68xRegister::ResHeat0) + i;
-                    rh_reg_data[index] = self.calc_res_heat(conf.heatr_temp_prof[index]);
-                    gw_reg_addr[index] = u8::from(BME68xRegister::GasWait0) + i;
-                    gw_reg_data[index] = calc_gas_wait(conf.heatr_dur_prof[index]);
-                    nb_conv = conf.profile_len;
-                    write_len = conf.profile_len;
-                }
+                regs.rh_reg_addr[0] = BME68xRegister::ResHeat0.into();
+                regs.rh_reg_data[0] = calib.calc_res_heat(conf.heatr_temp, amb_temp);
+                regs.gw_reg_addr[0] = BME68xRegister::GasWait0.into();
+                regs.gw_reg_data[0] = calc_gas_wait(conf.heatr_dur);
+                regs.write_len = 1;
             }
-            BME68xOpMode::ParallelMode => {
-                if conf.shared_heatr_dur == 0 {
-                    return Err(BME68xError::DefineShdHeatrDur);
+            BME68xOpMode::SequentialMode | BME68xOpMode::ParallelMode => {
+                if matches!(op_mode, BME68xOpMode::ParallelMode) {
+                    if conf.shared_heatr_dur == 0 {
+                        return Err(BME68xError::DefineShdHeatrDur);
+                    }
+                    if conf.profile_len > 0 {
+                        regs.shared_dur = Some(calc_heatr_dur_shared(conf.shared_heatr_dur));
+                    }
                 }
 
                 for i in 0..conf.profile_len {
                     let index: usize = i.into();
-                    rh_reg_addr[index] = u8::from(BME68xRegister::ResHeat0) + i;
-                    rh_reg_data[index] = self.calc_res_heat(conf.heatr_temp_prof[index]);
-                    gw_reg_addr[index] = u8::from(BME68xRegister::GasWait0) + i;
-                    gw_reg_data[index] = calc_gas_wait(conf.heatr_dur_prof[index]);
-                    nb_conv = conf.profile_len;
-                    write_len = conf.profile_len;
-                    let shared_dur = calc_heatr_dur_shared(conf.shared_heatr_dur);
-                    self.set_reg(BME68xRegister::ShdHeatrDur, shared_dur)?;
+                    regs.rh_reg_addr[index] = u8::from(BME68xRegister::ResHeat0) + i;
+                    regs.rh_reg_data[index] =
+                        calib.calc_res_heat(conf.heatr_temp_prof[index], amb_temp);
+                    regs.gw_reg_addr[index] = u8::from(BME68xRegister::GasWait0) + i;
+                    regs.gw_reg_data[index] = calc_gas_wait(conf.heatr_dur_prof[index]);
                 }
+                regs.nb_conv = conf.profile_len;
+                regs.write_len = conf.profile_len.into();
             }
             BME68xOpMode::SleepMode => return Err(BME68xError::DefineOpMode),
         }
+        Ok(regs)
+    }
+
+    /// Addresses of the heater resistance registers to write
+    fn rh_reg_addr(&self) -> &[u8] {
+        &self.rh_reg_addr[0..self.write_len]
+    }
+
+    /// Values of the heater resistance registers to write
+    fn rh_reg_data(&self) -> &[u8] {
+        &self.rh_reg_data[0..self.write_len]
+    }
+
+    /// Addresses of the gas wait registers to write
+    fn gw_reg_addr(&self) -> &[u8] {
+        &self.gw_reg_addr[0..self.write_len]
+    }
+
+    /// Values of the gas wait registers to write
+    fn gw_reg_data(&self) -> &[u8] {
+        &self.gw_reg_data[0..self.write_len]
+    }
+}
+
+/// Parse the status and index bytes of a data field
+///
+/// # Arguments
+/// * `buff`: The raw field data
+/// * `variant_id`: The variant of the sensor
+///
+/// # Returns
+/// Sensor data with only the status, gas index, and measurement index populated.
+fn parse_field_status(buff: &[u8], variant_id: BME68xVariant) -> BME68xData {
+    let mut data = BME68xData::new();
+    data.status = buff[0] & BME68X_NEW_DATA_MSK;
+    data.gas_index = buff[0] & BME68X_GAS_INDEX_MSK;
+    data.meas_index = buff[1];
+    let gas_reg = if matches!(variant_id, BME68xVariant::GasHigh) {
+        buff[16]
+    } else {
+        buff[14]
+    };
+    data.status |= gas_reg & BME68X_GASM_VALID_MSK;
+    data.status |= gas_reg & BME68X_HEAT_STAB_MSK;
+    data
+}
+
+/// Parse all three data fields of the sensor
+///
+/// # Arguments
+/// * `calib`: The calibration data of the sensor
+/// * `variant_id`: The variant of the sensor
+/// * `buff`: The raw data of all three fields
+/// * `set_val`: The raw values of the `IdacHeat`, `ResHeat` and `GasWait` registers
+///
+/// # Returns
+/// The compensated data of the three fields
+///
+/// # Errors
+/// Returns an error if compensating the data failed.
+fn parse_all_fields(
+    calib: &mut BME68xCalibData,
+    variant_id: BME68xVariant,
+    buff: &[u8; BME68X_LEN_FIELD * 3],
+    set_val: &[u8; 30],
+) -> Result<[BME68xData; 3], BME68xError> {
+    let mut data = [BME68xData::new(); 3];
+    for (field, field_data) in buff.chunks_exact(BME68X_LEN_FIELD).zip(data.iter_mut()) {
+        *field_data = parse_field_status(field, variant_id);
+        // Fields without new data may never have been written, so there is
+        // nothing meaningful to compensate.
+        if (field_data.status & BME68X_NEW_DATA_MSK) != 0 {
+            field_data.idac = set_val[usize::from(field_data.gas_index)];
+            field_data.res_heat = set_val[usize::from(10 + field_data.gas_index)];
+            field_data.gas_wait = set_val[usize::from(20 + field_data.gas_index)];
+            calib.compensate_field(field, variant_id, field_data)?;
+        }
+    }
+    Ok(data)
+}
+
+/// Calculate the measurement duration for a given configuration
+///
+/// # Arguments
+/// * `op_mode`: The operating mode of the sensor
+/// * `conf`: The sensor configuration
+///
+/// # Returns
+/// The measurement duration in microseconds
+fn calc_meas_dur(op_mode: BME68xOpMode, conf: BME68xConf) -> u32 {
+    let meas_cycles = conf.os_temp.get_meas_cycles()
+        + conf.os_pres.get_meas_cycles()
+        + conf.os_hum.get_meas_cycles();
+
+    // TPH Measurement Duration
+    let mut meas_dur = meas_cycles * 1963;
+    meas_dur += 477 * 4; // TPH Switching Duration
+    meas_dur += 477 * 5; // Gas measurement duration
+
+    if matches!(op_mode, BME68xOpMode::ParallelMode) {
+        meas_dur += 1000; // Wake up diration of 1 ms
+    }
+
+    meas_dur
+}
 
-        let write_len = usize::from(write_len);
+/// Calculate the duration of one full cycle through a sequential heater profile
+///
+/// Each step of the profile performs a TPH measurement, and then heats the
+/// hot plate for the step's duration before measuring the gas resistance.
+/// The standby time set by the `odr` configuration is not included.
+///
+/// # Arguments
+/// * `conf`: The sensor configuration
+/// * `heatr_conf`: The sequential heater configuration
+///
+/// # Returns
+/// The duration of a full sequential cycle in microseconds
+fn calc_sequential_dur(conf: BME68xConf, heatr_conf: &BME68xHeatrConf) -> u32 {
+    let meas_dur = calc_meas_dur(BME68xOpMode::SequentialMode, conf);
+    heatr_conf.heatr_dur_prof[0..usize::from(heatr_conf.profile_len)]
+        .iter()
+        .map(|dur| meas_dur + (u32::from(*dur) * 1000))
+        .sum()
+}
 
-        self.set_regs(&rh_reg_addr[0..write_len], &rh_reg_data[0..write_len])?;
-        self.set_regs(&gw_reg_addr[0..write_len], &gw_reg_data[0..write_len])?;
+/// Calculate the shared heater duration for parallel mode
+///
+/// # Arguments
+/// * `conf`: The sensor configuration
+///
+/// # Returns
+/// The shared heater duration in milliseconds
+///
+/// # Errors
+/// Returns an error if the measurement duration is too long.
+fn calc_shared_heatr_dur(conf: BME68xConf) -> Result<u16, BME68xError> {
+    // FIXME: Find out where the 140 comes from
+    Ok(u16::try_from(
+        140 - (calc_meas_dur(BME68xOpMode::ParallelMode, conf) / 1000),
+    )?)
+}
 
-        Ok(nb_conv)
+/// Sort the data fields by measurement index, and count the new fields
+///
+/// # Arguments
+/// * `data`: The data fields to sort
+///
+/// # Returns
+/// The number of fields containing new data
+fn sort_fields(data: &mut [BME68xData; 3]) -> u8 {
+    let mut new_fields = 0;
+    // TODO: Check over this. Probably a way to do it properly in rust.
+    for i in 0..3 {
+        if (data[i].status & BME68X_NEW_DATA_MSK) != 0 {
+            new_fields += 1;
+            for i in 0..2 {
+                for j in i + 1..3 {
+                    sort_sensor_data(i, j, data);
+                }
+            }
+        }
     }
+    new_fields
 }
 
 /// Caclulate register value for shared heater duration
@@ -2282,17 +2749,18 @@ fn sort_sensor_data(low_index: usize, high_index: usize, field: &mut [BME68xData
 /// Returns an error if analysis fails
 fn analyze_sensor_data(data: &[BME68xData], n_meas: usize) -> Result<(), BME68xError> {
     let mut cent_res = 0.0;
-    if (data[0].temperature < BME68X_MIN_TEMPERATURE)
-        || (data[0].temperature > BME68X_MAX_TEMPERATURE)
-    {
+    let temperature = data[0].temperature_degc();
+    if !(BME68X_MIN_TEMPERATURE..=BME68X_MAX_TEMPERATURE).contains(&temperature) {
         return Err(BME68xError::SelfTest);
     }
 
-    if (data[0].pressure < BME68X_MIN_PRESSURE) || (data[0].pressure > BME68X_MAX_PRESSURE) {
+    let pressure = data[0].pressure_pa();
+    if !(BME68X_MIN_PRESSURE..=BME68X_MAX_PRESSURE).contains(&pressure) {
         return Err(BME68xError::SelfTest);
     }
 
-    if (data[0].humidity < BME68X_MIN_HUMIDITY) || (data[0].humidity > BME68X_MAX_HUMIDITY) {
+    let humidity = data[0].humidity_percent();
+    if !(BME68X_MIN_HUMIDITY..=BME68X_MAX_HUMIDITY).contains(&humidity) {
         return Err(BME68xError::SelfTest);
     }
     for entry in data {
@@ -2302,8 +2770,8 @@ fn analyze_sensor_data(data: &[BME68xData], n_meas: usize) -> Result<(), BME68xE
     }
 
     if n_meas >= 6 {
-        cent_res = (5.0 * (data[3].gas_resistance + data[5].gas_resistance))
-            / (2.0 * data[4].gas_resistance);
+        cent_res = (5.0 * (data[3].gas_resistance_ohm() + data[5].gas_resistance_ohm()))
+            / (2.0 * data[4].gas_resistance_ohm());
     }
     if cent_res < 6.0 {
         return Err(BME68xError::SelfTest);
@@ -2319,6 +2787,7 @@ fn analyze_sensor_data(data: &[BME68xData], n_meas: usize) -> Result<(), BME68xE
 ///
 /// # Returns
 /// The gas resistance high value
+#[cfg(not(feature = "fixed-point"))]
 fn calc_gas_resistance_high(gas_res_adc: u16, gas_range: u8) -> f32 {
     let var1: u32 = 262144 >> gas_range;
     let var2: i32 = i32::from(gas_res_adc) - 512;
@@ -2327,6 +2796,25 @@ fn calc_gas_resistance_high(gas_res_adc: u16, gas_range: u8) -> f32 {
     1000000.0 * cast_u2f32(var1) / cast_i2f32(var2)
 }
 
+/// Calculate gas resistance high value using integer math
+///
+/// # Arguments:
+/// * `gas_res_adc`: Raw ADC gas resistance value
+/// * `gas_range`: The gas range to use for the calculation
+///
+/// # Returns
+/// The gas resistance high value in Ohms
+#[cfg(feature = "fixed-point")]
+fn calc_gas_resistance_high(gas_res_adc: u16, gas_range: u8) -> u32 {
+    let var1: u32 = 262144 >> gas_range;
+    // Equivalent to `4096 + ((gas_res_adc - 512) * 3)`, without going negative
+    let var2 = (u32::from(gas_res_adc) * 3) + 2560;
+
+    // Multiplying by 10000 then dividing then multiplying by 100 instead of
+    // multiplying by 1000000 to prevent overflow
+    ((10000 * var1) / var2) * 100
+}
+
 /// Concatenate two u8 into a u16
 ///
 /// # Arguments
@@ -2443,8 +2931,324 @@ fn cast_u2f32(value: u32) -> f32 {
 ///
 /// # Returns
 /// The converted value
+#[cfg(not(feature = "fixed-point"))]
 #[inline(always)]
 fn cast_i2f32(value: i32) -> f32 {
     #[allow(clippy::cast_precision_loss)]
     (value as f32)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_hal_mock::eh1::delay::NoopDelay;
+    use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction};
+
+    /// I2C address used for the sensor in the tests
+    const ADDR: u8 = BME68xAddr::HIGH as u8;
+
+    /// Test that `init` reports a missing device if the chip ID is wrong.
+    #[test]
+    fn test_init_wrong_chip_id() {
+        let expectations = [
+            I2cTransaction::write(ADDR, vec![0xe0, BME68X_SOFT_RESET_CMD]),
+            I2cTransaction::write_read(ADDR, vec![0xd0], vec![0x60]),
+        ];
+        let interface = I2cInterface::new(I2cMock::new(&expectations), BME68xAddr::HIGH);
+        let mut device = BME68xDev::new(interface, 25, NoopDelay);
+        assert!(matches!(device.init(), Err(BME68xError::DevNotFound)));
+
+        let (interface, _) = device.destroy();
+        interface.destroy().done();
+    }
+
+    /// Test that the sensor configuration is encoded into, and decoded from
+    /// the configuration registers.
+    #[test]
+    fn test_conf_regs() {
+        let conf = BME68xConf {
+            os_hum: BME68xOs::Os1x,
+            os_temp: BME68xOs::Os2x,
+            os_pres: BME68xOs::Os16x,
+            filter: BME68xFilter::Size3,
+            odr: BME68xODR::ODRNone,
+        };
+        let regs = conf_to_regs(conf, [0; BME68X_LEN_CONFIG]);
+        assert_eq!(regs, [0x80, 0x01, 0x00, 0x54, 0x08]);
+        assert_eq!(format!("{:?}", conf_from_regs(regs)), format!("{conf:?}"));
+    }
+
+    /// Test the output data rate is read back from the registers, rather than
+    /// reading back as none whenever a rate is set.
+    #[test]
+    fn test_conf_regs_odr() {
+        for odr in [
+            BME68xODR::ODR059Ms,
+            BME68xODR::ODR625Ms,
+            BME68xODR::ODR125Ms,
+            BME68xODR::ODR250Ms,
+            BME68xODR::ODR500Ms,
+            BME68xODR::ODR1000Ms,
+            BME68xODR::ODR10Ms,
+            BME68xODR::ODR20Ms,
+            BME68xODR::ODRNone,
+        ] {
+            let conf = BME68xConf {
+                os_hum: BME68xOs::Os1x,
+                os_temp: BME68xOs::Os2x,
+                os_pres: BME68xOs::Os16x,
+                filter: BME68xFilter::Size3,
+                odr,
+            };
+            let regs = conf_to_regs(conf, [0; BME68X_LEN_CONFIG]);
+            assert_eq!(
+                get_bits(regs[0], BME68X_ODR3_MSK, BME68X_ODR3_POS) != 0,
+                matches!(odr, BME68xODR::ODRNone)
+            );
+            assert_eq!(
+                format!("{:?}", conf_from_regs(regs).odr),
+                format!("{odr:?}")
+            );
+        }
+    }
+
+    /// Calibration data used for the compensation tests
+    fn test_calib() -> BME68xCalibData {
+        BME68xCalibData {
+            par_t1: 26215,
+            par_t2: 26363,
+            par_t3: 3,
+            par_p1: 36187,
+            par_p2: -10362,
+            par_p3: 88,
+            par_p4: 6874,
+            par_p5: -113,
+            par_p6: 30,
+            par_p7: 38,
+            par_p8: -2590,
+            par_p9: -3032,
+            par_p10: 30,
+            par_h1: 820,
+            par_h2: 1011,
+            par_h3: 0,
+            par_h4: 45,
+            par_h5: 20,
+            par_h6: 120,
+            par_h7: -100,
+            par_gh1: -30,
+            par_gh2: -12000,
+            par_gh3: 18,
+            res_heat_range: 1,
+            res_heat_val: 40,
+            ..BME68xCalibData::new()
+        }
+    }
+
+    /// Check that a value is within 0.01% of the expected value
+    #[cfg(not(feature = "fixed-point"))]
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            ((actual - expected) / expected).abs() < 0.0001,
+            "{actual} != {expected}"
+        );
+    }
+
+    /// Test the floating point compensation against values from Bosch's
+    /// reference implementation.
+    #[cfg(not(feature = "fixed-point"))]
+    #[test]
+    fn test_compensation_float() {
+        let mut calib = test_calib();
+        assert_close(calib.calc_temperature(500000), 25.32125);
+        assert_close(calib.calc_pressure(400000), 92837.72);
+        assert_close(calib.calc_humidity(22000), 45.44326);
+        assert_close(calib.calc_gas_resistance_low(300, 4), 593378.25);
+        assert_close(calib.calc_gas_resistance_low(700, 10), 6849.587);
+        assert_close(calc_gas_resistance_high(300, 4), 4735260.0);
+        assert_close(calc_gas_resistance_high(100, 4), 5728671.0);
+        assert_eq!(calib.calc_res_heat(300, 25), 111);
+    }
+
+    /// Test the integer compensation against values from Bosch's reference
+    /// implementation.
+    #[cfg(feature = "fixed-point")]
+    #[test]
+    fn test_compensation_fixed_point() {
+        let mut calib = test_calib();
+        assert_eq!(calib.calc_temperature(500000), 2532);
+        assert_eq!(calib.calc_pressure(400000), 92835);
+        assert_eq!(calib.calc_humidity(22000), 45431);
+        assert_eq!(calib.calc_gas_resistance_low(300, 4), 593378);
+        assert_eq!(calib.calc_gas_resistance_low(700, 10), 6850);
+        assert_eq!(calc_gas_resistance_high(300, 4), 4735200);
+        assert_eq!(calc_gas_resistance_high(100, 4), 5728600);
+        assert_eq!(calib.calc_res_heat(300, 25), 111);
+    }
+
+    /// Raw calibration coefficients of the compensation fixture, in the order
+    /// they are read from the sensor.
+    const FIXTURE_COEFF: [u8; BME68X_LEN_COEFF_ALL] = [
+        0x5b, 0x66, 0x03, 0x00, 0x42, 0x8c, 0x6e, 0xd7, 0x58, 0x00, 0xaf, 0x1b, 0xd1, 0xff, 0x35,
+        0x1e, 0x00, 0x00, 0x19, 0xf4, 0xb8, 0xf5, 0x1e, 0x3f, 0x2c, 0x30, 0x00, 0x2d, 0x14, 0x78,
+        0x9c, 0x2f, 0x66, 0x13, 0xcf, 0xe2, 0x12, 0x2e, 0x00, 0x16, 0x00, 0xf0,
+    ];
+
+    /// Raw field registers of the compensation fixture. The low gas channel
+    /// reads 419 in range 4, the high gas channel 544 in range 5.
+    const FIXTURE_FIELD: [u8; BME68X_LEN_FIELD] = [
+        0x80, 0x00, 0x65, 0x5a, 0xc0, 0x7e, 0xed, 0x00, 0x55, 0xf0, 0x00, 0x00, 0x00, 0x68, 0xf4,
+        0x88, 0x35,
+    ];
+
+    /// Compensate the fixture field registers with the fixture coefficients
+    ///
+    /// # Arguments
+    /// * `variant_id`: The variant of the sensor
+    ///
+    /// # Returns
+    /// The parsed calibration data and the compensated field
+    fn compensate_fixture(variant_id: BME68xVariant) -> (BME68xCalibData, BME68xData) {
+        let mut calib = BME68xCalibData::from_coeff_array(&FIXTURE_COEFF);
+        let mut data = parse_field_status(&FIXTURE_FIELD, variant_id);
+        calib
+            .compensate_field(&FIXTURE_FIELD, variant_id, &mut data)
+            .unwrap();
+        (calib, data)
+    }
+
+    /// Test the coefficients parsed from the raw calibration registers.
+    #[test]
+    fn test_calib_from_coeff_array() {
+        let calib = BME68xCalibData::from_coeff_array(&FIXTURE_COEFF);
+        assert_eq!(
+            (calib.par_t1, calib.par_t2, calib.par_t3),
+            (26159, 26203, 3)
+        );
+        assert_eq!(
+            (
+                calib.par_p1,
+                calib.par_p2,
+                calib.par_p3,
+                calib.par_p4,
+                calib.par_p5
+            ),
+            (35906, -10386, 88, 7087, -47)
+        );
+        assert_eq!(
+            (
+                calib.par_p6,
+                calib.par_p7,
+                calib.par_p8,
+                calib.par_p9,
+                calib.par_p10
+            ),
+            (30, 53, -3047, -2632, 30)
+        );
+        assert_eq!(
+            (calib.par_h1, calib.par_h2, calib.par_h3, calib.par_h4),
+            (780, 1010, 0, 45)
+        );
+        assert_eq!((calib.par_h5, calib.par_h6, calib.par_h7), (20, 120, -100));
+        assert_eq!(
+            (calib.par_gh1, calib.par_gh2, calib.par_gh3),
+            (-30, -12525, 18)
+        );
+        assert_eq!(
+            (calib.res_heat_range, calib.res_heat_val, calib.range_sw_err),
+            (1, 46, -1)
+        );
+    }
+
+    /// Test the floating point compensation of raw registers against the
+    /// fixture. The expected values were computed with the floating point
+    /// compensation functions of Bosch's `BME68x_SensorAPI` (`bme68x.c`),
+    /// compiled on their own and fed with the same registers.
+    #[cfg(not(feature = "fixed-point"))]
+    #[test]
+    fn test_compensation_fixture_float() {
+        let (calib, data) = compensate_fixture(BME68xVariant::GasLow);
+        assert_close(data.temperature, 31.661_835);
+        assert_close(data.pressure, 91_375.52);
+        assert_close(data.humidity, 50.140_3);
+        assert_close(data.gas_resistance, 536_902.7);
+        assert_eq!(calib.calc_res_heat(300, 25), 109);
+
+        let (_, data) = compensate_fixture(BME68xVariant::GasHigh);
+        assert_close(data.gas_resistance, 1_954_198.5);
+        assert_eq!(data.status, 0xb0);
+    }
+
+    /// Test the integer compensation of raw registers against the fixture.
+    /// The expected values were computed with the integer compensation
+    /// functions of Bosch's `BME68x_SensorAPI` (`bme68x.c`), compiled on their
+    /// own and fed with the same registers.
+    #[cfg(feature = "fixed-point")]
+    #[test]
+    fn test_compensation_fixture_fixed_point() {
+        let (calib, data) = compensate_fixture(BME68xVariant::GasLow);
+        assert_eq!(data.temperature, 3166);
+        assert_eq!(data.pressure, 91376);
+        assert_eq!(data.humidity, 50134);
+        assert_eq!(data.gas_resistance, 536_903);
+        assert_eq!(calib.calc_res_heat(300, 25), 108);
+
+        let (_, data) = compensate_fixture(BME68xVariant::GasHigh);
+        assert_eq!(data.gas_resistance, 1_954_100);
+        assert_eq!(data.status, 0xb0);
+    }
+
+    /// Test readings converted to and from the data structure are unchanged,
+    /// up to the resolution of the compensation mode.
+    #[test]
+    fn test_data_from_readings() {
+        let data = BME68xData::from_readings(-12.345, 101_325.4, 45.6789, 150_000.4);
+        assert!((data.temperature_degc() + 12.345).abs() < 0.01);
+        assert!((data.pressure_pa() - 101_325.4).abs() < 0.5);
+        assert!((data.humidity_percent() - 45.6789).abs() < 0.001);
+        assert!((data.gas_resistance_ohm() - 150_000.4).abs() < 0.5);
+        assert_eq!(data.status, 0);
+    }
+
+    /// Test the register values computed for the gas wait time.
+    #[test]
+    fn test_calc_gas_wait() {
+        assert_eq!(calc_gas_wait(0x3f), 0x3f);
+        assert_eq!(calc_gas_wait(100), 89);
+        assert_eq!(calc_gas_wait(0xfc0), 0xff);
+    }
+
+    /// Test the register values and timing computed for a sequential heater
+    /// profile.
+    #[test]
+    fn test_sequential_heatr_conf() {
+        assert!(matches!(
+            BME68xHeatrConf::new_sequential(&[300, 300], &[100]),
+            Err(BME68xError::InvalidLength)
+        ));
+
+        let calib = test_calib();
+        let heatr_conf = BME68xHeatrConf::new_sequential(&[300, 300, 300], &[100, 0x3f, 0xfc0])
+            .expect("Valid sequential profile");
+        let regs =
+            BME68xHeatrRegs::new(&heatr_conf, BME68xOpMode::SequentialMode, &calib, 25).unwrap();
+        assert_eq!(regs.shared_dur, None);
+        assert_eq!(regs.nb_conv, 3);
+        assert_eq!(regs.rh_reg_addr(), [0x5a, 0x5b, 0x5c]);
+        assert_eq!(regs.rh_reg_data(), [111, 111, 111]);
+        assert_eq!(regs.gw_reg_addr(), [0x64, 0x65, 0x66]);
+        assert_eq!(regs.gw_reg_data(), [89, 0x3f, 0xff]);
+
+        let conf = BME68xConf {
+            os_hum: BME68xOs::Os1x,
+            os_temp: BME68xOs::Os2x,
+            os_pres: BME68xOs::Os16x,
+            filter: BME68xFilter::Size3,
+            odr: BME68xODR::ODRNone,
+        };
+        let meas_dur = calc_meas_dur(BME68xOpMode::SequentialMode, conf);
+        assert_eq!(
+            calc_sequential_dur(conf, &heatr_conf),
+            (3 * meas_dur) + ((100 + 0x3f + 0xfc0) * 1000)
+        );
+    }
+}
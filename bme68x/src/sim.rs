@@ -0,0 +1,723 @@
+//! Simulated BME688 for running the driver without hardware.
+//!
+//! [`BME68xSim`] models the sensor from the register side, and implements the
+//! `embedded-hal` I2C and SPI traits, so it can be handed to [`crate::I2cInterface`]
+//! or [`crate::SpiInterface`] in place of a real bus.
+//!
+//! Raw ADC values are generated from a configurable [`BME68xSimEnvironment`]
+//! by searching for the ADC value that the driver's own compensation formulas
+//! turn back into the environment's value, so readings round trip to within
+//! the resolution of the ADC in both float and fixed-point builds.
+//!
+//! The simulation does not model time. A forced measurement completes as soon
+//! as it is triggered, and in parallel or sequential mode a new field is
+//! produced every time the field registers are read.
+use core::ops::RangeInclusive;
+use embedded_hal::i2c::{self, I2c, Operation as I2cOperation, SevenBitAddress};
+use embedded_hal::spi::{self, Operation as SpiOperation, SpiDevice};
+
+use crate::interface::{BME68X_MEM_PAGE_MSK, BME68X_SPI_RD_MSK, BME68X_SPI_WR_MSK};
+use crate::{
+    calc_gas_resistance_high, BME68xAddr, BME68xCalibData, BME68xData, BME68xOpMode,
+    BME68xRegister, BME68X_CHIP_ID, BME68X_GASM_VALID_MSK, BME68X_GAS_INDEX_MSK, BME68X_HCTRL_MSK,
+    BME68X_HEAT_STAB_MSK, BME68X_LEN_COEFF1, BME68X_LEN_COEFF2, BME68X_LEN_COEFF3,
+    BME68X_LEN_COEFF_ALL, BME68X_LEN_FIELD, BME68X_LEN_FIELD_OFFSET, BME68X_MODE_MSK,
+    BME68X_NBCONV_MSK, BME68X_NEW_DATA_MSK, BME68X_RUN_GAS_MSK, BME68X_SOFT_RESET_CMD,
+};
+
+/// Variant ID reported by the simulated sensor (BME688)
+const SIM_VARIANT_ID: u8 = 0x01;
+
+/// Unique ID reported by the simulated sensor
+const SIM_UNIQUE_ID: u8 = 0x5a;
+
+/// Calibration coefficients of the simulated sensor, in the order the driver
+/// reads them (`Coeff1`, `Coeff2`, then `Coeff3`)
+#[rustfmt::skip]
+const SIM_COEFFS: [u8; BME68X_LEN_COEFF_ALL] = [
+    // Coeff1 (0x8a - 0xa0)
+    0xfb, 0x66, 0x03, 0x00, 0x5b, 0x8d, 0x86, 0xd7, 0x58, 0x00, 0xda, 0x1a, 0x8f, 0xff, 0x26,
+    0x1e, 0x00, 0x00, 0xe2, 0xf5, 0x28, 0xf4, 0x1e,
+    // Coeff2 (0xe1 - 0xee)
+    0x3f, 0x34, 0x33, 0x00, 0x2d, 0x14, 0x78, 0x9c, 0x67, 0x66, 0x20, 0xd1, 0xe2, 0x12,
+    // Coeff3 (0x00 - 0x04)
+    0x28, 0x00, 0x10, 0x00, 0x00,
+];
+
+/// Number of data fields of the sensor
+const SIM_N_FIELDS: u8 = 3;
+
+/// Largest value of the 20 bit temperature ADC
+const SIM_ADC_MAX_TEMP: u32 = 0xfffff;
+
+/// Range of pressure ADC values the simulation uses.
+///
+/// This covers well beyond the 300 - 1100 hPa range of the sensor. ADC values
+/// outside of it overflow the integer compensation formula.
+const SIM_ADC_RANGE_PRES: RangeInclusive<u32> = 0x40000..=0xe0000;
+
+/// Largest humidity ADC value the simulation uses.
+///
+/// This is already well past 100 %RH. Larger values overflow the integer
+/// compensation formula, and are never produced by a working sensor.
+const SIM_ADC_MAX_HUM: u32 = 0xc000;
+
+/// Largest value of the 10 bit gas ADC
+const SIM_ADC_MAX_10: u32 = 0x3ff;
+
+/// Number of gas resistance ranges
+const SIM_GAS_RANGES: u8 = 16;
+
+/// Maximum heater temperature the sensor supports, in degrees C
+const SIM_MAX_HEATR_TEMP: u32 = 400;
+
+/// Heater temperature the environment's gas resistance is given for, in degrees C
+const SIM_GAS_REF_TEMP: f32 = 300.0;
+
+/// The physical environment measured by the simulated sensor
+#[derive(Debug, Clone, Copy)]
+pub struct BME68xSimEnvironment {
+    /// Temperature in degrees C
+    pub temperature: f32,
+
+    /// Pressure in Pascals
+    pub pressure: f32,
+
+    /// Relative humidity in percent
+    pub humidity: f32,
+
+    /// Gas resistance in Ohms, with the heater at 300 degrees C.
+    ///
+    /// The resistance at other heater temperatures is scaled inversely with
+    /// the heater temperature, so cooler heater steps read a higher resistance.
+    pub gas_resistance: f32,
+}
+
+impl Default for BME68xSimEnvironment {
+    fn default() -> Self {
+        Self {
+            temperature: 25.0,
+            pressure: 101325.0,
+            humidity: 40.0,
+            gas_resistance: 50000.0,
+        }
+    }
+}
+
+/// Simulated BME688 sensor
+pub struct BME68xSim {
+    /// The I2C address the sensor responds to
+    address: BME68xAddr,
+
+    /// Contents of the sensor's registers
+    regs: [u8; 256],
+
+    /// The environment the sensor is measuring
+    environment: BME68xSimEnvironment,
+
+    /// Calibration data matching the simulated coefficient registers
+    calib: BME68xCalibData,
+
+    /// Register that the next I2C read starts from
+    read_addr: u8,
+
+    /// Field that the next parallel or sequential measurement is written to
+    next_field: u8,
+
+    /// Heater profile step used for the next parallel or sequential measurement
+    next_step: u8,
+
+    /// Index of the next measurement
+    meas_index: u8,
+}
+
+impl BME68xSim {
+    /// Create a new simulated sensor
+    ///
+    /// # Arguments
+    /// * `address`: The I2C address the sensor responds to
+    /// * `environment`: The environment the sensor is measuring
+    ///
+    /// # Returns
+    /// A new simulated sensor, in the state the real sensor is in after power on
+    #[must_use]
+    pub fn new(address: BME68xAddr, environment: BME68xSimEnvironment) -> Self {
+        let mut regs = [0; 256];
+        let (coeff1, rest) = SIM_COEFFS.split_at(BME68X_LEN_COEFF1);
+        let (coeff2, coeff3) = rest.split_at(BME68X_LEN_COEFF2);
+        let coeff1_addr = usize::from(u8::from(BME68xRegister::Coeff1));
+        let coeff2_addr = usize::from(u8::from(BME68xRegister::Coeff2));
+        let coeff3_addr = usize::from(u8::from(BME68xRegister::Coeff3));
+        regs[coeff1_addr..coeff1_addr + BME68X_LEN_COEFF1].copy_from_slice(coeff1);
+        regs[coeff2_addr..coeff2_addr + BME68X_LEN_COEFF2].copy_from_slice(coeff2);
+        regs[coeff3_addr..coeff3_addr + BME68X_LEN_COEFF3].copy_from_slice(coeff3);
+        regs[usize::from(u8::from(BME68xRegister::ChipId))] = BME68X_CHIP_ID;
+        regs[usize::from(u8::from(BME68xRegister::VariantId))] = SIM_VARIANT_ID;
+        regs[usize::from(u8::from(BME68xRegister::UniqueId))] = SIM_UNIQUE_ID;
+
+        Self {
+            address,
+            regs,
+            environment,
+            calib: BME68xCalibData::from_coeff_array(&SIM_COEFFS),
+            read_addr: 0,
+            next_field: 0,
+            next_step: 0,
+            meas_index: 0,
+        }
+    }
+
+    /// Get the environment the sensor is measuring
+    ///
+    /// # Returns
+    /// The simulated environment
+    #[must_use]
+    pub fn environment(&self) -> BME68xSimEnvironment {
+        self.environment
+    }
+
+    /// Set the environment the sensor is measuring
+    ///
+    /// Takes effect from the next measurement.
+    ///
+    /// # Arguments
+    /// * `environment`: The new environment
+    pub fn set_environment(&mut self, environment: BME68xSimEnvironment) {
+        self.environment = environment;
+    }
+
+    /// Get the current value of a register
+    ///
+    /// Unlike reading through the bus, this does not trigger measurements or
+    /// clear any status flags.
+    ///
+    /// # Arguments
+    /// * `reg_addr`: The address of the register
+    ///
+    /// # Returns
+    /// The value of the register
+    #[must_use]
+    pub fn register(&self, reg_addr: u8) -> u8 {
+        self.regs[usize::from(reg_addr)]
+    }
+
+    /// Read a block of registers, as the bus would
+    ///
+    /// # Arguments
+    /// * `reg_addr`: The first register to read
+    /// * `data`: Buffer to place the register values in
+    fn read_regs(&mut self, reg_addr: u8, data: &mut [u8]) {
+        let start = usize::from(reg_addr);
+        let end = start + data.len();
+        let fields_start = usize::from(Self::field_addr(0));
+        let fields_end = usize::from(Self::field_addr(SIM_N_FIELDS));
+
+        let op_mode = BME68xOpMode::from(self.reg(BME68xRegister::CtrlMeas) & BME68X_MODE_MSK);
+        if matches!(
+            op_mode,
+            BME68xOpMode::ParallelMode | BME68xOpMode::SequentialMode
+        ) && (start < fields_end)
+            && (end > fields_start)
+        {
+            self.measure(self.next_field, self.next_step);
+            self.next_field = (self.next_field + 1) % SIM_N_FIELDS;
+            let nb_conv = (self.reg(BME68xRegister::CtrlGas1) & BME68X_NBCONV_MSK).max(1);
+            self.next_step = (self.next_step + 1) % nb_conv;
+        }
+
+        let mut addr = reg_addr;
+        for byte in data.iter_mut() {
+            *byte = self.regs[usize::from(addr)];
+            addr = addr.wrapping_add(1);
+        }
+
+        // Reading a field's status clears its new data flag
+        for field in 0..SIM_N_FIELDS {
+            let status_addr = usize::from(Self::field_addr(field));
+            if (start..end).contains(&status_addr) {
+                self.regs[status_addr] &= !BME68X_NEW_DATA_MSK;
+            }
+        }
+    }
+
+    /// Write a single register, as the bus would
+    ///
+    /// Writes to read-only registers are ignored.
+    ///
+    /// # Arguments
+    /// * `reg_addr`: The register to write to
+    /// * `value`: The value to write
+    fn write_reg(&mut self, reg_addr: u8, value: u8) {
+        let idac_heat0 = u8::from(BME68xRegister::IdacHeat0);
+        let shd_heatr_dur = u8::from(BME68xRegister::ShdHeatrDur);
+        let ctrl_gas0 = u8::from(BME68xRegister::CtrlGas0);
+        let config = u8::from(BME68xRegister::Config);
+
+        if reg_addr == u8::from(BME68xRegister::SoftReset) {
+            if value == BME68X_SOFT_RESET_CMD {
+                self.soft_reset();
+            }
+        } else if (idac_heat0..=shd_heatr_dur).contains(&reg_addr)
+            || (ctrl_gas0..=config).contains(&reg_addr)
+        {
+            self.regs[usize::from(reg_addr)] = value;
+            if reg_addr == u8::from(BME68xRegister::CtrlMeas) {
+                self.start_mode(BME68xOpMode::from(value & BME68X_MODE_MSK));
+            }
+        }
+    }
+
+    /// Put the sensor back into its power on state
+    fn soft_reset(&mut self) {
+        let fields_start = usize::from(Self::field_addr(0));
+        let config = usize::from(u8::from(BME68xRegister::Config));
+        self.regs[fields_start..=config].fill(0);
+        self.next_field = 0;
+        self.next_step = 0;
+        self.meas_index = 0;
+    }
+
+    /// Handle a change of the operating mode
+    ///
+    /// # Arguments
+    /// * `op_mode`: The operating mode that was written
+    fn start_mode(&mut self, op_mode: BME68xOpMode) {
+        match op_mode {
+            BME68xOpMode::ForcedMode => {
+                let step = self.reg(BME68xRegister::CtrlGas1) & BME68X_NBCONV_MSK;
+                self.measure(0, step);
+                // The sensor goes back to sleep once the forced measurement is done
+                self.regs[usize::from(u8::from(BME68xRegister::CtrlMeas))] &= !BME68X_MODE_MSK;
+            }
+            BME68xOpMode::ParallelMode | BME68xOpMode::SequentialMode => {
+                self.next_field = 0;
+                self.next_step = 0;
+            }
+            BME68xOpMode::SleepMode => {}
+        }
+    }
+
+    /// Perform a measurement and store the results in a data field
+    ///
+    /// # Arguments
+    /// * `field`: The field to store the measurement in
+    /// * `step`: The heater profile step to use for the measurement
+    fn measure(&mut self, field: u8, step: u8) {
+        let env = self.environment;
+        let mut calib = self.calib;
+        let mut data = BME68xData::new();
+
+        let adc_temp = search_adc(0..=SIM_ADC_MAX_TEMP, env.temperature, |adc| {
+            data.temperature = calib.calc_temperature(adc);
+            data.temperature_degc()
+        });
+        // Leave t_fine at the simulated temperature for pressure and humidity
+        calib.calc_temperature(adc_temp);
+        let adc_pres = search_adc(SIM_ADC_RANGE_PRES, env.pressure, |adc| {
+            data.pressure = calib.calc_pressure(adc);
+            data.pressure_pa()
+        });
+        let adc_hum = search_adc(0..=SIM_ADC_MAX_HUM, env.humidity, |adc| {
+            data.humidity = calib.calc_humidity(adc);
+            data.humidity_percent()
+        });
+
+        let step_addr = |base: BME68xRegister| usize::from(u8::from(base) + step);
+        let res_heat = self.regs[step_addr(BME68xRegister::ResHeat0)];
+        let gas_wait = self.regs[step_addr(BME68xRegister::GasWait0)];
+        let heater_on = (self.reg(BME68xRegister::CtrlGas0) & BME68X_HCTRL_MSK) == 0;
+        let run_gas = (self.reg(BME68xRegister::CtrlGas1) & BME68X_RUN_GAS_MSK) != 0;
+
+        #[allow(clippy::cast_possible_truncation)]
+        let amb_temp = env.temperature.clamp(-128.0, 127.0) as i8;
+        let heatr_temp = if heater_on {
+            // Bounded by SIM_MAX_HEATR_TEMP, so the conversions can't fail
+            let temp = search_adc(0..=SIM_MAX_HEATR_TEMP, f32::from(res_heat), |temp| {
+                f32::from(calib.calc_res_heat(u16::try_from(temp).unwrap_or(u16::MAX), amb_temp))
+            });
+            f32::from(u16::try_from(temp).unwrap_or(u16::MAX))
+        } else {
+            env.temperature
+        };
+        let gas_resistance = env.gas_resistance * SIM_GAS_REF_TEMP / heatr_temp.max(1.0);
+        let (adc_gas, gas_range) = gas_adc(gas_resistance);
+
+        let mut gas_status = gas_range;
+        if run_gas {
+            gas_status |= BME68X_GASM_VALID_MSK;
+        }
+        if run_gas && heater_on && (gas_wait != 0) {
+            gas_status |= BME68X_HEAT_STAB_MSK;
+        }
+
+        // The heater current, which the self test checks is neither 0 or full scale
+        self.regs[step_addr(BME68xRegister::IdacHeat0)] = if heater_on {
+            (res_heat / 2).clamp(1, 0xfe)
+        } else {
+            0
+        };
+
+        let adc_pres = adc_pres.to_be_bytes();
+        let adc_temp = adc_temp.to_be_bytes();
+        let adc_hum = adc_hum.to_be_bytes();
+        let adc_gas = adc_gas.to_be_bytes();
+        let base = usize::from(Self::field_addr(field));
+        let field_regs = &mut self.regs[base..base + BME68X_LEN_FIELD];
+        field_regs.fill(0);
+        field_regs[0] = BME68X_NEW_DATA_MSK | (step & BME68X_GAS_INDEX_MSK);
+        field_regs[1] = self.meas_index;
+        field_regs[2] = (adc_pres[1] << 4) | (adc_pres[2] >> 4);
+        field_regs[3] = (adc_pres[2] << 4) | (adc_pres[3] >> 4);
+        field_regs[4] = adc_pres[3] << 4;
+        field_regs[5] = (adc_temp[1] << 4) | (adc_temp[2] >> 4);
+        field_regs[6] = (adc_temp[2] << 4) | (adc_temp[3] >> 4);
+        field_regs[7] = adc_temp[3] << 4;
+        field_regs[8] = adc_hum[2];
+        field_regs[9] = adc_hum[3];
+        field_regs[15] = (adc_gas[0] << 6) | (adc_gas[1] >> 2);
+        field_regs[16] = (adc_gas[1] << 6) | gas_status;
+
+        self.meas_index = self.meas_index.wrapping_add(1);
+    }
+
+    /// Get the value of a register
+    ///
+    /// # Arguments
+    /// * `reg`: The register to get
+    ///
+    /// # Returns
+    /// The value of the register
+    fn reg(&self, reg: BME68xRegister) -> u8 {
+        self.regs[usize::from(u8::from(reg))]
+    }
+
+    /// Get the address of a data field
+    ///
+    /// # Arguments
+    /// * `field`: The index of the field
+    ///
+    /// # Returns
+    /// The address of the first register of the field
+    fn field_addr(field: u8) -> u8 {
+        u8::from(BME68xRegister::Field0) + (field * BME68X_LEN_FIELD_OFFSET)
+    }
+
+    /// Get the register an SPI address refers to, using the current memory page
+    ///
+    /// # Arguments
+    /// * `spi_addr`: The address sent over SPI
+    ///
+    /// # Returns
+    /// The full address of the register
+    fn spi_register(&self, spi_addr: u8) -> u8 {
+        let addr = spi_addr & BME68X_SPI_WR_MSK;
+        let mem_page_reg = u8::from(BME68xRegister::MemPage) & BME68X_SPI_WR_MSK;
+        let lower_page = (self.regs[usize::from(mem_page_reg)] & BME68X_MEM_PAGE_MSK) != 0;
+        // The memory page register is reachable from both pages
+        if (addr == mem_page_reg) || lower_page {
+            addr
+        } else {
+            addr | BME68X_SPI_RD_MSK
+        }
+    }
+}
+
+impl i2c::ErrorType for BME68xSim {
+    type Error = i2c::ErrorKind;
+}
+
+impl I2c<SevenBitAddress> for BME68xSim {
+    fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [I2cOperation<'_>],
+    ) -> Result<(), Self::Error> {
+        if address != u8::from(self.address) {
+            return Err(i2c::ErrorKind::NoAcknowledge(
+                i2c::NoAcknowledgeSource::Address,
+            ));
+        }
+        for operation in operations {
+            match operation {
+                I2cOperation::Write(bytes) => {
+                    // Writes are (register, data) pairs. A lone register sets
+                    // the start of the next read.
+                    if let Some(reg_addr) = bytes.first() {
+                        self.read_addr = *reg_addr;
+                    }
+                    for pair in bytes.chunks_exact(2) {
+                        self.write_reg(pair[0], pair[1]);
+                    }
+                }
+                I2cOperation::Read(buffer) => {
+                    self.read_regs(self.read_addr, buffer);
+                    // Only the lower byte of the length matters for the 8 bit address
+                    #[allow(clippy::cast_possible_truncation)]
+                    let len = buffer.len() as u8;
+                    self.read_addr = self.read_addr.wrapping_add(len);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::i2c::I2c<SevenBitAddress> for BME68xSim {
+    async fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [I2cOperation<'_>],
+    ) -> Result<(), Self::Error> {
+        I2c::transaction(self, address, operations)
+    }
+}
+
+impl spi::ErrorType for BME68xSim {
+    type Error = spi::ErrorKind;
+}
+
+impl SpiDevice for BME68xSim {
+    fn transaction(&mut self, operations: &mut [SpiOperation<'_, u8>]) -> Result<(), Self::Error> {
+        let mut read_addr = None;
+        for operation in operations {
+            match operation {
+                SpiOperation::Write(bytes) => match bytes.first() {
+                    Some(spi_addr) if (spi_addr & BME68X_SPI_RD_MSK) != 0 => {
+                        read_addr = Some(self.spi_register(*spi_addr));
+                    }
+                    _ => {
+                        for pair in bytes.chunks_exact(2) {
+                            let reg_addr = self.spi_register(pair[0]);
+                            self.write_reg(reg_addr, pair[1]);
+                        }
+                    }
+                },
+                SpiOperation::Read(buffer) => {
+                    let reg_addr = read_addr.ok_or(spi::ErrorKind::Other)?;
+                    self.read_regs(reg_addr, buffer);
+                }
+                SpiOperation::DelayNs(_) => {}
+                // The sensor is never accessed with full duplex transfers
+                SpiOperation::Transfer(_, _) | SpiOperation::TransferInPlace(_) => {
+                    return Err(spi::ErrorKind::Other)
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Find the ADC value that a compensation formula turns into a target value
+///
+/// The formula must be monotonic over the ADC range.
+///
+/// # Arguments
+/// * `adc_range`: The range of ADC values to search
+/// * `target`: The value to find the ADC value for
+/// * `compensate`: Function to compute the compensated value for an ADC value
+///
+/// # Returns
+/// The ADC value whose compensated value is closest to the target
+fn search_adc(
+    adc_range: RangeInclusive<u32>,
+    target: f32,
+    mut compensate: impl FnMut(u32) -> f32,
+) -> u32 {
+    let (mut low, mut high) = adc_range.into_inner();
+    let start = low;
+    let increasing = compensate(high) >= compensate(low);
+    while low < high {
+        let mid = low + ((high - low) / 2);
+        let value = compensate(mid);
+        if (increasing && (value < target)) || (!increasing && (value > target)) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+
+    let error = |value: f32| {
+        if value > target {
+            value - target
+        } else {
+            target - value
+        }
+    };
+    if (low > start) && (error(compensate(low - 1)) < error(compensate(low))) {
+        low - 1
+    } else {
+        low
+    }
+}
+
+/// Find the gas ADC value and range that read as the given resistance
+///
+/// # Arguments
+/// * `resistance`: The gas resistance in Ohms
+///
+/// # Returns
+/// The gas ADC value and gas range
+fn gas_adc(resistance: f32) -> (u16, u8) {
+    let compensate = |adc: u32, range: u8| {
+        let mut data = BME68xData::new();
+        // The ADC is bounded by SIM_ADC_MAX_10, so the conversion can't fail
+        data.gas_resistance = calc_gas_resistance_high(u16::try_from(adc).unwrap_or(0), range);
+        data.gas_resistance_ohm()
+    };
+
+    for range in 0..SIM_GAS_RANGES {
+        if (compensate(SIM_ADC_MAX_10, range)..=compensate(0, range)).contains(&resistance) {
+            let adc = search_adc(0..=SIM_ADC_MAX_10, resistance, |adc| compensate(adc, range));
+            return (u16::try_from(adc).unwrap_or(0), range);
+        }
+    }
+
+    // Out of the measurable range, so saturate
+    if resistance > compensate(0, 0) {
+        (0, 0)
+    } else {
+        (0x3ff, SIM_GAS_RANGES - 1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        BME68xConf, BME68xDev, BME68xError, BME68xFilter, BME68xODR, BME68xOs, I2cInterface,
+        SpiInterface,
+    };
+    use embedded_hal_mock::eh1::delay::NoopDelay;
+
+    /// Configuration used for the simulated measurements
+    const CONF: BME68xConf = BME68xConf {
+        os_hum: BME68xOs::Os1x,
+        os_temp: BME68xOs::Os2x,
+        os_pres: BME68xOs::Os16x,
+        filter: BME68xFilter::Off,
+        odr: BME68xODR::ODRNone,
+    };
+
+    /// Check that a value is within a relative tolerance of the expected value
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            ((actual - expected) / expected).abs() <= tolerance,
+            "{actual} is not within {tolerance} of {expected}"
+        );
+    }
+
+    /// Create a driver for a simulated sensor on I2C
+    fn i2c_dev(env: BME68xSimEnvironment) -> BME68xDev<I2cInterface<BME68xSim>, NoopDelay> {
+        let sim = BME68xSim::new(BME68xAddr::HIGH, env);
+        BME68xDev::new(I2cInterface::new(sim, BME68xAddr::HIGH), 25, NoopDelay)
+    }
+
+    /// Test a forced measurement reads back the simulated environment.
+    #[test]
+    fn test_forced_measurement() {
+        let env = BME68xSimEnvironment {
+            temperature: 21.5,
+            pressure: 98000.0,
+            humidity: 55.0,
+            gas_resistance: 120000.0,
+        };
+        let mut dev = i2c_dev(env);
+        dev.init().unwrap();
+        dev.set_config(&CONF).unwrap();
+        let data = dev.forced_measurent(300, 100).unwrap();
+
+        assert_close(data.temperature_degc(), env.temperature, 1e-3);
+        assert_close(data.pressure_pa(), env.pressure, 1e-4);
+        assert_close(data.humidity_percent(), env.humidity, 1e-3);
+        // The heater temperature is only resolved to a few degrees
+        assert_close(data.gas_resistance_ohm(), env.gas_resistance, 2e-2);
+        assert_ne!(data.status & BME68X_GASM_VALID_MSK, 0);
+        assert_ne!(data.status & BME68X_HEAT_STAB_MSK, 0);
+    }
+
+    /// Test the heater resistance follows the ambient temperature that is set.
+    #[test]
+    // The integer calculation only resolves much larger changes of the ambient temperature
+    #[cfg(not(feature = "fixed-point"))]
+    fn test_amb_temp() {
+        let env = BME68xSimEnvironment {
+            temperature: 0.0,
+            pressure: 101_325.0,
+            humidity: 40.0,
+            gas_resistance: 120_000.0,
+        };
+        let mut dev = i2c_dev(env);
+        dev.init().unwrap();
+        dev.set_config(&CONF).unwrap();
+        dev.set_heatr_conf_forced(300, 100).unwrap();
+        let (interface, _) = dev.destroy();
+        let default_res_heat = interface
+            .destroy()
+            .register(BME68xRegister::ResHeat0.into());
+
+        let mut dev = i2c_dev(env);
+        dev.init().unwrap();
+        dev.set_config(&CONF).unwrap();
+        dev.set_amb_temp(0);
+        assert_eq!(dev.get_amb_temp(), 0);
+        let data = dev.forced_measurent(300, 100).unwrap();
+        assert_close(data.gas_resistance_ohm(), env.gas_resistance, 2e-2);
+        let (interface, _) = dev.destroy();
+        let res_heat = interface
+            .destroy()
+            .register(BME68xRegister::ResHeat0.into());
+        assert_ne!(res_heat, default_res_heat);
+    }
+
+    /// Test the sensor can be used through the SPI memory pages.
+    #[test]
+    fn test_spi() {
+        let sim = BME68xSim::new(BME68xAddr::HIGH, BME68xSimEnvironment::default());
+        let mut dev = BME68xDev::new(SpiInterface::new(sim), 25, NoopDelay);
+        dev.init().unwrap();
+        dev.set_config(&CONF).unwrap();
+        let data = dev.forced_measurent(300, 100).unwrap();
+        assert_close(data.temperature_degc(), 25.0, 1e-3);
+    }
+
+    /// Test the sensor does not respond on the other address.
+    #[test]
+    fn test_wrong_address() {
+        let sim = BME68xSim::new(BME68xAddr::LOW, BME68xSimEnvironment::default());
+        let mut dev = BME68xDev::new(I2cInterface::new(sim, BME68xAddr::HIGH), 25, NoopDelay);
+        assert!(matches!(dev.init(), Err(BME68xError::ComFail)));
+    }
+
+    /// Test the simulated sensor passes the self test.
+    #[test]
+    fn test_selftest() {
+        let mut dev = i2c_dev(BME68xSimEnvironment::default());
+        dev.selftest_check().unwrap();
+    }
+
+    /// Test parallel mode steps through the heater profile.
+    #[test]
+    fn test_parallel() {
+        let mut dev = i2c_dev(BME68xSimEnvironment::default());
+        dev.init().unwrap();
+        dev.set_config(&CONF).unwrap();
+        dev.set_heatr_conf_parallel(&[320, 100, 100], &[5, 2, 10])
+            .unwrap();
+        dev.set_op_mode(BME68xOpMode::ParallelMode).unwrap();
+
+        let mut resistance = [0.0; 3];
+        for gas_index in [0, 1, 2] {
+            let (data, n_fields) = dev.get_data(BME68xOpMode::ParallelMode).unwrap();
+            assert_eq!(n_fields, 1);
+            assert_eq!(data[0].gas_index, gas_index);
+            resistance[usize::from(gas_index)] = data[0].gas_resistance_ohm();
+        }
+        // The cooler steps read a higher resistance
+        assert!(resistance[0] < resistance[1]);
+
+        // The profile repeats once it is complete
+        let (data, _) = dev.get_data(BME68xOpMode::ParallelMode).unwrap();
+        assert_eq!(data[0].gas_index, 0);
+    }
+}
@@ -1,18 +1,77 @@
 //! Build Script for integrating C code (ESP-IDF, drivers, etc) into the program
 use std::env;
-use std::path::PathBuf;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 fn main() {
-    // Linking for the ESP-IDF
-    embuild::espidf::sysenv::output();
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+    let target_arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap();
 
-    // Linking for the BSEC library
-    // FIXME: Use path module to use local path so it is not limited to my computer
+    // Linking for the ESP-IDF
+    if target_os == "espidf" {
+        embuild::espidf::sysenv::output();
+    }
 
     let crate_path = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    // The pure Rust stand-in for the BSEC library needs neither the library nor its headers
+    if env::var_os("CARGO_FEATURE_MOCK_BSEC").is_none() {
+        link_bsec(&crate_path, &target_os, &target_arch, &out_path);
+    }
+
+    // Embed the standard BSEC configurations that have been copied into the project
+    let config_dir = PathBuf::from(&crate_path).join("src/bsec/config");
+    println!("cargo:rerun-if-changed={}", config_dir.display());
+
+    let mut configs = Vec::new();
+    find_configs(&config_dir, &mut configs);
+    // The stand-in for the library only takes its own test configurations, and
+    // the library only takes Bosch's
+    let mock = env::var_os("CARGO_FEATURE_MOCK_BSEC").is_some();
+    configs.retain(|(name, _)| name.starts_with("mock_") == mock);
+    configs.sort();
+
+    let mut source = String::from("/// Configurations embedded in the firmware, by name\n");
+    source.push_str("const EMBEDDED_CONFIGS: &[(&str, &[u8])] = &[\n");
+    for (name, config) in configs {
+        writeln!(source, "    (\"{name}\", &{config:?}),").unwrap();
+    }
+    source.push_str("];\n");
 
-    println!("cargo:rustc-link-search={crate_path}/src/bsec/bin/esp/esp32");
+    fs::write(out_path.join("bsec_configs.rs"), source).expect("Failed to write configurations.");
+}
+
+/// Link the BSEC library, and generate the bindings for it
+///
+/// # Arguments
+/// * `crate_path`: The directory of the crate
+/// * `target_os`: The OS being built for
+/// * `target_arch`: The architecture being built for
+/// * `out_path`: The directory to write the bindings to
+fn link_bsec(crate_path: &str, target_os: &str, target_arch: &str, out_path: &Path) {
+    // Linking for the BSEC library. The directory of the library in the BSEC
+    // download (relative to `src/bsec/bin`, or absolute) can be overridden
+    // with BSEC_LIB_DIR for targets not listed here.
+    println!("cargo:rerun-if-env-changed=BSEC_LIB_DIR");
+    let lib_dir = env::var("BSEC_LIB_DIR").unwrap_or_else(|_| {
+        match (target_os, target_arch) {
+            ("espidf", _) => "esp/esp32",
+            // Used to replay recorded sensor data on the host
+            ("linux", "x86_64") => "Linux/x86_64",
+            _ => panic!("No BSEC library known for {target_arch}-{target_os}, set BSEC_LIB_DIR"),
+        }
+        .to_owned()
+    });
+
+    let lib_path = Path::new(crate_path).join("src/bsec/bin").join(lib_dir);
+    println!("cargo:rustc-link-search={}", lib_path.display());
     println!("cargo:rustc-link-lib=static=algobsec");
+    if target_os == "linux" {
+        // The Linux builds of the library use libm
+        println!("cargo:rustc-link-lib=m");
+    }
 
     // Use bindgen to generate the bindings for BSEC library.
     let bindings = bindgen::Builder::default()
@@ -24,8 +83,33 @@ fn main() {
         .expect("Unable to generate bindings");
 
     // Write the bindings to the build directory
-    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
     bindings
         .write_to_file(out_path.join("bsec_bindings.rs"))
         .expect("Failed to write bindings.");
 }
+
+/// Recursively find the BSEC IAQ configurations in a directory
+///
+/// # Arguments
+/// * `dir`: The directory to search
+/// * `configs`: The name and contents of each configuration that is found
+fn find_configs(dir: &Path, configs: &mut Vec<(String, Vec<u8>)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for path in entries.map(|entry| entry.unwrap().path()) {
+        if path.is_dir() {
+            find_configs(&path, configs);
+        } else if path.file_name().is_some_and(|name| name == "bsec_iaq.txt") {
+            // The text version of the configuration is a comma separated list of bytes
+            let config = fs::read_to_string(&path)
+                .unwrap()
+                .split(',')
+                .map(|byte| byte.trim().parse().expect("Invalid byte in configuration"))
+                .collect();
+            let name = dir.file_name().unwrap().to_string_lossy().into_owned();
+            configs.push((name, config));
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Header for BSEC blobs (configurations and states) saved outside of the library.
+//!
+//! The BSEC library does not say which library version a blob came from, or
+//! whether it was cut short by a bad write, so blobs are stored behind a small
+//! header that is checked before the blob is handed to the library.
+//!
+//! | Offset | Size | Contents                                             |
+//! | ------ | ---- | ---------------------------------------------------- |
+//! | 0      | 4    | Magic number identifying the kind of blob            |
+//! | 4      | 4    | BSEC version (major, minor, major/minor bugfix)      |
+//! | 8      | 8    | Unix time the blob was saved, in seconds             |
+//! | 16     | 1    | IAQ accuracy when the blob was saved (0 for configs) |
+//! | 17     | 3    | Reserved, zero                                       |
+//! | 20     | 4    | Length of the blob                                   |
+//! | 24     | 4    | CRC-32 of the rest of the header and the blob        |
+//! | 28     | n    | The blob                                             |
+//!
+//! All numbers are little-endian.
+
+/// Length of the header in bytes
+const HEADER_LEN: usize = 28;
+
+/// Offset of the CRC in the header
+const CRC_OFFSET: usize = 24;
+
+/// Reasons that a stored blob cannot be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlobError {
+    /// The data is too short to contain a header
+    TooShort,
+
+    /// The magic number does not match the expected kind of blob
+    BadMagic,
+
+    /// The blob was created by a different version of the BSEC library
+    VersionMismatch {
+        /// The version the blob was created by
+        stored: [u8; 4],
+    },
+
+    /// The length in the header does not match the length of the blob
+    LengthMismatch,
+
+    /// The CRC in the header does not match the blob
+    CrcMismatch,
+}
+
+/// Information about a blob, stored in its header
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlobMetadata {
+    /// Unix time the blob was saved, in seconds
+    pub timestamp_s: u64,
+
+    /// IAQ accuracy when the blob was saved
+    pub iaq_accuracy: u8,
+}
+
+/// Add the header to a blob
+///
+/// # Arguments
+/// * `magic`: Magic number identifying the kind of blob
+/// * `version`: Version of the BSEC library the blob came from
+/// * `metadata`: Information about the blob
+/// * `blob`: The blob to wrap
+///
+/// # Returns
+/// The header followed by the blob
+///
+/// # Panics
+/// Will panic if the blob is larger than 4 GiB.
+pub(crate) fn wrap(
+    magic: [u8; 4],
+    version: [u8; 4],
+    metadata: BlobMetadata,
+    blob: &[u8],
+) -> Vec<u8> {
+    let mut data = Vec::with_capacity(HEADER_LEN + blob.len());
+    data.extend_from_slice(&magic);
+    data.extend_from_slice(&version);
+    data.extend_from_slice(&metadata.timestamp_s.to_le_bytes());
+    data.extend_from_slice(&[metadata.iaq_accuracy, 0, 0, 0]);
+    data.extend_from_slice(&u32::try_from(blob.len()).unwrap().to_le_bytes());
+    let crc = crc32(&data, blob);
+    data.extend_from_slice(&crc.to_le_bytes());
+    data.extend_from_slice(blob);
+    data
+}
+
+/// Check the header of a stored blob, and strip it off
+///
+/// # Arguments
+/// * `magic`: Magic number identifying the expected kind of blob
+/// * `version`: Version of the BSEC library in use
+/// * `data`: The stored data
+///
+/// # Returns
+/// The information from the header, and the blob without its header
+///
+/// # Errors
+/// Returns an error if the header does not match the blob, or the expected kind and version
+pub(crate) fn unwrap(
+    magic: [u8; 4],
+    version: [u8; 4],
+    data: &[u8],
+) -> Result<(BlobMetadata, &[u8]), BlobError> {
+    if data.len() < HEADER_LEN {
+        return Err(BlobError::TooShort);
+    }
+    let (header, blob) = data.split_at(HEADER_LEN);
+
+    if header[0..4] != magic {
+        return Err(BlobError::BadMagic);
+    }
+
+    // Check the CRC before anything else in the header is trusted
+    if read_u32(&header[CRC_OFFSET..]) != crc32(&header[..CRC_OFFSET], blob) {
+        return Err(BlobError::CrcMismatch);
+    }
+
+    let stored = [header[4], header[5], header[6], header[7]];
+    if stored != version {
+        return Err(BlobError::VersionMismatch { stored });
+    }
+
+    let length = read_u32(&header[20..24]);
+    if usize::try_from(length) != Ok(blob.len()) {
+        return Err(BlobError::LengthMismatch);
+    }
+
+    let mut timestamp = [0; 8];
+    timestamp.copy_from_slice(&header[8..16]);
+    let metadata = BlobMetadata {
+        timestamp_s: u64::from_le_bytes(timestamp),
+        iaq_accuracy: header[16],
+    };
+
+    Ok((metadata, blob))
+}
+
+/// Read a little-endian u32 from the start of a slice
+///
+/// # Arguments
+/// * `bytes`: The bytes to read from
+///
+/// # Returns
+/// The value read
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Compute the CRC-32 (IEEE 802.3) of the header and blob
+///
+/// # Arguments
+/// * `header`: The header, up to the CRC
+/// * `blob`: The blob
+///
+/// # Returns
+/// The CRC of the header followed by the blob
+fn crc32(header: &[u8], blob: &[u8]) -> u32 {
+    !header.iter().chain(blob).fold(u32::MAX, |crc, byte| {
+        (0..8).fold(crc ^ u32::from(*byte), |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Magic number for the test blobs
+    const MAGIC: [u8; 4] = *b"TEST";
+
+    /// Library version for the test blobs
+    const VERSION: [u8; 4] = [2, 6, 1, 0];
+
+    /// Metadata for the test blobs
+    const METADATA: BlobMetadata = BlobMetadata {
+        timestamp_s: 1_700_000_000,
+        iaq_accuracy: 3,
+    };
+
+    /// Test a wrapped blob is unwrapped unchanged, with its metadata.
+    #[test]
+    fn test_round_trip() {
+        let data = wrap(MAGIC, VERSION, METADATA, &[1, 2, 3]);
+        assert_eq!(data.len(), HEADER_LEN + 3);
+        assert_eq!(
+            unwrap(MAGIC, VERSION, &data),
+            Ok((METADATA, [1, 2, 3].as_slice()))
+        );
+
+        let data = wrap(MAGIC, VERSION, BlobMetadata::default(), &[]);
+        assert_eq!(
+            unwrap(MAGIC, VERSION, &data),
+            Ok((BlobMetadata::default(), [].as_slice()))
+        );
+    }
+
+    /// Test the CRC matches the standard check value.
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"1234", b"56789"), 0xCBF4_3926);
+    }
+
+    /// Test data shorter than the header is rejected.
+    #[test]
+    fn test_too_short() {
+        let data = wrap(MAGIC, VERSION, METADATA, &[]);
+        assert_eq!(
+            unwrap(MAGIC, VERSION, &data[..HEADER_LEN - 1]),
+            Err(BlobError::TooShort)
+        );
+        assert_eq!(unwrap(MAGIC, VERSION, &[]), Err(BlobError::TooShort));
+    }
+
+    /// Test a blob of a different kind is rejected.
+    #[test]
+    fn test_bad_magic() {
+        let data = wrap(*b"OTHR", VERSION, METADATA, &[1, 2, 3]);
+        assert_eq!(unwrap(MAGIC, VERSION, &data), Err(BlobError::BadMagic));
+    }
+
+    /// Test a corrupt or truncated blob, or a corrupt header, is rejected.
+    #[test]
+    fn test_crc_mismatch() {
+        let data = wrap(MAGIC, VERSION, METADATA, &[1, 2, 3]);
+
+        let mut corrupt = data.clone();
+        corrupt[HEADER_LEN + 1] ^= 0x01;
+        assert_eq!(
+            unwrap(MAGIC, VERSION, &corrupt),
+            Err(BlobError::CrcMismatch)
+        );
+
+        let mut corrupt = data.clone();
+        corrupt[16] = 0;
+        assert_eq!(
+            unwrap(MAGIC, VERSION, &corrupt),
+            Err(BlobError::CrcMismatch)
+        );
+
+        assert_eq!(
+            unwrap(MAGIC, VERSION, &data[..data.len() - 1]),
+            Err(BlobError::CrcMismatch)
+        );
+    }
+
+    /// Test a blob from a different version of the library is rejected.
+    #[test]
+    fn test_version_mismatch() {
+        let stored = [2, 5, 0, 2];
+        let data = wrap(MAGIC, stored, METADATA, &[1, 2, 3]);
+        assert_eq!(
+            unwrap(MAGIC, VERSION, &data),
+            Err(BlobError::VersionMismatch { stored })
+        );
+    }
+
+    /// Test a header with the wrong length is rejected, even with a valid CRC.
+    #[test]
+    fn test_length_mismatch() {
+        let mut data = wrap(MAGIC, VERSION, METADATA, &[1, 2, 3]);
+        data[20..24].copy_from_slice(&4u32.to_le_bytes());
+        let crc = crc32(&data[..CRC_OFFSET], &data[HEADER_LEN..]);
+        data[CRC_OFFSET..HEADER_LEN].copy_from_slice(&crc.to_le_bytes());
+        assert_eq!(
+            unwrap(MAGIC, VERSION, &data),
+            Err(BlobError::LengthMismatch)
+        );
+    }
+}
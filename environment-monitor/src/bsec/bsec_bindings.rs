@@ -8,8 +8,13 @@
 #![allow(dead_code)]
 #![allow(clippy::missing_docs_in_private_items)]
 
+#[cfg(not(feature = "mock-bsec"))]
 include!(concat!(env!("OUT_DIR"), "/bsec_bindings.rs"));
 
+// Pure Rust stand-in for building without the BSEC library
+#[cfg(feature = "mock-bsec")]
+pub use super::mock::*;
+
 // Extra implementation logic for the generated structures.
 
 impl bsec_sensor_configuration_t {
@@ -0,0 +1,157 @@
+//! Selection of the standard BSEC configurations embedded in the firmware.
+//!
+//! The build script embeds every IAQ configuration (`bsec_iaq.txt`) found
+//! under `src/bsec/config`, as provided in the BSEC download from Bosch.
+//! Configurations in directories starting with `mock_` are test fixtures, and
+//! are only embedded with the `mock-bsec` feature, in place of Bosch's.
+
+// Generated by the build script. Defines `EMBEDDED_CONFIGS`, a list of
+// configuration names (the directory names from the BSEC download, e.g.
+// `bme688_iaq_33v_3s_4d`) and the serialized configurations.
+include!(concat!(env!("OUT_DIR"), "/bsec_configs.rs"));
+
+/// Supply voltage of the sensor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SupplyVoltage {
+    /// 1.8 V supply
+    V1_8,
+
+    /// 3.3 V supply
+    #[default]
+    V3_3,
+}
+
+/// Sample rate that the configuration is tuned for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConfigSampleRate {
+    /// Low Power Sampling (Every 3 seconds)
+    #[default]
+    LowPower,
+
+    /// Ultra Low Power Sampling (every 5 minutes)
+    UltraLowPower,
+}
+
+/// Length of the history the IAQ calibration is based on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CalibrationWindow {
+    /// 4 days of history
+    #[default]
+    Days4,
+
+    /// 28 days of history
+    Days28,
+}
+
+/// Selection of one of the standard BSEC configurations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConfigSelection {
+    /// Supply voltage of the sensor
+    pub supply_voltage: SupplyVoltage,
+
+    /// Sample rate the configuration is tuned for
+    pub sample_rate: ConfigSampleRate,
+
+    /// Length of the IAQ calibration history
+    pub calibration_window: CalibrationWindow,
+}
+
+impl ConfigSelection {
+    /// Get the name of the selected configuration, as used by Bosch
+    ///
+    /// # Returns
+    /// The name of the configuration, e.g. `33v_3s_4d`
+    #[must_use]
+    pub fn name(self) -> String {
+        let voltage = match self.supply_voltage {
+            SupplyVoltage::V1_8 => "18v",
+            SupplyVoltage::V3_3 => "33v",
+        };
+        let sample_rate = match self.sample_rate {
+            ConfigSampleRate::LowPower => "3s",
+            ConfigSampleRate::UltraLowPower => "300s",
+        };
+        let window = match self.calibration_window {
+            CalibrationWindow::Days4 => "4d",
+            CalibrationWindow::Days28 => "28d",
+        };
+        format!("{voltage}_{sample_rate}_{window}")
+    }
+
+    /// Find the selected configuration in the configurations embedded in the firmware
+    ///
+    /// # Returns
+    /// The full name and contents of the configuration, if it was embedded
+    #[must_use]
+    pub fn embedded(self) -> Option<(&'static str, &'static [u8])> {
+        let suffix = format!("_{}", self.name());
+        EMBEDDED_CONFIGS
+            .iter()
+            .find(|(name, _)| name.ends_with(&suffix))
+            .copied()
+    }
+}
+
+/// Get the names of the configurations embedded in the firmware
+///
+/// # Returns
+/// Iterator over the names of the embedded configurations
+pub fn embedded_configs() -> impl Iterator<Item = &'static str> {
+    EMBEDDED_CONFIGS.iter().map(|(name, _)| *name)
+}
+
+/// The configuration the BSEC library is running with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ActiveConfig {
+    /// The configuration stored on the filesystem
+    Stored,
+
+    /// One of the configurations embedded in the firmware
+    Embedded {
+        /// Name of the configuration
+        name: &'static str,
+    },
+
+    /// The built-in default configuration of the BSEC library
+    #[default]
+    LibraryDefault,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Test the names of the selections match the names used by Bosch.
+    #[test]
+    fn test_name() {
+        assert_eq!(ConfigSelection::default().name(), "33v_3s_4d");
+        let selection = ConfigSelection {
+            supply_voltage: SupplyVoltage::V1_8,
+            sample_rate: ConfigSampleRate::UltraLowPower,
+            calibration_window: CalibrationWindow::Days28,
+        };
+        assert_eq!(selection.name(), "18v_300s_28d");
+    }
+
+    /// Test the selection is found in the embedded test configurations.
+    #[cfg(feature = "mock-bsec")]
+    #[test]
+    fn test_embedded() {
+        assert_eq!(
+            embedded_configs().collect::<Vec<_>>(),
+            ["mock_iaq_33v_3s_4d"]
+        );
+
+        let (name, config) = ConfigSelection::default().embedded().unwrap();
+        assert_eq!(name, "mock_iaq_33v_3s_4d");
+        // The build script parsed the comma separated bytes
+        assert_eq!(config.len(), 32);
+        assert_eq!(config[..4], [0, 0, 2, 2]);
+
+        let selection = ConfigSelection {
+            calibration_window: CalibrationWindow::Days28,
+            ..ConfigSelection::default()
+        };
+        assert_eq!(selection.embedded(), None);
+    }
+}
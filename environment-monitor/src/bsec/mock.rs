@@ -0,0 +1,1089 @@
+//! Pure Rust stand-in for the BSEC library, enabled with the `mock-bsec` feature.
+//!
+//! This implements the parts of the BSEC interface that this crate uses, with
+//! the same names and signatures as the generated bindings, so the application
+//! can be built and tested without Bosch's closed-source library and headers.
+//!
+//! The outputs are deterministic and plausible, but are not Bosch's algorithm:
+//!
+//! * The sensor is always run in forced mode, and all subscribed outputs must
+//!   share one sample rate.
+//! * The IAQ is derived from how far the gas resistance is below its baseline,
+//!   the highest resistance seen recently (cleaner air has a higher resistance).
+//!   The static IAQ is the same as the IAQ.
+//! * The outputs stabilize 5 minutes after the first gas measurement. The IAQ
+//!   accuracy then rises with the time spent calibrating, which is kept in the state.
+//! * Gas estimates (from BME688 gas scanning) are never produced.
+//!
+//! Configurations are accepted without being interpreted, and work buffers are not used.
+#![allow(non_camel_case_types)]
+// The items mirroring the C interface are documented in Bosch's headers
+#![allow(clippy::missing_docs_in_private_items)]
+
+use std::sync::{Mutex, PoisonError};
+
+/// Return codes of the library. Negative values are errors, positive values are warnings.
+pub type bsec_library_return_t = i32;
+
+pub const BSEC_OK: bsec_library_return_t = 0;
+pub const BSEC_E_DOSTEPS_INVALIDINPUT: bsec_library_return_t = -1;
+pub const BSEC_E_DOSTEPS_VALUELIMITS: bsec_library_return_t = -2;
+pub const BSEC_W_DOSTEPS_TSINTRADIFFOUTOFRANGE: bsec_library_return_t = 4;
+pub const BSEC_E_DOSTEPS_DUPLICATEINPUT: bsec_library_return_t = -6;
+pub const BSEC_I_DOSTEPS_NOOUTPUTSRETURNABLE: bsec_library_return_t = 2;
+pub const BSEC_W_DOSTEPS_EXCESSOUTPUTS: bsec_library_return_t = 3;
+pub const BSEC_W_DOSTEPS_GASINDEXMISS: bsec_library_return_t = 5;
+pub const BSEC_E_SU_WRONGDATARATE: bsec_library_return_t = -10;
+pub const BSEC_E_SU_SAMPLERATELIMITS: bsec_library_return_t = -12;
+pub const BSEC_E_SU_DUPLICATEGATE: bsec_library_return_t = -13;
+pub const BSEC_E_SU_INVALIDSAMPLERATE: bsec_library_return_t = -14;
+pub const BSEC_E_SU_GATECOUNTEXCEEDSARRAY: bsec_library_return_t = -15;
+pub const BSEC_E_SU_SAMPLINTVLINTEGERMULT: bsec_library_return_t = -16;
+pub const BSEC_E_SU_MULTGASSAMPLINTVL: bsec_library_return_t = -17;
+pub const BSEC_E_SU_HIGHHEATERONDURATION: bsec_library_return_t = -18;
+pub const BSEC_W_SU_UNKNOWNOUTPUTGATE: bsec_library_return_t = 10;
+pub const BSEC_W_SU_MODINNOULP: bsec_library_return_t = 11;
+pub const BSEC_I_SU_SUBSCRIBEDOUTPUTGATES: bsec_library_return_t = 12;
+pub const BSEC_I_SU_GASESTIMATEPRECEDENCE: bsec_library_return_t = 13;
+pub const BSEC_E_PARSE_SECTIONEXCEEDSWORKBUFFER: bsec_library_return_t = -32;
+pub const BSEC_E_CONFIG_FAIL: bsec_library_return_t = -33;
+pub const BSEC_E_CONFIG_VERSIONMISMATCH: bsec_library_return_t = -34;
+pub const BSEC_E_CONFIG_FEATUREMISMATCH: bsec_library_return_t = -35;
+pub const BSEC_E_CONFIG_CRCMISMATCH: bsec_library_return_t = -36;
+pub const BSEC_E_CONFIG_EMPTY: bsec_library_return_t = -37;
+pub const BSEC_E_CONFIG_INSUFFICIENTWORKBUFFER: bsec_library_return_t = -38;
+pub const BSEC_E_CONFIG_INVALIDSTRINGSIZE: bsec_library_return_t = -40;
+pub const BSEC_E_CONFIG_INSUFFICIENTBUFFER: bsec_library_return_t = -41;
+pub const BSEC_E_SET_INVALIDCHANNELIDENTIFIER: bsec_library_return_t = -100;
+pub const BSEC_E_SET_INVALIDLENGTH: bsec_library_return_t = -104;
+pub const BSEC_W_SC_CALL_TIMING_VIOLATION: bsec_library_return_t = 100;
+pub const BSEC_W_SC_MODEXCEEDULPTIMELIMIT: bsec_library_return_t = 101;
+pub const BSEC_W_SC_MODINSUFFICIENTWAITTIME: bsec_library_return_t = 102;
+
+/// Identifiers of the physical sensor inputs
+pub type bsec_physical_sensor_t = u32;
+
+pub const BSEC_INPUT_PRESSURE: bsec_physical_sensor_t = 1;
+pub const BSEC_INPUT_HUMIDITY: bsec_physical_sensor_t = 4;
+pub const BSEC_INPUT_TEMPERATURE: bsec_physical_sensor_t = 6;
+pub const BSEC_INPUT_GASRESISTOR: bsec_physical_sensor_t = 7;
+pub const BSEC_INPUT_HEATSOURCE: bsec_physical_sensor_t = 14;
+pub const BSEC_INPUT_PROFILE_PART: bsec_physical_sensor_t = 24;
+
+/// Identifiers of the virtual sensor outputs
+pub type bsec_virtual_sensor_t = u32;
+
+pub const BSEC_OUTPUT_IAQ: bsec_virtual_sensor_t = 1;
+pub const BSEC_OUTPUT_STATIC_IAQ: bsec_virtual_sensor_t = 2;
+pub const BSEC_OUTPUT_CO2_EQUIVALENT: bsec_virtual_sensor_t = 3;
+pub const BSEC_OUTPUT_BREATH_VOC_EQUIVALENT: bsec_virtual_sensor_t = 4;
+pub const BSEC_OUTPUT_RAW_TEMPERATURE: bsec_virtual_sensor_t = 6;
+pub const BSEC_OUTPUT_RAW_PRESSURE: bsec_virtual_sensor_t = 7;
+pub const BSEC_OUTPUT_RAW_HUMIDITY: bsec_virtual_sensor_t = 8;
+pub const BSEC_OUTPUT_RAW_GAS: bsec_virtual_sensor_t = 9;
+pub const BSEC_OUTPUT_STABILIZATION_STATUS: bsec_virtual_sensor_t = 12;
+pub const BSEC_OUTPUT_RUN_IN_STATUS: bsec_virtual_sensor_t = 13;
+pub const BSEC_OUTPUT_SENSOR_HEAT_COMPENSATED_TEMPERATURE: bsec_virtual_sensor_t = 14;
+pub const BSEC_OUTPUT_SENSOR_HEAT_COMPENSATED_HUMIDITY: bsec_virtual_sensor_t = 15;
+pub const BSEC_OUTPUT_GAS_PERCENTAGE: bsec_virtual_sensor_t = 21;
+pub const BSEC_OUTPUT_GAS_ESTIMATE_1: bsec_virtual_sensor_t = 22;
+pub const BSEC_OUTPUT_GAS_ESTIMATE_2: bsec_virtual_sensor_t = 23;
+pub const BSEC_OUTPUT_GAS_ESTIMATE_3: bsec_virtual_sensor_t = 24;
+pub const BSEC_OUTPUT_GAS_ESTIMATE_4: bsec_virtual_sensor_t = 25;
+pub const BSEC_OUTPUT_RAW_GAS_INDEX: bsec_virtual_sensor_t = 26;
+
+pub const BSEC_SAMPLE_RATE_DISABLED: f64 = 65535.0;
+pub const BSEC_SAMPLE_RATE_ULP: f64 = 0.003_333_3;
+pub const BSEC_SAMPLE_RATE_CONT: f64 = 1.0;
+pub const BSEC_SAMPLE_RATE_LP: f64 = 0.33333;
+pub const BSEC_SAMPLE_RATE_SCAN: f64 = 0.055_556;
+pub const BSEC_SAMPLE_RATE_ULP_MEASUREMENT_ON_DEMAND: f64 = 0.0;
+
+pub const BSEC_MAX_PHYSICAL_SENSOR: u32 = 8;
+pub const BSEC_NUMBER_OUTPUTS: u32 = 19;
+pub const BSEC_MAX_WORKBUFFER_SIZE: u32 = 4096;
+pub const BSEC_MAX_STATE_BLOB_SIZE: u32 = 221;
+
+/// Version of the library
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct bsec_version_t {
+    pub major: u8,
+    pub minor: u8,
+    pub major_bugfix: u8,
+    pub minor_bugfix: u8,
+}
+
+/// A sample of a physical sensor
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct bsec_input_t {
+    pub time_stamp: i64,
+    pub signal: f32,
+    pub signal_dimensions: u8,
+    pub sensor_id: u8,
+}
+
+/// A sample of a virtual sensor
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct bsec_output_t {
+    pub time_stamp: i64,
+    pub signal: f32,
+    pub signal_dimensions: u8,
+    pub sensor_id: u8,
+    pub accuracy: u8,
+}
+
+/// A sensor and its sample rate, for subscriptions
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct bsec_sensor_configuration_t {
+    pub sample_rate: f32,
+    pub sensor_id: u8,
+}
+
+/// Settings for the next measurement of the sensor
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct bsec_bme_settings_t {
+    pub next_call: i64,
+    pub process_data: u32,
+    pub heater_temperature: u16,
+    pub heater_duration: u16,
+    pub heater_temperature_profile: [u16; 10],
+    pub heater_duration_profile: [u16; 10],
+    pub heater_profile_len: u8,
+    pub run_gas: u8,
+    pub pressure_oversampling: u8,
+    pub temperature_oversampling: u8,
+    pub humidity_oversampling: u8,
+    pub trigger_measurement: u8,
+    pub op_mode: u8,
+}
+
+/// Version reported by the stand-in. It never matches a real library, so
+/// blobs saved with the stand-in are not restored into the real library.
+const MOCK_VERSION: bsec_version_t = bsec_version_t {
+    major: 0,
+    minor: 0,
+    major_bugfix: 0,
+    minor_bugfix: 0,
+};
+
+/// Interval between measurements at the ULP sample rate, in ns
+const ULP_INTERVAL_NS: i64 = 300_000_000_000;
+
+/// Sample rates that can be subscribed to, and the interval between measurements at each in ns
+const SAMPLE_INTERVALS: [(f64, i64); 4] = [
+    (BSEC_SAMPLE_RATE_CONT, 1_000_000_000),
+    (BSEC_SAMPLE_RATE_LP, 3_000_000_000),
+    (BSEC_SAMPLE_RATE_SCAN, 18_000_000_000),
+    (BSEC_SAMPLE_RATE_ULP, ULP_INTERVAL_NS),
+];
+
+/// Interval between calls in ns when nothing is subscribed
+const IDLE_INTERVAL_NS: i64 = 1_000_000_000;
+
+/// The outputs that can be subscribed to
+const OUTPUTS: [bsec_virtual_sensor_t; 18] = [
+    BSEC_OUTPUT_IAQ,
+    BSEC_OUTPUT_STATIC_IAQ,
+    BSEC_OUTPUT_CO2_EQUIVALENT,
+    BSEC_OUTPUT_BREATH_VOC_EQUIVALENT,
+    BSEC_OUTPUT_RAW_TEMPERATURE,
+    BSEC_OUTPUT_RAW_PRESSURE,
+    BSEC_OUTPUT_RAW_HUMIDITY,
+    BSEC_OUTPUT_RAW_GAS,
+    BSEC_OUTPUT_STABILIZATION_STATUS,
+    BSEC_OUTPUT_RUN_IN_STATUS,
+    BSEC_OUTPUT_SENSOR_HEAT_COMPENSATED_TEMPERATURE,
+    BSEC_OUTPUT_SENSOR_HEAT_COMPENSATED_HUMIDITY,
+    BSEC_OUTPUT_GAS_PERCENTAGE,
+    BSEC_OUTPUT_GAS_ESTIMATE_1,
+    BSEC_OUTPUT_GAS_ESTIMATE_2,
+    BSEC_OUTPUT_GAS_ESTIMATE_3,
+    BSEC_OUTPUT_GAS_ESTIMATE_4,
+    BSEC_OUTPUT_RAW_GAS_INDEX,
+];
+
+/// Number of slots for subscriptions, indexed by output ID
+const OUTPUT_SLOTS: usize = BSEC_OUTPUT_RAW_GAS_INDEX as usize + 1;
+
+/// The physical sensors required by any subscription
+const REQUIRED_INPUTS: [bsec_physical_sensor_t; 5] = [
+    BSEC_INPUT_PRESSURE,
+    BSEC_INPUT_HUMIDITY,
+    BSEC_INPUT_TEMPERATURE,
+    BSEC_INPUT_GASRESISTOR,
+    BSEC_INPUT_HEATSOURCE,
+];
+
+/// Time from the first gas measurement until the outputs are stable, in ns
+const STABILIZATION_NS: i64 = 5 * 60 * 1_000_000_000;
+
+/// Calibration time after which the IAQ accuracy is 2 and the sensor is run in, in ns
+const RUN_IN_NS: i64 = 30 * 60 * 1_000_000_000;
+
+/// Calibration time after which the IAQ accuracy is 3, in ns
+const CALIBRATED_NS: i64 = 4 * 60 * 60 * 1_000_000_000;
+
+/// Time for the gas resistance baseline to follow a lower resistance, in ns
+const BASELINE_WINDOW_NS: i64 = 4 * 24 * 60 * 60 * 1_000_000_000;
+
+/// Length of the serialized state
+const STATE_LEN: usize = 12;
+
+/// Limits on the input signals, outside of which the inputs are rejected
+const INPUT_LIMITS: [(bsec_physical_sensor_t, f32, f32); 4] = [
+    (BSEC_INPUT_PRESSURE, 30_000.0, 125_000.0),
+    (BSEC_INPUT_HUMIDITY, 0.0, 100.0),
+    (BSEC_INPUT_TEMPERATURE, -40.0, 85.0),
+    (BSEC_INPUT_GASRESISTOR, 0.0, f32::MAX),
+];
+
+/// State of the stand-in library, which is global like the real library
+static STATE: Mutex<MockState> = Mutex::new(MockState::new());
+
+/// Internal state of the stand-in library
+struct MockState {
+    /// Interval between measurements in ns for each subscribed output, by output ID
+    intervals: [Option<i64>; OUTPUT_SLOTS],
+
+    /// Whether an on-demand measurement was requested
+    on_demand: bool,
+
+    /// Time of the next measurement in ns, or `None` to measure at the next call
+    next_call_ns: Option<i64>,
+
+    /// Baseline gas resistance in ohms, or zero before the first gas measurement
+    baseline_ohm: f32,
+
+    /// Time spent calibrating in ns, kept in the state
+    calibration_ns: i64,
+
+    /// Time of the first gas measurement since the library was initialized
+    first_gas_ns: Option<i64>,
+
+    /// Time of the most recent gas measurement
+    last_gas_ns: Option<i64>,
+}
+
+/// Inputs to a single processing step
+#[derive(Default)]
+struct StepInputs {
+    /// Time stamp of the inputs in ns
+    time_stamp: i64,
+
+    /// Pressure in Pa
+    pressure: Option<f32>,
+
+    /// Relative humidity in %
+    humidity: Option<f32>,
+
+    /// Temperature in degrees C
+    temperature: Option<f32>,
+
+    /// Gas resistance in ohms
+    gas_resistance: Option<f32>,
+
+    /// Temperature offset caused by the heat sources of the device, in degrees C
+    heat_source: Option<f32>,
+
+    /// Index of the heater profile step of the gas measurement
+    profile_part: Option<f32>,
+}
+
+impl MockState {
+    /// Create the state of a freshly initialized library
+    ///
+    /// # Returns
+    /// The initial state
+    const fn new() -> Self {
+        Self {
+            intervals: [None; OUTPUT_SLOTS],
+            on_demand: false,
+            next_call_ns: None,
+            baseline_ohm: 0.0,
+            calibration_ns: 0,
+            first_gas_ns: None,
+            last_gas_ns: None,
+        }
+    }
+
+    /// Get the interval between measurements
+    ///
+    /// # Returns
+    /// The shortest interval of the subscribed outputs in ns, or `None` if nothing is subscribed
+    fn interval(&self) -> Option<i64> {
+        self.intervals.iter().flatten().min().copied()
+    }
+
+    /// Check if the outputs have stabilized
+    ///
+    /// # Arguments
+    /// * `time_stamp`: The current time in ns
+    ///
+    /// # Returns
+    /// Whether the outputs have stabilized
+    fn stabilized(&self, time_stamp: i64) -> bool {
+        self.first_gas_ns
+            .is_some_and(|first| time_stamp - first >= STABILIZATION_NS)
+    }
+
+    /// Get the accuracy of the IAQ
+    ///
+    /// # Arguments
+    /// * `time_stamp`: The current time in ns
+    ///
+    /// # Returns
+    /// The accuracy of the IAQ, from 0 (stabilizing) to 3 (calibrated)
+    fn accuracy(&self, time_stamp: i64) -> u8 {
+        if !self.stabilized(time_stamp) {
+            0
+        } else if self.calibration_ns < RUN_IN_NS {
+            1
+        } else if self.calibration_ns < CALIBRATED_NS {
+            2
+        } else {
+            3
+        }
+    }
+
+    /// Update the gas resistance baseline and calibration time with a gas measurement
+    ///
+    /// # Arguments
+    /// * `time_stamp`: Time of the measurement in ns
+    /// * `gas_resistance`: The gas resistance in ohms
+    #[allow(clippy::cast_precision_loss)]
+    fn update_baseline(&mut self, time_stamp: i64, gas_resistance: f32) {
+        let elapsed = self
+            .last_gas_ns
+            .map_or(0, |last| (time_stamp - last).max(0));
+        self.first_gas_ns.get_or_insert(time_stamp);
+        self.last_gas_ns = Some(time_stamp);
+
+        if self.stabilized(time_stamp) {
+            self.calibration_ns = self.calibration_ns.saturating_add(elapsed);
+        }
+
+        if gas_resistance > self.baseline_ohm {
+            self.baseline_ohm = gas_resistance;
+        } else {
+            let weight = (elapsed as f32 / BASELINE_WINDOW_NS as f32).min(1.0);
+            self.baseline_ohm += (gas_resistance - self.baseline_ohm) * weight;
+        }
+    }
+
+    /// Compute the subscribed outputs for a processing step
+    ///
+    /// # Arguments
+    /// * `inputs`: The inputs of the step
+    ///
+    /// # Returns
+    /// The outputs of the step
+    fn step(&mut self, inputs: &StepInputs) -> Vec<bsec_output_t> {
+        let time_stamp = inputs.time_stamp;
+        let mut signals: Vec<(bsec_virtual_sensor_t, f32, u8)> = Vec::new();
+
+        if let Some(pressure) = inputs.pressure {
+            signals.push((BSEC_OUTPUT_RAW_PRESSURE, pressure, 0));
+        }
+        if let Some(humidity) = inputs.humidity {
+            signals.push((BSEC_OUTPUT_RAW_HUMIDITY, humidity, 0));
+        }
+        if let Some(temperature) = inputs.temperature {
+            let compensated = temperature - inputs.heat_source.unwrap_or(0.0);
+            signals.push((BSEC_OUTPUT_RAW_TEMPERATURE, temperature, 0));
+            signals.push((
+                BSEC_OUTPUT_SENSOR_HEAT_COMPENSATED_TEMPERATURE,
+                compensated,
+                0,
+            ));
+            if let Some(humidity) = inputs.humidity {
+                // The same amount of water is a higher relative humidity in cooler air
+                let compensated_humidity = humidity * saturation_vapor_pressure(temperature)
+                    / saturation_vapor_pressure(compensated);
+                signals.push((
+                    BSEC_OUTPUT_SENSOR_HEAT_COMPENSATED_HUMIDITY,
+                    compensated_humidity.clamp(0.0, 100.0),
+                    0,
+                ));
+            }
+        }
+
+        if let Some(gas_resistance) = inputs.gas_resistance {
+            self.update_baseline(time_stamp, gas_resistance);
+            let accuracy = self.accuracy(time_stamp);
+            let stabilized = f32::from(u8::from(self.stabilized(time_stamp)));
+            let run_in = f32::from(u8::from(self.calibration_ns >= RUN_IN_NS));
+
+            // Cleaner air has a higher gas resistance, so the IAQ rises as the
+            // resistance drops below the baseline. An IAQ of 25 is clean air.
+            let ratio = (gas_resistance / self.baseline_ohm).clamp(0.0, 1.0);
+            let iaq = (25.0 + (1.0 - ratio) * 500.0).min(500.0);
+            let co2_eq = 300.0 + iaq * 4.0;
+            let breath_voc_eq = 0.5 * ((iaq - 50.0) / 50.0).exp2();
+
+            signals.extend([
+                (BSEC_OUTPUT_RAW_GAS, gas_resistance, 0),
+                (BSEC_OUTPUT_IAQ, iaq, accuracy),
+                (BSEC_OUTPUT_STATIC_IAQ, iaq, accuracy),
+                (BSEC_OUTPUT_CO2_EQUIVALENT, co2_eq, accuracy),
+                (BSEC_OUTPUT_BREATH_VOC_EQUIVALENT, breath_voc_eq, accuracy),
+                (BSEC_OUTPUT_GAS_PERCENTAGE, ratio * 100.0, accuracy),
+                (BSEC_OUTPUT_STABILIZATION_STATUS, stabilized, 0),
+                (BSEC_OUTPUT_RUN_IN_STATUS, run_in, 0),
+                (
+                    BSEC_OUTPUT_RAW_GAS_INDEX,
+                    inputs.profile_part.unwrap_or(0.0),
+                    0,
+                ),
+            ]);
+        }
+
+        signals
+            .into_iter()
+            .filter_map(|(sensor_id, signal, accuracy)| {
+                let sensor_id = u8::try_from(sensor_id).ok()?;
+                self.intervals[usize::from(sensor_id)].map(|_| bsec_output_t {
+                    time_stamp,
+                    signal,
+                    signal_dimensions: 1,
+                    sensor_id,
+                    accuracy,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Lock the state of the stand-in library
+///
+/// # Returns
+/// The locked state
+fn state() -> std::sync::MutexGuard<'static, MockState> {
+    // The state is always left consistent, so a panic elsewhere does not poison it
+    STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Compute the saturation vapor pressure of water (Magnus formula)
+///
+/// # Arguments
+/// * `temperature`: The temperature in degrees C
+///
+/// # Returns
+/// The saturation vapor pressure in hPa
+fn saturation_vapor_pressure(temperature: f32) -> f32 {
+    6.112 * (17.62 * temperature / (243.12 + temperature)).exp()
+}
+
+/// Find the interval between measurements for a sample rate
+///
+/// # Arguments
+/// * `sample_rate`: The sample rate in Hz
+///
+/// # Returns
+/// The interval in ns, or `None` if the sample rate is not supported
+fn sample_interval(sample_rate: f32) -> Option<i64> {
+    SAMPLE_INTERVALS
+        .iter()
+        .find(|(rate, _)| same_rate(sample_rate, *rate))
+        .map(|(_, interval)| *interval)
+}
+
+/// Check if a requested sample rate is one of the library's sample rates
+///
+/// # Arguments
+/// * `requested`: The requested sample rate in Hz
+/// * `rate`: One of the library's sample rates in Hz
+///
+/// # Returns
+/// Whether the rates match, allowing for rounding
+fn same_rate(requested: f32, rate: f64) -> bool {
+    (f64::from(requested) - rate).abs() <= rate * 1e-4
+}
+
+/// Initialize the library, clearing any subscriptions, state and configuration
+///
+/// # Returns
+/// `BSEC_OK`
+///
+/// # Safety
+/// Always safe, this is `unsafe` to match the real library.
+pub unsafe fn bsec_init() -> bsec_library_return_t {
+    *state() = MockState::new();
+    BSEC_OK
+}
+
+/// Read the version of the library
+///
+/// # Arguments
+/// * `bsec_version_p`: Where to write the version
+///
+/// # Returns
+/// `BSEC_OK`
+///
+/// # Safety
+/// `bsec_version_p` must be valid for writes.
+pub unsafe fn bsec_get_version(bsec_version_p: *mut bsec_version_t) -> bsec_library_return_t {
+    *bsec_version_p = MOCK_VERSION;
+    BSEC_OK
+}
+
+/// Subscribe to virtual sensors
+///
+/// # Arguments
+/// * `requested_virtual_sensors`: The virtual sensors and their sample rates
+/// * `n_requested_virtual_sensors`: The number of requested virtual sensors
+/// * `required_sensor_settings`: Where to write the required physical sensors
+/// * `n_required_sensor_settings`: Capacity of `required_sensor_settings`, updated to the number written
+///
+/// # Returns
+/// `BSEC_OK`, a warning for unknown outputs or an on-demand request outside of ULP mode, or an error
+///
+/// # Safety
+/// The pointers must be valid for the given number of elements.
+pub unsafe fn bsec_update_subscription(
+    requested_virtual_sensors: *const bsec_sensor_configuration_t,
+    n_requested_virtual_sensors: u8,
+    required_sensor_settings: *mut bsec_sensor_configuration_t,
+    n_required_sensor_settings: *mut u8,
+) -> bsec_library_return_t {
+    let requested = std::slice::from_raw_parts(
+        requested_virtual_sensors,
+        usize::from(n_requested_virtual_sensors),
+    );
+    let mut state = state();
+    let mut intervals = state.intervals;
+    let mut on_demand = false;
+    let mut result = BSEC_OK;
+
+    for request in requested {
+        let sensor_id = u32::from(request.sensor_id);
+        if !OUTPUTS.contains(&sensor_id) {
+            result = BSEC_W_SU_UNKNOWNOUTPUTGATE;
+            continue;
+        }
+
+        if same_rate(request.sample_rate, BSEC_SAMPLE_RATE_DISABLED) {
+            intervals[usize::from(request.sensor_id)] = None;
+        } else if same_rate(
+            request.sample_rate,
+            BSEC_SAMPLE_RATE_ULP_MEASUREMENT_ON_DEMAND,
+        ) {
+            on_demand = true;
+        } else if let Some(interval) = sample_interval(request.sample_rate) {
+            intervals[usize::from(request.sensor_id)] = Some(interval);
+        } else {
+            return BSEC_E_SU_WRONGDATARATE;
+        }
+    }
+
+    // Only forced mode is supported, so every output is measured together
+    let mut subscribed = intervals.iter().flatten();
+    if let Some(first) = subscribed.next() {
+        if subscribed.any(|interval| interval != first) {
+            return BSEC_E_SU_MULTGASSAMPLINTVL;
+        }
+    }
+
+    let required: &[bsec_physical_sensor_t] = if intervals.iter().flatten().next().is_some() {
+        &REQUIRED_INPUTS
+    } else {
+        &[]
+    };
+    if required.len() > usize::from(*n_required_sensor_settings) {
+        return BSEC_E_SU_GATECOUNTEXCEEDSARRAY;
+    }
+
+    state.intervals = intervals;
+    if on_demand {
+        if state.interval() == Some(ULP_INTERVAL_NS) {
+            state.on_demand = true;
+        } else {
+            result = BSEC_W_SU_MODINNOULP;
+        }
+    }
+
+    let sample_rate = state.interval().map_or(0.0, |interval| {
+        SAMPLE_INTERVALS
+            .iter()
+            .find(|(_, rate_interval)| *rate_interval == interval)
+            .map_or(0.0, |(rate, _)| *rate)
+    });
+    let settings = std::slice::from_raw_parts_mut(required_sensor_settings, required.len());
+    for (setting, sensor_id) in settings.iter_mut().zip(required) {
+        // The sample rates and input IDs fit in the narrower types
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            *setting = bsec_sensor_configuration_t {
+                sample_rate: sample_rate as f32,
+                sensor_id: *sensor_id as u8,
+            };
+        }
+    }
+    // At most 5 inputs are required
+    #[allow(clippy::cast_possible_truncation)]
+    {
+        *n_required_sensor_settings = required.len() as u8;
+    }
+
+    result
+}
+
+/// Get the settings for the next measurement
+///
+/// # Arguments
+/// * `time_stamp`: The current time in ns
+/// * `sensor_settings`: Where to write the settings
+///
+/// # Returns
+/// `BSEC_OK`, or `BSEC_W_SC_CALL_TIMING_VIOLATION` if called before the next measurement is due
+///
+/// # Safety
+/// `sensor_settings` must be valid for writes.
+pub unsafe fn bsec_sensor_control(
+    time_stamp: i64,
+    sensor_settings: *mut bsec_bme_settings_t,
+) -> bsec_library_return_t {
+    let mut state = state();
+    let settings = &mut *sensor_settings;
+    *settings = bsec_bme_settings_t::new();
+
+    let Some(interval) = state.interval() else {
+        settings.next_call = time_stamp + IDLE_INTERVAL_NS;
+        return BSEC_OK;
+    };
+
+    // Allow for some jitter in the caller's timing
+    if let Some(next_call) = state.next_call_ns {
+        if !state.on_demand && time_stamp < next_call - interval / 16 {
+            settings.next_call = next_call;
+            return BSEC_W_SC_CALL_TIMING_VIOLATION;
+        }
+    }
+
+    // Stay on the same schedule, unless the caller fell a whole interval behind
+    let next_call = state
+        .next_call_ns
+        .map_or(time_stamp + interval, |next_call| next_call + interval);
+    let next_call = if next_call <= time_stamp {
+        time_stamp + interval
+    } else {
+        next_call
+    };
+    state.next_call_ns = Some(next_call);
+    state.on_demand = false;
+
+    settings.next_call = next_call;
+    settings.process_data = REQUIRED_INPUTS
+        .iter()
+        .fold(0, |bits, input| bits | (1 << (input - 1)));
+    settings.heater_temperature = 320;
+    settings.heater_duration = 197;
+    settings.run_gas = 1;
+    // Oversampling of 1x for humidity, 2x for temperature and 16x for pressure
+    settings.humidity_oversampling = 1;
+    settings.temperature_oversampling = 2;
+    settings.pressure_oversampling = 5;
+    settings.trigger_measurement = 1;
+    // Forced mode
+    settings.op_mode = 1;
+
+    BSEC_OK
+}
+
+/// Process the measurements of the physical sensors
+///
+/// # Arguments
+/// * `inputs`: The measurements, all with the same time stamp
+/// * `n_inputs`: The number of measurements
+/// * `outputs`: Where to write the outputs of the subscribed virtual sensors
+/// * `n_outputs`: Capacity of `outputs`, updated to the number written
+///
+/// # Returns
+/// `BSEC_OK`, `BSEC_W_DOSTEPS_EXCESSOUTPUTS` if `outputs` was too small, or an error
+///
+/// # Safety
+/// The pointers must be valid for the given number of elements.
+pub unsafe fn bsec_do_steps(
+    inputs: *const bsec_input_t,
+    n_inputs: u8,
+    outputs: *mut bsec_output_t,
+    n_outputs: *mut u8,
+) -> bsec_library_return_t {
+    let inputs = std::slice::from_raw_parts(inputs, usize::from(n_inputs));
+    let Some(first) = inputs.first() else {
+        return BSEC_E_DOSTEPS_INVALIDINPUT;
+    };
+
+    let mut step_inputs = StepInputs {
+        time_stamp: first.time_stamp,
+        ..StepInputs::default()
+    };
+    for input in inputs {
+        let sensor_id = u32::from(input.sensor_id);
+        if INPUT_LIMITS
+            .iter()
+            .any(|(id, min, max)| *id == sensor_id && !(*min..=*max).contains(&input.signal))
+        {
+            return BSEC_E_DOSTEPS_VALUELIMITS;
+        }
+
+        let slot = match sensor_id {
+            BSEC_INPUT_PRESSURE => &mut step_inputs.pressure,
+            BSEC_INPUT_HUMIDITY => &mut step_inputs.humidity,
+            BSEC_INPUT_TEMPERATURE => &mut step_inputs.temperature,
+            BSEC_INPUT_GASRESISTOR => &mut step_inputs.gas_resistance,
+            BSEC_INPUT_HEATSOURCE => &mut step_inputs.heat_source,
+            BSEC_INPUT_PROFILE_PART => &mut step_inputs.profile_part,
+            _ => return BSEC_E_DOSTEPS_INVALIDINPUT,
+        };
+        if slot.replace(input.signal).is_some() {
+            return BSEC_E_DOSTEPS_DUPLICATEINPUT;
+        }
+    }
+
+    let results = state().step(&step_inputs);
+    if results.is_empty() {
+        *n_outputs = 0;
+        return BSEC_I_DOSTEPS_NOOUTPUTSRETURNABLE;
+    }
+
+    let capacity = usize::from(*n_outputs);
+    let written = results.len().min(capacity);
+    std::slice::from_raw_parts_mut(outputs, written).copy_from_slice(&results[..written]);
+    // No more than the capacity, a u8, is written
+    #[allow(clippy::cast_possible_truncation)]
+    {
+        *n_outputs = written as u8;
+    }
+
+    if results.len() > capacity {
+        BSEC_W_DOSTEPS_EXCESSOUTPUTS
+    } else {
+        BSEC_OK
+    }
+}
+
+/// Load a configuration into the library
+///
+/// The configuration is not interpreted, so any non-empty configuration is accepted.
+///
+/// # Arguments
+/// * `serialized_settings`: The configuration
+/// * `n_serialized_settings`: The length of the configuration
+/// * `work_buffer`: Unused
+/// * `n_work_buffer_size`: Unused
+///
+/// # Returns
+/// `BSEC_OK`, or `BSEC_E_CONFIG_EMPTY` if the configuration is empty
+///
+/// # Safety
+/// Always safe, this is `unsafe` to match the real library.
+pub unsafe fn bsec_set_configuration(
+    _serialized_settings: *const u8,
+    n_serialized_settings: u32,
+    _work_buffer: *mut u8,
+    _n_work_buffer_size: u32,
+) -> bsec_library_return_t {
+    if n_serialized_settings == 0 {
+        BSEC_E_CONFIG_EMPTY
+    } else {
+        BSEC_OK
+    }
+}
+
+/// Restore the calibration of the library from a saved state
+///
+/// # Arguments
+/// * `serialized_state`: The saved state
+/// * `n_serialized_state`: The length of the saved state
+/// * `work_buffer`: Unused
+/// * `n_work_buffer_size`: Unused
+///
+/// # Returns
+/// `BSEC_OK`, or `BSEC_E_CONFIG_INVALIDSTRINGSIZE` if the state has the wrong length
+///
+/// # Safety
+/// `serialized_state` must be valid for `n_serialized_state` bytes.
+pub unsafe fn bsec_set_state(
+    serialized_state: *const u8,
+    n_serialized_state: u32,
+    _work_buffer: *mut u8,
+    _n_work_buffer_size: u32,
+) -> bsec_library_return_t {
+    if usize::try_from(n_serialized_state) != Ok(STATE_LEN) {
+        return BSEC_E_CONFIG_INVALIDSTRINGSIZE;
+    }
+    let data = std::slice::from_raw_parts(serialized_state, STATE_LEN);
+
+    let mut baseline = [0; 4];
+    baseline.copy_from_slice(&data[..4]);
+    let mut calibration = [0; 8];
+    calibration.copy_from_slice(&data[4..]);
+
+    let mut state = state();
+    state.baseline_ohm = f32::from_le_bytes(baseline);
+    state.calibration_ns = i64::from_le_bytes(calibration);
+    BSEC_OK
+}
+
+/// Save the calibration of the library
+///
+/// # Arguments
+/// * `state_set_id`: Unused, the whole state is always saved
+/// * `serialized_state`: Where to write the state
+/// * `n_serialized_state_max`: Capacity of `serialized_state`
+/// * `work_buffer`: Unused
+/// * `n_work_buffer`: Unused
+/// * `n_serialized_state`: Where to write the length of the state
+///
+/// # Returns
+/// `BSEC_OK`, or `BSEC_E_CONFIG_INSUFFICIENTBUFFER` if `serialized_state` is too small
+///
+/// # Safety
+/// `serialized_state` must be valid for `n_serialized_state_max` bytes, and
+/// `n_serialized_state` must be valid for writes.
+pub unsafe fn bsec_get_state(
+    _state_set_id: u8,
+    serialized_state: *mut u8,
+    n_serialized_state_max: u32,
+    _work_buffer: *mut u8,
+    _n_work_buffer: u32,
+    n_serialized_state: *mut u32,
+) -> bsec_library_return_t {
+    if usize::try_from(n_serialized_state_max).is_ok_and(|max| max < STATE_LEN) {
+        return BSEC_E_CONFIG_INSUFFICIENTBUFFER;
+    }
+
+    let state = state();
+    let data = std::slice::from_raw_parts_mut(serialized_state, STATE_LEN);
+    data[..4].copy_from_slice(&state.baseline_ohm.to_le_bytes());
+    data[4..].copy_from_slice(&state.calibration_ns.to_le_bytes());
+    // The state is only a few bytes
+    #[allow(clippy::cast_possible_truncation)]
+    {
+        *n_serialized_state = STATE_LEN as u32;
+    }
+    BSEC_OK
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::PoisonError;
+
+    use super::super::LIBRARY_LOCK;
+    use super::*;
+
+    /// Low power sample interval
+    const LP_INTERVAL_NS: i64 = 3_000_000_000;
+
+    /// Subscribe to outputs
+    ///
+    /// # Arguments
+    /// * `requests`: The outputs and their sample rates
+    ///
+    /// # Returns
+    /// The return code, and the number of required physical sensors
+    fn subscribe(requests: &[(bsec_virtual_sensor_t, f64)]) -> (bsec_library_return_t, u8) {
+        #[allow(clippy::cast_possible_truncation)]
+        let requests: Vec<bsec_sensor_configuration_t> = requests
+            .iter()
+            .map(|(sensor_id, sample_rate)| bsec_sensor_configuration_t {
+                sample_rate: *sample_rate as f32,
+                sensor_id: *sensor_id as u8,
+            })
+            .collect();
+        let mut required = [bsec_sensor_configuration_t::new(); BSEC_MAX_PHYSICAL_SENSOR as usize];
+        let mut n_required = required.len().try_into().unwrap();
+        let result = unsafe {
+            bsec_update_subscription(
+                requests.as_ptr(),
+                requests.len().try_into().unwrap(),
+                required.as_mut_ptr(),
+                &raw mut n_required,
+            )
+        };
+        (result, n_required)
+    }
+
+    /// Get the sensor settings at a time
+    ///
+    /// # Arguments
+    /// * `time_stamp`: The time in ns
+    ///
+    /// # Returns
+    /// The return code, and the sensor settings
+    fn sensor_control(time_stamp: i64) -> (bsec_library_return_t, bsec_bme_settings_t) {
+        let mut settings = bsec_bme_settings_t::new();
+        let result = unsafe { bsec_sensor_control(time_stamp, &raw mut settings) };
+        (result, settings)
+    }
+
+    /// Process a measurement of clean air
+    ///
+    /// # Arguments
+    /// * `time_stamp`: The time of the measurement in ns
+    ///
+    /// # Returns
+    /// The IAQ output
+    fn process(time_stamp: i64) -> bsec_output_t {
+        let inputs = [
+            (BSEC_INPUT_PRESSURE, 101_325.0),
+            (BSEC_INPUT_HUMIDITY, 45.0),
+            (BSEC_INPUT_TEMPERATURE, 22.5),
+            (BSEC_INPUT_GASRESISTOR, 150_000.0),
+        ]
+        .map(|(sensor_id, signal)| bsec_input_t {
+            time_stamp,
+            signal,
+            signal_dimensions: 0,
+            sensor_id: sensor_id.try_into().unwrap(),
+        });
+        let mut outputs = [bsec_output_t::new(); BSEC_NUMBER_OUTPUTS as usize];
+        let mut n_outputs = outputs.len().try_into().unwrap();
+        let result = unsafe {
+            bsec_do_steps(
+                inputs.as_ptr(),
+                inputs.len().try_into().unwrap(),
+                outputs.as_mut_ptr(),
+                &raw mut n_outputs,
+            )
+        };
+        assert_eq!(result, BSEC_OK);
+        *outputs[..usize::from(n_outputs)]
+            .iter()
+            .find(|output| u32::from(output.sensor_id) == BSEC_OUTPUT_IAQ)
+            .unwrap()
+    }
+
+    /// Test measurements are scheduled at the subscribed sample rate.
+    #[test]
+    fn test_schedule() {
+        let _lock = LIBRARY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        unsafe { bsec_init() };
+
+        let (result, n_required) = subscribe(&[
+            (BSEC_OUTPUT_IAQ, BSEC_SAMPLE_RATE_LP),
+            (BSEC_OUTPUT_RAW_TEMPERATURE, BSEC_SAMPLE_RATE_LP),
+        ]);
+        assert_eq!(result, BSEC_OK);
+        assert_eq!(n_required, 5);
+
+        let (result, settings) = sensor_control(0);
+        assert_eq!(result, BSEC_OK);
+        assert_eq!(settings.trigger_measurement, 1);
+        assert_eq!(settings.op_mode, 1);
+        assert_eq!(settings.next_call, LP_INTERVAL_NS);
+
+        // Too early
+        let (result, settings) = sensor_control(LP_INTERVAL_NS / 2);
+        assert_eq!(result, BSEC_W_SC_CALL_TIMING_VIOLATION);
+        assert_eq!(settings.trigger_measurement, 0);
+        assert_eq!(settings.next_call, LP_INTERVAL_NS);
+
+        // Slightly late calls stay on the schedule
+        let (result, settings) = sensor_control(LP_INTERVAL_NS + 1_000_000);
+        assert_eq!(result, BSEC_OK);
+        assert_eq!(settings.trigger_measurement, 1);
+        assert_eq!(settings.next_call, 2 * LP_INTERVAL_NS);
+    }
+
+    /// Test subscriptions to unknown outputs and mixed sample rates.
+    #[test]
+    fn test_subscription_errors() {
+        let _lock = LIBRARY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        unsafe { bsec_init() };
+
+        assert_eq!(
+            subscribe(&[(5, BSEC_SAMPLE_RATE_LP)]).0,
+            BSEC_W_SU_UNKNOWNOUTPUTGATE
+        );
+        assert_eq!(
+            subscribe(&[(BSEC_OUTPUT_IAQ, 0.5)]).0,
+            BSEC_E_SU_WRONGDATARATE
+        );
+        assert_eq!(
+            subscribe(&[
+                (BSEC_OUTPUT_IAQ, BSEC_SAMPLE_RATE_LP),
+                (BSEC_OUTPUT_RAW_GAS, BSEC_SAMPLE_RATE_ULP),
+            ])
+            .0,
+            BSEC_E_SU_MULTGASSAMPLINTVL
+        );
+
+        // Disabling every output leaves nothing to measure
+        assert_eq!(
+            subscribe(&[(BSEC_OUTPUT_IAQ, BSEC_SAMPLE_RATE_LP)]),
+            (BSEC_OK, 5)
+        );
+        assert_eq!(
+            subscribe(&[(BSEC_OUTPUT_IAQ, BSEC_SAMPLE_RATE_DISABLED)]),
+            (BSEC_OK, 0)
+        );
+        assert_eq!(sensor_control(0).1.trigger_measurement, 0);
+    }
+
+    /// Test the calibration is kept in the state.
+    #[test]
+    fn test_state_round_trip() {
+        let _lock = LIBRARY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        unsafe { bsec_init() };
+        assert_eq!(
+            subscribe(&[(BSEC_OUTPUT_IAQ, BSEC_SAMPLE_RATE_LP)]).0,
+            BSEC_OK
+        );
+
+        // Stabilizing, then calibrating
+        assert_eq!(process(0).accuracy, 0);
+        assert_eq!(process(STABILIZATION_NS).accuracy, 1);
+        let output = process(STABILIZATION_NS + CALIBRATED_NS);
+        assert_eq!(output.accuracy, 3);
+        assert!((output.signal - 25.0).abs() < f32::EPSILON);
+
+        let mut state = [0; BSEC_MAX_STATE_BLOB_SIZE as usize];
+        let mut work_buffer = [0; BSEC_MAX_WORKBUFFER_SIZE as usize];
+        let mut state_len = 0;
+        let result = unsafe {
+            bsec_get_state(
+                0,
+                state.as_mut_ptr(),
+                BSEC_MAX_STATE_BLOB_SIZE,
+                work_buffer.as_mut_ptr(),
+                BSEC_MAX_WORKBUFFER_SIZE,
+                &raw mut state_len,
+            )
+        };
+        assert_eq!(result, BSEC_OK);
+
+        // The restored library is calibrated as soon as it has stabilized
+        unsafe { bsec_init() };
+        assert_eq!(
+            subscribe(&[(BSEC_OUTPUT_IAQ, BSEC_SAMPLE_RATE_LP)]).0,
+            BSEC_OK
+        );
+        let result = unsafe {
+            bsec_set_state(
+                state.as_ptr(),
+                state_len,
+                work_buffer.as_mut_ptr(),
+                BSEC_MAX_WORKBUFFER_SIZE,
+            )
+        };
+        assert_eq!(result, BSEC_OK);
+        assert_eq!(process(0).accuracy, 0);
+        assert_eq!(process(STABILIZATION_NS).accuracy, 3);
+
+        let result = unsafe {
+            bsec_set_state(
+                state.as_ptr(),
+                state_len - 1,
+                work_buffer.as_mut_ptr(),
+                BSEC_MAX_WORKBUFFER_SIZE,
+            )
+        };
+        assert_eq!(result, BSEC_E_CONFIG_INVALIDSTRINGSIZE);
+    }
+}
@@ -1,11 +1,20 @@
 //! Main BSEC logic
 // pub mod bindings;
+mod blob;
 #[allow(clippy::module_name_repetitions)]
 mod bsec_bindings;
+pub mod config;
+#[cfg(feature = "mock-bsec")]
+mod mock;
+pub mod replay;
+pub mod save_policy;
+pub mod scheduler;
+pub mod state_store;
 
 use std::fs;
 use std::num::TryFromIntError;
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use self::bsec_bindings::{
     bsec_bme_settings_t, bsec_do_steps, bsec_get_state, bsec_get_version, bsec_init, bsec_input_t,
@@ -37,14 +46,23 @@ use self::bsec_bindings::{
     BSEC_W_SC_MODINSUFFICIENTWAITTIME, BSEC_W_SU_MODINNOULP, BSEC_W_SU_UNKNOWNOUTPUTGATE,
 };
 
+use embedded_hal::delay::DelayNs;
 use embedded_hal::i2c::I2c;
-use esp_idf_hal::delay::FreeRtos;
+#[cfg(target_os = "espidf")]
+use esp_idf_sys::{esp_err_t, EspError};
 
-use bme68x::{BME68xAddr, BME68xData, BME68xDev, BME68xError, BME68xIntf, BME68xOpMode, BME68xOs};
+use self::blob::{BlobError, BlobMetadata};
+use self::config::{ActiveConfig, ConfigSelection};
+use self::state_store::{FileStateStore, StateStore};
+
+use bme68x::{
+    BME68xAddr, BME68xData, BME68xDev, BME68xError, BME68xHeatrConf, BME68xOpMode, BME68xOs,
+    I2cInterface,
+};
 
 /// Enumeration of valid sample rates for the sensor
 #[allow(clippy::module_name_repetitions)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SampleRate {
     /// Disable Sampling
     Disabled,
@@ -86,33 +104,19 @@ impl SampleRate {
 
 /// Rust-Native wrapper for the BSEC Error codes.
 /// Has a few additional error codes beyond what the BSEC library provides.
+/// Warnings and informational codes from the library are a `BsecWarning` instead.
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, Clone, Copy)]
 pub enum BsecError {
-    /// Success
-    Ok,
-
     /// Invalid input to `bsec_do_steps`
     DoStepsInvalidInput,
 
     /// Value passed to `bsec_do_steps` is out of range
     DoStepsValueLimits,
 
-    /// Timestamp passed to `bsec_do_steps` is smaller than the previous
-    DoStepsTsIntRadifOutOfRange,
-
     /// Same Input provided more than once
     DoStepsDuplicateInput,
 
-    /// No memory allocated for returning outputs
-    DoStepsNoOutputsReturnable,
-
-    /// Not enough memory to hold return values
-    DoStepsExcessOutputs,
-
-    /// Gas index not provided
-    DoStepsGasIndexMiss,
-
     /// Data rate of requested output is 0
     WrongDataRate,
 
@@ -137,18 +141,6 @@ pub enum BsecError {
     /// Measurement duration longer than requested sample interval
     HighHeaterDuration,
 
-    /// Output sensor ID not in the valid range.
-    UnknownOutputGate,
-
-    /// ULP+ cannot be requrested in non-ulp mode
-    ModInNoULP,
-
-    /// No virtual sensor outputs were requested.
-    SubscribedOutputGates,
-
-    /// Gas Estimate is subscribed and takes precendence.
-    GasEstimatePrecedence,
-
     /// Work buffer size not sufficent
     SectionExceedsWorkBuffer,
 
@@ -182,16 +174,7 @@ pub enum BsecError {
     /// Internal error code
     SetInvalidLength,
 
-    /// Difference between actual and defined sampling rate too large
-    CallTimingViolation,
-
-    /// ULP+ not allowed becuase ULP measurement just/about to occur
-    ModExceedULPTimeLimit,
-
-    /// ULP+ Not allowed becuase not enough time since last ULP+
-    ModInsufficentWaitTime,
-
-    /// Error with the back-end BME68x Driver
+    /// Error with the back-end `BME68x` Driver
     DriverError {
         /// Back End driver error
         error: BME68xError,
@@ -206,6 +189,22 @@ pub enum BsecError {
     /// Error converting between numeric typoes
     NumericConversionErrror,
 
+    /// Error from an ESP-IDF service, such as NVS or MQTT
+    #[cfg(target_os = "espidf")]
+    EspIdfError {
+        /// The ESP-IDF error code
+        code: esp_err_t,
+    },
+
+    /// Timed out waiting for the state store
+    StateStoreTimeout,
+
+    /// Blob stored on the filesystem is corrupt or for a different BSEC version
+    InvalidStoredBlob {
+        /// Reason the blob cannot be used
+        error: BlobError,
+    },
+
     /// Unknown error code
     UnknownError {
         /// The unknown error code
@@ -217,14 +216,9 @@ impl From<bsec_library_return_t> for BsecError {
     #![allow(non_upper_case_globals)]
     fn from(value: bsec_library_return_t) -> Self {
         match value {
-            BSEC_OK => Self::Ok,
             BSEC_E_DOSTEPS_INVALIDINPUT => Self::DoStepsInvalidInput,
             BSEC_E_DOSTEPS_VALUELIMITS => Self::DoStepsValueLimits,
-            BSEC_W_DOSTEPS_TSINTRADIFFOUTOFRANGE => Self::DoStepsTsIntRadifOutOfRange,
             BSEC_E_DOSTEPS_DUPLICATEINPUT => Self::DoStepsDuplicateInput,
-            BSEC_I_DOSTEPS_NOOUTPUTSRETURNABLE => Self::DoStepsNoOutputsReturnable,
-            BSEC_W_DOSTEPS_EXCESSOUTPUTS => Self::DoStepsExcessOutputs,
-            BSEC_W_DOSTEPS_GASINDEXMISS => Self::DoStepsGasIndexMiss,
             BSEC_E_SU_WRONGDATARATE => Self::WrongDataRate,
             BSEC_E_SU_SAMPLERATELIMITS => Self::SampleRateLimits,
             BSEC_E_SU_DUPLICATEGATE => Self::DuplicateGate,
@@ -233,10 +227,6 @@ impl From<bsec_library_return_t> for BsecError {
             BSEC_E_SU_SAMPLINTVLINTEGERMULT => Self::SampleIntervalIntegerMult,
             BSEC_E_SU_MULTGASSAMPLINTVL => Self::MultGasSampleInterval,
             BSEC_E_SU_HIGHHEATERONDURATION => Self::HighHeaterDuration,
-            BSEC_W_SU_UNKNOWNOUTPUTGATE => Self::UnknownOutputGate,
-            BSEC_W_SU_MODINNOULP => Self::ModInNoULP,
-            BSEC_I_SU_SUBSCRIBEDOUTPUTGATES => Self::SubscribedOutputGates,
-            BSEC_I_SU_GASESTIMATEPRECEDENCE => Self::GasEstimatePrecedence,
             BSEC_E_PARSE_SECTIONEXCEEDSWORKBUFFER => Self::SectionExceedsWorkBuffer,
             BSEC_E_CONFIG_FAIL => Self::ConfigFail,
             BSEC_E_CONFIG_VERSIONMISMATCH => Self::ConfigVersionMisMatch,
@@ -248,10 +238,132 @@ impl From<bsec_library_return_t> for BsecError {
             BSEC_E_CONFIG_INSUFFICIENTBUFFER => Self::ConfigInsufficentBuffer,
             BSEC_E_SET_INVALIDCHANNELIDENTIFIER => Self::SetInvalidChannelIdentifier,
             BSEC_E_SET_INVALIDLENGTH => Self::SetInvalidLength,
+            _ => Self::UnknownError { code: value },
+        }
+    }
+}
+
+/// Rust-Native wrapper for the BSEC warning and informational codes.
+///
+/// These do not stop the library from working, so they are logged and counted
+/// instead of failing the call.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BsecWarning {
+    /// Timestamp passed to `bsec_do_steps` is smaller than the previous
+    DoStepsTsIntRadifOutOfRange,
+
+    /// No memory allocated for returning outputs
+    DoStepsNoOutputsReturnable,
+
+    /// Not enough memory to hold return values
+    DoStepsExcessOutputs,
+
+    /// Gas index not provided
+    DoStepsGasIndexMiss,
+
+    /// Output sensor ID not in the valid range.
+    UnknownOutputGate,
+
+    /// ULP+ cannot be requrested in non-ulp mode
+    ModInNoULP,
+
+    /// No virtual sensor outputs were requested.
+    SubscribedOutputGates,
+
+    /// Gas Estimate is subscribed and takes precendence.
+    GasEstimatePrecedence,
+
+    /// Difference between actual and defined sampling rate too large
+    CallTimingViolation,
+
+    /// ULP+ not allowed becuase ULP measurement just/about to occur
+    ModExceedULPTimeLimit,
+
+    /// ULP+ Not allowed becuase not enough time since last ULP+
+    ModInsufficentWaitTime,
+
+    /// Unknown warning code
+    UnknownWarning {
+        /// The unknown warning code
+        code: bsec_library_return_t,
+    },
+}
+
+impl BsecWarning {
+    /// Check if the warning is about the timing of the calls to the library
+    ///
+    /// # Returns
+    /// True if the warning came from scheduling the measurements
+    #[must_use]
+    pub fn is_timing(self) -> bool {
+        matches!(
+            self,
+            Self::CallTimingViolation | Self::ModExceedULPTimeLimit | Self::ModInsufficentWaitTime
+        )
+    }
+}
+
+impl From<bsec_library_return_t> for BsecWarning {
+    #![allow(non_upper_case_globals)]
+    fn from(value: bsec_library_return_t) -> Self {
+        match value {
+            BSEC_W_DOSTEPS_TSINTRADIFFOUTOFRANGE => Self::DoStepsTsIntRadifOutOfRange,
+            BSEC_I_DOSTEPS_NOOUTPUTSRETURNABLE => Self::DoStepsNoOutputsReturnable,
+            BSEC_W_DOSTEPS_EXCESSOUTPUTS => Self::DoStepsExcessOutputs,
+            BSEC_W_DOSTEPS_GASINDEXMISS => Self::DoStepsGasIndexMiss,
+            BSEC_W_SU_UNKNOWNOUTPUTGATE => Self::UnknownOutputGate,
+            BSEC_W_SU_MODINNOULP => Self::ModInNoULP,
+            BSEC_I_SU_SUBSCRIBEDOUTPUTGATES => Self::SubscribedOutputGates,
+            BSEC_I_SU_GASESTIMATEPRECEDENCE => Self::GasEstimatePrecedence,
             BSEC_W_SC_CALL_TIMING_VIOLATION => Self::CallTimingViolation,
             BSEC_W_SC_MODEXCEEDULPTIMELIMIT => Self::ModExceedULPTimeLimit,
             BSEC_W_SC_MODINSUFFICIENTWAITTIME => Self::ModInsufficentWaitTime,
-            _ => Self::UnknownError { code: value },
+            _ => Self::UnknownWarning { code: value },
+        }
+    }
+}
+
+/// Counters of the warnings from processing measurements since startup, for telemetry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WarningCounts {
+    /// Total number of warnings
+    pub total: u32,
+
+    /// Number of warnings about the timing of the calls to the library
+    pub timing: u32,
+
+    /// Number of warnings from processing the measurements
+    pub processing: u32,
+
+    /// The most recent warning
+    pub last: Option<BsecWarning>,
+}
+
+/// Tracking of the ambient temperature, for calculating the heater resistance
+///
+/// The `BME68x` heater needs a different resistance to reach the same
+/// temperature in colder or warmer surroundings, so the ambient temperature
+/// given to the driver follows the heat compensated temperature output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AmbientTempConfig {
+    /// Time constant of the smoothing, so a short burst of heat or cold does not move the heater target
+    pub time_constant: Duration,
+
+    /// Lowest ambient temperature given to the driver, in degrees C
+    pub min_temp: i8,
+
+    /// Highest ambient temperature given to the driver, in degrees C
+    pub max_temp: i8,
+}
+
+impl Default for AmbientTempConfig {
+    fn default() -> Self {
+        Self {
+            time_constant: Duration::from_mins(10),
+            // The operating range of the BME688
+            min_temp: -40,
+            max_temp: 85,
         }
     }
 }
@@ -268,6 +380,19 @@ impl From<TryFromIntError> for BsecError {
     }
 }
 
+impl From<BlobError> for BsecError {
+    fn from(value: BlobError) -> Self {
+        Self::InvalidStoredBlob { error: value }
+    }
+}
+
+#[cfg(target_os = "espidf")]
+impl From<EspError> for BsecError {
+    fn from(value: EspError) -> Self {
+        Self::EspIdfError { code: value.code() }
+    }
+}
+
 impl From<std::io::Error> for BsecError {
     fn from(value: std::io::Error) -> Self {
         Self::FileIOError { kind: value.kind() }
@@ -385,10 +510,227 @@ impl StructuredOutputs {
     }
 }
 
+/// Rust-native version of `bsec_virtual_sensor_t`
+///
+/// The virtual sensor outputs that can be subscribed to from the BSEC library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VirtualSensor {
+    /// Indoor air quality
+    Iaq,
+
+    /// Unscaled indoor air quality
+    StaticIaq,
+
+    /// Equivalent CO2 estimate (ppm)
+    Co2Equivalent,
+
+    /// Breath VOC estimate (ppm)
+    BreathVocEquivalent,
+
+    /// Raw temperature (degrees C)
+    RawTemperature,
+
+    /// Raw pressure (Pa)
+    RawPressure,
+
+    /// Raw humidity (%)
+    RawHumidity,
+
+    /// Raw gas sensor (Ohm)
+    RawGas,
+
+    /// Stabilization status
+    StabilizationStatus,
+
+    /// Sensor run in status
+    RunInStatus,
+
+    /// Heat compensated temperature (degrees C)
+    CompensatedTemperature,
+
+    /// Heat compensated humidity (%)
+    CompensatedHumidity,
+
+    /// Percentage of min/max filter gas (%)
+    GasPercentage,
+
+    /// Gas channel 1 estimate
+    GasEstimate1,
+
+    /// Gas channel 2 estimate
+    GasEstimate2,
+
+    /// Gas channel 3 estimate
+    GasEstimate3,
+
+    /// Gas channel 4 estimate
+    GasEstimate4,
+
+    /// Gas heater profile index
+    RawGasIndex,
+}
+
+impl VirtualSensor {
+    /// Get the BSEC library ID of the virtual sensor
+    ///
+    /// # Returns
+    /// The `bsec_virtual_sensor_t` value of the virtual sensor
+    fn id(self) -> u32 {
+        match self {
+            VirtualSensor::Iaq => BSEC_OUTPUT_IAQ,
+            VirtualSensor::StaticIaq => BSEC_OUTPUT_STATIC_IAQ,
+            VirtualSensor::Co2Equivalent => BSEC_OUTPUT_CO2_EQUIVALENT,
+            VirtualSensor::BreathVocEquivalent => BSEC_OUTPUT_BREATH_VOC_EQUIVALENT,
+            VirtualSensor::RawTemperature => BSEC_OUTPUT_RAW_TEMPERATURE,
+            VirtualSensor::RawPressure => BSEC_OUTPUT_RAW_PRESSURE,
+            VirtualSensor::RawHumidity => BSEC_OUTPUT_RAW_HUMIDITY,
+            VirtualSensor::RawGas => BSEC_OUTPUT_RAW_GAS,
+            VirtualSensor::StabilizationStatus => BSEC_OUTPUT_STABILIZATION_STATUS,
+            VirtualSensor::RunInStatus => BSEC_OUTPUT_RUN_IN_STATUS,
+            VirtualSensor::CompensatedTemperature => {
+                BSEC_OUTPUT_SENSOR_HEAT_COMPENSATED_TEMPERATURE
+            }
+            VirtualSensor::CompensatedHumidity => BSEC_OUTPUT_SENSOR_HEAT_COMPENSATED_HUMIDITY,
+            VirtualSensor::GasPercentage => BSEC_OUTPUT_GAS_PERCENTAGE,
+            VirtualSensor::GasEstimate1 => BSEC_OUTPUT_GAS_ESTIMATE_1,
+            VirtualSensor::GasEstimate2 => BSEC_OUTPUT_GAS_ESTIMATE_2,
+            VirtualSensor::GasEstimate3 => BSEC_OUTPUT_GAS_ESTIMATE_3,
+            VirtualSensor::GasEstimate4 => BSEC_OUTPUT_GAS_ESTIMATE_4,
+            VirtualSensor::RawGasIndex => BSEC_OUTPUT_RAW_GAS_INDEX,
+        }
+    }
+}
+
+/// Set of virtual sensors to subscribe to, each at its own sample rate
+///
+/// Built up by chaining calls to `sensor`, and then passed to `Bsec::subscribe`.
+#[derive(Debug, Clone, Default)]
+pub struct Subscription {
+    /// The requested virtual sensors and their sample rates
+    requests: Vec<(VirtualSensor, SampleRate)>,
+}
+
+impl Subscription {
+    /// Create a new, empty subscription
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            requests: Vec::new(),
+        }
+    }
+
+    /// Request a virtual sensor at the given sample rate
+    ///
+    /// Requesting a sensor that was already requested replaces its sample rate.
+    ///
+    /// # Arguments
+    /// * `sensor`: The virtual sensor to request
+    /// * `sample_rate`: The sample rate to request it at
+    #[must_use]
+    pub fn sensor(mut self, sensor: VirtualSensor, sample_rate: SampleRate) -> Self {
+        if let Some(request) = self
+            .requests
+            .iter_mut()
+            .find(|(requested, _)| *requested == sensor)
+        {
+            request.1 = sample_rate;
+        } else {
+            self.requests.push((sensor, sample_rate));
+        }
+        self
+    }
+
+    /// Request several virtual sensors at the same sample rate
+    ///
+    /// # Arguments
+    /// * `sensors`: The virtual sensors to request
+    /// * `sample_rate`: The sample rate to request them at
+    #[must_use]
+    pub fn sensors(self, sensors: &[VirtualSensor], sample_rate: SampleRate) -> Self {
+        sensors.iter().fold(self, |subscription, sensor| {
+            subscription.sensor(*sensor, sample_rate)
+        })
+    }
+
+    /// Get the requested virtual sensors
+    ///
+    /// # Returns
+    /// The requested virtual sensors and their sample rates
+    #[must_use]
+    pub fn requests(&self) -> &[(VirtualSensor, SampleRate)] {
+        &self.requests
+    }
+}
+
+/// Sample rate the BSEC library requires for one of the physical sensor inputs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RequiredSensorSetting {
+    /// The `bsec_physical_sensor_t` ID of the input
+    pub sensor_id: u8,
+
+    /// The sample rate required for the input, in hertz
+    pub sample_rate: f32,
+}
+
+/// Result of a successful subscription update
+#[derive(Debug, Clone, Default)]
+pub struct SubscriptionResult {
+    /// Physical sensor settings required by the BSEC library for the subscription
+    pub required_sensor_settings: Vec<RequiredSensorSetting>,
+
+    /// Warnings reported by the BSEC library while updating the subscription
+    pub warnings: Vec<BsecWarning>,
+}
+
+/// Sensor settings requested by BSEC that the sensor keeps once configured
+///
+/// In parallel and sequential mode the sensor runs continuously, so it only
+/// needs to be reconfigured when one of these changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SensorProfile {
+    /// Heater temperatures, in degrees C, with unused entries zeroed
+    heater_temperatures: [u16; 10],
+
+    /// Heater durations, with unused entries zeroed
+    heater_durations: [u16; 10],
+
+    /// Number of steps in the heater profile
+    heater_profile_len: u8,
+
+    /// Temperature oversampling
+    temperature_oversampling: u8,
+
+    /// Pressure oversampling
+    pressure_oversampling: u8,
+
+    /// Humidity oversampling
+    humidity_oversampling: u8,
+}
+
+impl From<&bsec_bme_settings_t> for SensorProfile {
+    fn from(settings: &bsec_bme_settings_t) -> Self {
+        let profile_len = usize::from(settings.heater_profile_len).min(10);
+        let mut heater_temperatures = [0; 10];
+        let mut heater_durations = [0; 10];
+        heater_temperatures[..profile_len]
+            .copy_from_slice(&settings.heater_temperature_profile[..profile_len]);
+        heater_durations[..profile_len]
+            .copy_from_slice(&settings.heater_duration_profile[..profile_len]);
+        Self {
+            heater_temperatures,
+            heater_durations,
+            heater_profile_len: settings.heater_profile_len,
+            temperature_oversampling: settings.temperature_oversampling,
+            pressure_oversampling: settings.pressure_oversampling,
+            humidity_oversampling: settings.humidity_oversampling,
+        }
+    }
+}
+
 /// Main BSEC Implementation structure
-pub struct Bsec<'a, I2C> {
-    /// The BME68x device to use with the BSEC library
-    bme: BME68xDev<'a, I2C>,
+pub struct Bsec<I2C, S, D> {
+    /// The `BME68x` device to use with the BSEC library
+    bme: BME68xDev<I2cInterface<I2C>, D>,
 
     /// Output data from BSEC
     outputs: StructuredOutputs,
@@ -400,94 +742,222 @@ pub struct Bsec<'a, I2C> {
     // TODO: Rust-native structure instead of the C one
     sensor_settings: bsec_bme_settings_t,
 
-    /// Current periodic_processing iteration time (in ns)
+    /// Operating mode the sensor was most recently configured for
+    op_mode: BME68xOpMode,
+
+    /// Heater profile and oversampling the sensor was most recently configured with
+    sensor_profile: Option<SensorProfile>,
+
+    /// Current `periodic_process` iteration time (in ns)
     curr_time_ns: i64,
 
-    /// Path to the file that stores the state of the BSEC library
-    state_path: PathBuf,
+    /// Warnings from the most recent `periodic_process` call
+    warnings: Vec<BsecWarning>,
+
+    /// Counters of the warnings since startup
+    warning_counts: WarningCounts,
+
+    /// Configuration of the ambient temperature tracking
+    ambient_config: AmbientTempConfig,
+
+    /// Smoothed ambient temperature in degrees C, once a temperature has been output
+    ambient_temp: Option<f32>,
+
+    /// Time stamp of the temperature the ambient temperature was last updated with (in ns)
+    ambient_time_ns: i64,
+
+    /// Whether the ambient temperature changed since the heater was configured
+    heater_outdated: bool,
+
+    /// Storage for the state of the BSEC library
+    state_store: S,
 
     /// Path to the file that stores the configuration of the BSEC library
     config_path: PathBuf,
+
+    /// The configuration the BSEC library was initialized with
+    active_config: ActiveConfig,
+
+    /// IAQ accuracy of the state restored when the library was initialized
+    restored_accuracy: Option<u8>,
 }
 
-// TODO: Rust enum for bsec_virtual_sensor_t
+/// Magic number for configurations stored on the filesystem
+const CONFIG_MAGIC: [u8; 4] = *b"BSCF";
+
+/// Magic number for saved states
+const STATE_MAGIC: [u8; 4] = *b"BSST";
+
+/// The BSEC library is global, so tests that use it must hold this lock
+#[cfg(test)]
+static LIBRARY_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Create an empty directory for a test to write files to
+///
+/// # Arguments
+/// * `name`: Name of the directory, unique to the test
+///
+/// # Returns
+/// The path to the directory
+#[cfg(test)]
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bsec-test-{}-{name}", std::process::id()));
+    // Left over from an earlier run of the test
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
 
-impl<'a, I2C: I2c> Bsec<'a, I2C> {
+impl<I2C: I2c, S: StateStore, D: DelayNs> Bsec<I2C, S, D> {
     /// Initialize the device for use with the BSEC system
     /// # Arguments
     /// * `i2c`: The i2c bus to use for communication with the sensor
     /// * `temp_offset`: The offset to apply to the temperature measurement, to correct for sensor or enclosure bias.
-    pub fn new(i2c: I2C, temp_offset: f32) -> Self {
+    /// * `state_store`: Storage to load and save the state of the BSEC library with
+    /// * `delay`: Delay implementation for the sensor driver
+    pub fn new(i2c: I2C, temp_offset: f32, state_store: S, delay: D) -> Self {
         Self {
-            bme: BME68xDev::new(i2c, BME68xAddr::HIGH, 25, BME68xIntf::I2CIntf, &|delay| {
-                FreeRtos::delay_ms(delay / 1000);
-            }),
+            bme: BME68xDev::new(I2cInterface::new(i2c, BME68xAddr::HIGH), 25, delay),
             outputs: StructuredOutputs::new(),
             temp_offset,
             sensor_settings: bsec_bme_settings_t::new(),
+            op_mode: BME68xOpMode::SleepMode,
+            sensor_profile: None,
             curr_time_ns: 0,
-            state_path: PathBuf::from("/littlefs/bsec_state.bin"),
+            warnings: Vec::new(),
+            warning_counts: WarningCounts::default(),
+            ambient_config: AmbientTempConfig::default(),
+            ambient_temp: None,
+            ambient_time_ns: 0,
+            heater_outdated: false,
+            state_store,
             config_path: PathBuf::from("/littlefs/bsec_config.bin"),
+            active_config: ActiveConfig::LibraryDefault,
+            restored_accuracy: None,
         }
     }
 
     /// Initialize the BSEC library.
     ///
+    /// The configuration stored on the filesystem is used if present. If it
+    /// is not, or it is corrupt or for a different version of the library, the
+    /// selected embedded configuration is used instead. If that is not
+    /// embedded in the firmware, the library's default configuration is used.
+    ///
+    /// # Arguments
+    /// * `selection`: The embedded configuration to use when there is no usable stored configuration
+    ///
+    /// # Returns
+    /// The configuration the library is running with
+    ///
     /// # Errors
     /// Returns an error if initializing the library failed.
     // TODO: Make this part of new()?
-    pub fn init(&mut self) -> Result<(), BsecError> {
+    pub fn init(&mut self, selection: ConfigSelection) -> Result<ActiveConfig, BsecError> {
         self.bme.init()?;
+        self.init_library(selection)
+    }
+
+    /// Initialize the BSEC library, and load its configuration and state
+    ///
+    /// # Arguments
+    /// * `selection`: The embedded configuration to use when there is no usable stored configuration
+    ///
+    /// # Returns
+    /// The configuration the library is running with
+    ///
+    /// # Errors
+    /// Returns an error if initializing the library failed.
+    fn init_library(&mut self, selection: ConfigSelection) -> Result<ActiveConfig, BsecError> {
         to_err(unsafe { bsec_init() })?;
 
-        // Load the config, if present
-        // TODO: Break into separate function
-        // TODO: Logic to overwrite config if it is bad
-        if self.config_path.exists() {
-            let config = fs::read(&self.config_path)?;
-            let mut work_buffer = [0; BSEC_MAX_WORKBUFFER_SIZE as usize];
-            let config_len = u32::try_from(config.len())?;
-            let work_buffer_len = u32::try_from(work_buffer.len())?;
-
-            to_err(unsafe {
-                bsec_set_configuration(
-                    config.as_ptr(),
-                    config_len,
-                    work_buffer.as_mut_ptr(),
-                    work_buffer_len,
-                )
-            })?;
+        self.active_config = self.load_config(selection)?;
+
+        // Without a saved state the library starts uncalibrated, which is
+        // better than not starting at all.
+        let restored = self.restore_newest_state();
+        match restored {
+            Some((index, metadata)) => log::info!(
+                "Restored BSEC state {index}, saved at {} with IAQ accuracy {}",
+                metadata.timestamp_s,
+                metadata.iaq_accuracy
+            ),
+            None => log::warn!("No usable saved BSEC state, starting uncalibrated"),
         }
+        self.restored_accuracy = restored.map(|(_, metadata)| metadata.iaq_accuracy);
 
-        // Load the state, if present
-        // TODO: Break into separate function
-        // TODO: Logic to overwrite state if it is bad
-        if self.state_path.exists() {
-            let state = fs::read(&self.state_path)?;
-            let mut work_buffer = [0; BSEC_MAX_WORKBUFFER_SIZE as usize];
-            let state_len = u32::try_from(state.len())?;
-            let work_buffer_len = u32::try_from(work_buffer.len())?;
-
-            to_err(unsafe {
-                bsec_set_state(
-                    state.as_ptr(),
-                    state_len,
-                    work_buffer.as_mut_ptr(),
-                    work_buffer_len,
-                )
-            })?;
-        }
+        Ok(self.active_config)
+    }
 
-        Ok(())
+    /// Restore the newest usable state from the state store
+    ///
+    /// # Returns
+    /// The index of the restored state in the states loaded from the store
+    /// (newest first), and its metadata, or `None` if no state was usable
+    fn restore_newest_state(&mut self) -> Option<(usize, BlobMetadata)> {
+        let states = self.state_store.load_all().unwrap_or_else(|error| {
+            log::warn!("Failed to load the BSEC state: {error:?}");
+            Vec::new()
+        });
+        states
+            .iter()
+            .enumerate()
+            .find_map(|(index, state)| match self.restore_state(state) {
+                Ok(metadata) => Some((index, metadata)),
+                Err(error) => {
+                    log::warn!("Saved BSEC state {index} is unusable: {error:?}");
+                    None
+                }
+            })
+    }
+
+    /// Get the configuration the BSEC library is running with
+    ///
+    /// # Returns
+    /// The configuration the BSEC library was initialized with
+    pub fn get_active_config(&self) -> ActiveConfig {
+        self.active_config
+    }
+
+    /// Get the IAQ accuracy the restored state was saved with
+    ///
+    /// # Returns
+    /// The IAQ accuracy, or `None` if no saved state was restored
+    pub fn get_restored_accuracy(&self) -> Option<u8> {
+        self.restored_accuracy
     }
 
     #[allow(clippy::doc_markdown)]
-    /// Save the BSEC library state to the LittleFS Partition
+    /// Store a configuration to the LittleFS Partition
+    ///
+    /// The configuration is used the next time the library is initialized,
+    /// in place of the embedded configuration. It is written to a temporary
+    /// file that is renamed over the stored configuration, so a power loss
+    /// while saving leaves the previous configuration in place.
+    ///
+    /// # Arguments
+    /// * `config`: The serialized configuration, as provided by Bosch
+    ///
+    /// # Errors
+    /// Returns an error if getting the library version failed, or if writing
+    /// the configuration to the filesystem failed.
+    pub fn save_config(&self, config: &[u8]) -> Result<(), BsecError> {
+        FileStateStore::new(&self.config_path, 1).save(&blob::wrap(
+            CONFIG_MAGIC,
+            self.get_version_bytes()?,
+            BlobMetadata {
+                timestamp_s: unix_time_s(),
+                iaq_accuracy: 0,
+            },
+            config,
+        ))
+    }
+
+    /// Save the BSEC library state to the state store
     ///
     /// # Errors
     /// Returns an error if getting the state from the BSEC library failed,
-    /// or if writing the state to the filesystem failed.
-    // TODO: Argument for path to save?
+    /// or if writing the state to the store failed.
     pub fn save_state(&mut self) -> Result<(), BsecError> {
         // Get the configuration from the library.
         let mut state_buffer = [0; BSEC_MAX_STATE_BLOB_SIZE as usize];
@@ -500,7 +970,7 @@ impl<'a, I2C: I2c> Bsec<'a, I2C> {
                 BSEC_MAX_STATE_BLOB_SIZE,
                 work_buffer.as_mut_ptr(),
                 BSEC_MAX_WORKBUFFER_SIZE,
-                &mut actual_buffer_size,
+                &raw mut actual_buffer_size,
             )
         })?;
 
@@ -509,12 +979,30 @@ impl<'a, I2C: I2c> Bsec<'a, I2C> {
             .into_iter()
             .take(usize::try_from(actual_buffer_size)?)
             .collect();
-        // Store the configuration to the filesystem
-        fs::write(&self.state_path, state_buffer)?;
+        // Store the state behind a header, so it can be checked before it is restored
+        let metadata = BlobMetadata {
+            timestamp_s: unix_time_s(),
+            iaq_accuracy: self.outputs.iaq.accuracy,
+        };
+        let state = blob::wrap(
+            STATE_MAGIC,
+            self.get_version_bytes()?,
+            metadata,
+            &state_buffer,
+        );
+        self.state_store.save(&state)?;
 
         Ok(())
     }
 
+    /// Get the store the BSEC library state is saved to
+    ///
+    /// # Returns
+    /// The state store
+    pub fn get_state_store(&self) -> &S {
+        &self.state_store
+    }
+
     /// Get the version of the BSEC library
     ///
     /// # Returns
@@ -530,35 +1018,197 @@ impl<'a, I2C: I2c> Bsec<'a, I2C> {
             major_bugfix: 0,
             minor_bugfix: 0,
         };
-        to_err(unsafe { bsec_get_version(&mut version) })?;
+        to_err(unsafe { bsec_get_version(&raw mut version) })?;
         Ok(version)
     }
 
-    /// Update the requested sensor data
+    /// Get the version of the BSEC library as bytes, for the header of stored blobs
+    ///
+    /// # Returns
+    /// The major, minor, major bugfix and minor bugfix version
+    ///
+    /// # Errors
+    /// Returns an error if reading the version fails.
+    fn get_version_bytes(&self) -> Result<[u8; 4], BsecError> {
+        let version = self.get_version()?;
+        Ok([
+            version.major,
+            version.minor,
+            version.major_bugfix,
+            version.minor_bugfix,
+        ])
+    }
+
+    /// Load the stored or selected embedded configuration into the library
     ///
     /// # Arguments
-    /// * `requested_virtual_sensors`: The requested virtual sensors to subscribe to
+    /// * `selection`: The embedded configuration to use when there is no usable stored configuration
+    ///
+    /// # Returns
+    /// The configuration that was loaded
     ///
     /// # Errors
-    /// Returns and error if updating the subscription failed
-    pub fn update_subscription(
-        &self,
-        requested_virtual_sensors: &[bsec_sensor_configuration_t],
-    ) -> Result<(), BsecError> {
+    /// Returns an error if resetting the library after a rejected configuration failed.
+    fn load_config(&self, selection: ConfigSelection) -> Result<ActiveConfig, BsecError> {
+        if self.config_path.exists() {
+            match self.load_stored_config() {
+                Ok(()) => return Ok(ActiveConfig::Stored),
+                Err(error) => {
+                    log::warn!("Stored BSEC configuration is unusable: {error:?}");
+                    // Make sure nothing from a rejected configuration is left behind
+                    to_err(unsafe { bsec_init() })?;
+                }
+            }
+        }
+
+        if let Some((name, config)) = selection.embedded() {
+            match Self::set_configuration(config) {
+                Ok(()) => return Ok(ActiveConfig::Embedded { name }),
+                Err(error) => {
+                    log::warn!("Embedded BSEC configuration {name} was rejected: {error:?}");
+                    to_err(unsafe { bsec_init() })?;
+                }
+            }
+        } else {
+            log::warn!(
+                "BSEC configuration {} is not embedded in the firmware",
+                selection.name()
+            );
+        }
+
+        log::warn!("Using the default BSEC configuration");
+        Ok(ActiveConfig::LibraryDefault)
+    }
+
+    /// Load the configuration stored on the filesystem into the library
+    ///
+    /// A configuration stored before configurations had a header is passed to
+    /// the library as is, and stored again with a header if it is accepted.
+    ///
+    /// # Errors
+    /// Returns an error if the stored configuration could not be read, is
+    /// corrupt or for a different library version, or was rejected by the library.
+    fn load_stored_config(&self) -> Result<(), BsecError> {
+        let data = fs::read(&self.config_path)?;
+        match blob::unwrap(CONFIG_MAGIC, self.get_version_bytes()?, &data) {
+            Ok((_, config)) => Self::set_configuration(config),
+            Err(BlobError::BadMagic) => {
+                log::warn!("Stored BSEC configuration is a legacy unwrapped configuration");
+                Self::set_configuration(&data)?;
+                match self.save_config(&data) {
+                    Ok(()) => log::info!("Migrated the legacy BSEC configuration"),
+                    Err(error) => {
+                        log::warn!("Failed to migrate the legacy BSEC configuration: {error:?}");
+                    }
+                }
+                Ok(())
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Check a saved state and pass it to the library
+    ///
+    /// # Arguments
+    /// * `data`: The saved state, including its header
+    ///
+    /// # Returns
+    /// The information from the header of the state
+    ///
+    /// # Errors
+    /// Returns an error if the state is corrupt or for a different library
+    /// version, or was rejected by the library.
+    fn restore_state(&self, data: &[u8]) -> Result<BlobMetadata, BsecError> {
+        let (metadata, state) = blob::unwrap(STATE_MAGIC, self.get_version_bytes()?, data)?;
+        let mut work_buffer = [0; BSEC_MAX_WORKBUFFER_SIZE as usize];
+        let state_len = u32::try_from(state.len())?;
+        let work_buffer_len = u32::try_from(work_buffer.len())?;
+
+        to_err(unsafe {
+            bsec_set_state(
+                state.as_ptr(),
+                state_len,
+                work_buffer.as_mut_ptr(),
+                work_buffer_len,
+            )
+        })?;
+        Ok(metadata)
+    }
+
+    /// Pass a serialized configuration to the library
+    ///
+    /// # Arguments
+    /// * `config`: The serialized configuration
+    ///
+    /// # Errors
+    /// Returns an error if the library rejected the configuration.
+    fn set_configuration(config: &[u8]) -> Result<(), BsecError> {
+        let mut work_buffer = [0; BSEC_MAX_WORKBUFFER_SIZE as usize];
+        let config_len = u32::try_from(config.len())?;
+        let work_buffer_len = u32::try_from(work_buffer.len())?;
+
+        to_err(unsafe {
+            bsec_set_configuration(
+                config.as_ptr(),
+                config_len,
+                work_buffer.as_mut_ptr(),
+                work_buffer_len,
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Subscribe to virtual sensors, each at its own sample rate
+    ///
+    /// This replaces any previous subscription to the requested sensors.
+    /// Warnings from the BSEC library (such as an unknown output) do not
+    /// fail the subscription, and are returned instead.
+    ///
+    /// # Arguments
+    /// * `subscription`: The virtual sensors to subscribe to
+    ///
+    /// # Returns
+    /// The physical sensor settings required by the BSEC library, and any warnings
+    ///
+    /// # Errors
+    /// Returns an error if the BSEC library rejected the subscription
+    pub fn subscribe(&self, subscription: &Subscription) -> Result<SubscriptionResult, BsecError> {
+        let requested_virtual_sensors = subscription
+            .requests()
+            .iter()
+            .map(|(sensor, sample_rate)| {
+                Ok(bsec_sensor_configuration_t {
+                    sample_rate: sample_rate.get_hz(),
+                    sensor_id: sensor.id().try_into()?,
+                })
+            })
+            .collect::<Result<Vec<_>, TryFromIntError>>()?;
+
         let mut required_sensor_settings =
             [bsec_sensor_configuration_t::new(); BSEC_MAX_PHYSICAL_SENSOR as usize];
-
         let mut n_required_sensor_settings: u8 = BSEC_MAX_PHYSICAL_SENSOR.try_into()?;
-        to_err(unsafe {
+        let result = unsafe {
             bsec_update_subscription(
                 requested_virtual_sensors.as_ptr(),
                 requested_virtual_sensors.len().try_into()?,
                 required_sensor_settings.as_mut_ptr(),
-                &mut n_required_sensor_settings,
+                &raw mut n_required_sensor_settings,
             )
-        })?;
+        };
 
-        Ok(())
+        let warnings = check_return(result)?.into_iter().collect();
+
+        Ok(SubscriptionResult {
+            required_sensor_settings: required_sensor_settings
+                .iter()
+                .take(usize::from(n_required_sensor_settings))
+                .map(|setting| RequiredSensorSetting {
+                    sensor_id: setting.sensor_id,
+                    sample_rate: setting.sample_rate,
+                })
+                .collect(),
+            warnings,
+        })
     }
 
     /// Subscribe to all non gas-scan sensors
@@ -570,62 +1220,30 @@ impl<'a, I2C: I2c> Bsec<'a, I2C> {
     /// Returns an error if subscribing fails
     ///
     pub fn subscribe_all_non_scan(&self, sample_rate: SampleRate) -> Result<(), BsecError> {
-        let sample_rate = sample_rate.get_hz();
-        let requested_sensors = [
-            bsec_sensor_configuration_t {
-                sample_rate,
-                sensor_id: BSEC_OUTPUT_RAW_TEMPERATURE.try_into()?,
-            },
-            bsec_sensor_configuration_t {
-                sample_rate,
-                sensor_id: BSEC_OUTPUT_RAW_PRESSURE.try_into()?,
-            },
-            bsec_sensor_configuration_t {
-                sample_rate,
-                sensor_id: BSEC_OUTPUT_RAW_HUMIDITY.try_into()?,
-            },
-            bsec_sensor_configuration_t {
-                sample_rate,
-                sensor_id: BSEC_OUTPUT_RAW_GAS.try_into()?,
-            },
-            bsec_sensor_configuration_t {
-                sample_rate,
-                sensor_id: BSEC_OUTPUT_IAQ.try_into()?,
-            },
-            bsec_sensor_configuration_t {
-                sample_rate,
-                sensor_id: BSEC_OUTPUT_STATIC_IAQ.try_into()?,
-            },
-            bsec_sensor_configuration_t {
-                sample_rate,
-                sensor_id: BSEC_OUTPUT_CO2_EQUIVALENT.try_into()?,
-            },
-            bsec_sensor_configuration_t {
-                sample_rate,
-                sensor_id: BSEC_OUTPUT_BREATH_VOC_EQUIVALENT.try_into()?,
-            },
-            bsec_sensor_configuration_t {
-                sample_rate,
-                sensor_id: BSEC_OUTPUT_SENSOR_HEAT_COMPENSATED_TEMPERATURE.try_into()?,
-            },
-            bsec_sensor_configuration_t {
-                sample_rate,
-                sensor_id: BSEC_OUTPUT_SENSOR_HEAT_COMPENSATED_HUMIDITY.try_into()?,
-            },
-            bsec_sensor_configuration_t {
-                sample_rate,
-                sensor_id: BSEC_OUTPUT_STABILIZATION_STATUS.try_into()?,
-            },
-            bsec_sensor_configuration_t {
-                sample_rate,
-                sensor_id: BSEC_OUTPUT_RUN_IN_STATUS.try_into()?,
-            },
-            bsec_sensor_configuration_t {
-                sample_rate,
-                sensor_id: BSEC_OUTPUT_GAS_PERCENTAGE.try_into()?,
-            },
-        ];
-        self.update_subscription(&requested_sensors)
+        let subscription = Subscription::new().sensors(
+            &[
+                VirtualSensor::RawTemperature,
+                VirtualSensor::RawPressure,
+                VirtualSensor::RawHumidity,
+                VirtualSensor::RawGas,
+                VirtualSensor::Iaq,
+                VirtualSensor::StaticIaq,
+                VirtualSensor::Co2Equivalent,
+                VirtualSensor::BreathVocEquivalent,
+                VirtualSensor::CompensatedTemperature,
+                VirtualSensor::CompensatedHumidity,
+                VirtualSensor::StabilizationStatus,
+                VirtualSensor::RunInStatus,
+                VirtualSensor::GasPercentage,
+            ],
+            sample_rate,
+        );
+
+        for warning in self.subscribe(&subscription)?.warnings {
+            log::warn!("BSEC subscription warning: {warning:?}");
+        }
+
+        Ok(())
     }
 
     ///  Read data from the sensor and process it
@@ -635,9 +1253,6 @@ impl<'a, I2C: I2c> Bsec<'a, I2C> {
     ///
     /// # Errors
     /// Errors if reading and processing the data failed.
-    ///
-    /// # Panics
-    /// Panics if an attempt to use the sensor's sequential mode is made.
     pub fn periodic_process(&mut self, timestamp_ns: i64) -> Result<(), BsecError> {
         let mut sensor_settings = bsec_bme_settings_t {
             heater_duration: 0,
@@ -657,15 +1272,40 @@ impl<'a, I2C: I2c> Bsec<'a, I2C> {
 
         self.curr_time_ns = timestamp_ns;
 
-        to_err(unsafe { bsec_sensor_control(timestamp_ns, &mut sensor_settings) })?;
+        self.warnings.clear();
+
+        // Warnings such as a call timing violation still come with valid
+        // settings, which must be followed to get back on schedule.
+        let warning =
+            check_return(unsafe { bsec_sensor_control(timestamp_ns, &raw mut sensor_settings) })?;
         self.sensor_settings = sensor_settings;
+        if let Some(warning) = warning {
+            self.record_warning(warning);
+        }
 
-        match BME68xOpMode::from(self.sensor_settings.op_mode) {
+        // Parallel and sequential modes run continuously once started, so the
+        // sensor only needs to be reconfigured when BSEC changes mode, heater
+        // profile or oversampling, or the heater needs a new resistance for
+        // the ambient temperature.
+        let op_mode = BME68xOpMode::from(self.sensor_settings.op_mode);
+        let sensor_profile = SensorProfile::from(&self.sensor_settings);
+        let reconfigure = self.op_mode != op_mode
+            || self.sensor_profile != Some(sensor_profile)
+            || self.heater_outdated;
+        match op_mode {
             BME68xOpMode::ForcedMode => self.configure_sensor_forced(),
-            BME68xOpMode::ParallelMode => self.configure_sensor_parallel(),
-            BME68xOpMode::SleepMode => self.bme.set_op_mode(BME68xOpMode::SleepMode),
-            BME68xOpMode::SequentialMode => panic!("Sequential Op Not Supported"),
+            BME68xOpMode::ParallelMode if reconfigure => self.configure_sensor_parallel(),
+            BME68xOpMode::SequentialMode if reconfigure => self.configure_sensor_sequential(),
+            BME68xOpMode::SleepMode if self.op_mode != op_mode => {
+                self.bme.set_op_mode(BME68xOpMode::SleepMode)
+            }
+            BME68xOpMode::ParallelMode | BME68xOpMode::SequentialMode | BME68xOpMode::SleepMode => {
+                Ok(())
+            }
         }?;
+        self.op_mode = op_mode;
+        self.sensor_profile = Some(sensor_profile);
+        self.heater_outdated = false;
 
         if (self.sensor_settings.trigger_measurement != 0)
             // TODO: Add method to sensor settings that returns enum mode?
@@ -702,6 +1342,54 @@ impl<'a, I2C: I2c> Bsec<'a, I2C> {
         self.outputs
     }
 
+    /// Get the warnings from the most recent `periodic_process` call
+    ///
+    /// # Returns
+    /// The warnings the library reported, if any
+    pub fn get_warnings(&self) -> &[BsecWarning] {
+        &self.warnings
+    }
+
+    /// Set how the ambient temperature used for the heater follows the measured temperature
+    ///
+    /// # Arguments
+    /// * `config`: Configuration of the ambient temperature tracking
+    pub fn set_ambient_temp_config(&mut self, config: AmbientTempConfig) {
+        self.ambient_config = config;
+    }
+
+    /// Get the smoothed ambient temperature used for the heater
+    ///
+    /// # Returns
+    /// The ambient temperature in degrees C, or `None` if no temperature has been output yet
+    pub fn get_ambient_temp(&self) -> Option<f32> {
+        self.ambient_temp
+    }
+
+    /// Get the counters of the warnings since startup
+    ///
+    /// # Returns
+    /// The counters of the warnings since startup
+    pub fn get_warning_counts(&self) -> WarningCounts {
+        self.warning_counts
+    }
+
+    /// Log and count a warning from processing measurements
+    ///
+    /// # Arguments
+    /// * `warning`: The warning the library reported
+    fn record_warning(&mut self, warning: BsecWarning) {
+        log::warn!("BSEC warning at {}: {warning:?}", self.curr_time_ns);
+        self.warnings.push(warning);
+        self.warning_counts.total += 1;
+        if warning.is_timing() {
+            self.warning_counts.timing += 1;
+        } else {
+            self.warning_counts.processing += 1;
+        }
+        self.warning_counts.last = Some(warning);
+    }
+
     /// Get the timestamp (in ns) for when the next call to `periodic_process` should occur
     ///
     /// # Returns
@@ -747,13 +1435,46 @@ impl<'a, I2C: I2c> Bsec<'a, I2C> {
         conf.os_temp = BME68xOs::from(self.sensor_settings.temperature_oversampling);
         conf.os_pres = BME68xOs::from(self.sensor_settings.pressure_oversampling);
         self.bme.set_config(&conf)?;
+        let profile_len = usize::from(self.sensor_settings.heater_profile_len);
         self.bme.set_heatr_conf_parallel(
-            &self.sensor_settings.heater_temperature_profile,
-            &self.sensor_settings.heater_duration_profile,
+            &self.sensor_settings.heater_temperature_profile[0..profile_len],
+            &self.sensor_settings.heater_duration_profile[0..profile_len],
         )?;
         self.bme.set_op_mode(BME68xOpMode::ParallelMode)
     }
 
+    /// Configure the sensor for a sequential measurement
+    ///
+    /// # Errors
+    /// Returns and error if configuring the sensor fails
+    fn configure_sensor_sequential(&mut self) -> Result<(), BME68xError> {
+        let mut conf = self.bme.get_config()?;
+        conf.os_hum = BME68xOs::from(self.sensor_settings.humidity_oversampling);
+        conf.os_temp = BME68xOs::from(self.sensor_settings.temperature_oversampling);
+        conf.os_pres = BME68xOs::from(self.sensor_settings.pressure_oversampling);
+        self.bme.set_config(&conf)?;
+        let profile_len = usize::from(self.sensor_settings.heater_profile_len);
+        let heatr_conf = BME68xHeatrConf::new_sequential(
+            &self.sensor_settings.heater_temperature_profile[0..profile_len],
+            &self.sensor_settings.heater_duration_profile[0..profile_len],
+        )?;
+        self.bme
+            .set_heatr_conf(BME68xOpMode::SequentialMode, &heatr_conf)?;
+
+        // The sensor runs through the profile on its own, so if a cycle takes
+        // longer than BSEC waits between calls, some calls find no new data.
+        let cycle_ns = i64::from(self.bme.get_sequential_dur(&conf, &heatr_conf)) * 1000;
+        let interval_ns = self.sensor_settings.next_call - self.curr_time_ns;
+        if interval_ns > 0 && cycle_ns > interval_ns {
+            log::warn!(
+                "Sequential heater profile takes {} ms, longer than the {} ms between BSEC calls",
+                cycle_ns / 1_000_000,
+                interval_ns / 1_000_000
+            );
+        }
+        self.bme.set_op_mode(BME68xOpMode::SequentialMode)
+    }
+
     /// Process the data and update internal record of most recent data
     ///
     /// Arguments
@@ -764,10 +1485,14 @@ impl<'a, I2C: I2c> Bsec<'a, I2C> {
     fn process_data(&mut self, data: &BME68xData) -> Result<(), BsecError> {
         let mut inputs: Vec<bsec_input_t> = Vec::new();
         // Conditionalyl add sensor data
-        self.add_sig_cond(BSEC_INPUT_PRESSURE, data.pressure, &mut inputs);
-        self.add_sig_cond(BSEC_INPUT_HUMIDITY, data.humidity, &mut inputs);
-        self.add_sig_cond(BSEC_INPUT_TEMPERATURE, data.temperature, &mut inputs);
-        self.add_sig_cond(BSEC_INPUT_GASRESISTOR, data.gas_resistance, &mut inputs);
+        self.add_sig_cond(BSEC_INPUT_PRESSURE, data.pressure_pa(), &mut inputs);
+        self.add_sig_cond(BSEC_INPUT_HUMIDITY, data.humidity_percent(), &mut inputs);
+        self.add_sig_cond(BSEC_INPUT_TEMPERATURE, data.temperature_degc(), &mut inputs);
+        self.add_sig_cond(
+            BSEC_INPUT_GASRESISTOR,
+            data.gas_resistance_ohm(),
+            &mut inputs,
+        );
         self.add_sig_cond(BSEC_INPUT_HEATSOURCE, self.temp_offset, &mut inputs);
 
         // TODO: BSEC_INPUT_DISABLE_BASELINE_TRACKER
@@ -787,20 +1512,56 @@ impl<'a, I2C: I2c> Bsec<'a, I2C> {
             let mut outputs = [bsec_output_t::new(); BSEC_NUMBER_OUTPUTS as usize];
 
             let mut num_outputs: u8 = outputs.len().try_into()?;
-            to_err(unsafe {
+            let warning = check_return(unsafe {
                 bsec_do_steps(
                     inputs.as_ptr(),
                     inputs.len().try_into()?,
                     outputs.as_mut_ptr(),
-                    &mut num_outputs,
+                    &raw mut num_outputs,
                 )
             })?;
+            if let Some(warning) = warning {
+                self.record_warning(warning);
+            }
             self.update_output_structure(&mut outputs, usize::from(num_outputs));
+            self.update_ambient_temp();
         }
 
         Ok(())
     }
 
+    /// Update the ambient temperature given to the driver from the heat compensated temperature
+    fn update_ambient_temp(&mut self) {
+        let temp = self.outputs.compensated_temp;
+        if !temp.valid || !temp.signal.is_finite() || temp.time_stamp <= self.ambient_time_ns {
+            return;
+        }
+
+        let measured = temp.signal.clamp(
+            f32::from(self.ambient_config.min_temp),
+            f32::from(self.ambient_config.max_temp),
+        );
+        let ambient_temp = self.ambient_temp.map_or(measured, |ambient_temp| {
+            let elapsed = Duration::from_nanos(
+                u64::try_from(temp.time_stamp - self.ambient_time_ns).unwrap_or(0),
+            );
+            let weight =
+                (elapsed.as_secs_f32() / self.ambient_config.time_constant.as_secs_f32()).min(1.0);
+            ambient_temp + (measured - ambient_temp) * weight
+        });
+        self.ambient_temp = Some(ambient_temp);
+        self.ambient_time_ns = temp.time_stamp;
+
+        // Clamped to the range of an i8 above
+        #[allow(clippy::cast_possible_truncation)]
+        let amb_temp = ambient_temp.round() as i8;
+        if amb_temp != self.bme.get_amb_temp() {
+            log::info!("Ambient temperature for the heater: {amb_temp} C");
+            self.bme.set_amb_temp(amb_temp);
+            self.heater_outdated = true;
+        }
+    }
+
     /// Conditionally aed a value to the inputs array used for updating a subscription
     ///
     /// # Arguments
@@ -866,6 +1627,17 @@ impl<'a, I2C: I2c> Bsec<'a, I2C> {
     }
 }
 
+/// Get the current Unix time, for the header of stored blobs
+///
+/// # Returns
+/// The Unix time in seconds, or 0 if the clock is before the Unix epoch
+fn unix_time_s() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
 /// Check if the given signal is requested
 ///
 /// # Arguments
@@ -879,18 +1651,272 @@ fn check_input_request(value: u32, shift: u32) -> bool {
     (value) & (1 << ((shift) - 1)) != 0
 }
 
-/// Wrap a BSEC library return to a result structure
+/// Split a BSEC library return code into an error or a warning
+///
+/// # Arguments
+/// * `result`: The return code to split
+///
+/// # Returns
+/// The warning, or `None` if the call succeeded without one
+///
+/// # Errors
+/// Returns the error if the code is an error
+fn check_return(result: bsec_library_return_t) -> Result<Option<BsecWarning>, BsecError> {
+    // Negative return codes are errors, positive ones are warnings
+    match result {
+        BSEC_OK => Ok(None),
+        1.. => Ok(Some(result.into())),
+        _ => Err(result.into()),
+    }
+}
+
+/// Wrap a BSEC library return to a result structure, logging any warning
 ///
 /// # Arguments
 /// * `result`: The result to wrap
 ///
 /// # Errors
-/// Returns the error code wrapped in result
+/// Returns the error if the code is an error
 #[inline(always)]
-fn to_err(result: bsec_library_return_t) -> Result<(), bsec_library_return_t> {
-    if result != 0 {
-        Err(result)
-    } else {
-        Ok(())
+fn to_err(result: bsec_library_return_t) -> Result<(), BsecError> {
+    if let Some(warning) = check_return(result)? {
+        log::warn!("BSEC warning: {warning:?}");
+    }
+    Ok(())
+}
+
+// The tests rely on the stand-in for the library accepting the test configuration
+#[cfg(all(test, feature = "mock-bsec"))]
+mod test {
+    use std::path::Path;
+    use std::sync::PoisonError;
+
+    use super::config::SupplyVoltage;
+    use super::replay::{NoDelay, NoSensor};
+    use super::state_store::{FileStateStore, MemoryStateStore};
+    use super::*;
+
+    /// Create a BSEC instance with its configuration stored in a test directory
+    ///
+    /// # Arguments
+    /// * `dir`: The directory to store the configuration in
+    ///
+    /// # Returns
+    /// The BSEC instance
+    fn bsec(dir: &Path) -> Bsec<NoSensor, MemoryStateStore, NoDelay> {
+        let mut bsec = Bsec::new(NoSensor, 0.0, MemoryStateStore::new(), NoDelay);
+        bsec.config_path = dir.join("bsec_config.bin");
+        bsec
+    }
+
+    /// Test the configuration falls back from the stored configuration, to the
+    /// embedded configuration, to the library default.
+    #[test]
+    fn test_config_fallback() {
+        let _lock = LIBRARY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let dir = test_dir("config-fallback");
+        let mut bsec = bsec(&dir);
+        let not_embedded = ConfigSelection {
+            supply_voltage: SupplyVoltage::V1_8,
+            ..ConfigSelection::default()
+        };
+
+        // Nothing stored, so the selected embedded configuration is used
+        assert_eq!(
+            bsec.init_library(ConfigSelection::default()).unwrap(),
+            ActiveConfig::Embedded {
+                name: "mock_iaq_33v_3s_4d"
+            }
+        );
+        assert_eq!(
+            bsec.init_library(not_embedded).unwrap(),
+            ActiveConfig::LibraryDefault
+        );
+
+        // A stored configuration takes precedence over the selection
+        bsec.save_config(&[1, 2, 3, 4]).unwrap();
+        assert_eq!(
+            bsec.init_library(not_embedded).unwrap(),
+            ActiveConfig::Stored
+        );
+        assert_eq!(bsec.get_active_config(), ActiveConfig::Stored);
+
+        // Unless it is corrupt, or rejected by the library
+        let mut stored = fs::read(&bsec.config_path).unwrap();
+        let last = stored.len() - 1;
+        stored[last] ^= 0xFF;
+        fs::write(&bsec.config_path, &stored).unwrap();
+        assert_eq!(
+            bsec.init_library(ConfigSelection::default()).unwrap(),
+            ActiveConfig::Embedded {
+                name: "mock_iaq_33v_3s_4d"
+            }
+        );
+        bsec.save_config(&[]).unwrap();
+        assert_eq!(
+            bsec.init_library(not_embedded).unwrap(),
+            ActiveConfig::LibraryDefault
+        );
+        assert!(!dir.join("bsec_config.bin.tmp").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Test a configuration stored without a header is used, and migrated.
+    #[test]
+    fn test_legacy_config() {
+        let _lock = LIBRARY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let dir = test_dir("legacy-config");
+        let mut bsec = bsec(&dir);
+        let legacy = [7; 64];
+        fs::write(&bsec.config_path, legacy).unwrap();
+
+        assert_eq!(
+            bsec.init_library(ConfigSelection::default()).unwrap(),
+            ActiveConfig::Stored
+        );
+        let stored = fs::read(&bsec.config_path).unwrap();
+        let (_, config) =
+            blob::unwrap(CONFIG_MAGIC, bsec.get_version_bytes().unwrap(), &stored).unwrap();
+        assert_eq!(config, legacy);
+        assert_eq!(
+            bsec.init_library(ConfigSelection::default()).unwrap(),
+            ActiveConfig::Stored
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Test the newest usable state is restored, skipping a truncated
+    /// temporary file and a corrupt newest generation.
+    #[test]
+    fn test_state_fallback() {
+        let _lock = LIBRARY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let dir = test_dir("state-fallback");
+        let path = dir.join("bsec_state.bin");
+        let mut bsec = Bsec::new(NoSensor, 0.0, FileStateStore::new(&path, 3), NoDelay);
+        bsec.config_path = dir.join("bsec_config.bin");
+        bsec.init_library(ConfigSelection::default()).unwrap();
+        assert_eq!(bsec.restore_newest_state(), None);
+
+        assert_eq!(bsec.get_restored_accuracy(), None);
+
+        bsec.save_state().unwrap();
+        bsec.save_state().unwrap();
+        assert_eq!(bsec.restore_newest_state().map(|(index, _)| index), Some(0));
+        bsec.init_library(ConfigSelection::default()).unwrap();
+        assert_eq!(bsec.get_restored_accuracy(), Some(0));
+
+        // Power lost while writing the temporary file
+        let newest = fs::read(&path).unwrap();
+        fs::write(dir.join("bsec_state.bin.tmp"), &newest[..newest.len() / 2]).unwrap();
+        assert_eq!(bsec.restore_newest_state().map(|(index, _)| index), Some(1));
+
+        // The newest generation is corrupt too, so `.1` is used
+        let mut corrupt = newest;
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xFF;
+        fs::write(&path, &corrupt).unwrap();
+        assert_eq!(bsec.restore_newest_state().map(|(index, _)| index), Some(2));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Test the sensor profile changes with the heater profile and
+    /// oversampling, but not with the timing of the next measurement.
+    #[test]
+    fn test_sensor_profile() {
+        let mut settings = bsec_bme_settings_t::new();
+        settings.heater_profile_len = 2;
+        settings.heater_temperature_profile[..2].copy_from_slice(&[320, 100]);
+        settings.heater_duration_profile[..2].copy_from_slice(&[5, 10]);
+        settings.temperature_oversampling = 2;
+        let profile = SensorProfile::from(&settings);
+
+        let mut same = settings;
+        same.next_call += 3_000_000_000;
+        same.trigger_measurement = 1;
+        // Entries past the end of the profile are unused
+        same.heater_temperature_profile[5] = 200;
+        assert_eq!(SensorProfile::from(&same), profile);
+
+        let mut changed = settings;
+        changed.heater_duration_profile[1] = 20;
+        assert_ne!(SensorProfile::from(&changed), profile);
+        let mut changed = settings;
+        changed.heater_profile_len = 3;
+        assert_ne!(SensorProfile::from(&changed), profile);
+        let mut changed = settings;
+        changed.pressure_oversampling = 5;
+        assert_ne!(SensorProfile::from(&changed), profile);
+    }
+
+    /// Test the ambient temperature follows a step in the measured temperature
+    /// with the configured time constant, and stays within the configured range.
+    #[test]
+    fn test_ambient_temp() {
+        /// Give the BSEC instance a heat compensated temperature, as if it had been output
+        fn output_temp(
+            bsec: &mut Bsec<NoSensor, MemoryStateStore, NoDelay>,
+            time_s: i64,
+            signal: f32,
+        ) {
+            bsec.outputs.compensated_temp = VirtualSensorData {
+                time_stamp: time_s * 1_000_000_000,
+                signal,
+                valid: true,
+                ..VirtualSensorData::new()
+            };
+            bsec.update_ambient_temp();
+        }
+
+        let config = AmbientTempConfig::default();
+        assert_eq!(config.time_constant, Duration::from_mins(10));
+        assert_eq!((config.min_temp, config.max_temp), (-40, 85));
+
+        let mut bsec = Bsec::new(NoSensor, 0.0, MemoryStateStore::new(), NoDelay);
+        assert_eq!(bsec.get_ambient_temp(), None);
+
+        // The first temperature is used as is
+        output_temp(&mut bsec, 1, 20.0);
+        assert_eq!(bsec.get_ambient_temp(), Some(20.0));
+        assert_eq!(bsec.bme.get_amb_temp(), 20);
+
+        // After a step, about 63% of the change is followed after one time constant
+        for time_s in (4..=601).step_by(3) {
+            output_temp(&mut bsec, time_s, 30.0);
+        }
+        let ambient_temp = bsec.get_ambient_temp().unwrap();
+        assert!(
+            (ambient_temp - (20.0 + 10.0 * (1.0 - (-1.0f32).exp()))).abs() < 0.05,
+            "{ambient_temp}"
+        );
+        assert_eq!(bsec.bme.get_amb_temp(), 26);
+        assert!(bsec.heater_outdated);
+
+        // Invalid, non-finite and old temperatures are ignored
+        bsec.outputs.compensated_temp.valid = false;
+        bsec.update_ambient_temp();
+        output_temp(&mut bsec, 604, f32::NAN);
+        output_temp(&mut bsec, 601, 0.0);
+        assert_eq!(bsec.get_ambient_temp(), Some(ambient_temp));
+
+        // A gap longer than the time constant jumps to the temperature, within the range
+        output_temp(&mut bsec, 1_300, 100.0);
+        assert_eq!(bsec.get_ambient_temp(), Some(85.0));
+        assert_eq!(bsec.bme.get_amb_temp(), 85);
+        output_temp(&mut bsec, 2_000, -60.0);
+        assert_eq!(bsec.get_ambient_temp(), Some(-40.0));
+
+        bsec.set_ambient_temp_config(AmbientTempConfig {
+            time_constant: Duration::from_secs(100),
+            min_temp: 0,
+            max_temp: 40,
+        });
+        output_temp(&mut bsec, 2_050, 10.0);
+        assert_eq!(bsec.get_ambient_temp(), Some(-15.0));
+        output_temp(&mut bsec, 2_150, 50.0);
+        assert_eq!(bsec.get_ambient_temp(), Some(40.0));
+        assert_eq!(bsec.bme.get_amb_temp(), 40);
     }
 }
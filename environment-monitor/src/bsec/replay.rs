@@ -0,0 +1,453 @@
+//! Replay of recorded sensor data through the BSEC library.
+//!
+//! This feeds recorded sensor readings through the same processing used on
+//! the device, without the sensor. Built for the host against Bosch's Linux
+//! build of the library, it allows regression testing the BSEC integration
+//! without hardware.
+//!
+//! Recordings are CSV files with a header line, and one reading per line:
+//!
+//! ```text
+//! timestamp_ns,temperature,pressure,humidity,gas_resistance,gas_index,iaq,co2_eq,iaq_accuracy
+//! ```
+//!
+//! The last three columns are optional. When present, they are the outputs
+//! expected after processing the reading. Lines starting with `#` are ignored.
+use bme68x::BME68xData;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{self, ErrorKind, ErrorType, I2c, Operation};
+
+use super::bsec_bindings::{bsec_bme_settings_t, bsec_sensor_control};
+use super::config::{ActiveConfig, ConfigSelection};
+use super::state_store::StateStore;
+use super::{check_return, Bsec, BsecError, StructuredOutputs, Subscription};
+
+/// Status of a reading with new, valid gas data from a stable heater
+const VALID_DATA_STATUS: u8 = 0xB0;
+
+/// Outputs expected after processing a reading
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExpectedOutputs {
+    /// Indoor air quality
+    pub iaq: f32,
+
+    /// Equivalent CO2 estimate (ppm)
+    pub co2_eq: f32,
+
+    /// Accuracy of the IAQ
+    pub iaq_accuracy: u8,
+}
+
+/// A reading recorded from the sensor
+#[derive(Debug, Clone, Copy)]
+pub struct RecordedSample {
+    /// Time stamp in ns of the reading
+    pub timestamp_ns: i64,
+
+    /// The reading
+    pub data: BME68xData,
+
+    /// The outputs expected after processing the reading, if known
+    pub expected: Option<ExpectedOutputs>,
+}
+
+/// Error parsing a recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError {
+    /// The line (starting from 1) that could not be parsed
+    pub line: usize,
+}
+
+/// Difference between the expected and actual outputs for a reading
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mismatch {
+    /// Time stamp in ns of the reading
+    pub timestamp_ns: i64,
+
+    /// The expected outputs
+    pub expected: ExpectedOutputs,
+
+    /// The actual outputs
+    pub actual: ExpectedOutputs,
+}
+
+/// Parse a recording
+///
+/// # Arguments
+/// * `text`: The contents of the recording
+///
+/// # Returns
+/// The recorded readings
+///
+/// # Errors
+/// Returns an error if a line of the recording is not valid.
+pub fn parse_recording(text: &str) -> Result<Vec<RecordedSample>, ParseError> {
+    text.lines()
+        .enumerate()
+        // Skip the header
+        .skip(1)
+        .filter(|(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
+        .map(|(index, line)| parse_sample(line).ok_or(ParseError { line: index + 1 }))
+        .collect()
+}
+
+/// Parse a single line of a recording
+///
+/// # Arguments
+/// * `line`: The line to parse
+///
+/// # Returns
+/// The recorded reading, or `None` if the line is not valid
+fn parse_sample(line: &str) -> Option<RecordedSample> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    if fields.len() != 6 && fields.len() != 9 {
+        return None;
+    }
+
+    // Recordings are always in floats, so they can be replayed with either compensation mode
+    let data = BME68xData {
+        status: VALID_DATA_STATUS,
+        gas_index: fields[5].parse().ok()?,
+        ..BME68xData::from_readings(
+            fields[1].parse().ok()?,
+            fields[2].parse().ok()?,
+            fields[3].parse().ok()?,
+            fields[4].parse().ok()?,
+        )
+    };
+
+    let expected = if fields.len() == 9 {
+        Some(ExpectedOutputs {
+            iaq: fields[6].parse().ok()?,
+            co2_eq: fields[7].parse().ok()?,
+            iaq_accuracy: fields[8].parse().ok()?,
+        })
+    } else {
+        None
+    };
+
+    Some(RecordedSample {
+        timestamp_ns: fields[0].parse().ok()?,
+        data,
+        expected,
+    })
+}
+
+/// Stand-in for the sensor's I2C bus, as there is no sensor when replaying
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoSensor;
+
+impl ErrorType for NoSensor {
+    type Error = ErrorKind;
+}
+
+impl I2c for NoSensor {
+    fn transaction(
+        &mut self,
+        _address: u8,
+        _operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        Err(ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Address))
+    }
+}
+
+/// Delay that returns immediately, as there is no sensor to wait for when replaying
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// Replays recorded readings through the BSEC library
+pub struct Replay<S> {
+    /// The BSEC instance the readings are processed with
+    bsec: Bsec<NoSensor, S, NoDelay>,
+}
+
+impl<S: StateStore> Replay<S> {
+    /// Initialize the BSEC library for replaying readings
+    ///
+    /// The BSEC library is global, so only one replay can run at a time.
+    ///
+    /// # Arguments
+    /// * `temp_offset`: The temperature offset the readings were recorded with
+    /// * `state_store`: Storage for the state of the BSEC library
+    /// * `selection`: The embedded configuration to use when there is no usable stored configuration
+    /// * `subscription`: The virtual sensors to subscribe to
+    ///
+    /// # Returns
+    /// The replay, and the configuration the library is running with
+    ///
+    /// # Errors
+    /// Returns an error if initializing or subscribing the library failed.
+    pub fn new(
+        temp_offset: f32,
+        state_store: S,
+        selection: ConfigSelection,
+        subscription: &Subscription,
+    ) -> Result<(Self, ActiveConfig), BsecError> {
+        let mut bsec = Bsec::new(NoSensor, temp_offset, state_store, NoDelay);
+        let active_config = bsec.init_library(selection)?;
+        bsec.subscribe(subscription)?;
+        Ok((Self { bsec }, active_config))
+    }
+
+    /// Process a recorded reading
+    ///
+    /// # Arguments
+    /// * `sample`: The reading to process
+    ///
+    /// # Returns
+    /// The outputs after processing the reading
+    ///
+    /// # Errors
+    /// Returns an error if the library failed to process the reading.
+    pub fn process(&mut self, sample: &RecordedSample) -> Result<StructuredOutputs, BsecError> {
+        let mut sensor_settings = bsec_bme_settings_t::new();
+        // Readings taken off schedule only produce a warning, so keep going
+        let warning = check_return(unsafe {
+            bsec_sensor_control(sample.timestamp_ns, &raw mut sensor_settings)
+        })?;
+
+        self.bsec.warnings.clear();
+        self.bsec.curr_time_ns = sample.timestamp_ns;
+        self.bsec.sensor_settings = sensor_settings;
+        if let Some(warning) = warning {
+            self.bsec.record_warning(warning);
+        }
+        self.bsec.process_data(&sample.data)?;
+        Ok(self.bsec.get_output_data())
+    }
+
+    /// Process a recording, and compare the outputs to the expected outputs
+    ///
+    /// # Arguments
+    /// * `samples`: The recorded readings
+    /// * `tolerance`: The largest allowed difference in IAQ and eCO2
+    ///
+    /// # Returns
+    /// The readings whose outputs did not match the expected outputs
+    ///
+    /// # Errors
+    /// Returns an error if the library failed to process a reading.
+    pub fn run(
+        &mut self,
+        samples: &[RecordedSample],
+        tolerance: f32,
+    ) -> Result<Vec<Mismatch>, BsecError> {
+        let mut mismatches = Vec::new();
+        for sample in samples {
+            let outputs = self.process(sample)?;
+            if let Some(expected) = sample.expected {
+                let actual = ExpectedOutputs {
+                    iaq: outputs.iaq.signal,
+                    co2_eq: outputs.co2_eq.signal,
+                    iaq_accuracy: outputs.iaq.accuracy,
+                };
+                if (actual.iaq - expected.iaq).abs() > tolerance
+                    || (actual.co2_eq - expected.co2_eq).abs() > tolerance
+                    || actual.iaq_accuracy != expected.iaq_accuracy
+                {
+                    mismatches.push(Mismatch {
+                        timestamp_ns: sample.timestamp_ns,
+                        expected,
+                        actual,
+                    });
+                }
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// Get the BSEC instance the readings are processed with
+    ///
+    /// # Returns
+    /// The BSEC instance, e.g. for saving its state
+    pub fn bsec(&mut self) -> &mut Bsec<NoSensor, S, NoDelay> {
+        &mut self.bsec
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fmt::Write;
+    use std::sync::PoisonError;
+
+    use super::super::state_store::MemoryStateStore;
+    use super::super::{SampleRate, VirtualSensor, LIBRARY_LOCK};
+    use super::*;
+
+    /// Low power sample interval
+    const LP_INTERVAL_NS: i64 = 3_000_000_000;
+
+    /// Create a recording of steady clean air
+    ///
+    /// # Arguments
+    /// * `samples`: The number of readings to create
+    ///
+    /// # Returns
+    /// The recording
+    fn clean_air(samples: u16) -> String {
+        let mut text =
+            String::from("timestamp_ns,temperature,pressure,humidity,gas_resistance,gas_index\n");
+        for sample in 0..samples {
+            let timestamp_ns = LP_INTERVAL_NS * (i64::from(sample) + 1);
+            // A little noise on the gas resistance, as a real sensor has
+            let gas_resistance = 150_000.0 + f32::from(sample % 7) * 500.0;
+            writeln!(text, "{timestamp_ns},22.5,101325.0,45.0,{gas_resistance},0").unwrap();
+        }
+        text
+    }
+
+    /// Create a replay subscribed to the IAQ outputs at the low power rate
+    ///
+    /// # Returns
+    /// The replay
+    fn replay() -> Replay<MemoryStateStore> {
+        let subscription = Subscription::new().sensors(
+            &[
+                VirtualSensor::Iaq,
+                VirtualSensor::Co2Equivalent,
+                VirtualSensor::RawTemperature,
+                VirtualSensor::RawPressure,
+                VirtualSensor::RawHumidity,
+                VirtualSensor::RawGas,
+            ],
+            SampleRate::LowPower,
+        );
+        let (replay, _) = Replay::new(
+            0.0,
+            MemoryStateStore::new(),
+            ConfigSelection::default(),
+            &subscription,
+        )
+        .unwrap();
+        replay
+    }
+
+    /// Test parsing a recording, with and without expected outputs.
+    #[test]
+    fn test_parse_recording() {
+        let samples = parse_recording(
+            "timestamp_ns,temperature,pressure,humidity,gas_resistance,gas_index,iaq,co2_eq,iaq_accuracy
+            # A comment
+            3000000000, 21.5, 100000.0, 40.0, 120000.0, 0, 50.0, 500.0, 0
+
+            6000000000, 21.6, 100010.0, 40.5, 121000.0, 0",
+        )
+        .unwrap();
+
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].timestamp_ns, 3_000_000_000);
+        assert!((samples[0].data.temperature_degc() - 21.5).abs() < f32::EPSILON);
+        assert_eq!(
+            samples[0].expected,
+            Some(ExpectedOutputs {
+                iaq: 50.0,
+                co2_eq: 500.0,
+                iaq_accuracy: 0
+            })
+        );
+        assert!((samples[1].data.gas_resistance_ohm() - 121_000.0).abs() < f32::EPSILON);
+        assert_eq!(samples[1].expected, None);
+
+        assert_eq!(
+            parse_recording("header\n1,2,3\n").unwrap_err(),
+            ParseError { line: 2 }
+        );
+    }
+
+    /// Test replaying steady clean air gives plausible outputs.
+    #[test]
+    fn test_clean_air_outputs() {
+        let _lock = LIBRARY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let samples = parse_recording(&clean_air(600)).unwrap();
+        let mut replay = replay();
+
+        let mut outputs = StructuredOutputs::new();
+        for sample in &samples {
+            outputs = replay.process(sample).unwrap();
+        }
+
+        // 30 minutes of steady clean air
+        assert!(outputs.iaq.valid);
+        assert!((0.0..=500.0).contains(&outputs.iaq.signal));
+        assert!(outputs.co2_eq.valid);
+        assert!(outputs.co2_eq.signal >= 400.0);
+        assert!((outputs.raw_temp.signal - 22.5).abs() < 0.01);
+        assert!((outputs.raw_pressure.signal - 101_325.0).abs() < 1.0);
+    }
+
+    /// Test the ambient temperature used for the heater follows the compensated temperature.
+    #[test]
+    fn test_ambient_temp() {
+        let _lock = LIBRARY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let samples = parse_recording(&clean_air(20)).unwrap();
+        let mut replay = replay();
+        replay
+            .bsec()
+            .subscribe(
+                &Subscription::new()
+                    .sensor(VirtualSensor::CompensatedTemperature, SampleRate::LowPower),
+            )
+            .unwrap();
+        assert_eq!(replay.bsec().get_ambient_temp(), None);
+        assert_eq!(replay.bsec().bme.get_amb_temp(), 25);
+
+        for sample in &samples {
+            replay.process(sample).unwrap();
+        }
+
+        let ambient_temp = replay.bsec().get_ambient_temp().unwrap();
+        assert!((ambient_temp - 22.5).abs() < 0.5, "{ambient_temp}");
+        assert!((22..=23).contains(&replay.bsec().bme.get_amb_temp()));
+    }
+
+    /// Test replaying a recording gives the recorded IAQ, eCO2 and accuracy.
+    // The expected outputs were recorded with the stand-in for the library
+    #[cfg(feature = "mock-bsec")]
+    #[test]
+    fn test_recording() {
+        let _lock = LIBRARY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let samples = parse_recording(include_str!("recordings/mock_pollution.csv")).unwrap();
+        assert_eq!(samples.len(), 800);
+        assert!(samples.iter().all(|sample| sample.expected.is_some()));
+
+        // The recording covers the accuracy rising, and the IAQ rising with pollution
+        let expected: Vec<ExpectedOutputs> = samples
+            .iter()
+            .filter_map(|sample| sample.expected)
+            .collect();
+        assert!((0..=2).all(|accuracy| expected
+            .iter()
+            .any(|outputs| outputs.iaq_accuracy == accuracy)));
+        assert!(expected.iter().any(|outputs| outputs.iaq > 200.0));
+
+        assert_eq!(replay().run(&samples, 0.01).unwrap(), vec![]);
+    }
+
+    /// Test differences from the expected outputs are reported.
+    #[cfg(feature = "mock-bsec")]
+    #[test]
+    fn test_mismatches() {
+        let _lock = LIBRARY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut samples = parse_recording(include_str!("recordings/mock_pollution.csv")).unwrap();
+        let mut changed = samples[700].expected.unwrap();
+        changed.iaq_accuracy = 3;
+        samples[700].expected = Some(changed);
+        let mut changed = samples[710].expected.unwrap();
+        changed.iaq += 1.0;
+        samples[710].expected = Some(changed);
+
+        let mismatches = replay().run(&samples, 0.01).unwrap();
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].timestamp_ns, samples[700].timestamp_ns);
+        assert_eq!(mismatches[0].actual.iaq_accuracy, 2);
+        assert_eq!(mismatches[1].timestamp_ns, samples[710].timestamp_ns);
+        assert!((mismatches[1].actual.iaq - mismatches[1].expected.iaq + 1.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,389 @@
+//! Policy for deciding when to save the state of the BSEC library.
+//!
+//! Saving too often wears down the flash, and saving too rarely loses
+//! calibration when the power is lost. The state is saved periodically, as
+//! soon as the IAQ accuracy improves (so a newly reached calibration is not
+//! lost), and whenever another task asks for it (e.g. before a planned reboot).
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Configuration of the save policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SavePolicyConfig {
+    /// Interval between periodic saves
+    pub periodic_interval: Duration,
+
+    /// Minimum interval between saves triggered by the IAQ accuracy improving.
+    /// Saves for an improvement that comes sooner are delayed until it has passed.
+    pub min_interval: Duration,
+}
+
+impl Default for SavePolicyConfig {
+    fn default() -> Self {
+        Self {
+            // Once an hour is 8760 writes a year
+            periodic_interval: Duration::from_hours(1),
+            min_interval: Duration::from_mins(10),
+        }
+    }
+}
+
+/// Reasons for saving the state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveReason {
+    /// The periodic interval passed
+    Periodic,
+
+    /// The IAQ accuracy improved
+    AccuracyImproved {
+        /// The new IAQ accuracy
+        accuracy: u8,
+    },
+
+    /// Another task requested the state be saved
+    Flush,
+}
+
+/// Counters of the state saves since startup, for tracking flash wear
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SaveStats {
+    /// Number of successful saves
+    pub writes: u32,
+
+    /// Number of saves that failed
+    pub failures: u32,
+
+    /// Number of periodic saves
+    pub periodic: u32,
+
+    /// Number of saves triggered by the IAQ accuracy improving
+    pub accuracy: u32,
+
+    /// Number of saves requested by other tasks
+    pub flushes: u32,
+}
+
+/// Handle for other tasks to request the state be saved
+#[derive(Debug, Clone, Default)]
+pub struct FlushRequest {
+    /// Whether a save has been requested and not yet attempted
+    pending: Arc<AtomicBool>,
+}
+
+impl FlushRequest {
+    /// Create a new handle
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            pending: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Request the state be saved at the next call to `periodic_process`
+    pub fn request(&self) {
+        self.pending.store(true, Ordering::Release);
+    }
+
+    /// Check if a requested save has not been attempted yet
+    ///
+    /// # Returns
+    /// Whether a requested save is still pending
+    #[must_use]
+    pub fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::Acquire)
+    }
+
+    /// Request the state be saved, and wait for the save to be attempted
+    ///
+    /// # Arguments
+    /// * `timeout`: The longest time to wait
+    ///
+    /// # Returns
+    /// Whether the save was attempted before the timeout
+    #[must_use]
+    pub fn request_and_wait(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        self.request();
+        while self.is_pending() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        true
+    }
+
+    /// Take the pending request, if there is one
+    ///
+    /// # Returns
+    /// Whether a save was requested
+    fn take(&self) -> bool {
+        self.pending.swap(false, Ordering::AcqRel)
+    }
+}
+
+/// Decides when to save the state of the BSEC library
+pub struct SavePolicy {
+    /// Configuration of the policy
+    config: SavePolicyConfig,
+
+    /// Requests from other tasks to save the state
+    flush: FlushRequest,
+
+    /// Time of the last save attempt, or startup
+    last_save: Instant,
+
+    /// IAQ accuracy at the last check
+    last_accuracy: u8,
+
+    /// IAQ accuracy of the most recently saved or restored state
+    saved_accuracy: u8,
+
+    /// Counters of the saves
+    stats: SaveStats,
+}
+
+impl SavePolicy {
+    /// Create a new policy
+    ///
+    /// # Arguments
+    /// * `config`: Configuration of the policy
+    /// * `flush`: Handle other tasks use to request the state be saved
+    /// * `initial_accuracy`: IAQ accuracy of the restored state (0 if none
+    ///   was restored), so recovering it after a restart is not saved again
+    /// * `now`: The current time
+    #[must_use]
+    pub fn new(
+        config: SavePolicyConfig,
+        flush: FlushRequest,
+        initial_accuracy: u8,
+        now: Instant,
+    ) -> Self {
+        Self {
+            config,
+            flush,
+            last_save: now,
+            last_accuracy: 0,
+            saved_accuracy: initial_accuracy,
+            stats: SaveStats::default(),
+        }
+    }
+
+    /// Check if the state should be saved
+    ///
+    /// An improvement is measured against the accuracy of the saved state, so
+    /// the accuracy dropping after a restart and recovering is not saved early.
+    ///
+    /// # Arguments
+    /// * `iaq_accuracy`: The current IAQ accuracy
+    /// * `now`: The current time
+    ///
+    /// # Returns
+    /// The reason to save the state, or `None` if it should not be saved
+    pub fn check(&mut self, iaq_accuracy: u8, now: Instant) -> Option<SaveReason> {
+        self.last_accuracy = iaq_accuracy;
+
+        let elapsed = now.saturating_duration_since(self.last_save);
+        if self.flush.take() {
+            Some(SaveReason::Flush)
+        } else if elapsed >= self.config.min_interval && iaq_accuracy > self.saved_accuracy {
+            Some(SaveReason::AccuracyImproved {
+                accuracy: iaq_accuracy,
+            })
+        } else if elapsed >= self.config.periodic_interval {
+            Some(SaveReason::Periodic)
+        } else {
+            None
+        }
+    }
+
+    /// Record the outcome of a save
+    ///
+    /// # Arguments
+    /// * `reason`: The reason the state was saved
+    /// * `success`: Whether the save succeeded
+    /// * `now`: The time of the save
+    pub fn record_save(&mut self, reason: SaveReason, success: bool, now: Instant) {
+        // Failed saves are retried after the usual interval, rather than on
+        // every iteration
+        self.last_save = now;
+
+        match reason {
+            SaveReason::Periodic => self.stats.periodic += 1,
+            SaveReason::AccuracyImproved { .. } => self.stats.accuracy += 1,
+            SaveReason::Flush => self.stats.flushes += 1,
+        }
+        if success {
+            self.stats.writes += 1;
+            // Any save stores the latest accuracy
+            self.saved_accuracy = self.last_accuracy;
+        } else {
+            self.stats.failures += 1;
+        }
+    }
+
+    /// Get the counters of the saves since startup
+    ///
+    /// # Returns
+    /// The counters of the saves
+    #[must_use]
+    pub fn stats(&self) -> SaveStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Create a policy with the default configuration
+    ///
+    /// # Returns
+    /// The policy, the handle to request saves with, and the time it started
+    fn policy() -> (SavePolicy, FlushRequest, Instant) {
+        let flush = FlushRequest::new();
+        let start = Instant::now();
+        let policy = SavePolicy::new(SavePolicyConfig::default(), flush.clone(), 0, start);
+        (policy, flush, start)
+    }
+
+    /// Test the state is saved each periodic interval, and not before.
+    #[test]
+    fn test_periodic() {
+        let (mut policy, _flush, start) = policy();
+        let interval = SavePolicyConfig::default().periodic_interval;
+
+        assert_eq!(policy.check(0, start), None);
+        assert_eq!(policy.check(0, start + interval / 2), None);
+        let now = start + interval;
+        assert_eq!(policy.check(0, now), Some(SaveReason::Periodic));
+        policy.record_save(SaveReason::Periodic, true, now);
+        assert_eq!(policy.check(0, now + interval / 2), None);
+        assert_eq!(policy.check(0, now + interval), Some(SaveReason::Periodic));
+    }
+
+    /// Test a requested flush takes precedence over the other reasons, and is only taken once.
+    #[test]
+    fn test_flush_precedence() {
+        let (mut policy, flush, start) = policy();
+        let config = SavePolicyConfig::default();
+
+        flush.request();
+        assert!(flush.is_pending());
+        assert_eq!(policy.check(0, start), Some(SaveReason::Flush));
+        assert!(!flush.is_pending());
+        assert_eq!(policy.check(0, start), None);
+
+        flush.request();
+        let now = start + config.periodic_interval;
+        assert_eq!(policy.check(3, now), Some(SaveReason::Flush));
+        policy.record_save(SaveReason::Flush, true, now);
+
+        // The flush stored the improved accuracy, so it is not saved again
+        assert_eq!(policy.check(3, now + config.min_interval), None);
+    }
+
+    /// Test a save for an improved accuracy is deferred until the minimum
+    /// interval has passed, and saves the newest accuracy.
+    #[test]
+    fn test_accuracy_deferred() {
+        let (mut policy, _flush, start) = policy();
+        let config = SavePolicyConfig::default();
+
+        assert_eq!(policy.check(1, start), None);
+        assert_eq!(policy.check(2, start + config.min_interval / 2), None);
+        let now = start + config.min_interval;
+        assert_eq!(
+            policy.check(2, now),
+            Some(SaveReason::AccuracyImproved { accuracy: 2 })
+        );
+        policy.record_save(SaveReason::AccuracyImproved { accuracy: 2 }, true, now);
+
+        // Neither a drop in accuracy nor recovering the saved accuracy is
+        // saved early, but improving on it is
+        assert_eq!(policy.check(1, now + config.min_interval / 2), None);
+        assert_eq!(policy.check(2, now + config.min_interval), None);
+        assert_eq!(
+            policy.check(3, now + config.min_interval * 2),
+            Some(SaveReason::AccuracyImproved { accuracy: 3 })
+        );
+    }
+
+    /// Test recovering the accuracy of a restored state after a restart is
+    /// not saved early, but improving on it is.
+    #[test]
+    fn test_restored_accuracy() {
+        let config = SavePolicyConfig::default();
+        let start = Instant::now();
+        let mut policy = SavePolicy::new(config, FlushRequest::new(), 2, start);
+
+        for (minutes, accuracy) in [(0, 0), (5, 1), (20, 2), (40, 2)] {
+            let now = start + Duration::from_mins(minutes);
+            assert_eq!(policy.check(accuracy, now), None, "{minutes}");
+        }
+        let now = start + Duration::from_mins(50);
+        assert_eq!(
+            policy.check(3, now),
+            Some(SaveReason::AccuracyImproved { accuracy: 3 })
+        );
+        policy.record_save(SaveReason::AccuracyImproved { accuracy: 3 }, true, now);
+        assert_eq!(policy.check(3, now + config.min_interval), None);
+
+        // Without a restored state, any accuracy above 0 is an improvement
+        let mut policy = SavePolicy::new(config, FlushRequest::new(), 0, start);
+        assert_eq!(
+            policy.check(1, start + config.min_interval),
+            Some(SaveReason::AccuracyImproved { accuracy: 1 })
+        );
+    }
+
+    /// Test a failed save is retried after the interval, rather than immediately.
+    #[test]
+    fn test_retry_after_failure() {
+        let (mut policy, _flush, start) = policy();
+        let config = SavePolicyConfig::default();
+
+        let now = start + config.min_interval;
+        let reason = SaveReason::AccuracyImproved { accuracy: 3 };
+        assert_eq!(policy.check(3, now), Some(reason));
+        policy.record_save(reason, false, now);
+        assert_eq!(policy.check(3, now), None);
+        assert_eq!(policy.check(3, now + config.min_interval), Some(reason));
+
+        let now = now + config.periodic_interval;
+        policy.record_save(reason, true, now);
+        let now = now + config.periodic_interval;
+        assert_eq!(policy.check(3, now), Some(SaveReason::Periodic));
+        policy.record_save(SaveReason::Periodic, false, now);
+        assert_eq!(policy.check(3, now + config.min_interval), None);
+        assert_eq!(
+            policy.check(3, now + config.periodic_interval),
+            Some(SaveReason::Periodic)
+        );
+    }
+
+    /// Test the counters count each reason, and successful and failed saves.
+    #[test]
+    fn test_stats() {
+        let (mut policy, _flush, start) = policy();
+        assert_eq!(policy.stats(), SaveStats::default());
+
+        policy.record_save(SaveReason::Periodic, true, start);
+        policy.record_save(SaveReason::Periodic, false, start);
+        policy.record_save(SaveReason::AccuracyImproved { accuracy: 1 }, true, start);
+        policy.record_save(SaveReason::Flush, true, start);
+        policy.record_save(SaveReason::Flush, false, start);
+        policy.record_save(SaveReason::Flush, true, start);
+        assert_eq!(
+            policy.stats(),
+            SaveStats {
+                writes: 4,
+                failures: 2,
+                periodic: 2,
+                accuracy: 1,
+                flushes: 3,
+            }
+        );
+    }
+}
@@ -0,0 +1,333 @@
+//! Scheduling of the calls to the BSEC library.
+//!
+//! BSEC expects `periodic_process` to be called at the time it asks for, and
+//! reports a timing violation when a call is too early or too late. Calls can
+//! be late when another task holds the I2C bus, or when a higher priority task
+//! runs. The time to sleep is always computed from the time the next call is
+//! due, read from the clock after processing, so such delays do not add up and
+//! the cadence stays the one BSEC asked for.
+//!
+//! The time stamps passed to BSEC must never go backwards. If the clock does
+//! (e.g. because it was reset), the time stamps are held until the clock has
+//! caught up, so BSEC only sees a pause.
+use std::time::Duration;
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+#[cfg(target_os = "espidf")]
+use esp_idf_svc::timer::EspTaskTimerService;
+
+use super::state_store::StateStore;
+use super::{Bsec, BsecError};
+
+/// A clock that only moves forwards, such as the time since boot
+pub trait MonotonicClock {
+    /// Get the current time
+    ///
+    /// # Returns
+    /// The current time in ns, from an arbitrary starting point
+    fn now_ns(&self) -> i64;
+}
+
+#[cfg(target_os = "espidf")]
+impl MonotonicClock for EspTaskTimerService {
+    fn now_ns(&self) -> i64 {
+        i64::try_from(self.now().as_nanos()).unwrap_or(i64::MAX)
+    }
+}
+
+/// Configuration of the scheduler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedulerConfig {
+    /// Longest time to sleep between calls, in case BSEC asks for an
+    /// implausible next call time
+    pub max_sleep: Duration,
+
+    /// Time to sleep before retrying when BSEC did not give a next call time,
+    /// e.g. because processing failed
+    pub retry_interval: Duration,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            // A little longer than the ultra low power sample interval of 5 minutes
+            max_sleep: Duration::from_secs(330),
+            retry_interval: Duration::from_secs(3),
+        }
+    }
+}
+
+/// Counters of the calls to BSEC since startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SchedulerStats {
+    /// Number of calls to `periodic_process`
+    pub calls: u32,
+
+    /// Number of calls made later than BSEC tolerates
+    pub late_calls: u32,
+
+    /// Number of calls BSEC reported a timing warning for
+    pub timing_violations: u32,
+
+    /// Number of times the clock went backwards
+    pub clock_jumps: u32,
+
+    /// Number of calls that failed
+    pub errors: u32,
+}
+
+/// Calls `periodic_process` on the schedule BSEC asks for
+pub struct Scheduler<C> {
+    /// Clock the time stamps are taken from
+    clock: C,
+
+    /// Configuration of the scheduler
+    config: SchedulerConfig,
+
+    /// Amount added to the clock, to hide it going backwards, in ns
+    offset_ns: i64,
+
+    /// The most recent time stamp, in ns
+    last_timestamp_ns: Option<i64>,
+
+    /// Time stamp the next call is due at, in ns
+    due_ns: Option<i64>,
+
+    /// Interval between the most recent calls BSEC asked for, in ns
+    interval_ns: i64,
+
+    /// Counters of the calls since startup
+    stats: SchedulerStats,
+}
+
+impl<C: MonotonicClock> Scheduler<C> {
+    /// Create a new scheduler
+    ///
+    /// # Arguments
+    /// * `clock`: Clock the time stamps are taken from
+    /// * `config`: Configuration of the scheduler
+    #[must_use]
+    pub fn new(clock: C, config: SchedulerConfig) -> Self {
+        Self {
+            clock,
+            config,
+            offset_ns: 0,
+            last_timestamp_ns: None,
+            due_ns: None,
+            interval_ns: 0,
+            stats: SchedulerStats::default(),
+        }
+    }
+
+    /// Call `periodic_process` with the current time
+    ///
+    /// Late calls and timing warnings from BSEC are counted and logged, but
+    /// do not fail the call.
+    ///
+    /// # Arguments
+    /// * `bsec`: The BSEC instance to process with
+    ///
+    /// # Errors
+    /// Returns an error if reading or processing the data failed.
+    pub fn step<I2C: I2c, S: StateStore, D: DelayNs>(
+        &mut self,
+        bsec: &mut Bsec<I2C, S, D>,
+    ) -> Result<(), BsecError> {
+        let timestamp_ns = self.timestamp_ns();
+        if let Some(due_ns) = self.due_ns {
+            // BSEC tolerates calls within 1/16 of the interval
+            if timestamp_ns - due_ns > self.interval_ns / 16 {
+                self.stats.late_calls += 1;
+                log::warn!(
+                    "BSEC call is {} ms late",
+                    (timestamp_ns - due_ns) / 1_000_000
+                );
+            }
+        }
+
+        self.stats.calls += 1;
+        let result = bsec.periodic_process(timestamp_ns);
+        // The warning itself is logged by `periodic_process`
+        if bsec
+            .get_warnings()
+            .iter()
+            .any(|warning| warning.is_timing())
+        {
+            self.stats.timing_violations += 1;
+        }
+        if result.is_err() {
+            self.stats.errors += 1;
+        }
+
+        let next_call_ns = bsec.get_next_call_time();
+        if next_call_ns > timestamp_ns {
+            let previous_ns = self
+                .due_ns
+                .filter(|due_ns| *due_ns < next_call_ns)
+                .unwrap_or(timestamp_ns);
+            self.interval_ns = next_call_ns - previous_ns;
+            self.due_ns = Some(next_call_ns);
+        } else {
+            self.due_ns = None;
+        }
+
+        result
+    }
+
+    /// Get the time to sleep until the next call is due
+    ///
+    /// # Returns
+    /// The time until the next call is due, zero if it is already due, or the
+    /// retry interval if BSEC did not give a next call time
+    pub fn time_until_next_call(&self) -> Duration {
+        let Some(due_ns) = self.due_ns else {
+            return self.config.retry_interval;
+        };
+
+        // The clock going backwards is only handled when stepping, so polling
+        // the sleep time does not change the time stamps
+        let now_ns = self.clock.now_ns().saturating_add(self.offset_ns);
+        let remaining_ns = due_ns - now_ns.max(self.last_timestamp_ns.unwrap_or(now_ns));
+        Duration::from_nanos(u64::try_from(remaining_ns).unwrap_or(0)).min(self.config.max_sleep)
+    }
+
+    /// Get the counters of the calls since startup
+    ///
+    /// # Returns
+    /// The counters of the calls since startup
+    #[must_use]
+    pub fn stats(&self) -> SchedulerStats {
+        self.stats
+    }
+
+    /// Get the time stamp for BSEC, which never goes backwards
+    ///
+    /// # Returns
+    /// The time stamp in ns
+    fn timestamp_ns(&mut self) -> i64 {
+        let mut timestamp_ns = self.clock.now_ns().saturating_add(self.offset_ns);
+        if let Some(last_timestamp_ns) = self.last_timestamp_ns {
+            if timestamp_ns < last_timestamp_ns {
+                self.stats.clock_jumps += 1;
+                log::warn!(
+                    "Clock went back {} ms",
+                    (last_timestamp_ns - timestamp_ns) / 1_000_000
+                );
+                self.offset_ns += last_timestamp_ns - timestamp_ns;
+                timestamp_ns = last_timestamp_ns;
+            }
+        }
+        self.last_timestamp_ns = Some(timestamp_ns);
+        timestamp_ns
+    }
+}
+
+// The tests rely on the exact schedule of the stand-in for the library
+#[cfg(all(test, feature = "mock-bsec"))]
+mod test {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::PoisonError;
+
+    use super::super::config::ConfigSelection;
+    use super::super::replay::{NoDelay, NoSensor};
+    use super::super::state_store::MemoryStateStore;
+    use super::super::{BsecWarning, SampleRate, LIBRARY_LOCK};
+    use super::*;
+
+    /// Low power sample interval
+    const LP_INTERVAL_NS: i64 = 3_000_000_000;
+
+    /// Clock that is moved by the test
+    #[derive(Clone, Default)]
+    struct TestClock {
+        /// The current time in ns
+        now_ns: Rc<Cell<i64>>,
+    }
+
+    impl MonotonicClock for TestClock {
+        fn now_ns(&self) -> i64 {
+            self.now_ns.get()
+        }
+    }
+
+    /// Create a BSEC instance subscribed at the low power sample rate
+    ///
+    /// # Returns
+    /// The BSEC instance
+    fn bsec() -> Bsec<NoSensor, MemoryStateStore, NoDelay> {
+        let mut bsec = Bsec::new(NoSensor, 0.0, MemoryStateStore::new(), NoDelay);
+        bsec.init_library(ConfigSelection::default()).unwrap();
+        bsec.subscribe_all_non_scan(SampleRate::LowPower).unwrap();
+        bsec
+    }
+
+    /// Test early and late calls are counted, and the schedule is kept.
+    #[test]
+    fn test_schedule() {
+        let _lock = LIBRARY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut bsec = bsec();
+        let clock = TestClock::default();
+        let mut scheduler = Scheduler::new(clock.clone(), SchedulerConfig::default());
+
+        // There is no sensor, so the measurement fails, but the schedule is still set
+        assert!(scheduler.step(&mut bsec).is_err());
+        assert_eq!(scheduler.time_until_next_call(), Duration::from_secs(3));
+
+        // An early call only gives a warning, and the schedule stays the same
+        clock.now_ns.set(LP_INTERVAL_NS / 3);
+        scheduler.step(&mut bsec).unwrap();
+        assert_eq!(scheduler.time_until_next_call(), Duration::from_secs(2));
+
+        // A late call, e.g. because the bus was busy
+        clock.now_ns.set(LP_INTERVAL_NS + LP_INTERVAL_NS / 3);
+        assert!(scheduler.step(&mut bsec).is_err());
+        assert_eq!(scheduler.time_until_next_call(), Duration::from_secs(2));
+
+        assert_eq!(
+            scheduler.stats(),
+            SchedulerStats {
+                calls: 3,
+                late_calls: 1,
+                timing_violations: 1,
+                clock_jumps: 0,
+                errors: 2,
+            }
+        );
+        let warnings = bsec.get_warning_counts();
+        assert_eq!(warnings.timing, 1);
+        assert_eq!(warnings.last, Some(BsecWarning::CallTimingViolation));
+    }
+
+    /// Test the time stamps do not go backwards when the clock does.
+    #[test]
+    fn test_clock_jump() {
+        let _lock = LIBRARY_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut bsec = bsec();
+        let clock = TestClock::default();
+        clock.now_ns.set(10 * LP_INTERVAL_NS);
+        let mut scheduler = Scheduler::new(clock.clone(), SchedulerConfig::default());
+        assert!(scheduler.step(&mut bsec).is_err());
+
+        // Polling the sleep time does not handle the jump
+        clock.now_ns.set(LP_INTERVAL_NS);
+        assert_eq!(scheduler.time_until_next_call(), Duration::from_secs(3));
+        assert_eq!(scheduler.time_until_next_call(), Duration::from_secs(3));
+        assert_eq!(scheduler.stats().clock_jumps, 0);
+
+        // The next call holds the time stamp, as the time lost is unknown, so it is early
+        clock.now_ns.set(2 * LP_INTERVAL_NS);
+        scheduler.step(&mut bsec).unwrap();
+        assert_eq!(scheduler.stats().clock_jumps, 1);
+        assert_eq!(scheduler.time_until_next_call(), Duration::from_secs(3));
+
+        // After which the calls are on schedule again
+        clock.now_ns.set(3 * LP_INTERVAL_NS);
+        assert!(scheduler.step(&mut bsec).is_err());
+        let stats = scheduler.stats();
+        assert_eq!(stats.clock_jumps, 1);
+        assert_eq!(stats.late_calls, 0);
+        assert_eq!(stats.timing_violations, 1);
+    }
+}
@@ -0,0 +1,482 @@
+//! Backends for storing the state of the BSEC library.
+//!
+//! The state holds the calibration of the library, so restoring it avoids
+//! having to recalibrate after a restart. Storing it somewhere other than the
+//! local flash avoids wearing the flash down, and allows the calibration to be
+//! restored after replacing a board.
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+#[cfg(target_os = "espidf")]
+use std::sync::mpsc;
+#[cfg(target_os = "espidf")]
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "espidf")]
+use esp_idf_svc::mqtt::client::{
+    Details, EspMqttClient, EventPayload, MqttClientConfiguration, QoS,
+};
+#[cfg(target_os = "espidf")]
+use esp_idf_svc::nvs::{EspNvs, EspNvsPartition, NvsPartitionId};
+#[cfg(target_os = "espidf")]
+use esp_idf_sys::esp_crt_bundle_attach;
+
+use super::BsecError;
+
+/// Storage for the state of the BSEC library
+pub trait StateStore {
+    /// Load the most recently saved state
+    ///
+    /// # Returns
+    /// The saved state, or `None` if no state has been saved
+    ///
+    /// # Errors
+    /// Returns an error if reading the state from the store failed
+    fn load(&mut self) -> Result<Option<Vec<u8>>, BsecError>;
+
+    /// Load every saved state the store still holds
+    ///
+    /// Stores that keep older generations of the state return them as well,
+    /// so that an older state can be used if the newest one is corrupt.
+    ///
+    /// # Returns
+    /// The saved states, newest first
+    ///
+    /// # Errors
+    /// Returns an error if reading the states from the store failed
+    fn load_all(&mut self) -> Result<Vec<Vec<u8>>, BsecError> {
+        Ok(self.load()?.into_iter().collect())
+    }
+
+    /// Save the state, replacing any previously saved state
+    ///
+    /// # Arguments
+    /// * `state`: The state to save
+    ///
+    /// # Errors
+    /// Returns an error if writing the state to the store failed
+    fn save(&mut self, state: &[u8]) -> Result<(), BsecError>;
+}
+
+/// State stored in a file, such as on the `LittleFS` partition
+///
+/// The state is written to a temporary file that is then renamed over the
+/// state file, so a power loss while saving never corrupts the saved state.
+/// Older generations of the state are kept in `<path>.1`, `<path>.2`, etc.
+pub struct FileStateStore {
+    /// Path to the file that stores the state
+    path: PathBuf,
+
+    /// Number of generations of the state to keep, including the newest
+    generations: usize,
+}
+
+impl FileStateStore {
+    /// Create a new file backed store
+    ///
+    /// # Arguments
+    /// * `path`: Path to the file to store the state in
+    /// * `generations`: Number of generations of the state to keep, including the newest
+    ///
+    /// # Panics
+    /// Will panic if `generations` is zero
+    #[must_use]
+    pub fn new(path: impl Into<PathBuf>, generations: usize) -> Self {
+        assert!(generations > 0, "At least one generation must be kept");
+        Self {
+            path: path.into(),
+            generations,
+        }
+    }
+
+    /// Get the path of a generation of the state
+    ///
+    /// # Arguments
+    /// * `generation`: The generation, where 0 is the newest
+    ///
+    /// # Returns
+    /// The path the generation is stored at
+    fn generation_path(&self, generation: usize) -> PathBuf {
+        if generation == 0 {
+            self.path.clone()
+        } else {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{generation}"));
+            PathBuf::from(path)
+        }
+    }
+
+    /// Get the path of the temporary file new states are written to
+    ///
+    /// # Returns
+    /// The path of the temporary file
+    fn temp_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".tmp");
+        PathBuf::from(path)
+    }
+}
+
+impl StateStore for FileStateStore {
+    fn load(&mut self) -> Result<Option<Vec<u8>>, BsecError> {
+        Ok(self.load_all()?.into_iter().next())
+    }
+
+    fn load_all(&mut self) -> Result<Vec<Vec<u8>>, BsecError> {
+        // A temporary file left behind is only complete if the power was lost
+        // between writing it and renaming it, so it is the newest if it is valid.
+        // A generation that cannot be read is skipped, so the others can still be used.
+        Ok(std::iter::once(self.temp_path())
+            .chain((0..self.generations).map(|generation| self.generation_path(generation)))
+            .filter(|path| path.exists())
+            .filter_map(|path| match fs::read(&path) {
+                Ok(state) => Some(state),
+                Err(error) => {
+                    log::warn!("Failed to read the BSEC state {}: {error}", path.display());
+                    None
+                }
+            })
+            .collect())
+    }
+
+    fn save(&mut self, state: &[u8]) -> Result<(), BsecError> {
+        let temp_path = self.temp_path();
+        let mut file = File::create(&temp_path)?;
+        file.write_all(state)?;
+        file.sync_all()?;
+        drop(file);
+
+        // Shift the older generations along, dropping the oldest
+        for generation in (1..self.generations).rev() {
+            let previous = self.generation_path(generation - 1);
+            if previous.exists() {
+                fs::rename(previous, self.generation_path(generation))?;
+            }
+        }
+
+        fs::rename(temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "espidf")]
+/// State stored as a blob in a NVS partition
+pub struct NvsStateStore<T: NvsPartitionId> {
+    /// The NVS namespace the state is stored in
+    nvs: EspNvs<T>,
+
+    /// Key the state is stored under
+    key: String,
+}
+
+#[cfg(target_os = "espidf")]
+impl<T: NvsPartitionId> NvsStateStore<T> {
+    /// Create a new NVS backed store
+    ///
+    /// # Arguments
+    /// * `partition`: The NVS partition to store the state in
+    /// * `namespace`: The NVS namespace to store the state in
+    /// * `key`: The key to store the state under (15 characters at most)
+    ///
+    /// # Errors
+    /// Returns an error if opening the namespace failed
+    pub fn new(
+        partition: EspNvsPartition<T>,
+        namespace: &str,
+        key: &str,
+    ) -> Result<Self, BsecError> {
+        Ok(Self {
+            nvs: EspNvs::new(partition, namespace, true)?,
+            key: key.to_owned(),
+        })
+    }
+}
+
+#[cfg(target_os = "espidf")]
+impl<T: NvsPartitionId> StateStore for NvsStateStore<T> {
+    fn load(&mut self) -> Result<Option<Vec<u8>>, BsecError> {
+        let Some(len) = self.nvs.blob_len(&self.key)? else {
+            return Ok(None);
+        };
+        let mut buffer = vec![0; len];
+        Ok(self
+            .nvs
+            .get_blob(&self.key, &mut buffer)?
+            .map(<[u8]>::to_vec))
+    }
+
+    fn save(&mut self, state: &[u8]) -> Result<(), BsecError> {
+        self.nvs.set_blob(&self.key, state)?;
+        Ok(())
+    }
+}
+
+/// State held in memory, for testing or when the state does not need to outlive the program
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStateStore {
+    /// The saved state
+    state: Option<Vec<u8>>,
+}
+
+impl MemoryStateStore {
+    /// Create a new, empty in-memory store
+    #[must_use]
+    pub fn new() -> Self {
+        Self { state: None }
+    }
+
+    /// Get the saved state
+    ///
+    /// # Returns
+    /// The saved state, if one has been saved
+    #[must_use]
+    pub fn state(&self) -> Option<&[u8]> {
+        self.state.as_deref()
+    }
+}
+
+impl StateStore for MemoryStateStore {
+    fn load(&mut self) -> Result<Option<Vec<u8>>, BsecError> {
+        Ok(self.state.clone())
+    }
+
+    fn save(&mut self, state: &[u8]) -> Result<(), BsecError> {
+        self.state = Some(state.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "espidf")]
+/// Events forwarded from the MQTT connection to the store
+enum MqttEvent {
+    /// Connected to the broker
+    Connected,
+
+    /// State received on the state topic
+    Received(Vec<u8>),
+}
+
+#[cfg(target_os = "espidf")]
+/// State stored as a retained message on a MQTT broker
+///
+/// Each device should use its own topic.
+pub struct MqttStateStore {
+    /// The MQTT client to publish and subscribe with
+    client: EspMqttClient<'static>,
+
+    /// Topic the state is stored on
+    topic: String,
+
+    /// Events from the MQTT connection
+    events: mpsc::Receiver<MqttEvent>,
+
+    /// Whether the client has connected to the broker
+    connected: bool,
+
+    /// How long to wait for the broker when loading the state
+    timeout: Duration,
+}
+
+#[cfg(target_os = "espidf")]
+impl MqttStateStore {
+    /// Create a new MQTT backed store
+    ///
+    /// # Arguments
+    /// * `broker_url`: The MQTT Broker URL
+    /// * `username`: MQTT Broker Username
+    /// * `password`: MQTT Broker Password
+    /// * `client_id`: MQTT client ID, which no other client on the broker may
+    ///   use, including the one publishing the sensor data
+    /// * `topic`: Topic to store the state on
+    /// * `timeout`: How long to wait for the broker when loading the state
+    ///
+    /// # Errors
+    /// Returns an error if creating the MQTT client or spawning its event thread failed
+    pub fn new(
+        broker_url: &'static str,
+        username: &'static str,
+        password: &'static str,
+        client_id: &str,
+        topic: &str,
+        timeout: Duration,
+    ) -> Result<Self, BsecError> {
+        let mqtt_config = MqttClientConfiguration {
+            crt_bundle_attach: Some(esp_crt_bundle_attach),
+            client_id: Some(client_id),
+            username: Some(username),
+            password: Some(password),
+            ..Default::default()
+        };
+
+        let (client, mut connection) = EspMqttClient::new(broker_url, &mqtt_config)?;
+        let (sender, events) = mpsc::channel();
+        let state_topic = topic.to_owned();
+
+        std::thread::Builder::new()
+            .stack_size(6000)
+            .spawn(move || {
+                while let Ok(event) = connection.next() {
+                    let event = match event.payload() {
+                        EventPayload::Connected(_) => MqttEvent::Connected,
+                        EventPayload::Received {
+                            topic: Some(topic),
+                            data,
+                            details: Details::Complete,
+                            ..
+                        } if topic == state_topic => MqttEvent::Received(data.to_vec()),
+                        _ => continue,
+                    };
+                    if sender.send(event).is_err() {
+                        break;
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            client,
+            topic: topic.to_owned(),
+            events,
+            connected: false,
+            timeout,
+        })
+    }
+
+    /// Wait for the next event from the connection
+    ///
+    /// # Arguments
+    /// * `deadline`: When to stop waiting
+    ///
+    /// # Returns
+    /// The next event, or `None` if the deadline passed first
+    fn next_event(&self, deadline: Instant) -> Option<MqttEvent> {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        self.events.recv_timeout(remaining).ok()
+    }
+}
+
+#[cfg(target_os = "espidf")]
+impl StateStore for MqttStateStore {
+    fn load(&mut self) -> Result<Option<Vec<u8>>, BsecError> {
+        let deadline = Instant::now() + self.timeout;
+
+        while !self.connected {
+            match self.next_event(deadline) {
+                Some(MqttEvent::Connected) => self.connected = true,
+                Some(MqttEvent::Received(_)) => {}
+                None => return Err(BsecError::StateStoreTimeout),
+            }
+        }
+
+        // The broker sends the retained message as soon as we subscribe. If
+        // nothing arrives in time, then no state has been saved.
+        self.client.subscribe(&self.topic, QoS::AtLeastOnce)?;
+        let mut state = None;
+        while let Some(event) = self.next_event(deadline) {
+            if let MqttEvent::Received(data) = event {
+                state = Some(data);
+                break;
+            }
+        }
+        self.client.unsubscribe(&self.topic)?;
+
+        Ok(state)
+    }
+
+    fn save(&mut self, state: &[u8]) -> Result<(), BsecError> {
+        self.client
+            .publish(&self.topic, QoS::AtLeastOnce, true, state)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_dir;
+    use super::*;
+
+    /// Test a state saved to a file is loaded back, and replaces the previous state.
+    #[test]
+    fn test_file_store() {
+        let dir = test_dir("file-store");
+        let mut store = FileStateStore::new(dir.join("state.bin"), 1);
+        assert_eq!(store.load().unwrap(), None);
+        assert_eq!(store.load_all().unwrap(), Vec::<Vec<u8>>::new());
+
+        store.save(&[1, 2, 3]).unwrap();
+        assert_eq!(store.load().unwrap(), Some(vec![1, 2, 3]));
+        store.save(&[4, 5]).unwrap();
+        assert_eq!(store.load().unwrap(), Some(vec![4, 5]));
+
+        // A new store for the same file sees the saved state, as after a restart
+        let mut store = FileStateStore::new(dir.join("state.bin"), 1);
+        assert_eq!(store.load_all().unwrap(), vec![vec![4, 5]]);
+        assert!(!store.temp_path().exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Test only the configured number of generations is kept, newest first.
+    #[test]
+    fn test_file_store_rotation() {
+        let dir = test_dir("file-store-rotation");
+        let mut store = FileStateStore::new(dir.join("state.bin"), 1);
+        for state in 1..=3 {
+            store.save(&[state]).unwrap();
+        }
+        assert_eq!(store.load_all().unwrap(), vec![vec![3]]);
+        assert!(!store.generation_path(1).exists());
+
+        let mut store = FileStateStore::new(dir.join("state3.bin"), 3);
+        store.save(&[1]).unwrap();
+        assert_eq!(store.load_all().unwrap(), vec![vec![1]]);
+        for state in 2..=4 {
+            store.save(&[state]).unwrap();
+        }
+        assert_eq!(store.load_all().unwrap(), vec![vec![4], vec![3], vec![2]]);
+        assert_eq!(store.load().unwrap(), Some(vec![4]));
+        assert!(!store.generation_path(3).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Test a temporary file left behind is loaded first, even if it is
+    /// truncated, and an unreadable generation does not hide the others.
+    #[test]
+    fn test_file_store_leftovers() {
+        let dir = test_dir("file-store-leftovers");
+        let mut store = FileStateStore::new(dir.join("state.bin"), 3);
+        for state in 1..=3 {
+            store.save(&[state, state]).unwrap();
+        }
+
+        // Power lost part way through writing the temporary file
+        fs::write(store.temp_path(), [4]).unwrap();
+        assert_eq!(
+            store.load_all().unwrap(),
+            vec![vec![4], vec![3, 3], vec![2, 2], vec![1, 1]]
+        );
+
+        fs::remove_file(store.generation_path(1)).unwrap();
+        fs::create_dir(store.generation_path(1)).unwrap();
+        assert_eq!(
+            store.load_all().unwrap(),
+            vec![vec![4], vec![3, 3], vec![1, 1]]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Test a state saved in memory is loaded back, and replaces the previous state.
+    #[test]
+    fn test_memory_store() {
+        let mut store = MemoryStateStore::new();
+        assert_eq!(store.load().unwrap(), None);
+        assert_eq!(store.state(), None);
+
+        store.save(&[1, 2, 3]).unwrap();
+        store.save(&[4, 5]).unwrap();
+        assert_eq!(store.load().unwrap(), Some(vec![4, 5]));
+        assert_eq!(store.load_all().unwrap(), vec![vec![4, 5]]);
+        assert_eq!(store.state(), Some([4, 5].as_slice()));
+    }
+}
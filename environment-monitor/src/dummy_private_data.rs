@@ -4,6 +4,7 @@
 /// WiFi SSID
 pub const SSID: &str = "ssid";
 
+#[allow(clippy::doc_markdown)]
 /// WiFi Password
 pub const WIFI_PASS: &str = "pass";
 
@@ -41,3 +42,18 @@ pub const AIO_TVOC_TOPIC: &str = "topics/dummy";
 
 /// Lux Topic
 pub const AIO_LUX_TOPIC: &str = "topics/dummy";
+
+/// Daily Light Integral Topic
+pub const AIO_DLI_TOPIC: &str = "topics/dummy";
+
+/// Light Statistics Topic
+pub const AIO_LIGHT_STATS_TOPIC: &str = "topics/dummy";
+
+/// Finished Day Light Statistics Topic
+pub const AIO_LIGHT_DAY_TOPIC: &str = "topics/dummy";
+
+/// BSEC Warnings Topic
+pub const AIO_BSEC_WARNINGS_TOPIC: &str = "topics/dummy";
+
+/// Command Topic
+pub const AIO_COMMAND_TOPIC: &str = "topics/dummy";
@@ -0,0 +1,523 @@
+//! Open-source indoor air quality estimator, for builds without the BSEC library.
+//!
+//! The raw BME688 readings are turned into an IAQ-like index from 0 (clean) to
+//! 500 (heavily polluted), and the same outputs as BSEC are filled so that the
+//! rest of the application works unchanged. It is much simpler than Bosch's
+//! algorithm, and its values are not comparable with those of BSEC:
+//!
+//! * The gas resistance is corrected for humidity, as water vapor lowers the
+//!   resistance the same way pollutants do.
+//! * The clean air baseline adapts to the sensor. It rises quickly towards
+//!   higher (cleaner) resistances, and falls slowly, so that drift of the
+//!   sensor is followed but lasting pollution is not learned as clean air.
+//! * 75 % of the index comes from the resistance relative to the baseline, and
+//!   25 % from how far the humidity is from the optimum.
+//! * The eCO2 and breath VOC outputs are rough mappings of the index.
+use std::time::Duration;
+
+use bme68x::BME68xData;
+
+use crate::bsec::{StructuredOutputs, VirtualSensorData};
+
+/// Status bits of a reading with a valid gas measurement from a stable heater
+const GAS_VALID_STATUS: u8 = 0x30;
+
+/// Longest gap between readings that counts towards the calibration. Longer
+/// gaps (e.g. while the sensor could not be read) are left out.
+const MAX_READING_GAP: Duration = Duration::from_mins(5);
+
+/// Share of the index that comes from the gas resistance, the rest comes from the humidity
+const GAS_WEIGHT: f32 = 0.75;
+
+/// Highest value of the index
+const MAX_IAQ: f32 = 500.0;
+
+/// Magic number at the start of a serialized state
+const STATE_MAGIC: [u8; 4] = *b"IAQE";
+
+/// Configuration of the estimator
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IaqEstimatorConfig {
+    /// Offset of the temperature caused by the heat of the device, in degrees C
+    pub temp_offset: f32,
+
+    /// Time after startup that the heater needs to settle, before the gas
+    /// resistance is used
+    pub burn_in: Duration,
+
+    /// Time constant for the baseline to rise towards a higher gas resistance
+    pub baseline_rise: Duration,
+
+    /// Time constant for the baseline to fall towards a lower gas resistance
+    pub baseline_fall: Duration,
+
+    /// Calibration time after which the index is considered fully accurate
+    pub calibration_period: Duration,
+
+    /// Relative humidity with the best air quality, in %
+    pub humidity_optimum: f32,
+
+    /// Change of the natural log of the gas resistance per g/m³ of absolute humidity
+    pub humidity_slope: f32,
+
+    /// Absolute humidity the gas resistance is corrected to, in g/m³
+    pub humidity_reference: f32,
+}
+
+impl Default for IaqEstimatorConfig {
+    fn default() -> Self {
+        Self {
+            temp_offset: 0.0,
+            burn_in: Duration::from_mins(5),
+            baseline_rise: Duration::from_mins(10),
+            baseline_fall: Duration::from_hours(48),
+            calibration_period: Duration::from_hours(24),
+            humidity_optimum: 40.0,
+            humidity_slope: 0.03,
+            // 40 % at 21 degrees C
+            humidity_reference: 7.3,
+        }
+    }
+}
+
+/// Calibration of the estimator, which can be saved and restored across restarts
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EstimatorState {
+    /// Clean air gas resistance, corrected for humidity, in ohms. Zero if not known yet.
+    pub gas_baseline: f32,
+
+    /// Time the baseline has been calibrated for, in seconds
+    pub calibration_s: u64,
+}
+
+impl EstimatorState {
+    /// Length of the serialized state in bytes
+    pub const SERIALIZED_LEN: usize = 16;
+
+    /// Serialize the state, for saving it
+    ///
+    /// # Returns
+    /// The serialized state
+    #[must_use]
+    pub fn to_bytes(self) -> [u8; Self::SERIALIZED_LEN] {
+        let mut bytes = [0; Self::SERIALIZED_LEN];
+        bytes[..4].copy_from_slice(&STATE_MAGIC);
+        bytes[4..8].copy_from_slice(&self.gas_baseline.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.calibration_s.to_le_bytes());
+        bytes
+    }
+
+    /// Deserialize a saved state
+    ///
+    /// # Arguments
+    /// * `bytes`: The serialized state
+    ///
+    /// # Returns
+    /// The state, or `None` if the data is not a valid state
+    #[must_use]
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SERIALIZED_LEN || bytes[..4] != STATE_MAGIC {
+            return None;
+        }
+
+        let gas_baseline = f32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let mut calibration = [0; 8];
+        calibration.copy_from_slice(&bytes[8..]);
+        (gas_baseline.is_finite() && gas_baseline >= 0.0).then_some(Self {
+            gas_baseline,
+            calibration_s: u64::from_le_bytes(calibration),
+        })
+    }
+}
+
+/// Estimates the indoor air quality from the raw sensor readings
+pub struct IaqEstimator {
+    /// Configuration of the estimator
+    config: IaqEstimatorConfig,
+
+    /// Calibration of the estimator
+    state: EstimatorState,
+
+    /// Fraction of a second of calibration time not yet added to the state
+    calibration_remainder_ns: u64,
+
+    /// Time stamp of the first reading, in ns
+    first_reading_ns: Option<i64>,
+
+    /// Time stamp of the previous gas reading, in ns
+    last_gas_ns: Option<i64>,
+
+    /// Outputs of the most recent reading
+    outputs: StructuredOutputs,
+}
+
+impl IaqEstimator {
+    /// Create a new, uncalibrated estimator
+    ///
+    /// # Arguments
+    /// * `config`: Configuration of the estimator
+    #[must_use]
+    pub fn new(config: IaqEstimatorConfig) -> Self {
+        Self {
+            config,
+            state: EstimatorState::default(),
+            calibration_remainder_ns: 0,
+            first_reading_ns: None,
+            last_gas_ns: None,
+            outputs: StructuredOutputs::new(),
+        }
+    }
+
+    /// Get the calibration of the estimator, for saving it
+    ///
+    /// # Returns
+    /// The calibration of the estimator
+    #[must_use]
+    pub fn get_state(&self) -> EstimatorState {
+        self.state
+    }
+
+    /// Restore a saved calibration
+    ///
+    /// The heater still has to burn in before the index is used again.
+    ///
+    /// # Arguments
+    /// * `state`: The saved calibration
+    pub fn set_state(&mut self, state: EstimatorState) {
+        self.state = state;
+        self.calibration_remainder_ns = 0;
+    }
+
+    /// Process a reading from the sensor
+    ///
+    /// # Arguments
+    /// * `data`: The reading from the sensor
+    /// * `timestamp_ns`: Time stamp of the reading in ns
+    pub fn process(&mut self, data: &BME68xData, timestamp_ns: i64) {
+        let first_reading_ns = *self.first_reading_ns.get_or_insert(timestamp_ns);
+        let burnt_in = duration_between(first_reading_ns, timestamp_ns) >= self.config.burn_in;
+
+        let temperature = data.temperature_degc();
+        let humidity = data.humidity_percent();
+        let compensated_temp = temperature - self.config.temp_offset;
+        // The same amount of water is a higher relative humidity in cooler air
+        let compensated_humidity = (humidity * saturation_vapor_pressure(temperature)
+            / saturation_vapor_pressure(compensated_temp))
+        .clamp(0.0, 100.0);
+
+        let mut outputs = StructuredOutputs::new();
+        let output = |signal, accuracy| VirtualSensorData {
+            time_stamp: timestamp_ns,
+            signal,
+            signal_dimensions: 1,
+            accuracy,
+            valid: true,
+        };
+        outputs.raw_temp = output(temperature, 0);
+        outputs.raw_pressure = output(data.pressure_pa(), 0);
+        outputs.raw_humidity = output(humidity, 0);
+        outputs.compensated_temp = output(compensated_temp, 0);
+        outputs.compensated_humidity = output(compensated_humidity, 0);
+        outputs.stabilization_status = output(f32::from(u8::from(burnt_in)), 0);
+
+        if data.status & GAS_VALID_STATUS == GAS_VALID_STATUS {
+            let gas_resistance = data.gas_resistance_ohm();
+            let corrected_gas = gas_resistance
+                * (self.config.humidity_slope
+                    * (absolute_humidity(compensated_temp, compensated_humidity)
+                        - self.config.humidity_reference))
+                    .exp();
+
+            if burnt_in {
+                self.update_baseline(corrected_gas, timestamp_ns);
+            }
+            let accuracy = self.accuracy(burnt_in);
+
+            // Cleaner air has a higher gas resistance
+            let gas_ratio = if self.state.gas_baseline > 0.0 {
+                (corrected_gas / self.state.gas_baseline).clamp(0.0, 1.0)
+            } else {
+                1.0
+            };
+            let humidity_score = if compensated_humidity < self.config.humidity_optimum {
+                compensated_humidity / self.config.humidity_optimum
+            } else {
+                (100.0 - compensated_humidity) / (100.0 - self.config.humidity_optimum)
+            };
+            let static_iaq = (1.0 - gas_ratio) * MAX_IAQ;
+            let iaq =
+                (1.0 - (GAS_WEIGHT * gas_ratio + (1.0 - GAS_WEIGHT) * humidity_score)) * MAX_IAQ;
+
+            outputs.raw_gas = output(gas_resistance, 0);
+            outputs.raw_gas_index = output(f32::from(data.gas_index), 0);
+            outputs.iaq = output(iaq, accuracy);
+            outputs.static_iaq = output(static_iaq, accuracy);
+            outputs.co2_eq = output(400.0 + iaq * 4.0, accuracy);
+            outputs.breath_voc_eq = output(0.5 * ((iaq - 50.0) / 50.0).exp2(), accuracy);
+            outputs.gas_percentage = output(gas_ratio * 100.0, accuracy);
+            outputs.run_in_status = output(f32::from(u8::from(accuracy >= 2)), 0);
+        }
+
+        self.outputs = outputs;
+    }
+
+    /// Get the outputs of the most recent reading
+    ///
+    /// # Returns
+    /// The outputs of the most recent reading. Gas based outputs are not
+    /// valid if the reading had no valid gas measurement.
+    #[must_use]
+    pub fn get_output_data(&self) -> StructuredOutputs {
+        self.outputs
+    }
+
+    /// Move the baseline towards a gas reading, and add to the calibration time
+    ///
+    /// # Arguments
+    /// * `corrected_gas`: The gas resistance corrected for humidity, in ohms
+    /// * `timestamp_ns`: Time stamp of the reading in ns
+    #[allow(clippy::cast_possible_truncation)]
+    fn update_baseline(&mut self, corrected_gas: f32, timestamp_ns: i64) {
+        let elapsed = self
+            .last_gas_ns
+            .map(|last_gas_ns| duration_between(last_gas_ns, timestamp_ns))
+            .filter(|elapsed| *elapsed <= MAX_READING_GAP)
+            .unwrap_or_default();
+        self.last_gas_ns = Some(timestamp_ns);
+
+        if self.state.gas_baseline <= 0.0 {
+            self.state.gas_baseline = corrected_gas;
+            return;
+        }
+
+        let time_constant = if corrected_gas > self.state.gas_baseline {
+            self.config.baseline_rise
+        } else {
+            self.config.baseline_fall
+        };
+        let weight = (elapsed.as_secs_f32() / time_constant.as_secs_f32()).min(1.0);
+        self.state.gas_baseline += (corrected_gas - self.state.gas_baseline) * weight;
+
+        // The gaps are at most a few minutes, so fit easily
+        self.calibration_remainder_ns += elapsed.as_nanos() as u64;
+        self.state.calibration_s += self.calibration_remainder_ns / 1_000_000_000;
+        self.calibration_remainder_ns %= 1_000_000_000;
+    }
+
+    /// Get the accuracy the index will have once the heater has burnt in
+    ///
+    /// # Returns
+    /// The accuracy for the current calibration
+    #[must_use]
+    pub fn get_calibrated_accuracy(&self) -> u8 {
+        self.accuracy(true)
+    }
+
+    /// Get the accuracy of the index
+    ///
+    /// # Arguments
+    /// * `burnt_in`: Whether the heater has burnt in
+    ///
+    /// # Returns
+    /// 0 while the heater burns in, then 1 and 2 while the baseline is
+    /// calibrating, and 3 once it has calibrated for the calibration period
+    fn accuracy(&self, burnt_in: bool) -> u8 {
+        let calibration = Duration::from_secs(self.state.calibration_s);
+        if !burnt_in || self.state.gas_baseline <= 0.0 {
+            0
+        } else if calibration < self.config.calibration_period / 4 {
+            1
+        } else if calibration < self.config.calibration_period {
+            2
+        } else {
+            3
+        }
+    }
+}
+
+/// Get the time between two time stamps
+///
+/// # Arguments
+/// * `start_ns`: The earlier time stamp, in ns
+/// * `end_ns`: The later time stamp, in ns
+///
+/// # Returns
+/// The time between the time stamps, or zero if they are out of order
+fn duration_between(start_ns: i64, end_ns: i64) -> Duration {
+    Duration::from_nanos(u64::try_from(end_ns - start_ns).unwrap_or(0))
+}
+
+/// Compute the saturation vapor pressure of water (Magnus formula)
+///
+/// # Arguments
+/// * `temperature`: The temperature in degrees C
+///
+/// # Returns
+/// The saturation vapor pressure in hPa
+fn saturation_vapor_pressure(temperature: f32) -> f32 {
+    6.112 * (17.62 * temperature / (243.12 + temperature)).exp()
+}
+
+/// Compute the absolute humidity
+///
+/// # Arguments
+/// * `temperature`: The temperature in degrees C
+/// * `humidity`: The relative humidity in %
+///
+/// # Returns
+/// The absolute humidity in g/m³
+fn absolute_humidity(temperature: f32, humidity: f32) -> f32 {
+    // 216.7 g·K/(m³·hPa) is the molar mass of water over the gas constant
+    216.7 * (humidity / 100.0) * saturation_vapor_pressure(temperature) / (273.15 + temperature)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Interval between readings
+    const INTERVAL_NS: i64 = 3_000_000_000;
+
+    /// Create a reading
+    ///
+    /// # Arguments
+    /// * `humidity`: The relative humidity in %
+    /// * `gas_resistance`: The gas resistance in ohms
+    ///
+    /// # Returns
+    /// The reading
+    fn reading(humidity: f32, gas_resistance: f32) -> BME68xData {
+        BME68xData {
+            status: 0x80 | GAS_VALID_STATUS,
+            ..BME68xData::from_readings(21.0, 101_325.0, humidity, gas_resistance)
+        }
+    }
+
+    /// Feed the same reading to the estimator for a while
+    ///
+    /// # Arguments
+    /// * `estimator`: The estimator
+    /// * `data`: The reading
+    /// * `start_ns`: Time stamp of the first reading
+    /// * `duration`: How long to feed the reading for
+    ///
+    /// # Returns
+    /// The time stamp after the last reading
+    fn feed(
+        estimator: &mut IaqEstimator,
+        data: &BME68xData,
+        start_ns: i64,
+        duration: Duration,
+    ) -> i64 {
+        let end_ns = start_ns + i64::try_from(duration.as_nanos()).unwrap();
+        let mut timestamp_ns = start_ns;
+        while timestamp_ns < end_ns {
+            estimator.process(data, timestamp_ns);
+            timestamp_ns += INTERVAL_NS;
+        }
+        timestamp_ns
+    }
+
+    /// Test the accuracy goes up as the estimator burns in and calibrates.
+    #[test]
+    fn test_calibration() {
+        let mut estimator = IaqEstimator::new(IaqEstimatorConfig::default());
+        let clean = reading(40.0, 200_000.0);
+
+        let mut now = feed(&mut estimator, &clean, 0, Duration::from_mins(4));
+        let outputs = estimator.get_output_data();
+        assert_eq!(outputs.iaq.accuracy, 0);
+        assert!(outputs.stabilization_status.signal < 0.5);
+
+        now = feed(&mut estimator, &clean, now, Duration::from_mins(2));
+        let outputs = estimator.get_output_data();
+        assert_eq!(outputs.iaq.accuracy, 1);
+        assert!(outputs.stabilization_status.signal > 0.5);
+        // Clean air at the optimum humidity
+        assert!(outputs.iaq.signal < 1.0, "{}", outputs.iaq.signal);
+        assert!((outputs.co2_eq.signal - 400.0).abs() < 5.0);
+
+        now = feed(&mut estimator, &clean, now, Duration::from_hours(6));
+        assert_eq!(estimator.get_output_data().iaq.accuracy, 2);
+        feed(&mut estimator, &clean, now, Duration::from_hours(18));
+        assert_eq!(estimator.get_output_data().iaq.accuracy, 3);
+        assert!(estimator.get_state().calibration_s >= 24 * 60 * 60 - 5 * 60);
+
+        // A restored calibration is only used once the heater burns in again
+        let mut restored = IaqEstimator::new(IaqEstimatorConfig::default());
+        assert_eq!(restored.get_calibrated_accuracy(), 0);
+        restored.set_state(estimator.get_state());
+        assert_eq!(restored.get_calibrated_accuracy(), 3);
+        feed(&mut restored, &clean, 0, Duration::from_mins(1));
+        assert_eq!(restored.get_output_data().iaq.accuracy, 0);
+    }
+
+    /// Test pollution and humidity raise the index, and pollution is not learned as clean air.
+    #[test]
+    fn test_pollution() {
+        let mut estimator = IaqEstimator::new(IaqEstimatorConfig::default());
+        let now = feed(
+            &mut estimator,
+            &reading(40.0, 200_000.0),
+            0,
+            Duration::from_hours(1),
+        );
+        let baseline = estimator.get_state().gas_baseline;
+
+        let now = feed(
+            &mut estimator,
+            &reading(40.0, 50_000.0),
+            now,
+            Duration::from_hours(1),
+        );
+        let polluted = estimator.get_output_data();
+        assert!(polluted.iaq.signal > 200.0, "{}", polluted.iaq.signal);
+        assert!(polluted.iaq.signal <= MAX_IAQ);
+        assert!(polluted.co2_eq.signal > 1000.0);
+        // The baseline only falls slowly
+        assert!(estimator.get_state().gas_baseline > baseline * 0.9);
+
+        // Humid air lowers the resistance, which is mostly compensated for
+        feed(
+            &mut estimator,
+            &reading(80.0, 150_000.0),
+            now,
+            Duration::from_mins(1),
+        );
+        let humid = estimator.get_output_data();
+        assert!(humid.iaq.signal > 0.0);
+        assert!(humid.iaq.signal < polluted.iaq.signal);
+    }
+
+    /// Test readings without a valid gas measurement leave the gas outputs invalid.
+    #[test]
+    fn test_invalid_gas() {
+        let mut estimator = IaqEstimator::new(IaqEstimatorConfig::default());
+        let data = BME68xData {
+            status: 0x80,
+            ..reading(40.0, 200_000.0)
+        };
+        estimator.process(&data, 0);
+
+        let outputs = estimator.get_output_data();
+        assert!(outputs.raw_temp.valid);
+        assert!(!outputs.iaq.valid);
+        assert!(!outputs.raw_gas.valid);
+    }
+
+    /// Test the state survives serialization, and corrupt states are rejected.
+    #[test]
+    fn test_state_bytes() {
+        let state = EstimatorState {
+            gas_baseline: 123_456.0,
+            calibration_s: 98765,
+        };
+        let bytes = state.to_bytes();
+        assert_eq!(EstimatorState::from_bytes(&bytes), Some(state));
+        assert_eq!(EstimatorState::from_bytes(&bytes[1..]), None);
+
+        let mut corrupt = bytes;
+        corrupt[0] = 0;
+        assert_eq!(EstimatorState::from_bytes(&corrupt), None);
+    }
+}
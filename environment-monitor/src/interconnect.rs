@@ -1,6 +1,7 @@
 //! Data and types for interconnect between tasks.
 /// Structure for holding data from all of the sensors
-use crate::bsec::StructuredOutputs;
+use crate::bsec::{StructuredOutputs, WarningCounts};
+use crate::light_stats::DailyLight;
 use veml7700::VemlOutput;
 
 /// Structure used to hold data collected by the sensor hub.
@@ -9,8 +10,17 @@ pub struct SensorHubData {
     /// Data from the BME688 sensor
     pub bsec: StructuredOutputs,
 
+    /// Counters of the warnings from the BSEC library
+    pub bsec_warnings: WarningCounts,
+
     /// Data from the VEML7700 sensor
     pub veml: VemlOutput,
+
+    /// Light exposure statistics for the current day
+    pub light: DailyLight,
+
+    /// Light exposure statistics for the previous day, if it was recorded
+    pub light_yesterday: Option<DailyLight>,
 }
 
 impl SensorHubData {
@@ -19,7 +29,10 @@ impl SensorHubData {
     pub fn new() -> Self {
         Self {
             bsec: StructuredOutputs::new(),
+            bsec_warnings: WarningCounts::default(),
             veml: VemlOutput::new(),
+            light: DailyLight::default(),
+            light_yesterday: None,
         }
     }
 }
@@ -1,6 +1,9 @@
 //! Logic for the application
 
 pub mod bsec;
+pub mod iaq_estimator;
 pub mod interconnect;
+pub mod light_stats;
+#[cfg(target_os = "espidf")]
 pub mod mqtt;
 pub mod private_data;
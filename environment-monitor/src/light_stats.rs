@@ -0,0 +1,410 @@
+//! Daily light exposure statistics accumulated from the VEML7700 readings.
+//!
+//! Readings are integrated over time into lux-hours and the daily light
+//! integral (DLI), along with the time spent above a set of lux bands, and the
+//! first and last times of the day it was light. The statistics reset at local
+//! midnight, using the wall clock set over SNTP.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use veml7700::VemlOutput;
+
+/// Number of lux bands that time above is accumulated for
+pub const LIGHT_BAND_COUNT: usize = 3;
+
+/// Seconds in a day
+const SECONDS_PER_DAY: i64 = 86400;
+
+/// Unix time (2024-01-01) before which the wall clock is assumed not to be set yet
+const MIN_VALID_UNIX_TIME_S: u64 = 1_704_067_200;
+
+/// Configuration of the light statistics
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightStatsConfig {
+    /// Lux thresholds to accumulate the time spent at or above
+    pub bands_lux: [f32; LIGHT_BAND_COUNT],
+
+    /// Lux at or above which it is considered light, for the first and last light times
+    pub daylight_lux: f32,
+
+    /// Offset of local time from UTC in seconds, which sets when the day rolls over
+    pub utc_offset_s: i64,
+
+    /// Photosynthetic photon flux density per lux (µmol/m²/s per lux) used
+    /// to compute the DLI. This depends on the light source.
+    pub ppfd_per_lux: f64,
+
+    /// Longest gap between readings that is integrated over. Longer gaps (e.g.
+    /// while the sensor could not be read) are left out of the statistics.
+    pub max_reading_gap: Duration,
+
+    /// Whether the light level is held at each reading until the next one,
+    /// instead of interpolated between them. This suits readings that are
+    /// only taken when the light level changes, such as in interrupt mode.
+    pub hold_readings: bool,
+}
+
+impl Default for LightStatsConfig {
+    fn default() -> Self {
+        Self {
+            bands_lux: [100.0, 1000.0, 10000.0],
+            daylight_lux: 50.0,
+            utc_offset_s: 0,
+            // Conversion for sunlight
+            ppfd_per_lux: 0.0185,
+            max_reading_gap: Duration::from_mins(5),
+            hold_readings: false,
+        }
+    }
+}
+
+/// Light exposure statistics for a single day
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DailyLight {
+    /// The day, as the number of days since the Unix epoch in local time
+    pub day: i64,
+
+    /// Light exposure in lux-hours
+    pub lux_hours: f64,
+
+    /// Daily light integral in mol/m²/day
+    pub dli: f64,
+
+    /// Seconds spent at or above each of the configured lux bands
+    pub time_above_s: [f64; LIGHT_BAND_COUNT],
+
+    /// Seconds after local midnight that it first became light, if it has
+    pub first_light_s: Option<u32>,
+
+    /// Seconds after local midnight that it was last light, if it has been
+    pub last_light_s: Option<u32>,
+}
+
+/// Accumulator for the daily light exposure statistics
+pub struct LightStats {
+    /// Configuration of the statistics
+    config: LightStatsConfig,
+
+    /// Statistics for the current day
+    today: DailyLight,
+
+    /// Statistics for the previous day, if it was recorded
+    yesterday: Option<DailyLight>,
+
+    /// Time and lux of the previous reading, if it can be integrated from
+    last_reading: Option<(Duration, f32)>,
+}
+
+impl LightStats {
+    /// Create a new accumulator
+    ///
+    /// # Arguments
+    /// * `config`: Configuration of the statistics
+    #[must_use]
+    pub fn new(config: LightStatsConfig) -> Self {
+        Self {
+            config,
+            today: DailyLight::default(),
+            yesterday: None,
+            last_reading: None,
+        }
+    }
+
+    /// Add a reading from the sensor to the statistics
+    ///
+    /// Stale readings, and readings taken before the wall clock is set, are
+    /// skipped, and the time up to the next usable reading is not counted.
+    ///
+    /// # Arguments
+    /// * `output`: The reading from the sensor
+    /// * `now`: The wall clock time the reading was taken
+    pub fn update(&mut self, output: &VemlOutput, now: SystemTime) {
+        let since_epoch = match now.duration_since(UNIX_EPOCH) {
+            Ok(since_epoch) if !output.stale && since_epoch.as_secs() >= MIN_VALID_UNIX_TIME_S => {
+                since_epoch
+            }
+            _ => {
+                self.last_reading = None;
+                return;
+            }
+        };
+
+        // Unix time will not overflow an i64 for billions of years
+        #[allow(clippy::cast_possible_wrap)]
+        let local_s = since_epoch.as_secs() as i64 + self.config.utc_offset_s;
+        let day = local_s.div_euclid(SECONDS_PER_DAY);
+        // Always less than the number of seconds in a day
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let second_of_day = local_s.rem_euclid(SECONDS_PER_DAY) as u32;
+
+        // The light level over the interval since the previous reading, if it can be integrated
+        let interval = self.last_reading.and_then(|(last_time, last_lux)| {
+            let interval = since_epoch.checked_sub(last_time)?;
+            let lux = if self.config.hold_readings {
+                last_lux
+            } else {
+                f32::midpoint(last_lux, output.lux)
+            };
+            (interval <= self.config.max_reading_gap).then_some((interval, lux))
+        });
+
+        let mut remaining = interval;
+        if day != self.today.day {
+            // Split an interval spanning midnight between the two days
+            if let Some((interval, lux)) = interval {
+                let since_midnight = Duration::from_secs(u64::from(second_of_day))
+                    + Duration::from_nanos(u64::from(since_epoch.subsec_nanos()));
+                if let Some(before_midnight) = interval.checked_sub(since_midnight) {
+                    self.integrate(before_midnight, lux);
+                    remaining = Some((since_midnight, lux));
+                }
+            }
+
+            if self.today.day != 0 {
+                self.yesterday = Some(self.today);
+            }
+            self.today = DailyLight {
+                day,
+                ..DailyLight::default()
+            };
+        }
+
+        if let Some((interval, lux)) = remaining {
+            self.integrate(interval, lux);
+        }
+        self.last_reading = Some((since_epoch, output.lux));
+
+        if output.lux >= self.config.daylight_lux {
+            self.today.first_light_s.get_or_insert(second_of_day);
+            self.today.last_light_s = Some(second_of_day);
+        }
+    }
+
+    /// Get the statistics for the current day so far
+    ///
+    /// # Returns
+    /// The statistics for the current day
+    #[must_use]
+    pub fn today(&self) -> DailyLight {
+        self.today
+    }
+
+    /// Get the statistics for the previous day
+    ///
+    /// These are the final totals of the day, which change once a day when
+    /// the day rolls over.
+    ///
+    /// # Returns
+    /// The statistics for the previous day, if it was recorded
+    #[must_use]
+    pub fn yesterday(&self) -> Option<DailyLight> {
+        self.yesterday
+    }
+
+    /// Integrate the light level over an interval into the current day
+    ///
+    /// # Arguments
+    /// * `interval`: The length of the interval
+    /// * `lux`: The average light level over the interval
+    fn integrate(&mut self, interval: Duration, lux: f32) {
+        let seconds = interval.as_secs_f64();
+        let lux_seconds = f64::from(lux) * seconds;
+
+        self.today.lux_hours += lux_seconds / 3600.0;
+        // µmol/m² to mol/m²
+        self.today.dli += lux_seconds * self.config.ppfd_per_lux / 1_000_000.0;
+        for (band, time_above) in self
+            .config
+            .bands_lux
+            .iter()
+            .zip(self.today.time_above_s.iter_mut())
+        {
+            if lux >= *band {
+                *time_above += seconds;
+            }
+        }
+    }
+}
+
+/// Format a time of day as `HH:MM:SS`
+///
+/// # Arguments
+/// * `second_of_day`: Seconds after midnight
+///
+/// # Returns
+/// The formatted time
+#[must_use]
+pub fn format_time_of_day(second_of_day: u32) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        second_of_day / 3600,
+        (second_of_day / 60) % 60,
+        second_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Local midnight (UTC+2) at the start of 2024-06-01
+    const MIDNIGHT_S: u64 = 1_717_192_800;
+
+    /// Offset of local time from UTC used by the tests
+    const UTC_OFFSET_S: i64 = 2 * 3600;
+
+    /// Create a reading
+    ///
+    /// # Arguments
+    /// * `lux`: The light level
+    ///
+    /// # Returns
+    /// A fresh reading of the light level
+    fn reading(lux: f32) -> VemlOutput {
+        VemlOutput {
+            lux,
+            stale: false,
+            ..VemlOutput::new()
+        }
+    }
+
+    /// Get a wall clock time relative to local midnight
+    ///
+    /// # Arguments
+    /// * `seconds`: Seconds after local midnight at the start of 2024-06-01
+    ///
+    /// # Returns
+    /// The wall clock time
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(MIDNIGHT_S + seconds)
+    }
+
+    /// Create an accumulator in UTC+2
+    ///
+    /// # Returns
+    /// The accumulator
+    fn stats() -> LightStats {
+        LightStats::new(LightStatsConfig {
+            utc_offset_s: UTC_OFFSET_S,
+            ..LightStatsConfig::default()
+        })
+    }
+
+    /// Check the time spent above each band
+    ///
+    /// # Arguments
+    /// * `light`: The statistics to check
+    /// * `expected`: The expected seconds above each band
+    fn assert_time_above(light: DailyLight, expected: [f64; LIGHT_BAND_COUNT]) {
+        for (actual, expected) in light.time_above_s.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-6, "{actual} != {expected}");
+        }
+    }
+
+    /// Test lux-hours, DLI and band times are integrated from the readings.
+    #[test]
+    fn test_integration() {
+        let mut stats = stats();
+        // An hour at 1000 lx, one reading a minute
+        for minute in 0..=60 {
+            stats.update(&reading(1000.0), at(8 * 3600 + minute * 60));
+        }
+
+        let today = stats.today();
+        assert!((today.lux_hours - 1000.0).abs() < 1e-6);
+        // 1000 lx * 0.0185 µmol/m²/s per lx * 3600 s
+        assert!((today.dli - 0.0666).abs() < 1e-9);
+        assert_time_above(today, [3600.0, 3600.0, 0.0]);
+
+        // A ramp is integrated with the average of each pair of readings
+        stats.update(&reading(0.0), at(9 * 3600 + 60));
+        assert!((stats.today().lux_hours - (1000.0 + 500.0 / 60.0)).abs() < 1e-6);
+        assert_time_above(stats.today(), [3660.0, 3600.0, 0.0]);
+    }
+
+    /// Test the first and last light times of the day.
+    #[test]
+    fn test_first_last_light() {
+        let mut stats = stats();
+        stats.update(&reading(10.0), at(5 * 3600));
+        assert_eq!(stats.today().first_light_s, None);
+
+        stats.update(&reading(60.0), at(6 * 3600));
+        stats.update(&reading(2000.0), at(12 * 3600));
+        stats.update(&reading(55.0), at(20 * 3600 + 30));
+        stats.update(&reading(5.0), at(21 * 3600));
+
+        let today = stats.today();
+        assert_eq!(today.first_light_s, Some(6 * 3600));
+        assert_eq!(today.last_light_s, Some(20 * 3600 + 30));
+        assert_eq!(format_time_of_day(20 * 3600 + 30), "20:00:30");
+    }
+
+    /// Test the day rolls over at local midnight, and the finished day is kept.
+    #[test]
+    fn test_rollover() {
+        let mut stats = stats();
+        stats.update(&reading(100.0), at(23 * 3600 + 59 * 60));
+        assert_eq!(stats.today().day, 19875);
+        assert_eq!(stats.yesterday(), None);
+
+        // 00:01 in local time is still 22:01 the day before in UTC, but the
+        // minute before and after local midnight go to their own days
+        stats.update(&reading(100.0), at(24 * 3600 + 60));
+        let yesterday = stats.yesterday().unwrap();
+        assert_eq!(yesterday.day, 19875);
+        assert!((yesterday.lux_hours - 100.0 / 60.0).abs() < 1e-6);
+        assert_eq!(yesterday.first_light_s, Some(23 * 3600 + 59 * 60));
+
+        let today = stats.today();
+        assert_eq!(today.day, 19876);
+        assert!((today.lux_hours - 100.0 / 60.0).abs() < 1e-6);
+        assert_eq!(today.first_light_s, Some(60));
+    }
+
+    /// Test stale readings, readings before the wall clock is set, and long
+    /// gaps are left out of the statistics.
+    #[test]
+    fn test_skipped_readings() {
+        let mut stats = stats();
+
+        // Before NTP has set the clock
+        stats.update(&reading(1000.0), UNIX_EPOCH + Duration::from_mins(1));
+        stats.update(&reading(1000.0), UNIX_EPOCH + Duration::from_mins(2));
+        assert_eq!(stats.today(), DailyLight::default());
+
+        // The time until the first reading after a stale one is not counted
+        stats.update(&reading(1000.0), at(3600));
+        stats.update(&VemlOutput::new(), at(3660));
+        stats.update(&reading(1000.0), at(3720));
+        assert!(stats.today().lux_hours.abs() < f64::EPSILON);
+
+        // Nor is a gap longer than the limit
+        stats.update(&reading(1000.0), at(3720 + 6 * 60));
+        assert!(stats.today().lux_hours.abs() < f64::EPSILON);
+        stats.update(&reading(1000.0), at(3720 + 7 * 60));
+        assert!((stats.today().lux_hours - 1000.0 / 60.0).abs() < 1e-6);
+    }
+
+    /// Test readings that are only taken when the light changes are held until the next one.
+    #[test]
+    fn test_hold_readings() {
+        let mut stats = LightStats::new(LightStatsConfig {
+            utc_offset_s: UTC_OFFSET_S,
+            max_reading_gap: Duration::from_hours(12),
+            hold_readings: true,
+            ..LightStatsConfig::default()
+        });
+
+        // Lights on for two hours without another reading, then off
+        stats.update(&reading(500.0), at(18 * 3600));
+        stats.update(&reading(0.0), at(20 * 3600));
+        assert!((stats.today().lux_hours - 1000.0).abs() < 1e-6);
+        assert_time_above(stats.today(), [7200.0, 0.0, 0.0]);
+
+        // And a long steady period across midnight is split between the days
+        stats.update(&reading(200.0), at(22 * 3600));
+        stats.update(&reading(0.0), at(26 * 3600));
+        assert!((stats.yesterday().unwrap().lux_hours - 1400.0).abs() < 1e-6);
+        assert!((stats.today().lux_hours - 400.0).abs() < 1e-6);
+    }
+}
@@ -1,6 +1,7 @@
 //! Environment Monitoring application
 
 use environment_monitor_rust::interconnect::SensorHubData;
+use environment_monitor_rust::light_stats::{LightStats, LightStatsConfig};
 use environment_monitor_rust::mqtt::mqtt_task;
 use esp_idf_hal::cpu::Core;
 use esp_idf_hal::task::thread::ThreadSpawnConfiguration;
@@ -12,18 +13,82 @@ use esp_idf_svc::wifi::{BlockingWifi, ClientConfiguration, Configuration, EspWif
 use esp_idf_sys::EspError;
 use std::ffi::CString;
 use std::io;
+#[cfg(feature = "veml-interrupt")]
+use std::num::NonZeroU32;
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread::{self, JoinHandle};
-use std::time::SystemTime;
+#[cfg(feature = "veml-interrupt")]
+use std::time::Duration;
+use std::time::{Instant, SystemTime};
 use veml7700::{Veml7700, VemlOutput};
+#[cfg(feature = "veml-interrupt")]
+use veml7700::{VemlLuxThresholds, VemlPersistence, VemlVariant};
 
+#[cfg(feature = "iaq-estimator")]
+use bme68x::{BME68xAddr, BME68xConf, BME68xDev, BME68xFilter, BME68xODR, BME68xOs, I2cInterface};
 use embedded_hal_bus::i2c::MutexDevice;
 use environment_monitor_rust::bsec;
+use environment_monitor_rust::bsec::save_policy::{FlushRequest, SavePolicy, SavePolicyConfig};
+#[cfg(not(feature = "iaq-estimator"))]
+use environment_monitor_rust::bsec::scheduler::{Scheduler, SchedulerConfig};
+use environment_monitor_rust::bsec::state_store::FileStateStore;
+#[cfg(feature = "iaq-estimator")]
+use environment_monitor_rust::bsec::state_store::StateStore;
+#[cfg(feature = "iaq-estimator")]
+use environment_monitor_rust::iaq_estimator::{EstimatorState, IaqEstimator, IaqEstimatorConfig};
 use environment_monitor_rust::private_data;
+#[cfg(feature = "veml-interrupt")]
+use esp_idf_hal::delay;
 use esp_idf_hal::delay::FreeRtos;
+#[cfg(feature = "veml-interrupt")]
+use esp_idf_hal::gpio::{InputPin, InterruptType, PinDriver, Pull};
 use esp_idf_hal::i2c::{I2cConfig, I2cDriver};
 use esp_idf_hal::peripherals::Peripherals;
 use esp_idf_hal::prelude::*;
+#[cfg(feature = "veml-interrupt")]
+use esp_idf_hal::task::notification::Notification;
+
+/// I2C address of the VEML6030 used with the `veml-interrupt` feature, with its ADDR pin low.
+///
+/// The VEML7700 package does not bring out the interrupt, so that feature
+/// needs a VEML6030 with its interrupt pin wired to GPIO 27.
+#[cfg(feature = "veml-interrupt")]
+const VEML6030_ADDR: u8 = 0x10;
+
+/// Time to wait before retrying after failing to read the VEML7700, in milliseconds.
+const VEML_RETRY_MS: u32 = 1000;
+
+/// Shortest time between reads of the VEML7700, in milliseconds.
+///
+/// Without a power saving mode the sensor refreshes every integration time,
+/// which is far more often than the light statistics need.
+#[cfg(not(feature = "veml-interrupt"))]
+const VEML_MIN_INTERVAL_MS: u32 = 1000;
+
+/// Fraction the light level must change by to raise an interrupt.
+#[cfg(feature = "veml-interrupt")]
+const VEML_INTERRUPT_BAND: f32 = 0.25;
+
+/// Minimum change in lux to raise an interrupt, so darkness does not
+/// interrupt on every sample.
+#[cfg(feature = "veml-interrupt")]
+const VEML_INTERRUPT_MIN_LUX: f32 = 1.0;
+
+/// Number of generations of the BSEC state to keep, so an older state can be
+/// restored if the newest one is corrupt.
+const BSEC_STATE_GENERATIONS: usize = 3;
+
+/// Heater temperature for the IAQ estimator's measurements, in degrees C
+#[cfg(feature = "iaq-estimator")]
+const IAQ_HEATER_TEMPERATURE: u16 = 320;
+
+/// Heater duration for the IAQ estimator's measurements, in milliseconds
+#[cfg(feature = "iaq-estimator")]
+const IAQ_HEATER_DURATION_MS: u16 = 150;
+
+/// Time between the IAQ estimator's measurements, in milliseconds
+#[cfg(feature = "iaq-estimator")]
+const IAQ_SAMPLE_INTERVAL_MS: u32 = 3000;
 
 /// Enumeration to hold data sent from sensor tasks to the sensor hub task.
 #[derive(Debug)]
@@ -33,6 +98,9 @@ enum SensorData {
     Bsec {
         /// The data from the sensor
         data: bsec::StructuredOutputs,
+
+        /// Counters of the warnings from the BSEC library
+        warnings: bsec::WarningCounts,
     },
 
     /// Data from the VEML7700 sensor.
@@ -112,6 +180,8 @@ fn main() {
     let i2c_mutex = Arc::new(Mutex::new(i2c_driver));
     let bsec_i2c = i2c_mutex.clone();
     let veml_i2c = i2c_mutex.clone();
+    #[cfg(feature = "veml-interrupt")]
+    let veml_int_pin = peripherals.pins.gpio27;
 
     // Set up channel for sensor tasks to send data over
     let (tx, rx) = mpsc::sync_channel(5);
@@ -124,29 +194,50 @@ fn main() {
     let hub_data = data_mutex.clone();
     let adafruit_io_data = data_mutex.clone();
 
+    // Lets other tasks ask the BSEC task to save its state, e.g. before a reboot
+    let bsec_flush_request = FlushRequest::new();
+    let mqtt_flush_request = bsec_flush_request.clone();
+
     spawn_thread(b"Sensor Hub Thread\0", 4096, 2, None, move || {
         sensor_hub_task(&hub_data, &rx);
     })
     .unwrap();
 
     // FIXME: This seems very large. Should try to make it smaller
+    #[cfg(not(feature = "iaq-estimator"))]
     spawn_thread(b"BSEC Thread\0", 16384, 1, None, move || {
-        bsec_task(&bsec_i2c, &bsec_transmitter);
+        bsec_task(&bsec_i2c, &bsec_transmitter, &bsec_flush_request);
+    })
+    .unwrap();
+
+    #[cfg(feature = "iaq-estimator")]
+    spawn_thread(b"IAQ Estimator Thread\0", 8192, 1, None, move || {
+        iaq_estimator_task(&bsec_i2c, &bsec_transmitter, &bsec_flush_request);
     })
     .unwrap();
 
+    #[cfg(not(feature = "veml-interrupt"))]
     spawn_thread(b"VEML Thread\0", 4096, 1, None, move || {
         veml_task(&veml_i2c, &veml_transmitter);
     })
     .unwrap();
 
+    #[cfg(feature = "veml-interrupt")]
+    spawn_thread(b"VEML Thread\0", 4096, 1, None, move || {
+        veml_interrupt_task(&veml_i2c, veml_int_pin, &veml_transmitter);
+    })
+    .unwrap();
+
+    let data_client_id = mqtt_client_id("data").unwrap();
     spawn_thread(b"Adafruit IO Thread\0", 4096, 1, None, move || {
         mqtt_task(
             &adafruit_io_data,
             private_data::AIO_MQTT_URL,
             private_data::AIO_MQTT_USER,
             private_data::AIO_MQTT_PASS,
+            &data_client_id,
             20000,
+            &mqtt_flush_request,
         );
     })
     .unwrap();
@@ -216,21 +307,61 @@ fn mount_littlefs(partition_label: &str, mount_point: &str) -> Result<(usize, us
     Ok((fs_total_bytes, fs_used_bytes))
 }
 
+/// Get a MQTT client ID that is unique to this device and use
+///
+/// Each MQTT client on the device needs its own ID, as the broker disconnects
+/// a client when another connects with the same ID.
+///
+/// # Arguments
+/// * `role`: What the client is used for, e.g. `data`
+///
+/// # Returns
+/// The client ID, made from the WiFi MAC address and the role
+///
+/// # Errors
+/// Returns an error if reading the MAC address fails.
+fn mqtt_client_id(role: &str) -> Result<String, EspError> {
+    let mut mac = [0; 6];
+    unsafe {
+        esp_idf_sys::esp!(esp_idf_sys::esp_read_mac(
+            mac.as_mut_ptr(),
+            esp_idf_sys::esp_mac_type_t_ESP_MAC_WIFI_STA
+        ))?;
+    };
+    let mac: String = mac.iter().map(|byte| format!("{byte:02x}")).collect();
+    Ok(format!("envmon-{mac}-{role}"))
+}
+
 /// Task for processing data from the BME688 with BSEC
 ///
 /// # Arguments
 /// * `i2c_handle`: Handle to a Mutex-protected I2C driver used to
 ///     communicate with the sensor.
 /// * `transmitter`: The transmitter that will be used to send data to the sensor hub thread
+/// * `flush_request`: Handle other tasks use to request the BSEC state be saved
 // TODO: Change to use SystemTime::now for the timestamp.
 // Requires waiting until the NTP system is up and running.
-fn bsec_task(i2c_handle: &Arc<Mutex<I2cDriver<'_>>>, transmitter: &mpsc::SyncSender<SensorData>) {
+#[cfg(not(feature = "iaq-estimator"))]
+fn bsec_task(
+    i2c_handle: &Arc<Mutex<I2cDriver<'_>>>,
+    transmitter: &mpsc::SyncSender<SensorData>,
+    flush_request: &FlushRequest,
+) {
     let i2c_driver = MutexDevice::new(i2c_handle);
-    let mut bsec = bsec::Bsec::new(i2c_driver, 0.0);
-    let mut last_thread_time = SystemTime::now();
+    // Use a MqttStateStore or NvsStateStore instead to store the state somewhere else.
+    // A MqttStateStore needs its own client ID, e.g. `mqtt_client_id("state")`.
+    let state_store = FileStateStore::new("/littlefs/bsec_state.bin", BSEC_STATE_GENERATIONS);
+    let mut bsec = bsec::Bsec::new(i2c_driver, 0.0, state_store, FreeRtos);
 
     log::info!("Starting BSEC");
-    bsec.init().unwrap();
+    let active_config = bsec.init(bsec::config::ConfigSelection::default()).unwrap();
+    log::info!("BSEC Config: {active_config:?}");
+    let mut save_policy = SavePolicy::new(
+        SavePolicyConfig::default(),
+        flush_request.clone(),
+        bsec.get_restored_accuracy().unwrap_or(0),
+        Instant::now(),
+    );
     bsec.subscribe_all_non_scan(bsec::SampleRate::LowPower)
         .unwrap();
     let version = bsec.get_version().unwrap();
@@ -242,36 +373,117 @@ fn bsec_task(i2c_handle: &Arc<Mutex<I2cDriver<'_>>>, transmitter: &mpsc::SyncSen
         version.minor_bugfix
     );
 
-    let timer_service = EspTimerService::new().unwrap();
+    let mut scheduler = Scheduler::new(EspTimerService::new().unwrap(), SchedulerConfig::default());
 
     loop {
-        bsec.periodic_process(timer_service.now().as_nanos().try_into().unwrap())
-            .unwrap();
+        match scheduler.step(&mut bsec) {
+            Ok(()) => {
+                let data = bsec.get_output_data();
+                let warnings = bsec.get_warning_counts();
+                transmitter
+                    .send(SensorData::Bsec { data, warnings })
+                    .unwrap();
+
+                if let Some(reason) = save_policy.check(data.iaq.accuracy, Instant::now()) {
+                    log::info!("Saving State: {reason:?}");
+                    let result = bsec.save_state();
+                    if let Err(error) = result {
+                        log::error!("Failed to save the BSEC state: {error:?}");
+                    }
+                    save_policy.record_save(reason, result.is_ok(), Instant::now());
+                    log::info!("BSEC state saves: {:?}", save_policy.stats());
+                    log::info!("BSEC calls: {:?}", scheduler.stats());
+                }
+            }
+            Err(error) => log::error!("BSEC processing failed: {error:?}"),
+        }
 
-        let data = bsec.get_output_data();
+        let sleep_time = scheduler.time_until_next_call();
+        FreeRtos::delay_ms(u32::try_from(sleep_time.as_millis()).unwrap_or(u32::MAX));
+    }
+}
 
-        transmitter.send(SensorData::Bsec { data }).unwrap();
+/// Task for processing data from the BME688 with the open-source IAQ estimator
+///
+/// # Arguments
+/// * `i2c_handle`: Handle to a Mutex-protected I2C driver used to
+///     communicate with the sensor.
+/// * `transmitter`: The transmitter that will be used to send data to the sensor hub thread
+/// * `flush_request`: Handle other tasks use to request the estimator state be saved
+#[cfg(feature = "iaq-estimator")]
+fn iaq_estimator_task(
+    i2c_handle: &Arc<Mutex<I2cDriver<'_>>>,
+    transmitter: &mpsc::SyncSender<SensorData>,
+    flush_request: &FlushRequest,
+) {
+    let i2c_driver = MutexDevice::new(i2c_handle);
+    let mut bme = BME68xDev::new(
+        I2cInterface::new(i2c_driver, BME68xAddr::HIGH),
+        25,
+        FreeRtos,
+    );
+    let mut state_store = FileStateStore::new("/littlefs/iaq_state.bin", BSEC_STATE_GENERATIONS);
+    let mut estimator = IaqEstimator::new(IaqEstimatorConfig::default());
+
+    log::info!("Starting IAQ estimator");
+    bme.init().unwrap();
+    bme.set_config(&BME68xConf {
+        os_hum: BME68xOs::Os1x,
+        os_temp: BME68xOs::Os2x,
+        os_pres: BME68xOs::Os16x,
+        filter: BME68xFilter::Off,
+        odr: BME68xODR::ODRNone,
+    })
+    .unwrap();
 
-        let remaining_time =
-            bsec.get_next_call_time_us() - i64::try_from(timer_service.now().as_micros()).unwrap();
+    // Use the newest saved state that is valid
+    match state_store.load_all() {
+        Ok(states) => {
+            if let Some(state) = states
+                .iter()
+                .find_map(|state| EstimatorState::from_bytes(state))
+            {
+                log::info!("Restored IAQ estimator state: {state:?}");
+                estimator.set_state(state);
+            }
+        }
+        Err(error) => log::warn!("Failed to load the IAQ estimator state: {error:?}"),
+    }
+    let mut save_policy = SavePolicy::new(
+        SavePolicyConfig::default(),
+        flush_request.clone(),
+        estimator.get_calibrated_accuracy(),
+        Instant::now(),
+    );
 
-        // TODO: in the future, maybe use MQTT to send and get the state from a remove server so we don't wear down flash?
+    let timer_service = EspTimerService::new().unwrap();
 
-        // Save the configuration state once per hour, (so 8760 times a year)
-        let elapsed = last_thread_time.elapsed().unwrap();
-        if elapsed.as_secs() > 3600 {
-            log::info!("Saving State.");
-            bsec.save_state().unwrap();
-            last_thread_time = SystemTime::now();
+    loop {
+        match bme.forced_measurent(IAQ_HEATER_TEMPERATURE, IAQ_HEATER_DURATION_MS) {
+            Ok(reading) => {
+                estimator.process(&reading, timer_service.now().as_nanos().try_into().unwrap());
+                let data = estimator.get_output_data();
+                // The estimator does not give warnings
+                transmitter
+                    .send(SensorData::Bsec {
+                        data,
+                        warnings: bsec::WarningCounts::default(),
+                    })
+                    .unwrap();
+
+                if let Some(reason) = save_policy.check(data.iaq.accuracy, Instant::now()) {
+                    log::info!("Saving State: {reason:?}");
+                    let result = state_store.save(&estimator.get_state().to_bytes());
+                    if let Err(error) = &result {
+                        log::error!("Failed to save the IAQ estimator state: {error:?}");
+                    }
+                    save_policy.record_save(reason, result.is_ok(), Instant::now());
+                }
+            }
+            Err(error) => log::warn!("Failed to read the BME688: {error:?}"),
         }
 
-        let remaining_time_32 = u32::try_from(remaining_time);
-        if let Ok(remaining_time_32) = remaining_time_32 {
-            FreeRtos::delay_ms(remaining_time_32 / 1000);
-        } else {
-            log::warn!("Bad Remaining Time: {remaining_time}. Delaying for 3 seconds instead");
-            FreeRtos::delay_ms(3000);
-        }
+        FreeRtos::delay_ms(IAQ_SAMPLE_INTERVAL_MS);
     }
 }
 
@@ -281,17 +493,104 @@ fn bsec_task(i2c_handle: &Arc<Mutex<I2cDriver<'_>>>, transmitter: &mpsc::SyncSen
 /// * `i2c_handle`: Handle to a Mutex-protected I2C driver used to
 ///     communicate with the sensor.
 /// * `transmitter`: The transmitter that will be used to send data to the sensor hub thread.
+#[cfg(not(feature = "veml-interrupt"))]
 fn veml_task(i2c_handle: &Arc<Mutex<I2cDriver<'_>>>, transmitter: &mpsc::SyncSender<SensorData>) {
     let i2c_driver = MutexDevice::new(i2c_handle);
     let mut veml = Veml7700::new(i2c_driver);
-    veml.set_power_state(false).unwrap();
+    // Periodic processing re-applies the configuration if this fails.
+    if let Err(error) = veml.set_power_state(false) {
+        log::warn!("Failed to configure the VEML7700: {error:?}");
+    }
+
+    loop {
+        if let Err(error) = veml.periodic_process_auto_range(&mut FreeRtos) {
+            log::warn!("Failed to read the VEML7700: {error:?}");
+        }
+        let data = veml.get_outputs();
+        transmitter.send(SensorData::Veml { data }).unwrap();
+
+        let refresh_time_ms = veml
+            .get_operating_point()
+            .map_or(VEML_RETRY_MS, |point| point.refresh_time_ms)
+            .max(VEML_MIN_INTERVAL_MS);
+        FreeRtos::delay_ms(refresh_time_ms);
+    }
+}
+
+/// Task for reading data from the VEML6030 sensor when the light level changes.
+///
+/// After each sample the ALS interrupt thresholds are set to a band around
+/// the measured light level, and the task sleeps until the sensor pulls its
+/// interrupt line low, so the bus is idle while the light is steady.
+///
+/// # Arguments
+/// * `i2c_handle`: Handle to a Mutex-protected I2C driver used to
+///     communicate with the sensor.
+/// * `int_pin`: The GPIO connected to the sensor's interrupt line.
+/// * `transmitter`: The transmitter that will be used to send data to the sensor hub thread.
+#[cfg(feature = "veml-interrupt")]
+fn veml_interrupt_task<P: InputPin>(
+    i2c_handle: &Arc<Mutex<I2cDriver<'_>>>,
+    int_pin: P,
+    transmitter: &mpsc::SyncSender<SensorData>,
+) {
+    let i2c_driver = MutexDevice::new(i2c_handle);
+    let mut veml = Veml7700::new_variant(i2c_driver, VemlVariant::Veml6030, VEML6030_ADDR).unwrap();
+    // Periodic processing re-applies the configuration if this fails.
+    if let Err(error) = veml.set_power_state(false) {
+        log::warn!("Failed to configure the VEML6030: {error:?}");
+    }
+
+    // The interrupt line is open drain and active low.
+    let mut int_pin = PinDriver::input(int_pin).unwrap();
+    int_pin.set_pull(Pull::Up).unwrap();
+    int_pin.set_interrupt_type(InterruptType::NegEdge).unwrap();
+
+    let notification = Notification::new();
+    let notifier = notification.notifier();
+    // SAFETY: The callback only notifies this task, which is ISR safe.
+    unsafe {
+        int_pin
+            .subscribe(move || {
+                notifier.notify_and_yield(NonZeroU32::MIN);
+            })
+            .unwrap();
+    }
 
     loop {
-        veml.periodic_process();
+        if let Err(error) = veml.periodic_process_auto_range(&mut FreeRtos) {
+            log::warn!("Failed to read the VEML6030: {error:?}");
+        }
         let data = veml.get_outputs();
         transmitter.send(SensorData::Veml { data }).unwrap();
+        if data.stale {
+            FreeRtos::delay_ms(VEML_RETRY_MS);
+            continue;
+        }
+
+        let band = (data.lux * VEML_INTERRUPT_BAND).max(VEML_INTERRUPT_MIN_LUX);
+        let armed = veml
+            .set_lux_thresholds(VemlLuxThresholds {
+                low: data.lux - band,
+                high: data.lux + band,
+                persistence: VemlPersistence::Persist1,
+            })
+            // Reading the status releases the interrupt line.
+            .and_then(|()| veml.get_interrupt_status());
+        if let Err(error) = armed {
+            log::warn!("Failed to set the VEML6030 thresholds: {error:?}");
+            FreeRtos::delay_ms(VEML_RETRY_MS);
+            continue;
+        }
 
-        FreeRtos::delay_ms(1000);
+        int_pin.enable_interrupt().unwrap();
+        // Skip waiting if the line fell before the interrupt was enabled.
+        if int_pin.is_high() {
+            notification.wait(delay::BLOCK);
+        }
+        if let Err(error) = veml.clear_lux_thresholds() {
+            log::warn!("Failed to clear the VEML6030 thresholds: {error:?}");
+        }
     }
 }
 
@@ -301,6 +600,18 @@ fn veml_task(i2c_handle: &Arc<Mutex<I2cDriver<'_>>>, transmitter: &mpsc::SyncSen
 /// * `receiver`: The receiver that will get data from the sensor tasks.
 /// * `data_mutex`: Mutex protected sensor data that the sensor hub will collect.
 fn sensor_hub_task(data_mutex: &Arc<Mutex<SensorHubData>>, receiver: &mpsc::Receiver<SensorData>) {
+    // In interrupt mode the sensor is only read when the light changes, so
+    // the light is steady between readings, which can be far apart.
+    #[cfg(feature = "veml-interrupt")]
+    let light_config = LightStatsConfig {
+        max_reading_gap: Duration::from_hours(12),
+        hold_readings: true,
+        ..LightStatsConfig::default()
+    };
+    #[cfg(not(feature = "veml-interrupt"))]
+    let light_config = LightStatsConfig::default();
+    let mut light_stats = LightStats::new(light_config);
+
     loop {
         // Read here first so that we don't try to acquire the mutex until we have
         // data to act on
@@ -309,8 +620,16 @@ fn sensor_hub_task(data_mutex: &Arc<Mutex<SensorHubData>>, receiver: &mpsc::Rece
         let mut locked_mutex = data_mutex.lock().unwrap();
         // Copy over the most recently send data from the channel into the structure.
         match received_data {
-            SensorData::Bsec { data } => locked_mutex.bsec = data,
-            SensorData::Veml { data } => locked_mutex.veml = data,
+            SensorData::Bsec { data, warnings } => {
+                locked_mutex.bsec = data;
+                locked_mutex.bsec_warnings = warnings;
+            }
+            SensorData::Veml { data } => {
+                light_stats.update(&data, SystemTime::now());
+                locked_mutex.veml = data;
+                locked_mutex.light = light_stats.today();
+                locked_mutex.light_yesterday = light_stats.yesterday();
+            }
         }
     }
 }
@@ -1,20 +1,31 @@
 //! Implementation for sending data to MQTT brokers.
 use esp_idf_hal::delay::FreeRtos;
-use esp_idf_svc::mqtt::client::{EspMqttClient, MqttClientConfiguration, QoS};
+use esp_idf_svc::mqtt::client::{EspMqttClient, EventPayload, MqttClientConfiguration, QoS};
 use esp_idf_sys::esp_crt_bundle_attach;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
-use crate::bsec::VirtualSensorData;
+use crate::bsec::save_policy::FlushRequest;
+use crate::bsec::{VirtualSensorData, WarningCounts};
 use crate::interconnect::SensorHubData;
+use crate::light_stats::{self, DailyLight};
 use crate::private_data;
+
+/// Command, received on the command topic, to save the BSEC state
+const SAVE_BSEC_STATE_COMMAND: &[u8] = b"save_bsec_state";
+
 /// Task for sending data to a MQTT Broker
 ///
+/// Also listens for commands on the command topic.
+///
 /// # Arguments
 /// * `data_mutex`: The mutex for the sensor hub data
 /// * `broker_url`: The MQTT Broker URL
 /// * `username`: MQTT Broker Username
 /// * `password`: MQTT Broker Password
+/// * `client_id`: MQTT client ID, which no other client on the broker may use
 /// * `sleep_time`: The time to sleep between each publish.
+/// * `flush_request`: Handle to request the BSEC state be saved, for the save command
 ///
 /// # Panics
 /// Will panic in any of the following conditions
@@ -27,10 +38,13 @@ pub fn mqtt_task(
     broker_url: &str,
     username: &str,
     password: &str,
+    client_id: &str,
     sleep_time: u32,
+    flush_request: &FlushRequest,
 ) {
     let mqtt_config = MqttClientConfiguration {
         crt_bundle_attach: Some(esp_crt_bundle_attach),
+        client_id: Some(client_id),
         username: Some(username),
         password: Some(password),
         ..Default::default()
@@ -38,6 +52,11 @@ pub fn mqtt_task(
 
     let (mut client, mut connection) = EspMqttClient::new(broker_url, &mqtt_config).unwrap();
 
+    // Subscriptions are lost when reconnecting, so subscribe again after each connection
+    let subscribe_needed = Arc::new(AtomicBool::new(false));
+    let connected = subscribe_needed.clone();
+    let flush_request = flush_request.clone();
+
     // Need this for some reason to make the MQTT publishing working. Look at the esp-idf-svc mqtt client example
     // FIXME: Can I get rid of this?
     std::thread::Builder::new()
@@ -47,13 +66,34 @@ pub fn mqtt_task(
 
             while let Ok(event) = connection.next() {
                 log::info!("[Queue] Event: {}", event.payload());
+
+                match event.payload() {
+                    EventPayload::Connected(_) => connected.store(true, Ordering::Release),
+                    EventPayload::Received {
+                        topic: Some(private_data::AIO_COMMAND_TOPIC),
+                        data: SAVE_BSEC_STATE_COMMAND,
+                        ..
+                    } => flush_request.request(),
+                    _ => {}
+                }
             }
 
             log::info!("Connection closed");
         })
         .unwrap();
 
+    // The most recent finished day that the light statistics were published for
+    let mut published_day = None;
+
     loop {
+        if subscribe_needed.swap(false, Ordering::AcqRel)
+            && client
+                .subscribe(private_data::AIO_COMMAND_TOPIC, QoS::AtLeastOnce)
+                .is_err()
+        {
+            subscribe_needed.store(true, Ordering::Release);
+        }
+
         // Get The data and release the mutex as quickly as possible.
 
         let locked_mutex = data_mutex.lock().unwrap();
@@ -109,21 +149,144 @@ pub fn mqtt_task(
             false,
         );
 
-        let payload = format!("{}", data.veml.lux);
-        // FIXME: Log error instead of unwrapping
-        client
-            .publish(
-                private_data::AIO_LUX_TOPIC,
-                QoS::AtLeastOnce,
-                false,
-                payload.as_bytes(),
-            )
-            .unwrap();
+        // Don't republish an old light level while the sensor can't be read
+        if !data.veml.stale {
+            let payload = format!("{}", data.veml.lux);
+            // FIXME: Log error instead of unwrapping
+            client
+                .publish(
+                    private_data::AIO_LUX_TOPIC,
+                    QoS::AtLeastOnce,
+                    false,
+                    payload.as_bytes(),
+                )
+                .unwrap();
+        }
+
+        publish_light_stats(&mut client, &data.light);
+        // The totals of a finished day are only published once, at rollover
+        if let Some(light) = data.light_yesterday {
+            if published_day != Some(light.day) {
+                publish_finished_day(&mut client, &light);
+                published_day = Some(light.day);
+            }
+        }
+        publish_bsec_warnings(&mut client, &data.bsec_warnings);
 
         FreeRtos::delay_ms(sleep_time);
     }
 }
 
+/// Publish the daily light statistics to the given MQTT Client
+///
+/// The DLI is published on its own, and all of the statistics are published
+/// together as a JSON.
+///
+/// # Arguments
+/// * `client`: The MQTT client to publish to
+/// * `light`: The light statistics to publish
+///
+/// # Panics
+/// Will panic if publishing the data failed.
+fn publish_light_stats(client: &mut EspMqttClient, light: &DailyLight) {
+    // FIXME: Log error instead of unwrapping
+    client
+        .publish(
+            private_data::AIO_DLI_TOPIC,
+            QoS::AtLeastOnce,
+            false,
+            format!("{}", light.dli).as_bytes(),
+        )
+        .unwrap();
+
+    client
+        .publish(
+            private_data::AIO_LIGHT_STATS_TOPIC,
+            QoS::AtLeastOnce,
+            false,
+            light_stats_json(light).as_bytes(),
+        )
+        .unwrap();
+}
+
+/// Publish the final light statistics of a finished day to the given MQTT Client
+///
+/// # Arguments
+/// * `client`: The MQTT client to publish to
+/// * `light`: The light statistics of the finished day
+///
+/// # Panics
+/// Will panic if publishing the data failed.
+fn publish_finished_day(client: &mut EspMqttClient, light: &DailyLight) {
+    // FIXME: Log error instead of unwrapping
+    client
+        .publish(
+            private_data::AIO_LIGHT_DAY_TOPIC,
+            QoS::AtLeastOnce,
+            // Retained, so the last day's totals are available after a reconnect
+            true,
+            light_stats_json(light).as_bytes(),
+        )
+        .unwrap();
+}
+
+/// Format the light statistics as a JSON
+///
+/// # Arguments
+/// * `light`: The light statistics to format
+///
+/// # Returns
+/// The statistics as a JSON
+fn light_stats_json(light: &DailyLight) -> String {
+    let time_or_null = |second_of_day: Option<u32>| {
+        second_of_day.map_or_else(
+            || String::from("null"),
+            |second_of_day| format!("\"{}\"", light_stats::format_time_of_day(second_of_day)),
+        )
+    };
+
+    // TODO: Use serde to create this.
+    format!(
+        "{{\"lux_hours\": {}, \"dli\": {}, \"time_above_s\": {:?}, \"first_light\": {}, \"last_light\": {}}}",
+        light.lux_hours,
+        light.dli,
+        light.time_above_s,
+        time_or_null(light.first_light_s),
+        time_or_null(light.last_light_s),
+    )
+}
+
+/// Publish the counters of the BSEC warnings to the given MQTT Client as a JSON
+///
+/// # Arguments
+/// * `client`: The MQTT client to publish to
+/// * `warnings`: The warning counters to publish
+///
+/// # Panics
+/// Will panic if publishing the data failed.
+fn publish_bsec_warnings(client: &mut EspMqttClient, warnings: &WarningCounts) {
+    // TODO: Use serde to create this.
+    let payload = format!(
+        "{{\"total\": {}, \"timing\": {}, \"processing\": {}, \"last\": {}}}",
+        warnings.total,
+        warnings.timing,
+        warnings.processing,
+        warnings
+            .last
+            .map_or_else(|| String::from("null"), |last| format!("\"{last:?}\"")),
+    );
+
+    // FIXME: Log error instead of unwrapping
+    client
+        .publish(
+            private_data::AIO_BSEC_WARNINGS_TOPIC,
+            QoS::AtLeastOnce,
+            false,
+            payload.as_bytes(),
+        )
+        .unwrap();
+}
+
 /// Publish BSEC data to the given MQTT Client if the data is valid
 ///
 /// # Arguments
@@ -0,0 +1,506 @@
+//! Async VEML7700 driver built on `embedded-hal-async`.
+use embedded_hal_async::{delay::DelayNs, i2c::I2c};
+
+use crate::{
+    correct_lux, settle_time_ms, AutoRange, VemlConfigReg, VemlError, VemlGain, VemlIntegration,
+    VemlLuxThresholds, VemlOperatingPoint, VemlOutput, VemlPersistence, VemlPowerSavingMode,
+    VemlRegister, VemlVariant, VEML_ADDR,
+};
+
+/// Async driver for the VEML7700
+pub struct Veml7700Async<I2C> {
+    /// Concrete I2C implementation
+    i2c: I2C,
+
+    /// The sensor being driven
+    variant: VemlVariant,
+
+    /// I2C address of the sensor
+    address: u8,
+
+    /// Sensor configuration
+    configuration: VemlConfigReg,
+
+    /// Values from the most recent sensor measurement.
+    last_output: VemlOutput,
+
+    /// Whether the high-lux non-linearity correction is applied
+    lux_correction: bool,
+
+    /// Interrupt thresholds in lux, if set
+    lux_thresholds: Option<VemlLuxThresholds>,
+
+    /// Value last written to the power saving register
+    power_saving: u16,
+}
+
+impl<I2C: I2c> Veml7700Async<I2C> {
+    /// Create a new instance of the async VEML7700 driver.
+    pub fn new(i2c: I2C) -> Self {
+        Self::with_address(i2c, VemlVariant::Veml7700, VEML_ADDR)
+    }
+
+    /// Create a new instance of the async driver for one of the supported sensors.
+    ///
+    /// # Arguments
+    /// * `i2c`: The I2C bus the sensor is on
+    /// * `variant`: The sensor being driven
+    /// * `address`: The I2C address of the sensor
+    ///
+    /// # Errors
+    /// Will return [`VemlError::UnsupportedAddress`] if `address` is not one
+    /// of [`VemlVariant::addresses`]
+    pub fn new_variant(
+        i2c: I2C,
+        variant: VemlVariant,
+        address: u8,
+    ) -> Result<Self, VemlError<I2C::Error>> {
+        if variant.addresses().contains(&address) {
+            Ok(Self::with_address(i2c, variant, address))
+        } else {
+            Err(VemlError::UnsupportedAddress(address))
+        }
+    }
+
+    /// Create a new instance of the async driver, with an address already checked
+    ///
+    /// # Arguments
+    /// * `i2c`: The I2C bus the sensor is on
+    /// * `variant`: The sensor being driven
+    /// * `address`: The I2C address of the sensor
+    fn with_address(i2c: I2C, variant: VemlVariant, address: u8) -> Self {
+        Self {
+            i2c,
+            variant,
+            address,
+            configuration: VemlConfigReg {
+                gain: VemlGain::Gain1,
+                integration_time: VemlIntegration::Int100,
+                persistence: VemlPersistence::Persist1,
+                interrupt_enabled: false,
+                shutdown: false,
+            },
+            last_output: VemlOutput::new(),
+            lux_correction: true,
+            lux_thresholds: None,
+            power_saving: 0,
+        }
+    }
+
+    /// Enable or disable the high-lux non-linearity correction.
+    ///
+    /// The correction is enabled by default. It only applies to the VEML7700
+    /// and VEML6030, see [`VemlVariant::has_lux_correction`].
+    ///
+    /// # Arguments
+    /// * `enable`: Whether to apply the correction to computed lux values
+    pub fn set_lux_correction(&mut self, enable: bool) {
+        self.lux_correction = enable;
+    }
+
+    /// Write configuration structure to the sensor
+    ///
+    /// # Arguments
+    /// * `config`: The configuration to write to the sensor
+    ///
+    /// A gain the sensor does not support is replaced with the nearest one it
+    /// does, see [`VemlVariant::supported_gain`].
+    ///
+    /// # Errors
+    /// Will return an error if the I2C Transation Fails
+    pub async fn set_configuration(&mut self, config: VemlConfigReg) -> Result<(), I2C::Error> {
+        self.configuration = VemlConfigReg {
+            gain: self.variant.supported_gain(config.gain),
+            ..config
+        };
+        self.write_internal_configuration().await
+    }
+
+    /// Get the sensor configuration
+    ///
+    /// # Returns
+    /// The read sensor configration, or an error
+    ///
+    /// # Errors
+    /// Will return an error if the I2C Transation Fails
+    pub async fn get_configuration(&mut self) -> Result<VemlConfigReg, I2C::Error> {
+        let result = self.write_read_u16(VemlRegister::Config).await?;
+        Ok(VemlConfigReg::from_register(result, self.variant))
+    }
+
+    /// Get the raw ALS value from the sensor
+    ///
+    /// # Errors
+    /// Will return an error if the I2C Transation Fails
+    pub async fn get_ambient_level(&mut self) -> Result<u16, I2C::Error> {
+        self.write_read_u16(VemlRegister::ALSValue).await
+    }
+
+    /// Gets the white value from the sensor
+    ///
+    /// # Errors
+    /// Will return an error if the I2C Transation Fails
+    pub async fn get_white_level(&mut self) -> Result<u16, I2C::Error> {
+        self.write_read_u16(VemlRegister::WhiteValue).await
+    }
+
+    /// Get the computed ALS Lux Value
+    ///
+    /// # Errors
+    /// Will return an error if the I2C Transation Fails
+    pub async fn get_lux(&mut self) -> Result<f32, I2C::Error> {
+        let raw_als = self.get_ambient_level().await?;
+        let lux = self.configuration.als_scale(self.variant) * f32::from(raw_als);
+        Ok(
+            if self.lux_correction && self.variant.has_lux_correction() {
+                correct_lux(lux)
+            } else {
+                lux
+            },
+        )
+    }
+
+    /// Set the sensor gain to the specified value.
+    ///
+    /// # Arugments
+    /// * `gain` The gain to set
+    ///
+    /// A gain the sensor does not support is replaced with the nearest one it
+    /// does, see [`VemlVariant::supported_gain`].
+    ///
+    /// # Errors
+    /// Will return an error if the I2C Transation Fails
+    pub async fn set_gain(&mut self, gain: VemlGain) -> Result<(), I2C::Error> {
+        self.configuration.gain = self.variant.supported_gain(gain);
+        self.write_internal_configuration().await
+    }
+
+    /// Set the sensor integration time
+    ///
+    /// # Arguments
+    /// * `integration_time`: The integration time to set
+    ///
+    /// # Errors
+    /// Will return an error if the I2C Transation Fails
+    pub async fn set_integration_time(
+        &mut self,
+        integration_time: VemlIntegration,
+    ) -> Result<(), I2C::Error> {
+        self.configuration.integration_time = integration_time;
+        self.write_internal_configuration().await
+    }
+
+    /// Set the sensor power state
+    ///
+    /// # Arguments
+    /// * `shutdown`: If true, sets the sensor to the shutdown power state
+    ///
+    /// # Errors
+    /// Will return an error if the I2C Transation Fails
+    pub async fn set_power_state(&mut self, shutdown: bool) -> Result<(), I2C::Error> {
+        self.configuration.shutdown = shutdown;
+        self.write_internal_configuration().await
+    }
+
+    /// Set the ALS Interrupt high threshold.
+    ///
+    /// # Arguments
+    /// * `threshold`: The value to set for the ALS interrupt high threshold
+    ///
+    /// # Errors
+    /// Will return an error if the I2C transaction fails.
+    pub async fn set_als_int_high_threshold(&mut self, threshold: u16) -> Result<(), I2C::Error> {
+        self.write_u16(VemlRegister::ALSHighThreshold, threshold)
+            .await
+    }
+
+    /// Set the ALS Interrupt low threshold.
+    ///
+    /// # Arguments
+    /// * `threshold`: The value to set for the ALS interrupt low threshold
+    ///
+    /// # Errors
+    /// Will return an error if the I2C transaction fails.
+    pub async fn set_als_int_low_threshold(&mut self, threshold: u16) -> Result<(), I2C::Error> {
+        self.write_u16(VemlRegister::ALSLowThreshold, threshold)
+            .await
+    }
+
+    /// Set the ALS interrupt thresholds in lux, and enable the interrupt.
+    ///
+    /// The raw thresholds are recomputed whenever the gain or integration time
+    /// is changed through the driver, replacing any set with
+    /// [`Self::set_als_int_high_threshold`] or [`Self::set_als_int_low_threshold`].
+    ///
+    /// # Arguments
+    /// * `thresholds`: The thresholds and persistence to use
+    ///
+    /// # Errors
+    /// Will return an error if the I2C transaction fails.
+    pub async fn set_lux_thresholds(
+        &mut self,
+        thresholds: VemlLuxThresholds,
+    ) -> Result<(), I2C::Error> {
+        self.lux_thresholds = Some(thresholds);
+        self.configuration.persistence = thresholds.persistence;
+        self.configuration.interrupt_enabled = true;
+        self.write_internal_configuration().await
+    }
+
+    /// Stop using lux thresholds, and disable the ALS interrupt.
+    ///
+    /// # Errors
+    /// Will return an error if the I2C transaction fails.
+    pub async fn clear_lux_thresholds(&mut self) -> Result<(), I2C::Error> {
+        self.lux_thresholds = None;
+        self.configuration.interrupt_enabled = false;
+        self.write_internal_configuration().await
+    }
+
+    /// Get the raw interrupt status register of the sensor
+    ///
+    /// # Errors
+    /// Returns an error if reading the interrupt status register failed.
+    pub async fn get_interrupt_status_raw(&mut self) -> Result<u16, I2C::Error> {
+        self.write_read_u16(VemlRegister::ALSInterruptStatus).await
+    }
+
+    /// Set the power saving mode of the sensor.
+    ///
+    /// # Arguments
+    /// * `mode`: The power saving mode to use.
+    /// * `enable`: Whether or not to enable power saving mode.
+    ///
+    /// # Errors
+    /// Returns an error if setting the mode over I2C failed.
+    pub async fn set_power_saving(
+        &mut self,
+        mode: VemlPowerSavingMode,
+        enable: bool,
+    ) -> Result<(), I2C::Error> {
+        let reg_value = ((mode as u16) << 1) | u16::from(enable);
+        self.power_saving = reg_value;
+        self.write_u16(VemlRegister::PowerSaving, reg_value).await
+    }
+
+    /// Get the timing, resolution and power figures for the current configuration
+    ///
+    /// # Arguments
+    /// * `power_saving`: The power saving mode, or `None` if power saving is disabled
+    ///
+    /// # Returns
+    /// The operating point of the sensor
+    pub fn get_operating_point(
+        &self,
+        power_saving: Option<VemlPowerSavingMode>,
+    ) -> VemlOperatingPoint {
+        self.configuration
+            .operating_point(self.variant, power_saving)
+    }
+
+    /// Perform the VEML task's periodic prrocessing
+    ///
+    /// If the sensor has reset since it was configured, the configuration is
+    /// re-applied. On any error the previous outputs are kept, but marked stale.
+    ///
+    /// # Errors
+    /// Returns an error if the sensor had reset, or reading it fails
+    pub async fn periodic_process(&mut self) -> Result<(), VemlError<I2C::Error>> {
+        let result = self.read_outputs().await;
+        self.mark_stale_on_error(result)
+    }
+
+    /// Perform the VEML task's periodic processing with automatic ranging
+    ///
+    /// Gain and integration time are chosen following Vishay's application
+    /// note, waiting out each new integration period before sampling again.
+    /// The chosen settings are left configured and recorded in the output,
+    /// and ranging carries on from them at the next call, so steady light
+    /// needs no reconfiguration.
+    /// As with [`Self::periodic_process`], a reset sensor is reconfigured and
+    /// outputs are marked stale on any error.
+    ///
+    /// # Arguments
+    /// * `delay`: Delay used to wait for new samples
+    ///
+    /// # Errors
+    /// Returns an error if the sensor had reset, or configuring or reading it fails
+    pub async fn periodic_process_auto_range<D: DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<(), VemlError<I2C::Error>> {
+        let result = self.read_outputs_auto_range(delay).await;
+        self.mark_stale_on_error(result)
+    }
+
+    /// Get the most recent set of data read from the sensor
+    ///
+    /// # Returns
+    /// Most recently read data from the sensor
+    pub fn get_outputs(&self) -> VemlOutput {
+        self.last_output
+    }
+
+    /// Destroy the sensor and return the I2C bus
+    ///
+    /// # Returns
+    /// The I2C Bus.
+    pub fn destroy(self) -> I2C {
+        self.i2c
+    }
+
+    /// Read a new set of outputs with the current configuration
+    ///
+    /// # Errors
+    /// Returns an error if the sensor had reset, or reading it fails
+    async fn read_outputs(&mut self) -> Result<(), VemlError<I2C::Error>> {
+        self.recover_from_reset().await?;
+        let raw_als = self.get_ambient_level().await.map_err(VemlError::I2c)?;
+        let raw_white = self.get_white_level().await.map_err(VemlError::I2c)?;
+        self.last_output = VemlOutput::from_raw(
+            raw_als,
+            raw_white,
+            self.configuration,
+            self.variant,
+            self.lux_correction,
+        );
+        Ok(())
+    }
+
+    /// Read a new set of outputs, auto-ranging the gain and integration time
+    ///
+    /// # Arguments
+    /// * `delay`: Delay used to wait for new samples
+    ///
+    /// # Errors
+    /// Returns an error if the sensor had reset, or configuring or reading it fails
+    async fn read_outputs_auto_range<D: DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<(), VemlError<I2C::Error>> {
+        self.recover_from_reset().await?;
+        let mut ranging = AutoRange::resume(self.variant, self.configuration);
+        let raw_als = loop {
+            let mut configuration = self.configuration;
+            if ranging.apply(&mut configuration) {
+                self.write_configuration(configuration)
+                    .await
+                    .map_err(VemlError::I2c)?;
+                delay
+                    .delay_ms(settle_time_ms(configuration.integration_time))
+                    .await;
+            }
+            let raw_als = self.get_ambient_level().await.map_err(VemlError::I2c)?;
+            if !ranging.step(raw_als) {
+                break raw_als;
+            }
+        };
+        let raw_white = self.get_white_level().await.map_err(VemlError::I2c)?;
+        self.last_output = VemlOutput::from_raw(
+            raw_als,
+            raw_white,
+            self.configuration,
+            self.variant,
+            self.lux_correction,
+        );
+        Ok(())
+    }
+
+    /// Check whether the sensor has reset, and re-apply the configuration if so.
+    ///
+    /// # Errors
+    /// Returns [`VemlError::SensorReset`] if the sensor had reset, or an error
+    /// if the I2C transactions fail.
+    async fn recover_from_reset(&mut self) -> Result<(), VemlError<I2C::Error>> {
+        let config = self
+            .write_read_u16(VemlRegister::Config)
+            .await
+            .map_err(VemlError::I2c)?;
+        if config == self.configuration.to_register(self.variant) {
+            return Ok(());
+        }
+        self.write_internal_configuration()
+            .await
+            .map_err(VemlError::I2c)?;
+        self.write_u16(VemlRegister::PowerSaving, self.power_saving)
+            .await
+            .map_err(VemlError::I2c)?;
+        Err(VemlError::SensorReset)
+    }
+
+    /// Mark the most recent outputs as stale if processing failed
+    ///
+    /// # Arguments
+    /// * `result`: The result of processing
+    ///
+    /// # Returns
+    /// The result of processing, unchanged
+    fn mark_stale_on_error(
+        &mut self,
+        result: Result<(), VemlError<I2C::Error>>,
+    ) -> Result<(), VemlError<I2C::Error>> {
+        if result.is_err() {
+            self.last_output.stale = true;
+        }
+        result
+    }
+
+    /// Write the VEML Configuration from the internal structure
+    async fn write_internal_configuration(&mut self) -> Result<(), I2C::Error> {
+        self.write_configuration(self.configuration).await
+    }
+
+    /// Write a configuration, and keep it as the internal configuration once
+    /// the sensor has accepted it
+    ///
+    /// # Arguments
+    /// * `configuration`: The configuration to write
+    async fn write_configuration(
+        &mut self,
+        configuration: VemlConfigReg,
+    ) -> Result<(), I2C::Error> {
+        self.write_u16(
+            VemlRegister::Config,
+            configuration.to_register(self.variant),
+        )
+        .await?;
+        self.configuration = configuration;
+        if let Some(thresholds) = self.lux_thresholds {
+            let high = self.configuration.lux_to_counts(
+                self.variant,
+                thresholds.high,
+                self.lux_correction,
+            );
+            let low =
+                self.configuration
+                    .lux_to_counts(self.variant, thresholds.low, self.lux_correction);
+            self.write_u16(VemlRegister::ALSHighThreshold, high).await?;
+            self.write_u16(VemlRegister::ALSLowThreshold, low).await?;
+        }
+        Ok(())
+    }
+
+    /// Read a u16 Data register
+    ///
+    /// # Arguments
+    /// * `reg` The reagister to read.
+    async fn write_read_u16(&mut self, reg: VemlRegister) -> Result<u16, I2C::Error> {
+        let tx_buf = [reg as u8];
+        let mut rx_buf = [0, 0];
+        self.i2c
+            .write_read(self.address, &tx_buf, &mut rx_buf)
+            .await?;
+        Ok(u16::from_le_bytes(rx_buf))
+    }
+
+    /// Write to a u16 data register
+    ///
+    /// # Arguments
+    /// * `reg`: The register to write to
+    /// * `data`: The data to write to the register.
+    async fn write_u16(&mut self, reg: VemlRegister, data: u16) -> Result<(), I2C::Error> {
+        let data_bytes = data.to_le_bytes();
+        let tx_buf = [reg as u8, data_bytes[0], data_bytes[1]];
+        self.i2c.write(self.address, &tx_buf).await
+    }
+}
@@ -1,14 +1,95 @@
 //! Logic for accessing a VEML7700 sensor attached over I2C
+//!
+//! The register compatible VEML6030 and VEML6035 are also supported, by
+//! creating the driver with [`Veml7700::new_variant`].
 // Allow std for unit tests, but not normal builds.
 #![cfg_attr(not(test), no_std)]
 
-use embedded_hal::i2c::I2c;
+use embedded_hal::{delay::DelayNs, i2c::I2c};
 
-/// I2C Address of the sensor
+#[cfg(feature = "async")]
+pub mod asynchronous;
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
+
+#[cfg(feature = "async")]
+pub use asynchronous::Veml7700Async;
+
+/// I2C Address of the VEML7700, and the VEML6030 with its ADDR pin low
 const VEML_ADDR: u8 = 0x10;
 
-/// Base scale for the sensor (at min gain and integration time)
-const ALS_BASE_SCALE: f32 = 0.0042;
+/// I2C Address of the VEML6030 with its ADDR pin high
+const VEML6030_ALT_ADDR: u8 = 0x48;
+
+/// I2C Address of the VEML6035
+const VEML6035_ADDR: u8 = 0x29;
+
+/// Lux per count of the VEML7700 at its maximum gain and integration time
+const VEML7700_BASE_SCALE: f32 = 0.0042;
+
+/// Lux per count of the VEML6030 at its maximum gain and integration time
+const VEML6030_BASE_SCALE: f32 = 0.0036;
+
+/// Lux per count of the VEML6035 at its maximum gain and integration time
+const VEML6035_BASE_SCALE: f32 = 0.0004;
+
+/// Gains of the VEML7700 and VEML6030, from least to most sensitive.
+const VEML7700_GAINS: [VemlGain; 4] = [
+    VemlGain::Gain1_8,
+    VemlGain::Gain1_4,
+    VemlGain::Gain1,
+    VemlGain::Gain2,
+];
+
+/// Gains of the VEML6035, from least to most sensitive.
+const VEML6035_GAINS: [VemlGain; 6] = [
+    VemlGain::Gain1_8,
+    VemlGain::Gain1_4,
+    VemlGain::Gain1_2,
+    VemlGain::Gain1,
+    VemlGain::Gain2,
+    VemlGain::Gain4,
+];
+
+/// VEML6035 configuration bit selecting 1/8 sensitivity
+const VEML6035_SENS_LOW: u16 = 1 << 12;
+
+/// VEML6035 configuration bit doubling the digital gain
+const VEML6035_DG_DOUBLE: u16 = 1 << 11;
+
+/// VEML6035 configuration bit doubling the sensitivity
+const VEML6035_GAIN_DOUBLE: u16 = 1 << 10;
+
+/// VEML6035 configuration bit enabling the white channel
+const VEML6035_WHITE_ENABLE: u16 = 1 << 2;
+
+/// Typical supply current while measuring, in microamps
+const ACTIVE_CURRENT_UA: f32 = 45.0;
+
+/// Typical supply current while shut down or waiting in power saving mode, in microamps
+const SHUTDOWN_CURRENT_UA: f32 = 0.5;
+
+/// Lux above which the non-linearity correction is applied
+const LUX_CORRECTION_THRESHOLD: f32 = 1000.0;
+
+/// Integration times that auto-ranging steps through, from shortest to longest.
+const AUTO_RANGE_INTEGRATIONS: [VemlIntegration; 6] = [
+    VemlIntegration::Int25,
+    VemlIntegration::Int50,
+    VemlIntegration::Int100,
+    VemlIntegration::Int200,
+    VemlIntegration::Int400,
+    VemlIntegration::Int800,
+];
+
+/// Index into [`AUTO_RANGE_INTEGRATIONS`] of the 100 ms auto-ranging starts at
+const AUTO_RANGE_START_INTEGRATION: usize = 2;
+
+/// Raw ALS count at or below which auto-ranging increases the sensitivity
+const AUTO_RANGE_LOW_COUNTS: u16 = 100;
+
+/// Raw ALS count above which auto-ranging shortens the integration time
+const AUTO_RANGE_HIGH_COUNTS: u16 = 10000;
 
 /// Enumeration of the VEML7700's registers
 #[repr(u8)]
@@ -23,6 +104,167 @@ enum VemlRegister {
     ALSInterruptStatus = 0x06,
 }
 
+/// The supported sensors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VemlVariant {
+    /// VEML7700
+    #[default]
+    Veml7700,
+
+    /// VEML6030
+    Veml6030,
+
+    /// VEML6035
+    Veml6035,
+}
+
+impl VemlVariant {
+    /// Get the I2C addresses the sensor can be configured for
+    ///
+    /// # Returns
+    /// The possible addresses, with the default first
+    #[must_use]
+    pub fn addresses(self) -> &'static [u8] {
+        match self {
+            VemlVariant::Veml7700 => &[VEML_ADDR],
+            VemlVariant::Veml6030 => &[VEML_ADDR, VEML6030_ALT_ADDR],
+            VemlVariant::Veml6035 => &[VEML6035_ADDR],
+        }
+    }
+
+    /// Get the gains the sensor supports
+    ///
+    /// # Returns
+    /// The supported gains, from least to most sensitive
+    #[must_use]
+    pub fn gains(self) -> &'static [VemlGain] {
+        match self {
+            VemlVariant::Veml7700 | VemlVariant::Veml6030 => &VEML7700_GAINS,
+            VemlVariant::Veml6035 => &VEML6035_GAINS,
+        }
+    }
+
+    /// Get the gain the sensor uses in place of a gain
+    ///
+    /// Gains the sensor does not support are replaced with the nearest one
+    /// it does. Between two equally near gains the less sensitive one is
+    /// used, so bright light does not saturate the sensor.
+    ///
+    /// # Arguments
+    /// * `gain`: The requested gain
+    ///
+    /// # Returns
+    /// The gain itself if the sensor supports it, otherwise the nearest supported gain
+    #[must_use]
+    pub fn supported_gain(self, gain: VemlGain) -> VemlGain {
+        match (self, gain) {
+            (VemlVariant::Veml7700 | VemlVariant::Veml6030, VemlGain::Gain1_2) => VemlGain::Gain1_4,
+            (VemlVariant::Veml7700 | VemlVariant::Veml6030, VemlGain::Gain4) => VemlGain::Gain2,
+            _ => gain,
+        }
+    }
+
+    /// Check whether the high-lux non-linearity correction applies to the sensor
+    ///
+    /// Vishay publishes the correction polynomial for the VEML7700 and VEML6030.
+    /// The VEML6035 has a different response and no published correction, so
+    /// its lux values are never corrected.
+    ///
+    /// # Returns
+    /// Whether [`Veml7700::set_lux_correction`] has an effect for the sensor
+    #[must_use]
+    pub fn has_lux_correction(self) -> bool {
+        match self {
+            VemlVariant::Veml7700 | VemlVariant::Veml6030 => true,
+            VemlVariant::Veml6035 => false,
+        }
+    }
+
+    /// Get the lux per count at the maximum gain and integration time
+    fn base_scale(self) -> f32 {
+        match self {
+            VemlVariant::Veml7700 => VEML7700_BASE_SCALE,
+            VemlVariant::Veml6030 => VEML6030_BASE_SCALE,
+            VemlVariant::Veml6035 => VEML6035_BASE_SCALE,
+        }
+    }
+
+    /// Encode the gain and other variant specific bits of the configuration register
+    ///
+    /// # Arguments
+    /// * `gain`: The gain to encode
+    ///
+    /// # Returns
+    /// The bits to set in the configuration register, for the gain given by
+    /// [`Self::supported_gain`]
+    fn encode_gain(self, gain: VemlGain) -> u16 {
+        match self {
+            VemlVariant::Veml7700 | VemlVariant::Veml6030 => {
+                let code = match self.supported_gain(gain) {
+                    VemlGain::Gain1 | VemlGain::Gain1_2 | VemlGain::Gain4 => 0b00,
+                    VemlGain::Gain2 => 0b01,
+                    VemlGain::Gain1_8 => 0b10,
+                    VemlGain::Gain1_4 => 0b11,
+                };
+                code << 11
+            }
+            VemlVariant::Veml6035 => {
+                let gain_bits = match gain {
+                    VemlGain::Gain1_8 => VEML6035_SENS_LOW,
+                    VemlGain::Gain1_4 => VEML6035_SENS_LOW | VEML6035_GAIN_DOUBLE,
+                    VemlGain::Gain1_2 => {
+                        VEML6035_SENS_LOW | VEML6035_DG_DOUBLE | VEML6035_GAIN_DOUBLE
+                    }
+                    VemlGain::Gain1 => 0,
+                    VemlGain::Gain2 => VEML6035_GAIN_DOUBLE,
+                    VemlGain::Gain4 => VEML6035_DG_DOUBLE | VEML6035_GAIN_DOUBLE,
+                };
+                gain_bits | VEML6035_WHITE_ENABLE
+            }
+        }
+    }
+
+    /// Decode the gain from the configuration register
+    ///
+    /// # Arguments
+    /// * `value`: The configuration register
+    ///
+    /// # Returns
+    /// The gain set in the register
+    fn decode_gain(self, value: u16) -> VemlGain {
+        match self {
+            VemlVariant::Veml7700 | VemlVariant::Veml6030 => VemlGain::from((value >> 11) & 0b11),
+            VemlVariant::Veml6035 => {
+                let sens_low = (value & VEML6035_SENS_LOW) != 0;
+                let doubles = u8::from((value & VEML6035_DG_DOUBLE) != 0)
+                    + u8::from((value & VEML6035_GAIN_DOUBLE) != 0);
+                match (sens_low, doubles) {
+                    (true, 0) => VemlGain::Gain1_8,
+                    (true, 1) => VemlGain::Gain1_4,
+                    (true, _) => VemlGain::Gain1_2,
+                    (false, 0) => VemlGain::Gain1,
+                    (false, 1) => VemlGain::Gain2,
+                    (false, _) => VemlGain::Gain4,
+                }
+            }
+        }
+    }
+}
+
+/// Errors from the VEML7700 driver
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VemlError<E> {
+    /// An I2C transaction with the sensor failed
+    I2c(E),
+
+    /// The sensor had reset since it was last configured. The configuration
+    /// has been re-applied, but no sample was taken.
+    SensorReset,
+
+    /// The sensor can not be configured for the I2C address
+    UnsupportedAddress(u8),
+}
+
 /// Enumeration of the power saving modes of the sensor
 #[repr(u16)]
 #[derive(Clone, Copy, Debug)]
@@ -40,6 +282,22 @@ pub enum VemlPowerSavingMode {
     Mode4 = 0b11,
 }
 
+impl VemlPowerSavingMode {
+    /// Get the time the sensor waits between measurements in this mode
+    ///
+    /// # Returns
+    /// The wait time in milliseconds
+    #[must_use]
+    pub fn wait_time_ms(self) -> u32 {
+        match self {
+            VemlPowerSavingMode::Mode1 => 500,
+            VemlPowerSavingMode::Mode2 => 1000,
+            VemlPowerSavingMode::Mode3 => 2000,
+            VemlPowerSavingMode::Mode4 => 4000,
+        }
+    }
+}
+
 impl From<u16> for VemlPowerSavingMode {
     fn from(value: u16) -> Self {
         match value {
@@ -53,23 +311,46 @@ impl From<u16> for VemlPowerSavingMode {
 }
 
 /// Enumeration of the possible gain values for the sensor
-#[repr(u16)]
-#[derive(Clone, Copy, Debug)]
+///
+/// Not all gains are supported by every sensor, see [`VemlVariant::gains`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum VemlGain {
     /// 1X gain
-    Gain1 = 0x00,
+    #[default]
+    Gain1,
 
     /// 2X Gain
-    Gain2 = 0x01,
+    Gain2,
 
     /// 1/8 Gain
-    Gain1_8 = 0x02,
+    Gain1_8,
 
     /// 1/4 Gain
-    Gain1_4 = 0x04,
+    Gain1_4,
+
+    /// 1/2 Gain (VEML6035 only)
+    Gain1_2,
+
+    /// 4X Gain (VEML6035 only)
+    Gain4,
 }
+
+impl VemlGain {
+    /// Get the gain in eighths
+    fn eighths(self) -> u16 {
+        match self {
+            VemlGain::Gain1_8 => 1,
+            VemlGain::Gain1_4 => 2,
+            VemlGain::Gain1_2 => 4,
+            VemlGain::Gain1 => 8,
+            VemlGain::Gain2 => 16,
+            VemlGain::Gain4 => 32,
+        }
+    }
+}
+
 impl From<u16> for VemlGain {
-    /// Try to create enum from integer
+    /// Try to create enum from the VEML7700 gain bits
     ///
     /// # Arguments
     /// * `value`: The value to create the enum from
@@ -92,7 +373,7 @@ impl From<u16> for VemlGain {
 
 /// Enumeration of integration times for the sensor.
 #[repr(u16)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum VemlIntegration {
     /// 25ms integration time
     Int25 = 0b1100,
@@ -101,6 +382,7 @@ pub enum VemlIntegration {
     Int50 = 0b1000,
 
     /// 100ms integration time
+    #[default]
     Int100 = 0b0000,
 
     /// 200ms integration time
@@ -137,9 +419,27 @@ impl From<u16> for VemlIntegration {
     }
 }
 
+impl VemlIntegration {
+    /// Get the integration time in milliseconds
+    ///
+    /// # Returns
+    /// The integration time in milliseconds
+    #[must_use]
+    pub fn as_millis(self) -> u32 {
+        match self {
+            VemlIntegration::Int25 => 25,
+            VemlIntegration::Int50 => 50,
+            VemlIntegration::Int100 => 100,
+            VemlIntegration::Int200 => 200,
+            VemlIntegration::Int400 => 400,
+            VemlIntegration::Int800 => 800,
+        }
+    }
+}
+
 /// Enumeration of the persistence protection value of the sensor.
 #[repr(u16)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum VemlPersistence {
     /// Persistence Protect 1
     Persist1 = 0x00,
@@ -151,7 +451,7 @@ pub enum VemlPersistence {
     Persist4 = 0x02,
 
     /// Persistence Protect 8
-    Persist8 = 0x04,
+    Persist8 = 0b11,
 }
 
 impl From<u16> for VemlPersistence {
@@ -192,13 +492,25 @@ pub struct VemlConfigReg {
     pub shutdown: bool,
 }
 
-impl From<u16> for VemlConfigReg {
-    fn from(value: u16) -> Self {
+impl VemlConfigReg {
+    /// Decode the configuration register of a sensor
+    ///
+    /// # Arguments
+    /// * `value`: The value of the configuration register
+    /// * `variant`: The sensor the register was read from
+    ///
+    /// # Returns
+    /// The decoded configuration
+    ///
+    /// # Panics
+    /// Will panic if the register holds an unsupported integration time
+    #[must_use]
+    pub fn from_register(value: u16, variant: VemlVariant) -> Self {
         let shutdown = (value & 0x01) != 0;
         let interrupt_enabled = (value & 0x02) != 0;
         let persistence = VemlPersistence::from((value >> 4) & 0b11);
         let integration_time = VemlIntegration::from((value >> 6) & 0b1111);
-        let gain = VemlGain::from((value >> 11) & 0b11);
+        let gain = variant.decode_gain(value);
 
         Self {
             gain,
@@ -208,26 +520,160 @@ impl From<u16> for VemlConfigReg {
             shutdown,
         }
     }
-}
 
-impl From<VemlConfigReg> for u16 {
-    fn from(value: VemlConfigReg) -> Self {
-        let gain_int = value.gain as u16;
-        let integration_int = value.integration_time as u16;
-        let persist_int = value.persistence as u16;
-        let interrupt_int = u16::from(value.interrupt_enabled);
-        let shutdown_int = u16::from(value.shutdown);
+    /// Encode the configuration register of a sensor
+    ///
+    /// # Arguments
+    /// * `variant`: The sensor the register will be written to
+    ///
+    /// # Returns
+    /// The value of the configuration register. A gain the sensor does not
+    /// support is replaced with the nearest one it does, see
+    /// [`VemlVariant::supported_gain`].
+    #[must_use]
+    pub fn to_register(self, variant: VemlVariant) -> u16 {
+        let integration_int = self.integration_time as u16;
+        let persist_int = self.persistence as u16;
+        let interrupt_int = u16::from(self.interrupt_enabled);
+        let shutdown_int = u16::from(self.shutdown);
 
-        (gain_int << 11)
+        variant.encode_gain(self.gain)
             | (integration_int << 6)
             | (persist_int << 4)
             | (interrupt_int << 1)
             | shutdown_int
     }
+
+    /// Get the scale factor (lux per count) for the gain and integration time
+    ///
+    /// # Arguments
+    /// * `variant`: The sensor the configuration is for
+    fn als_scale(self, variant: VemlVariant) -> f32 {
+        let max_gain = variant.gains().last().map_or(1, |gain| gain.eighths());
+        let gain_scale = max_gain / self.gain.eighths();
+
+        let integration_scale: u16 = match self.integration_time {
+            VemlIntegration::Int25 => 32,
+            VemlIntegration::Int50 => 16,
+            VemlIntegration::Int100 => 8,
+            VemlIntegration::Int200 => 4,
+            VemlIntegration::Int400 => 2,
+            VemlIntegration::Int800 => 1,
+        };
+
+        variant.base_scale() * f32::from(gain_scale * integration_scale)
+    }
+
+    /// Compute the timing, resolution and power figures for the configuration
+    ///
+    /// The supply current is the typical active current averaged over the
+    /// integration time and the power saving wait, which matches the table
+    /// in Vishay's application note "Designing the VEML7700 Into an Application".
+    ///
+    /// # Arguments
+    /// * `variant`: The sensor the configuration is for
+    /// * `power_saving`: The power saving mode, or `None` if power saving is disabled
+    ///
+    /// # Returns
+    /// The operating point of the sensor
+    #[must_use]
+    pub fn operating_point(
+        self,
+        variant: VemlVariant,
+        power_saving: Option<VemlPowerSavingMode>,
+    ) -> VemlOperatingPoint {
+        let integration_ms = self.integration_time.as_millis();
+        let wait_ms = power_saving.map_or(0, VemlPowerSavingMode::wait_time_ms);
+        let refresh_time_ms = integration_ms + wait_ms;
+
+        // Times are at most a few seconds, so are exact as f32
+        #[allow(clippy::cast_precision_loss)]
+        let supply_current_ua = if self.shutdown {
+            SHUTDOWN_CURRENT_UA
+        } else {
+            (ACTIVE_CURRENT_UA * integration_ms as f32 + SHUTDOWN_CURRENT_UA * wait_ms as f32)
+                / refresh_time_ms as f32
+        };
+
+        let resolution = self.als_scale(variant);
+        VemlOperatingPoint {
+            refresh_time_ms,
+            resolution,
+            max_lux: resolution * f32::from(u16::MAX),
+            supply_current_ua,
+        }
+    }
+
+    /// Convert a lux value into the raw ALS count for the gain and integration time
+    ///
+    /// # Arguments
+    /// * `variant`: The sensor the configuration is for
+    /// * `lux`: The lux value to convert
+    /// * `lux_correction`: Whether `lux` has had the non-linearity correction applied,
+    ///   if the sensor has one
+    ///
+    /// # Returns
+    /// The raw count, saturated to the range of the ALS register
+    fn lux_to_counts(self, variant: VemlVariant, lux: f32, lux_correction: bool) -> u16 {
+        let lux = if lux_correction && variant.has_lux_correction() {
+            uncorrect_lux(lux)
+        } else {
+            lux
+        };
+        saturate_counts(lux / self.als_scale(variant))
+    }
+}
+
+/// Timing, resolution and power figures for a sensor configuration
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VemlOperatingPoint {
+    /// Time between new measurements in milliseconds
+    pub refresh_time_ms: u32,
+
+    /// Lux per count of the ALS and white channels
+    pub resolution: f32,
+
+    /// Maximum measurable lux, before any non-linearity correction
+    pub max_lux: f32,
+
+    /// Typical average supply current in microamps
+    pub supply_current_ua: f32,
+}
+
+/// ALS interrupt thresholds expressed in lux
+///
+/// The sensor compares raw counts, so the thresholds are converted with the
+/// active gain and integration time, and rewritten whenever those change. An
+/// interrupt is raised once `persistence` consecutive samples are outside the
+/// thresholds, so the latency is that many integration periods.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VemlLuxThresholds {
+    /// Lux below which the low threshold interrupt is raised
+    pub low: f32,
+
+    /// Lux above which the high threshold interrupt is raised
+    pub high: f32,
+
+    /// Number of consecutive samples outside the thresholds to raise an interrupt
+    pub persistence: VemlPersistence,
+}
+
+impl From<u16> for VemlConfigReg {
+    /// Decode a VEML7700 configuration register
+    fn from(value: u16) -> Self {
+        Self::from_register(value, VemlVariant::Veml7700)
+    }
+}
+
+impl From<VemlConfigReg> for u16 {
+    /// Encode a VEML7700 configuration register
+    fn from(value: VemlConfigReg) -> Self {
+        value.to_register(VemlVariant::Veml7700)
+    }
 }
 
 /// Structure of the output data from the sensor
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug)]
 pub struct VemlOutput {
     /// Raw ambient ligth sensor value.
     pub raw_als: u16,
@@ -237,6 +683,27 @@ pub struct VemlOutput {
 
     /// Computed Brightness in LUX
     pub lux: f32,
+
+    /// Brightness in LUX computed from the white channel with the ALS
+    /// resolution. The non-linearity correction is never applied to it, as
+    /// Vishay only publishes a correction for the ALS channel.
+    pub white_lux: f32,
+
+    /// Gain the sample was taken with
+    pub gain: VemlGain,
+
+    /// Integration time the sample was taken with
+    pub integration_time: VemlIntegration,
+
+    /// Whether the values are out of date, because the most recent attempt
+    /// to read the sensor failed, or it has not been read yet.
+    pub stale: bool,
+}
+
+impl Default for VemlOutput {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VemlOutput {
@@ -247,29 +714,328 @@ impl VemlOutput {
             raw_als: 0,
             raw_white: 0,
             lux: 0.0,
+            white_lux: 0.0,
+            gain: VemlGain::Gain1,
+            integration_time: VemlIntegration::Int100,
+            stale: true,
+        }
+    }
+
+    /// Create the output from raw sensor values
+    ///
+    /// # Arguments
+    /// * `raw_als`: The raw ALS value
+    /// * `raw_white`: The raw white value
+    /// * `config`: The configuration the values were measured with
+    /// * `variant`: The sensor the values were measured by
+    /// * `lux_correction`: Whether to apply the non-linearity correction to the
+    ///   ALS lux, if the sensor has one
+    fn from_raw(
+        raw_als: u16,
+        raw_white: u16,
+        config: VemlConfigReg,
+        variant: VemlVariant,
+        lux_correction: bool,
+    ) -> Self {
+        let scale = config.als_scale(variant);
+        let lux = f32::from(raw_als) * scale;
+        Self {
+            raw_als,
+            raw_white,
+            lux: if lux_correction && variant.has_lux_correction() {
+                correct_lux(lux)
+            } else {
+                lux
+            },
+            white_lux: f32::from(raw_white) * scale,
+            gain: config.gain,
+            integration_time: config.integration_time,
+            stale: false,
+        }
+    }
+}
+
+/// Apply the non-linearity correction to a lux value
+///
+/// The sensor response is not linear at high illuminance. Vishay's application
+/// note "Designing the VEML7700 Into an Application" gives a polynomial
+/// correction for values above 1000 lx; lower values are returned unchanged.
+///
+/// # Arguments
+/// * `lux`: The lux computed from the raw ALS count and resolution
+///
+/// # Returns
+/// The corrected lux
+pub(crate) fn correct_lux(lux: f32) -> f32 {
+    if lux <= LUX_CORRECTION_THRESHOLD {
+        return lux;
+    }
+    (((6.0135e-13 * lux - 9.3924e-9) * lux + 8.1488e-5) * lux + 1.0023) * lux
+}
+
+/// Convert a number of counts to an output register value
+///
+/// # Arguments
+/// * `counts`: The number of counts measured
+///
+/// # Returns
+/// The counts, rounded and saturated to the range of the register
+pub(crate) fn saturate_counts(counts: f32) -> u16 {
+    if counts >= f32::from(u16::MAX) {
+        u16::MAX
+    } else if counts <= 0.0 {
+        0
+    } else {
+        // Range was checked above
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let counts = (counts + 0.5) as u16;
+        counts
+    }
+}
+
+/// Undo the non-linearity correction of a lux value
+///
+/// This is the inverse of [`correct_lux`], found by bisection as the
+/// correction polynomial is monotonic.
+///
+/// # Arguments
+/// * `lux`: The corrected lux
+///
+/// # Returns
+/// The lux computed from the raw ALS count and resolution
+pub(crate) fn uncorrect_lux(lux: f32) -> f32 {
+    let (mut low, mut high) = (0.0, lux);
+    while high - low > high * f32::EPSILON * 4.0 {
+        let mid = f32::midpoint(low, high);
+        if correct_lux(mid) < lux {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    high
+}
+
+/// State of the auto-ranging algorithm from Vishay's application note
+/// "Designing the VEML7700 Into an Application".
+///
+/// The application note's settings form a ladder of increasing sensitivity:
+/// the lowest gain at 25 ms and 50 ms, every gain at 100 ms, then the highest
+/// gain at longer integration times. Ranging starts from the previous settings
+/// if they are on the ladder, or the lowest gain and 100 ms if not. While the
+/// count is at or below [`AUTO_RANGE_LOW_COUNTS`] it climbs the ladder. Once
+/// the count is above it, it descends for as long as the count exceeds
+/// [`AUTO_RANGE_HIGH_COUNTS`].
+#[derive(Clone, Copy, Debug)]
+struct AutoRange {
+    /// Gains of the sensor, from least to most sensitive
+    gains: &'static [VemlGain],
+
+    /// Index into `gains`
+    gain_index: usize,
+
+    /// Index into [`AUTO_RANGE_INTEGRATIONS`]
+    integration_index: usize,
+
+    /// Whether the sensitivity is being decreased instead of increased
+    decreasing: bool,
+}
+
+impl AutoRange {
+    /// Create the algorithm state at the application note's starting point.
+    ///
+    /// # Arguments
+    /// * `variant`: The sensor being ranged
+    fn new(variant: VemlVariant) -> Self {
+        Self {
+            gains: variant.gains(),
+            gain_index: 0,
+            integration_index: AUTO_RANGE_START_INTEGRATION,
+            decreasing: false,
+        }
+    }
+
+    /// Create the algorithm state at the settings of a configuration.
+    ///
+    /// # Arguments
+    /// * `variant`: The sensor being ranged
+    /// * `config`: The configuration the sensor was last sampled with
+    ///
+    /// # Returns
+    /// The state at the configured settings if they are on the ladder,
+    /// otherwise at the application note's starting point
+    fn resume(variant: VemlVariant, config: VemlConfigReg) -> Self {
+        let start = Self::new(variant);
+        let gain_index = start.gains.iter().position(|gain| *gain == config.gain);
+        let integration_index = AUTO_RANGE_INTEGRATIONS
+            .iter()
+            .position(|integration_time| *integration_time == config.integration_time);
+        let (Some(gain_index), Some(integration_index)) = (gain_index, integration_index) else {
+            return start;
+        };
+
+        let on_ladder = (integration_index >= AUTO_RANGE_START_INTEGRATION || gain_index == 0)
+            && (integration_index <= AUTO_RANGE_START_INTEGRATION
+                || gain_index == start.gains.len() - 1);
+        if on_ladder {
+            Self {
+                gain_index,
+                integration_index,
+                ..start
+            }
+        } else {
+            start
+        }
+    }
+
+    /// Apply the current gain and integration time to a configuration.
+    ///
+    /// # Arguments
+    /// * `config`: The configuration to update
+    ///
+    /// # Returns
+    /// Whether the configuration was changed
+    fn apply(self, config: &mut VemlConfigReg) -> bool {
+        let gain = self.gains[self.gain_index];
+        let integration_time = AUTO_RANGE_INTEGRATIONS[self.integration_index];
+        let changed = config.gain != gain || config.integration_time != integration_time;
+        config.gain = gain;
+        config.integration_time = integration_time;
+        changed
+    }
+
+    /// Step the algorithm with a newly measured count.
+    ///
+    /// # Arguments
+    /// * `raw_als`: The raw ALS count measured with the current settings
+    ///
+    /// # Returns
+    /// True if the settings changed and a new sample is needed, false if
+    /// the sample is accepted.
+    fn step(&mut self, raw_als: u16) -> bool {
+        if raw_als <= AUTO_RANGE_LOW_COUNTS && !self.decreasing {
+            return self.increase();
+        }
+        self.decreasing = true;
+        raw_als > AUTO_RANGE_HIGH_COUNTS && self.decrease()
+    }
+
+    /// Move one step up the ladder of sensitivity.
+    ///
+    /// # Returns
+    /// False if the settings are already the most sensitive
+    fn increase(&mut self) -> bool {
+        let start = AUTO_RANGE_START_INTEGRATION;
+        if self.integration_index >= start && self.gain_index < self.gains.len() - 1 {
+            self.gain_index += 1;
+            return true;
         }
+        if self.integration_index < AUTO_RANGE_INTEGRATIONS.len() - 1 {
+            self.integration_index += 1;
+            return true;
+        }
+        false
+    }
+
+    /// Move one step down the ladder of sensitivity.
+    ///
+    /// # Returns
+    /// False if the settings are already the least sensitive
+    fn decrease(&mut self) -> bool {
+        let start = AUTO_RANGE_START_INTEGRATION;
+        if self.integration_index <= start && self.gain_index > 0 {
+            self.gain_index -= 1;
+            return true;
+        }
+        if self.integration_index > 0 {
+            self.integration_index -= 1;
+            return true;
+        }
+        false
     }
 }
 
+/// Time to wait after changing the integration time before sampling.
+///
+/// The sensor may be part way through a cycle with the previous setting, and
+/// auto-ranging changes the integration time by at most a factor of two, so
+/// two cycles of the new integration time always covers a full new sample.
+///
+/// # Arguments
+/// * `integration_time`: The new integration time
+///
+/// # Returns
+/// The time to wait in milliseconds
+fn settle_time_ms(integration_time: VemlIntegration) -> u32 {
+    2 * integration_time.as_millis()
+}
+
 /// Main structure for the VEML7700
 pub struct Veml7700<I2C> {
     /// Concrete I2C implementation
     i2c: I2C,
 
+    /// The sensor being driven
+    variant: VemlVariant,
+
+    /// I2C address of the sensor
+    address: u8,
+
     /// Sensor configuration
     configuration: VemlConfigReg,
 
     /// Values from the most recent sensor measurement.
     last_output: VemlOutput,
-}
 
-// TODO: Add method for computing refresh time and resolution given gain, power saving mode, integration time
+    /// Whether the high-lux non-linearity correction is applied
+    lux_correction: bool,
+
+    /// Interrupt thresholds in lux, if set
+    lux_thresholds: Option<VemlLuxThresholds>,
+
+    /// Value last written to the power saving register
+    power_saving: u16,
+}
 
 impl<I2C: I2c> Veml7700<I2C> {
     /// Create a new instance of the VEML7700 driver.
     pub fn new(i2c: I2C) -> Self {
+        Self::with_address(i2c, VemlVariant::Veml7700, VEML_ADDR)
+    }
+
+    /// Create a new instance of the driver for one of the supported sensors.
+    ///
+    /// # Arguments
+    /// * `i2c`: The I2C bus the sensor is on
+    /// * `variant`: The sensor being driven
+    /// * `address`: The I2C address of the sensor
+    ///
+    /// # Errors
+    /// Will return [`VemlError::UnsupportedAddress`] if `address` is not one
+    /// of [`VemlVariant::addresses`]
+    pub fn new_variant(
+        i2c: I2C,
+        variant: VemlVariant,
+        address: u8,
+    ) -> Result<Self, VemlError<I2C::Error>> {
+        if variant.addresses().contains(&address) {
+            Ok(Self::with_address(i2c, variant, address))
+        } else {
+            Err(VemlError::UnsupportedAddress(address))
+        }
+    }
+
+    /// Create a new instance of the driver, with an address already checked
+    ///
+    /// # Arguments
+    /// * `i2c`: The I2C bus the sensor is on
+    /// * `variant`: The sensor being driven
+    /// * `address`: The I2C address of the sensor
+    fn with_address(i2c: I2C, variant: VemlVariant, address: u8) -> Self {
         Self {
             i2c,
+            variant,
+            address,
             configuration: VemlConfigReg {
                 gain: VemlGain::Gain1,
                 integration_time: VemlIntegration::Int100,
@@ -277,14 +1043,76 @@ impl<I2C: I2c> Veml7700<I2C> {
                 interrupt_enabled: false,
                 shutdown: false,
             },
-            last_output: VemlOutput {
-                raw_als: 0,
-                raw_white: 0,
-                lux: 0.0,
-            },
+            last_output: VemlOutput::new(),
+            lux_correction: true,
+            lux_thresholds: None,
+            power_saving: 0,
         }
     }
 
+    /// Get the sensor being driven
+    ///
+    /// # Returns
+    /// The sensor variant
+    pub fn get_variant(&self) -> VemlVariant {
+        self.variant
+    }
+
+    /// Enable or disable the high-lux non-linearity correction.
+    ///
+    /// The correction is enabled by default. It only applies to the VEML7700
+    /// and VEML6030, see [`VemlVariant::has_lux_correction`].
+    ///
+    /// # Arguments
+    /// * `enable`: Whether to apply the correction to computed lux values
+    pub fn set_lux_correction(&mut self, enable: bool) {
+        self.lux_correction = enable;
+    }
+
+    /// Set the ALS interrupt thresholds in lux, and enable the interrupt.
+    ///
+    /// The raw thresholds are recomputed whenever the gain or integration time
+    /// is changed through the driver, replacing any set with
+    /// [`Self::set_als_int_high_threshold`] or [`Self::set_als_int_low_threshold`].
+    ///
+    /// # Arguments
+    /// * `thresholds`: The thresholds and persistence to use
+    ///
+    /// # Errors
+    /// Will return an error if the I2C transaction fails.
+    pub fn set_lux_thresholds(&mut self, thresholds: VemlLuxThresholds) -> Result<(), I2C::Error> {
+        self.lux_thresholds = Some(thresholds);
+        self.configuration.persistence = thresholds.persistence;
+        self.configuration.interrupt_enabled = true;
+        self.write_internal_configuration()
+    }
+
+    /// Get the ALS interrupt thresholds in lux
+    ///
+    /// # Returns
+    /// The thresholds set with [`Self::set_lux_thresholds`], if any
+    pub fn get_lux_thresholds(&self) -> Option<VemlLuxThresholds> {
+        self.lux_thresholds
+    }
+
+    /// Stop using lux thresholds, and disable the ALS interrupt.
+    ///
+    /// # Errors
+    /// Will return an error if the I2C transaction fails.
+    pub fn clear_lux_thresholds(&mut self) -> Result<(), I2C::Error> {
+        self.lux_thresholds = None;
+        self.configuration.interrupt_enabled = false;
+        self.write_internal_configuration()
+    }
+
+    /// Get whether the high-lux non-linearity correction is enabled.
+    ///
+    /// # Returns
+    /// True if the correction is applied to computed lux values
+    pub fn get_lux_correction(&self) -> bool {
+        self.lux_correction
+    }
+
     /// Write configuration structure to the sensor
     ///
     /// # Arguments
@@ -293,10 +1121,16 @@ impl<I2C: I2c> Veml7700<I2C> {
     /// # Returns
     /// Result of writing the configuration to the sensor.
     ///
+    /// A gain the sensor does not support is replaced with the nearest one it
+    /// does, see [`VemlVariant::supported_gain`].
+    ///
     /// # Errors
     /// Will return an error if the I2C Transation Fails
     pub fn set_configuration(&mut self, config: VemlConfigReg) -> Result<(), I2C::Error> {
-        self.configuration = config;
+        self.configuration = VemlConfigReg {
+            gain: self.variant.supported_gain(config.gain),
+            ..config
+        };
         self.write_internal_configuration()
     }
 
@@ -309,7 +1143,7 @@ impl<I2C: I2c> Veml7700<I2C> {
     /// Will return an error if the I2C Transation Fails
     pub fn get_configuration(&mut self) -> Result<VemlConfigReg, I2C::Error> {
         let result = self.write_read_u16(VemlRegister::Config)?;
-        Ok(VemlConfigReg::from(result))
+        Ok(VemlConfigReg::from_register(result, self.variant))
     }
 
     /// Get the raw ALS value from the sensor
@@ -343,7 +1177,14 @@ impl<I2C: I2c> Veml7700<I2C> {
     /// Computed ALS lux value
     pub fn get_lux(&mut self) -> Result<f32, I2C::Error> {
         let raw_als = self.get_ambient_level()?;
-        Ok(self.get_als_scale() * f32::from(raw_als))
+        let lux = self.get_als_scale() * f32::from(raw_als);
+        Ok(
+            if self.lux_correction && self.variant.has_lux_correction() {
+                correct_lux(lux)
+            } else {
+                lux
+            },
+        )
     }
 
     /// Set the sensor gain to the specified value.
@@ -354,10 +1195,13 @@ impl<I2C: I2c> Veml7700<I2C> {
     /// # Returns
     /// Result of setting the gain
     ///
+    /// A gain the sensor does not support is replaced with the nearest one it
+    /// does, see [`VemlVariant::supported_gain`].
+    ///
     /// # Errors
     /// Will return an error if the I2C Transation Fails
     pub fn set_gain(&mut self, gain: VemlGain) -> Result<(), I2C::Error> {
-        self.configuration.gain = gain;
+        self.configuration.gain = self.variant.supported_gain(gain);
         self.write_internal_configuration()
     }
 
@@ -483,8 +1327,8 @@ impl<I2C: I2c> Veml7700<I2C> {
     /// Returns an error if reading the interrupt status register failed.
     pub fn get_interrupt_status(&mut self) -> Result<(bool, bool), I2C::Error> {
         let reg_value = self.write_read_u16(VemlRegister::ALSInterruptStatus)?;
-        let low = reg_value & 8000;
-        let high = reg_value & 4000;
+        let low = reg_value & 0x8000;
+        let high = reg_value & 0x4000;
 
         Ok((low > 0, high > 0))
     }
@@ -540,19 +1384,59 @@ impl<I2C: I2c> Veml7700<I2C> {
         Ok(mode_bits == 1)
     }
 
+    /// Get the timing, resolution and power figures for the current configuration
+    ///
+    /// # Returns
+    /// The operating point, using the power saving mode read from the sensor
+    ///
+    /// # Errors
+    /// Returns an error if reading the power saving register fails.
+    pub fn get_operating_point(&mut self) -> Result<VemlOperatingPoint, I2C::Error> {
+        let reg_val = self.write_read_u16(VemlRegister::PowerSaving)?;
+        let power_saving = if (reg_val & 0b01) != 0 {
+            Some(VemlPowerSavingMode::from((reg_val & 0b110) >> 1))
+        } else {
+            None
+        };
+        Ok(self
+            .configuration
+            .operating_point(self.variant, power_saving))
+    }
+
     /// Perform the VEML task's periodic prrocessing
     ///
-    /// # Panics
-    /// Will panic if reading the ambient level or white level fails
-    pub fn periodic_process(&mut self) {
-        let raw_als = self.get_ambient_level().unwrap();
-        let raw_white = self.get_white_level().unwrap();
-        let lux = f32::from(raw_als) * self.get_als_scale();
-        self.last_output = VemlOutput {
-            raw_als,
-            raw_white,
-            lux,
-        }
+    /// If the sensor has reset since it was configured, the configuration is
+    /// re-applied. On any error the previous outputs are kept, but marked stale.
+    ///
+    /// # Errors
+    /// Returns an error if the sensor had reset, or reading it fails
+    pub fn periodic_process(&mut self) -> Result<(), VemlError<I2C::Error>> {
+        let result = self.read_outputs();
+        self.mark_stale_on_error(result)
+    }
+
+    /// Perform the VEML task's periodic processing with automatic ranging
+    ///
+    /// Gain and integration time are chosen following Vishay's application
+    /// note, waiting out each new integration period before sampling again.
+    /// The chosen settings are left configured and recorded in the output,
+    /// and ranging carries on from them at the next call, so steady light
+    /// needs no reconfiguration.
+    ///
+    /// # Arguments
+    /// * `delay`: Delay used to wait for new samples
+    ///
+    /// As with [`Self::periodic_process`], a reset sensor is reconfigured and
+    /// outputs are marked stale on any error.
+    ///
+    /// # Errors
+    /// Returns an error if the sensor had reset, or configuring or reading it fails
+    pub fn periodic_process_auto_range<D: DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<(), VemlError<I2C::Error>> {
+        let result = self.read_outputs_auto_range(delay);
+        self.mark_stale_on_error(result)
     }
 
     /// Get the most recent set of data read from the sensor
@@ -582,28 +1466,106 @@ impl<I2C: I2c> Veml7700<I2C> {
         enable: bool,
     ) -> Result<(), I2C::Error> {
         let reg_value = ((mode as u16) << 1) | u16::from(enable);
+        self.power_saving = reg_value;
         self.write_u16(VemlRegister::PowerSaving, reg_value)
     }
 
-    /// Get the current scale factor based on gain and integration time
-    fn get_als_scale(&self) -> f32 {
-        let gain_scale: u16 = match self.configuration.gain {
-            VemlGain::Gain2 => 1,
-            VemlGain::Gain1 => 2,
-            VemlGain::Gain1_4 => 8,
-            VemlGain::Gain1_8 => 16,
-        };
+    /// Read a new set of outputs with the current configuration
+    ///
+    /// # Errors
+    /// Returns an error if the sensor had reset, or reading it fails
+    fn read_outputs(&mut self) -> Result<(), VemlError<I2C::Error>> {
+        self.recover_from_reset()?;
+        let raw_als = self.get_ambient_level().map_err(VemlError::I2c)?;
+        let raw_white = self.get_white_level().map_err(VemlError::I2c)?;
+        self.last_output = VemlOutput::from_raw(
+            raw_als,
+            raw_white,
+            self.configuration,
+            self.variant,
+            self.lux_correction,
+        );
+        Ok(())
+    }
 
-        let integration_scale: u16 = match self.configuration.integration_time {
-            VemlIntegration::Int25 => 32,
-            VemlIntegration::Int50 => 16,
-            VemlIntegration::Int100 => 8,
-            VemlIntegration::Int200 => 4,
-            VemlIntegration::Int400 => 2,
-            VemlIntegration::Int800 => 1,
+    /// Read a new set of outputs, auto-ranging the gain and integration time
+    ///
+    /// # Arguments
+    /// * `delay`: Delay used to wait for new samples
+    ///
+    /// # Errors
+    /// Returns an error if the sensor had reset, or configuring or reading it fails
+    fn read_outputs_auto_range<D: DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<(), VemlError<I2C::Error>> {
+        self.recover_from_reset()?;
+        let mut ranging = AutoRange::resume(self.variant, self.configuration);
+        let raw_als = loop {
+            let mut configuration = self.configuration;
+            if ranging.apply(&mut configuration) {
+                self.write_configuration(configuration)
+                    .map_err(VemlError::I2c)?;
+                delay.delay_ms(settle_time_ms(configuration.integration_time));
+            }
+            let raw_als = self.get_ambient_level().map_err(VemlError::I2c)?;
+            if !ranging.step(raw_als) {
+                break raw_als;
+            }
         };
+        let raw_white = self.get_white_level().map_err(VemlError::I2c)?;
+        self.last_output = VemlOutput::from_raw(
+            raw_als,
+            raw_white,
+            self.configuration,
+            self.variant,
+            self.lux_correction,
+        );
+        Ok(())
+    }
+
+    /// Check whether the sensor has reset, and re-apply the configuration if so.
+    ///
+    /// A reset is detected by the configuration register no longer matching
+    /// the configuration last written to it.
+    ///
+    /// # Errors
+    /// Returns [`VemlError::SensorReset`] if the sensor had reset, or an error
+    /// if the I2C transactions fail.
+    fn recover_from_reset(&mut self) -> Result<(), VemlError<I2C::Error>> {
+        let config = self
+            .write_read_u16(VemlRegister::Config)
+            .map_err(VemlError::I2c)?;
+        if config == self.configuration.to_register(self.variant) {
+            return Ok(());
+        }
+        self.write_internal_configuration()
+            .map_err(VemlError::I2c)?;
+        self.write_u16(VemlRegister::PowerSaving, self.power_saving)
+            .map_err(VemlError::I2c)?;
+        Err(VemlError::SensorReset)
+    }
 
-        ALS_BASE_SCALE * f32::from(gain_scale * integration_scale)
+    /// Mark the most recent outputs as stale if processing failed
+    ///
+    /// # Arguments
+    /// * `result`: The result of processing
+    ///
+    /// # Returns
+    /// The result of processing, unchanged
+    fn mark_stale_on_error(
+        &mut self,
+        result: Result<(), VemlError<I2C::Error>>,
+    ) -> Result<(), VemlError<I2C::Error>> {
+        if result.is_err() {
+            self.last_output.stale = true;
+        }
+        result
+    }
+
+    /// Get the current scale factor based on gain and integration time
+    fn get_als_scale(&self) -> f32 {
+        self.configuration.als_scale(self.variant)
     }
 
     /// Write the VEML Configuration from the internal structure
@@ -611,7 +1573,36 @@ impl<I2C: I2c> Veml7700<I2C> {
     /// # Returns
     /// Result of writing the config
     fn write_internal_configuration(&mut self) -> Result<(), I2C::Error> {
-        self.write_u16(VemlRegister::Config, self.configuration.into())
+        self.write_configuration(self.configuration)
+    }
+
+    /// Write a configuration, and keep it as the internal configuration once
+    /// the sensor has accepted it
+    ///
+    /// # Arguments
+    /// * `configuration`: The configuration to write
+    ///
+    /// # Returns
+    /// Result of writing the config
+    fn write_configuration(&mut self, configuration: VemlConfigReg) -> Result<(), I2C::Error> {
+        self.write_u16(
+            VemlRegister::Config,
+            configuration.to_register(self.variant),
+        )?;
+        self.configuration = configuration;
+        if let Some(thresholds) = self.lux_thresholds {
+            let high = self.configuration.lux_to_counts(
+                self.variant,
+                thresholds.high,
+                self.lux_correction,
+            );
+            let low =
+                self.configuration
+                    .lux_to_counts(self.variant, thresholds.low, self.lux_correction);
+            self.write_u16(VemlRegister::ALSHighThreshold, high)?;
+            self.write_u16(VemlRegister::ALSLowThreshold, low)?;
+        }
+        Ok(())
     }
 
     /// Read a u16 Data register
@@ -624,7 +1615,7 @@ impl<I2C: I2c> Veml7700<I2C> {
     fn write_read_u16(&mut self, reg: VemlRegister) -> Result<u16, I2C::Error> {
         let tx_buf = [reg as u8];
         let mut rx_buf = [0, 0];
-        self.i2c.write_read(VEML_ADDR, &tx_buf, &mut rx_buf)?;
+        self.i2c.write_read(self.address, &tx_buf, &mut rx_buf)?;
         let result = ((u16::from(rx_buf[1])) << 8) | (u16::from(rx_buf[0]));
         Ok(result)
     }
@@ -640,7 +1631,7 @@ impl<I2C: I2c> Veml7700<I2C> {
     fn write_u16(&mut self, reg: VemlRegister, data: u16) -> Result<(), I2C::Error> {
         let data_bytes = data.to_le_bytes();
         let tx_buf = [reg as u8, data_bytes[0], data_bytes[1]];
-        self.i2c.write(VEML_ADDR, &tx_buf)
+        self.i2c.write(self.address, &tx_buf)
     }
 }
 
@@ -648,6 +1639,7 @@ impl<I2C: I2c> Veml7700<I2C> {
 mod test {
 
     use super::*;
+    use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
     use embedded_hal_mock::eh1::i2c::{Mock as I2cMock, Transaction as I2cTransaction};
     // Without this use statement, unit tests will not run in the library crate.
     // Not sure why, but it is what it is.
@@ -681,6 +1673,135 @@ mod test {
 
     // TODO: Method to test `write_internal_configuration`.
 
+    /// Test the configuration register encodings match the VEML7700 datasheet.
+    #[test]
+    fn test_config_register_encoding() {
+        let config = |gain, integration_time, persistence| VemlConfigReg {
+            gain,
+            integration_time,
+            persistence,
+            interrupt_enabled: false,
+            shutdown: false,
+        };
+
+        // ALS_GAIN, bits 12:11
+        let gains = [
+            (VemlGain::Gain1, 0b00),
+            (VemlGain::Gain2, 0b01),
+            (VemlGain::Gain1_8, 0b10),
+            (VemlGain::Gain1_4, 0b11),
+        ];
+        for (gain, bits) in gains {
+            let value = config(gain, VemlIntegration::Int100, VemlPersistence::Persist1)
+                .to_register(VemlVariant::Veml7700);
+            assert_eq!(value, bits << 11, "{gain:?}");
+            assert_eq!(
+                VemlConfigReg::from_register(value, VemlVariant::Veml7700).gain,
+                gain
+            );
+        }
+
+        // ALS_IT, bits 9:6
+        let integration_times = [
+            (VemlIntegration::Int25, 0b1100),
+            (VemlIntegration::Int50, 0b1000),
+            (VemlIntegration::Int100, 0b0000),
+            (VemlIntegration::Int200, 0b0001),
+            (VemlIntegration::Int400, 0b0010),
+            (VemlIntegration::Int800, 0b0011),
+        ];
+        for (integration_time, bits) in integration_times {
+            let value = config(VemlGain::Gain1, integration_time, VemlPersistence::Persist1)
+                .to_register(VemlVariant::Veml7700);
+            assert_eq!(value, bits << 6, "{integration_time:?}");
+            assert_eq!(
+                VemlConfigReg::from_register(value, VemlVariant::Veml7700).integration_time,
+                integration_time
+            );
+        }
+
+        // ALS_PERS, bits 5:4
+        let persistences = [
+            (VemlPersistence::Persist1, 0b00),
+            (VemlPersistence::Persist2, 0b01),
+            (VemlPersistence::Persist4, 0b10),
+            (VemlPersistence::Persist8, 0b11),
+        ];
+        for (persistence, bits) in persistences {
+            let value = config(VemlGain::Gain1, VemlIntegration::Int100, persistence)
+                .to_register(VemlVariant::Veml7700);
+            assert_eq!(value, bits << 4, "{persistence:?}");
+            assert_eq!(
+                VemlConfigReg::from_register(value, VemlVariant::Veml7700).persistence,
+                persistence
+            );
+        }
+
+        // ALS_INT_EN is bit 1, and ALS_SD bit 0
+        let value = VemlConfigReg {
+            interrupt_enabled: true,
+            shutdown: true,
+            ..config(
+                VemlGain::Gain1,
+                VemlIntegration::Int100,
+                VemlPersistence::Persist1,
+            )
+        }
+        .to_register(VemlVariant::Veml7700);
+        assert_eq!(value, 0b11);
+    }
+
+    /// Test the interrupt status flags match the VEML7700 datasheet: `int_th_low`
+    /// is bit 15 and `int_th_high` bit 14 of register 6.
+    #[test]
+    fn test_interrupt_status() {
+        let expectations = [
+            I2cTransaction::write_read(VEML_ADDR, vec![0x06], vec![0x00, 0x80]),
+            I2cTransaction::write_read(VEML_ADDR, vec![0x06], vec![0x00, 0x40]),
+            I2cTransaction::write_read(VEML_ADDR, vec![0x06], vec![0xFF, 0x3F]),
+        ];
+        let interface = I2cMock::new(&expectations);
+        let mut device = Veml7700::new(interface);
+        assert_eq!(device.get_interrupt_status(), Ok((true, false)));
+        assert_eq!(device.get_interrupt_status(), Ok((false, true)));
+        assert_eq!(device.get_interrupt_status(), Ok((false, false)));
+        let mut interface = device.destroy();
+        interface.done();
+    }
+
+    /// Test a failed read marks the outputs stale, rather than panicking.
+    #[test]
+    fn test_periodic_process_error() {
+        let expectations = [
+            I2cTransaction::write_read(VEML_ADDR, vec![0x00], vec![0x00, 0x00]),
+            I2cTransaction::write_read(VEML_ADDR, vec![0x04], vec![0x10, 0x00]),
+            I2cTransaction::write_read(VEML_ADDR, vec![0x05], vec![0x20, 0x00]),
+            I2cTransaction::write_read(VEML_ADDR, vec![0x00], vec![0x00, 0x00]),
+            I2cTransaction::write_read(VEML_ADDR, vec![0x04], vec![0x00, 0x00])
+                .with_error(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data)),
+        ];
+        let interface = I2cMock::new(&expectations);
+        let mut device = Veml7700::new(interface);
+        assert!(device.get_outputs().stale);
+
+        assert_eq!(device.periodic_process(), Ok(()));
+        let outputs = device.get_outputs();
+        assert!(!outputs.stale);
+        assert_eq!(outputs.raw_als, 0x10);
+
+        assert_eq!(
+            device.periodic_process(),
+            Err(VemlError::I2c(ErrorKind::NoAcknowledge(
+                NoAcknowledgeSource::Data
+            )))
+        );
+        let outputs = device.get_outputs();
+        assert!(outputs.stale);
+        assert_eq!(outputs.raw_als, 0x10);
+
+        device.destroy().done();
+    }
+
     /// Test the `get_als_scale` function.
     #[test]
     fn test_get_als_scale() {
@@ -736,4 +1857,285 @@ mod test {
 
         device.destroy().done();
     }
+
+    /// Test the scale at maximum sensitivity, and the gain steps of each variant
+    #[test]
+    fn test_variant_scale() {
+        let cases = [
+            (VemlVariant::Veml7700, VemlGain::Gain2, 0.0042),
+            (VemlVariant::Veml7700, VemlGain::Gain1_8, 0.0672),
+            (VemlVariant::Veml6030, VemlGain::Gain2, 0.0036),
+            (VemlVariant::Veml6030, VemlGain::Gain1_8, 0.0576),
+            (VemlVariant::Veml6035, VemlGain::Gain4, 0.0004),
+            (VemlVariant::Veml6035, VemlGain::Gain1, 0.0016),
+            (VemlVariant::Veml6035, VemlGain::Gain1_8, 0.0128),
+        ];
+        for (variant, gain, scale) in cases {
+            let config = VemlConfigReg {
+                gain,
+                integration_time: VemlIntegration::Int800,
+                ..VemlConfigReg::from(0)
+            };
+            let computed_scale = config.als_scale(variant);
+            assert!(
+                (computed_scale - scale).abs() < 0.00001,
+                "{variant:?} {gain:?}: {computed_scale}"
+            );
+
+            let register = config.to_register(variant);
+            assert_eq!(VemlConfigReg::from_register(register, variant).gain, gain);
+        }
+    }
+
+    /// Test a gain the sensor does not support is replaced with the nearest supported gain
+    #[test]
+    fn test_unsupported_gain() {
+        for (gain, expected) in [
+            (VemlGain::Gain4, VemlGain::Gain2),
+            (VemlGain::Gain1_2, VemlGain::Gain1_4),
+        ] {
+            for variant in [VemlVariant::Veml7700, VemlVariant::Veml6030] {
+                assert_eq!(variant.supported_gain(gain), expected);
+                let config = VemlConfigReg {
+                    gain,
+                    ..VemlConfigReg::from(0)
+                };
+                let register = config.to_register(variant);
+                assert_eq!(
+                    VemlConfigReg::from_register(register, variant).gain,
+                    expected
+                );
+            }
+            assert_eq!(VemlVariant::Veml6035.supported_gain(gain), gain);
+        }
+    }
+
+    /// Test the driver can only be created for the addresses the sensor supports
+    #[test]
+    fn test_new_variant_address() {
+        let mut i2c = I2cMock::new(&[]);
+        assert!(matches!(
+            Veml7700::new_variant(&mut i2c, VemlVariant::Veml7700, 0x48),
+            Err(VemlError::UnsupportedAddress(0x48))
+        ));
+        let device = Veml7700::new_variant(&mut i2c, VemlVariant::Veml6030, 0x48).unwrap();
+        assert_eq!(device.get_variant(), VemlVariant::Veml6030);
+        i2c.done();
+    }
+
+    /// Test the operating point calculation.
+    #[test]
+    fn test_operating_point() {
+        // Refresh time and current from the power saving mode table in the
+        // Vishay application note "Designing the VEML7700 Into an Application".
+        let cases = [
+            (
+                VemlPowerSavingMode::Mode1,
+                VemlIntegration::Int100,
+                600,
+                8.0,
+            ),
+            (
+                VemlPowerSavingMode::Mode1,
+                VemlIntegration::Int400,
+                900,
+                20.0,
+            ),
+            (
+                VemlPowerSavingMode::Mode2,
+                VemlIntegration::Int200,
+                1200,
+                8.0,
+            ),
+            (
+                VemlPowerSavingMode::Mode3,
+                VemlIntegration::Int400,
+                2400,
+                8.0,
+            ),
+            (
+                VemlPowerSavingMode::Mode4,
+                VemlIntegration::Int100,
+                4100,
+                2.0,
+            ),
+        ];
+        let mut config = VemlConfigReg::from(0);
+        for (mode, integration, refresh_time_ms, current) in cases {
+            config.integration_time = integration;
+            let point = config.operating_point(VemlVariant::Veml7700, Some(mode));
+            assert_eq!(point.refresh_time_ms, refresh_time_ms);
+            assert!(
+                (point.supply_current_ua - current).abs() < 1.0,
+                "{mode:?} {integration:?}: {}",
+                point.supply_current_ua
+            );
+        }
+
+        // Without power saving the sensor measures continuously
+        config.integration_time = VemlIntegration::Int100;
+        let point = config.operating_point(VemlVariant::Veml7700, None);
+        assert_eq!(point.refresh_time_ms, 100);
+        assert!((point.supply_current_ua - ACTIVE_CURRENT_UA).abs() < f32::EPSILON);
+
+        // Maximum lux from the resolution table, at 2x gain and 800ms
+        config.gain = VemlGain::Gain2;
+        config.integration_time = VemlIntegration::Int800;
+        let point = config.operating_point(VemlVariant::Veml7700, None);
+        assert!((point.resolution - 0.0042).abs() < 0.00001);
+        assert!((point.max_lux - 275.0).abs() < 1.0);
+
+        config.shutdown = true;
+        let point = config.operating_point(VemlVariant::Veml7700, Some(VemlPowerSavingMode::Mode1));
+        assert!((point.supply_current_ua - SHUTDOWN_CURRENT_UA).abs() < f32::EPSILON);
+    }
+
+    /// Test the non-linearity correction.
+    #[test]
+    fn test_correct_lux() {
+        // Values at and below the threshold are unchanged
+        assert!((correct_lux(0.0)).abs() < f32::EPSILON);
+        assert!((correct_lux(LUX_CORRECTION_THRESHOLD) - LUX_CORRECTION_THRESHOLD).abs() < 0.001);
+
+        // Values above are raised by the polynomial
+        let cases = [(2000.0, 2265.0), (10000.0, 14792.9)];
+        for (lux, expected) in cases {
+            let corrected = correct_lux(lux);
+            assert!((corrected - expected).abs() < 0.5, "{lux}: {corrected}");
+        }
+    }
+
+    /// Test the auto-ranging steps match the application note flow.
+    #[test]
+    fn test_auto_range_step() {
+        // Dark: Gain is increased to maximum, then the integration time.
+        let mut ranging = AutoRange::new(VemlVariant::Veml7700);
+        let mut config = VemlConfigReg::from(0);
+        for _ in 0..6 {
+            assert!(ranging.step(AUTO_RANGE_LOW_COUNTS));
+        }
+        assert!(!ranging.step(0));
+        ranging.apply(&mut config);
+        assert_eq!(config.gain, VemlGain::Gain2);
+        assert_eq!(config.integration_time, VemlIntegration::Int800);
+
+        // Bright: Integration time is shortened down to the minimum
+        let mut ranging = AutoRange::new(VemlVariant::Veml7700);
+        assert!(ranging.step(u16::MAX));
+        assert!(ranging.step(u16::MAX));
+        assert!(!ranging.step(u16::MAX));
+        ranging.apply(&mut config);
+        assert_eq!(config.gain, VemlGain::Gain1_8);
+        assert_eq!(config.integration_time, VemlIntegration::Int25);
+
+        // Once decreasing, a dim sample is accepted as is
+        let mut ranging = AutoRange::new(VemlVariant::Veml7700);
+        assert!(ranging.step(AUTO_RANGE_HIGH_COUNTS + 1));
+        assert!(!ranging.step(AUTO_RANGE_LOW_COUNTS));
+
+        // In range at the starting point is accepted immediately
+        let mut ranging = AutoRange::new(VemlVariant::Veml7700);
+        assert!(!ranging.step(AUTO_RANGE_HIGH_COUNTS));
+    }
+
+    /// Test auto-ranging resumes from settings on the application note's
+    /// ladder, and restarts from anything else.
+    #[test]
+    fn test_auto_range_resume() {
+        let resume = |gain, integration_time| {
+            AutoRange::resume(
+                VemlVariant::Veml7700,
+                VemlConfigReg {
+                    gain,
+                    integration_time,
+                    ..VemlConfigReg::from(0)
+                },
+            )
+        };
+        let settings = |ranging: AutoRange| {
+            let mut config = VemlConfigReg::from(0);
+            ranging.apply(&mut config);
+            (config.gain, config.integration_time)
+        };
+
+        // Bright after dark: Integration time is shortened, then the gain lowered
+        let mut ranging = resume(VemlGain::Gain2, VemlIntegration::Int200);
+        assert!(ranging.step(u16::MAX));
+        assert_eq!(
+            settings(ranging),
+            (VemlGain::Gain2, VemlIntegration::Int100)
+        );
+        assert!(ranging.step(u16::MAX));
+        assert_eq!(
+            settings(ranging),
+            (VemlGain::Gain1, VemlIntegration::Int100)
+        );
+
+        // Dark after bright: Integration time is lengthened, then the gain raised
+        let mut ranging = resume(VemlGain::Gain1_8, VemlIntegration::Int50);
+        assert!(ranging.step(0));
+        assert_eq!(
+            settings(ranging),
+            (VemlGain::Gain1_8, VemlIntegration::Int100)
+        );
+        assert!(ranging.step(0));
+        assert_eq!(
+            settings(ranging),
+            (VemlGain::Gain1_4, VemlIntegration::Int100)
+        );
+
+        // In range is accepted without a change
+        let mut ranging = resume(VemlGain::Gain2, VemlIntegration::Int800);
+        assert!(!ranging.step(AUTO_RANGE_HIGH_COUNTS));
+        assert_eq!(
+            settings(ranging),
+            (VemlGain::Gain2, VemlIntegration::Int800)
+        );
+
+        // Off the ladder, ranging restarts
+        let ranging = resume(VemlGain::Gain2, VemlIntegration::Int25);
+        assert_eq!(
+            settings(ranging),
+            (VemlGain::Gain1_8, VemlIntegration::Int100)
+        );
+        let ranging = resume(VemlGain::Gain1, VemlIntegration::Int400);
+        assert_eq!(
+            settings(ranging),
+            (VemlGain::Gain1_8, VemlIntegration::Int100)
+        );
+    }
+
+    /// Test a failed configuration write during auto-ranging leaves the
+    /// configuration as it was, so it is not mistaken for a reset.
+    #[test]
+    fn test_auto_range_write_error() {
+        let expectations = [
+            I2cTransaction::write_read(VEML_ADDR, vec![0x00], vec![0x00, 0x00]),
+            I2cTransaction::write_read(VEML_ADDR, vec![0x04], vec![0xFF, 0xFF]),
+            // Gain 1/4, 100 ms
+            I2cTransaction::write(VEML_ADDR, vec![0x00, 0x00, 0x18])
+                .with_error(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data)),
+            I2cTransaction::write_read(VEML_ADDR, vec![0x00], vec![0x00, 0x00]),
+            I2cTransaction::write_read(VEML_ADDR, vec![0x04], vec![0x00, 0x10]),
+            I2cTransaction::write_read(VEML_ADDR, vec![0x05], vec![0x00, 0x20]),
+        ];
+        let mut interface = I2cMock::new(&expectations);
+        let mut device = Veml7700::new(&mut interface);
+        let mut delay = embedded_hal_mock::eh1::delay::NoopDelay::new();
+
+        assert_eq!(
+            device.periodic_process_auto_range(&mut delay),
+            Err(VemlError::I2c(ErrorKind::NoAcknowledge(
+                NoAcknowledgeSource::Data
+            )))
+        );
+        assert_eq!(device.configuration.gain, VemlGain::Gain1);
+        assert!(device.get_outputs().stale);
+
+        assert_eq!(device.periodic_process_auto_range(&mut delay), Ok(()));
+        let outputs = device.get_outputs();
+        assert_eq!(outputs.gain, VemlGain::Gain1);
+        assert_eq!(outputs.raw_als, 0x1000);
+        interface.done();
+    }
 }
@@ -0,0 +1,799 @@
+//! Simulated VEML7700 for running the driver without hardware.
+//!
+//! The VEML6030 and VEML6035 can be simulated too, with
+//! [`Veml7700Sim::new_variant`].
+//!
+//! [`Veml7700Sim`] models the sensor's registers, and implements the
+//! `embedded-hal` I2C trait, so it can be handed to [`crate::Veml7700`] in
+//! place of a real bus. Since `embedded-hal` implements `I2c` for `&mut T`, a
+//! mutable reference to the simulator can be passed in instead, so the scene can
+//! be changed and the registers inspected while the driver is in use.
+//!
+//! The simulation does not model time. Each read of the ALS register completes
+//! one integration cycle, which measures the current [`VemlSimScene`] using
+//! the configured gain and integration time, and updates the interrupt flags.
+//! Above 1000 lx the counts follow the sensor's non-linear response, so the
+//! scene's lux is only recovered with the driver's lux correction enabled.
+use embedded_hal::i2c::{self, I2c, Operation, SevenBitAddress};
+
+use crate::{
+    saturate_counts, uncorrect_lux, VemlConfigReg, VemlPersistence, VemlRegister, VemlVariant,
+    VEML6035_WHITE_ENABLE, VEML_ADDR,
+};
+
+/// Power on value of the configuration register (shut down)
+const SIM_CONFIG_DEFAULT: u16 = 0x0001;
+
+/// Interrupt status bit for crossing the low threshold
+const SIM_INT_TH_LOW: u16 = 0x8000;
+
+/// Interrupt status bit for crossing the high threshold
+const SIM_INT_TH_HIGH: u16 = 0x4000;
+
+/// The light measured by the simulated sensor
+#[derive(Clone, Copy, Debug)]
+pub struct VemlSimScene {
+    /// Ambient light level in lux
+    pub lux: f32,
+
+    /// Ratio of the white channel counts to the ALS channel counts
+    pub white_ratio: f32,
+}
+
+impl Default for VemlSimScene {
+    fn default() -> Self {
+        Self {
+            lux: 100.0,
+            white_ratio: 1.0,
+        }
+    }
+}
+
+/// Simulated VEML7700 sensor
+pub struct Veml7700Sim {
+    /// The sensor being simulated
+    variant: VemlVariant,
+
+    /// I2C address the sensor responds to
+    address: u8,
+
+    /// The light the sensor is measuring
+    scene: VemlSimScene,
+
+    /// Configuration register
+    config: u16,
+
+    /// ALS high threshold window register
+    als_high_threshold: u16,
+
+    /// ALS low threshold window register
+    als_low_threshold: u16,
+
+    /// Power saving mode register
+    power_saving: u16,
+
+    /// ALS output register
+    als: u16,
+
+    /// White channel output register
+    white: u16,
+
+    /// Interrupt status register
+    interrupt_status: u16,
+
+    /// Number of consecutive integration cycles outside the threshold window
+    persist_count: u8,
+
+    /// Number of integration cycles that have been completed
+    integration_cycles: u32,
+
+    /// Register that the next read is from
+    reg_pointer: u8,
+}
+
+impl Veml7700Sim {
+    /// Create a new simulated sensor
+    ///
+    /// # Arguments
+    /// * `scene`: The light the sensor is measuring
+    ///
+    /// # Returns
+    /// A new simulated sensor, in the state the real sensor is in after power on
+    #[must_use]
+    pub fn new(scene: VemlSimScene) -> Self {
+        Self::new_variant(VemlVariant::Veml7700, VEML_ADDR, scene)
+    }
+
+    /// Create a new simulated sensor of one of the supported variants
+    ///
+    /// # Arguments
+    /// * `variant`: The sensor to simulate
+    /// * `address`: The I2C address the sensor responds to
+    /// * `scene`: The light the sensor is measuring
+    ///
+    /// # Returns
+    /// A new simulated sensor, in the state the real sensor is in after power on
+    #[must_use]
+    pub fn new_variant(variant: VemlVariant, address: u8, scene: VemlSimScene) -> Self {
+        Self {
+            variant,
+            address,
+            scene,
+            config: SIM_CONFIG_DEFAULT,
+            als_high_threshold: 0,
+            als_low_threshold: 0,
+            power_saving: 0,
+            als: 0,
+            white: 0,
+            interrupt_status: 0,
+            persist_count: 0,
+            integration_cycles: 0,
+            reg_pointer: 0,
+        }
+    }
+
+    /// Simulate a power cycle or brown-out of the sensor
+    ///
+    /// All registers return to their power on values. The scene and the
+    /// integration cycle count are kept.
+    pub fn power_cycle(&mut self) {
+        *self = Self {
+            integration_cycles: self.integration_cycles,
+            ..Self::new_variant(self.variant, self.address, self.scene)
+        };
+    }
+
+    /// Get the light the sensor is measuring
+    ///
+    /// # Returns
+    /// The simulated scene
+    #[must_use]
+    pub fn scene(&self) -> VemlSimScene {
+        self.scene
+    }
+
+    /// Set the light the sensor is measuring
+    ///
+    /// Takes effect from the next integration cycle.
+    ///
+    /// # Arguments
+    /// * `scene`: The new scene
+    pub fn set_scene(&mut self, scene: VemlSimScene) {
+        self.scene = scene;
+    }
+
+    /// Get the number of integration cycles that have been completed
+    ///
+    /// # Returns
+    /// The number of completed integration cycles
+    #[must_use]
+    pub fn integration_cycles(&self) -> u32 {
+        self.integration_cycles
+    }
+
+    /// Get the current value of the configuration register
+    ///
+    /// # Returns
+    /// The raw configuration register
+    #[must_use]
+    pub fn config(&self) -> u16 {
+        self.config
+    }
+
+    /// Get the current value of the power saving register
+    ///
+    /// # Returns
+    /// The raw power saving register
+    #[must_use]
+    pub fn power_saving(&self) -> u16 {
+        self.power_saving
+    }
+
+    /// Complete an integration cycle, updating the outputs and interrupt flags
+    fn integrate(&mut self) {
+        let config = VemlConfigReg::from_register(self.config, self.variant);
+        if config.shutdown {
+            return;
+        }
+
+        // The sensors with a published non-linearity correction under-read
+        // bright light by the amount it corrects.
+        let lux = if self.variant.has_lux_correction() {
+            uncorrect_lux(self.scene.lux)
+        } else {
+            self.scene.lux
+        };
+        let counts = lux / config.als_scale(self.variant);
+        self.als = saturate_counts(counts);
+        // The VEML6035 white channel has to be enabled separately
+        let white_enabled =
+            (self.variant != VemlVariant::Veml6035) || ((self.config & VEML6035_WHITE_ENABLE) != 0);
+        self.white = if white_enabled {
+            saturate_counts(counts * self.scene.white_ratio)
+        } else {
+            0
+        };
+        self.integration_cycles += 1;
+
+        let flag = if self.als > self.als_high_threshold {
+            SIM_INT_TH_HIGH
+        } else if self.als < self.als_low_threshold {
+            SIM_INT_TH_LOW
+        } else {
+            0
+        };
+
+        if flag == 0 {
+            self.persist_count = 0;
+        } else {
+            self.persist_count = self.persist_count.saturating_add(1);
+            let persistence = match config.persistence {
+                VemlPersistence::Persist1 => 1,
+                VemlPersistence::Persist2 => 2,
+                VemlPersistence::Persist4 => 4,
+                VemlPersistence::Persist8 => 8,
+            };
+            if config.interrupt_enabled && (self.persist_count >= persistence) {
+                self.interrupt_status |= flag;
+            }
+        }
+    }
+
+    /// Read a register, as the bus would
+    ///
+    /// # Arguments
+    /// * `reg`: The address of the register to read
+    ///
+    /// # Returns
+    /// The value of the register
+    fn read_reg(&mut self, reg: u8) -> u16 {
+        match reg {
+            r if r == VemlRegister::Config as u8 => self.config,
+            r if r == VemlRegister::ALSHighThreshold as u8 => self.als_high_threshold,
+            r if r == VemlRegister::ALSLowThreshold as u8 => self.als_low_threshold,
+            r if r == VemlRegister::PowerSaving as u8 => self.power_saving,
+            r if r == VemlRegister::ALSValue as u8 => {
+                self.integrate();
+                self.als
+            }
+            r if r == VemlRegister::WhiteValue as u8 => self.white,
+            r if r == VemlRegister::ALSInterruptStatus as u8 => {
+                // The interrupt flags are cleared by reading them
+                let status = self.interrupt_status;
+                self.interrupt_status = 0;
+                status
+            }
+            _ => 0,
+        }
+    }
+
+    /// Write a register, as the bus would
+    ///
+    /// Writes to read-only registers are ignored.
+    ///
+    /// # Arguments
+    /// * `reg`: The address of the register to write
+    /// * `value`: The value to write
+    fn write_reg(&mut self, reg: u8, value: u16) {
+        match reg {
+            r if r == VemlRegister::Config as u8 => self.config = value,
+            r if r == VemlRegister::ALSHighThreshold as u8 => self.als_high_threshold = value,
+            r if r == VemlRegister::ALSLowThreshold as u8 => self.als_low_threshold = value,
+            r if r == VemlRegister::PowerSaving as u8 => self.power_saving = value,
+            _ => {}
+        }
+    }
+}
+
+impl i2c::ErrorType for Veml7700Sim {
+    type Error = i2c::ErrorKind;
+}
+
+impl I2c<SevenBitAddress> for Veml7700Sim {
+    fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        if address != self.address {
+            return Err(i2c::ErrorKind::NoAcknowledge(
+                i2c::NoAcknowledgeSource::Address,
+            ));
+        }
+        for operation in operations {
+            match operation {
+                // Registers are 16 bits, sent least significant byte first
+                Operation::Write([reg]) => self.reg_pointer = *reg,
+                Operation::Write([reg, lsb, msb]) => {
+                    self.write_reg(*reg, u16::from_le_bytes([*lsb, *msb]));
+                }
+                Operation::Read(buffer) if buffer.len() == 2 => {
+                    let value = self.read_reg(self.reg_pointer);
+                    buffer.copy_from_slice(&value.to_le_bytes());
+                }
+                _ => return Err(i2c::ErrorKind::Other),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::i2c::I2c<SevenBitAddress> for Veml7700Sim {
+    async fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        I2c::transaction(self, address, operations)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        Veml7700, VemlError, VemlGain, VemlIntegration, VemlLuxThresholds, VemlPowerSavingMode,
+        VemlVariant,
+    };
+    use embedded_hal::delay::DelayNs;
+    use embedded_hal_mock::eh1::delay::NoopDelay;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Configuration used for the simulated measurements
+    const CONFIG: VemlConfigReg = VemlConfigReg {
+        gain: VemlGain::Gain1,
+        integration_time: VemlIntegration::Int100,
+        persistence: VemlPersistence::Persist1,
+        interrupt_enabled: false,
+        shutdown: false,
+    };
+
+    /// Configuration at the application note's starting point for auto-ranging
+    const AUTO_RANGE_START: VemlConfigReg = VemlConfigReg {
+        gain: VemlGain::Gain1_8,
+        ..CONFIG
+    };
+
+    /// Test the counts follow the scene and the gain and integration time.
+    #[test]
+    fn test_counts() {
+        let mut sim = Veml7700Sim::new(VemlSimScene {
+            lux: 100.0,
+            white_ratio: 1.5,
+        });
+        let mut device = Veml7700::new(&mut sim);
+        device.set_configuration(CONFIG).unwrap();
+        // 0.0672 lux per count
+        assert_eq!(device.get_ambient_level(), Ok(1488));
+        assert_eq!(device.get_white_level(), Ok(2232));
+
+        device.set_gain(VemlGain::Gain1_4).unwrap();
+        device
+            .set_integration_time(VemlIntegration::Int800)
+            .unwrap();
+        // 0.0336 lux per count
+        assert_eq!(device.get_ambient_level(), Ok(2976));
+        assert!(matches!(device.get_gain(), Ok(VemlGain::Gain1_4)));
+
+        let lux = device.get_lux().unwrap();
+        assert!((lux - 100.0).abs() < 0.1, "{lux}");
+    }
+
+    /// Test the counts saturate at the maximum register value
+    #[test]
+    fn test_saturation() {
+        let mut sim = Veml7700Sim::new(VemlSimScene {
+            lux: 1000.0,
+            white_ratio: 1.0,
+        });
+        let mut device = Veml7700::new(&mut sim);
+        device
+            .set_configuration(VemlConfigReg {
+                gain: VemlGain::Gain2,
+                integration_time: VemlIntegration::Int800,
+                ..CONFIG
+            })
+            .unwrap();
+        assert_eq!(device.get_ambient_level(), Ok(u16::MAX));
+    }
+
+    /// Test no measurements are made while the sensor is shut down.
+    #[test]
+    fn test_shutdown() {
+        let mut sim = Veml7700Sim::new(VemlSimScene::default());
+        let mut device = Veml7700::new(&mut sim);
+        assert_eq!(device.get_ambient_level(), Ok(0));
+        device.set_power_state(false).unwrap();
+        assert_ne!(device.get_ambient_level(), Ok(0));
+        assert_eq!(sim.integration_cycles(), 1);
+    }
+
+    /// Test the interrupt flags are raised according to the persistence.
+    #[test]
+    fn test_interrupt_persistence() {
+        let mut sim = Veml7700Sim::new(VemlSimScene::default());
+        let config = VemlConfigReg {
+            persistence: VemlPersistence::Persist4,
+            interrupt_enabled: true,
+            ..CONFIG
+        };
+        let mut device = Veml7700::new(&mut sim);
+        device.set_configuration(config).unwrap();
+        device.set_als_int_high_threshold(1000).unwrap();
+        device.set_als_int_low_threshold(500).unwrap();
+
+        for _ in 0..3 {
+            device.periodic_process().unwrap();
+        }
+        assert_eq!(device.get_interrupt_status(), Ok((false, false)));
+        device.periodic_process().unwrap();
+        assert_eq!(device.get_interrupt_status(), Ok((false, true)));
+        // Reading the status clears it
+        assert_eq!(device.get_interrupt_status(), Ok((false, false)));
+
+        sim.set_scene(VemlSimScene {
+            lux: 10.0,
+            white_ratio: 1.0,
+        });
+        let mut device = Veml7700::new(&mut sim);
+        device.set_configuration(config).unwrap();
+        for _ in 0..4 {
+            device.periodic_process().unwrap();
+        }
+        assert_eq!(device.get_interrupt_status(), Ok((true, false)));
+    }
+
+    /// Test periodic processing reads the outputs
+    #[test]
+    fn test_periodic_process() {
+        let mut sim = Veml7700Sim::new(VemlSimScene {
+            lux: 250.0,
+            white_ratio: 2.0,
+        });
+        let mut device = Veml7700::new(&mut sim);
+        device.set_configuration(CONFIG).unwrap();
+        device.periodic_process().unwrap();
+
+        let outputs = device.get_outputs();
+        assert_eq!(outputs.raw_als, 3720);
+        assert_eq!(outputs.raw_white, 7440);
+        assert!((outputs.lux - 250.0).abs() < 0.1, "{}", outputs.lux);
+        assert!(
+            (outputs.white_lux - 500.0).abs() < 0.1,
+            "{}",
+            outputs.white_lux
+        );
+    }
+
+    /// Test lux thresholds follow the gain and raise interrupts
+    #[test]
+    fn test_lux_thresholds() {
+        let mut sim = Veml7700Sim::new(VemlSimScene {
+            lux: 250.0,
+            white_ratio: 1.0,
+        });
+        let mut device = Veml7700::new(&mut sim);
+        device.set_configuration(CONFIG).unwrap();
+        device
+            .set_lux_thresholds(VemlLuxThresholds {
+                low: 100.0,
+                high: 400.0,
+                persistence: VemlPersistence::Persist2,
+            })
+            .unwrap();
+
+        // Counts are 0.0672 lux each at 1x gain and 100ms
+        assert_eq!(device.get_als_int_high_threshold(), Ok(5952));
+        assert_eq!(device.get_als_int_low_threshold(), Ok(1488));
+        let config = device.get_configuration().unwrap();
+        assert!(config.interrupt_enabled);
+        assert_eq!(config.persistence, VemlPersistence::Persist2);
+
+        // Doubling the gain halves the lux per count
+        device.set_gain(VemlGain::Gain2).unwrap();
+        assert_eq!(device.get_als_int_high_threshold(), Ok(11905));
+        assert_eq!(device.get_als_int_low_threshold(), Ok(2976));
+
+        device.periodic_process().unwrap();
+        assert_eq!(device.get_interrupt_status(), Ok((false, false)));
+
+        // Lights off is reported after the persistence count
+        sim.set_scene(VemlSimScene {
+            lux: 10.0,
+            white_ratio: 1.0,
+        });
+        let mut device = Veml7700::new(&mut sim);
+        device.set_configuration(CONFIG).unwrap();
+        device
+            .set_lux_thresholds(VemlLuxThresholds {
+                low: 100.0,
+                high: 400.0,
+                persistence: VemlPersistence::Persist2,
+            })
+            .unwrap();
+        device.periodic_process().unwrap();
+        assert_eq!(device.get_interrupt_status(), Ok((false, false)));
+        device.periodic_process().unwrap();
+        assert_eq!(device.get_interrupt_status(), Ok((true, false)));
+
+        device.clear_lux_thresholds().unwrap();
+        assert!(device.get_lux_thresholds().is_none());
+        assert!(!device.get_configuration().unwrap().interrupt_enabled);
+    }
+
+    /// Simulator shared between the driver and the test
+    struct SharedSim(Rc<RefCell<Veml7700Sim>>);
+
+    impl i2c::ErrorType for SharedSim {
+        type Error = i2c::ErrorKind;
+    }
+
+    impl I2c for SharedSim {
+        fn transaction(
+            &mut self,
+            address: SevenBitAddress,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            self.0.borrow_mut().transaction(address, operations)
+        }
+    }
+
+    /// Test the other variants at their addresses and scales
+    #[test]
+    fn test_variants() {
+        let scene = VemlSimScene {
+            lux: 10.0,
+            white_ratio: 1.0,
+        };
+
+        // VEML6030 with its ADDR pin high
+        let mut sim = Veml7700Sim::new_variant(VemlVariant::Veml6030, 0x48, scene);
+        let mut device = Veml7700::new(&mut sim);
+        assert!(device.get_ambient_level().is_err());
+        let mut device = Veml7700::new_variant(&mut sim, VemlVariant::Veml6030, 0x48).unwrap();
+        device.set_configuration(CONFIG).unwrap();
+        device.periodic_process().unwrap();
+        // 0.0576 lux per count at 1x gain and 100ms
+        assert_eq!(device.get_outputs().raw_als, 174);
+
+        // VEML6035 at its maximum gain
+        let mut sim = Veml7700Sim::new_variant(VemlVariant::Veml6035, 0x29, scene);
+        let mut device = Veml7700::new_variant(&mut sim, VemlVariant::Veml6035, 0x29).unwrap();
+        device
+            .set_configuration(VemlConfigReg {
+                gain: VemlGain::Gain4,
+                integration_time: VemlIntegration::Int800,
+                ..CONFIG
+            })
+            .unwrap();
+        assert_eq!(device.get_gain(), Ok(VemlGain::Gain4));
+        device.periodic_process().unwrap();
+        let outputs = device.get_outputs();
+        assert_eq!(outputs.raw_als, 25000);
+        assert_eq!(outputs.raw_white, 25000);
+        assert!((outputs.lux - 10.0).abs() < 0.01, "{}", outputs.lux);
+
+        // Each of the VEML6035 gains is read back
+        for gain in VemlVariant::Veml6035.gains() {
+            device.set_gain(*gain).unwrap();
+            let config = device.get_configuration().unwrap();
+            assert_eq!(config.gain, *gain);
+        }
+
+        // Auto-ranging in the dark reaches the VEML6035's 4x gain
+        sim.set_scene(VemlSimScene {
+            lux: 0.01,
+            white_ratio: 1.0,
+        });
+        let mut device = Veml7700::new_variant(&mut sim, VemlVariant::Veml6035, 0x29).unwrap();
+        // The new driver has not configured the sensor yet, so first sees a reset
+        device
+            .periodic_process_auto_range(&mut NoopDelay::new())
+            .unwrap_err();
+        device
+            .periodic_process_auto_range(&mut NoopDelay::new())
+            .unwrap();
+        let outputs = device.get_outputs();
+        assert_eq!(outputs.gain, VemlGain::Gain4);
+        assert_eq!(outputs.integration_time, VemlIntegration::Int800);
+    }
+
+    /// Test a reset sensor is detected and reconfigured
+    #[test]
+    fn test_reset_recovery() {
+        let sim = Rc::new(RefCell::new(Veml7700Sim::new(VemlSimScene::default())));
+        let mut device = Veml7700::new(SharedSim(sim.clone()));
+        device.set_configuration(CONFIG).unwrap();
+        device
+            .set_power_saving_mode(VemlPowerSavingMode::Mode3)
+            .unwrap();
+        device.toggle_power_saving(true).unwrap();
+        device.periodic_process().unwrap();
+        let outputs = device.get_outputs();
+        assert!(!outputs.stale);
+
+        sim.borrow_mut().power_cycle();
+        assert_eq!(sim.borrow().config(), SIM_CONFIG_DEFAULT);
+        assert_eq!(device.periodic_process(), Err(VemlError::SensorReset));
+        assert!(device.get_outputs().stale);
+        assert_eq!(device.get_outputs().raw_als, outputs.raw_als);
+        assert_eq!(sim.borrow().config(), u16::from(CONFIG));
+        assert_eq!(sim.borrow().power_saving(), 0b101);
+
+        device.periodic_process().unwrap();
+        assert!(!device.get_outputs().stale);
+    }
+
+    /// Test a gain the sensor does not support is replaced, also when recovering from a reset
+    #[test]
+    fn test_reset_recovery_unsupported_gain() {
+        let sim = Rc::new(RefCell::new(Veml7700Sim::new_variant(
+            VemlVariant::Veml6030,
+            0x10,
+            VemlSimScene::default(),
+        )));
+        let mut device =
+            Veml7700::new_variant(SharedSim(sim.clone()), VemlVariant::Veml6030, 0x10).unwrap();
+        device
+            .set_configuration(VemlConfigReg {
+                gain: VemlGain::Gain4,
+                ..CONFIG
+            })
+            .unwrap();
+        assert_eq!(device.get_gain(), Ok(VemlGain::Gain2));
+        device.periodic_process().unwrap();
+        assert_eq!(device.get_outputs().gain, VemlGain::Gain2);
+
+        sim.borrow_mut().power_cycle();
+        assert_eq!(device.periodic_process(), Err(VemlError::SensorReset));
+        assert_eq!(device.get_gain(), Ok(VemlGain::Gain2));
+        device.periodic_process().unwrap();
+
+        device.set_gain(VemlGain::Gain1_2).unwrap();
+        assert_eq!(device.get_gain(), Ok(VemlGain::Gain1_4));
+    }
+
+    /// Test the lux correction recovers the scene above 1000 lx
+    #[test]
+    fn test_lux_correction() {
+        let mut sim = Veml7700Sim::new(VemlSimScene {
+            lux: 5000.0,
+            white_ratio: 1.0,
+        });
+        let mut device = Veml7700::new(&mut sim);
+        device.set_configuration(CONFIG).unwrap();
+        assert!(device.get_lux_correction());
+        device.periodic_process().unwrap();
+        let corrected = device.get_outputs().lux;
+        assert!((corrected - 5000.0).abs() < 1.0, "{corrected}");
+
+        device.set_lux_correction(false);
+        device.periodic_process().unwrap();
+        let uncorrected = device.get_outputs().lux;
+        assert!(uncorrected < 4500.0, "{uncorrected}");
+
+        // The VEML6035 reading is used as is, whether or not the correction
+        // is enabled.
+        let mut sim = Veml7700Sim::new_variant(
+            VemlVariant::Veml6035,
+            0x29,
+            VemlSimScene {
+                lux: 5000.0,
+                white_ratio: 1.0,
+            },
+        );
+        let mut device = Veml7700::new_variant(&mut sim, VemlVariant::Veml6035, 0x29).unwrap();
+        device.set_configuration(AUTO_RANGE_START).unwrap();
+        assert!(device.get_lux_correction());
+        device.periodic_process().unwrap();
+        let outputs = device.get_outputs();
+        assert!((outputs.lux - 5000.0).abs() < 1.0, "{}", outputs.lux);
+        assert!(
+            (outputs.white_lux - 5000.0).abs() < 1.0,
+            "{}",
+            outputs.white_lux
+        );
+        assert!((device.get_lux().unwrap() - 5000.0).abs() < 1.0);
+    }
+
+    /// Delay that records how long it was asked to wait
+    #[derive(Default)]
+    struct CountingDelay {
+        /// Total time waited in ns
+        total_ns: u64,
+    }
+
+    impl DelayNs for CountingDelay {
+        fn delay_ns(&mut self, ns: u32) {
+            self.total_ns += u64::from(ns);
+        }
+    }
+
+    /// Test auto-ranging picks the settings from the application note
+    #[test]
+    fn test_auto_range() {
+        let cases = [
+            // Direct sunlight saturates at 100ms, so integration is shortened.
+            (100_000.0, VemlGain::Gain1_8, VemlIntegration::Int25),
+            // Indoor lighting is in range at the starting point
+            (250.0, VemlGain::Gain1_8, VemlIntegration::Int100),
+            // Dim light needs the maximum gain
+            (5.0, VemlGain::Gain2, VemlIntegration::Int100),
+            // Darkness needs the maximum gain and integration time
+            (0.1, VemlGain::Gain2, VemlIntegration::Int800),
+        ];
+
+        for (lux, gain, integration_time) in cases {
+            let mut sim = Veml7700Sim::new(VemlSimScene {
+                lux,
+                white_ratio: 1.0,
+            });
+            let mut device = Veml7700::new(&mut sim);
+            device.set_configuration(AUTO_RANGE_START).unwrap();
+            device
+                .periodic_process_auto_range(&mut NoopDelay::new())
+                .unwrap();
+
+            let outputs = device.get_outputs();
+            assert_eq!(outputs.gain, gain, "{lux}");
+            assert_eq!(outputs.integration_time, integration_time, "{lux}");
+            assert!(outputs.raw_als < u16::MAX, "{lux}");
+            assert!(
+                (outputs.lux - lux).abs() / lux < 0.05,
+                "{lux}: {}",
+                outputs.lux
+            );
+
+            let config = VemlConfigReg::from(sim.config());
+            assert_eq!(config.gain, gain);
+            assert_eq!(config.integration_time, integration_time);
+        }
+    }
+
+    /// Test auto-ranging carries on from the previous settings, so steady
+    /// light needs no reconfiguration.
+    #[test]
+    fn test_auto_range_resume() {
+        let sim = Rc::new(RefCell::new(Veml7700Sim::new(VemlSimScene {
+            lux: 250.0,
+            white_ratio: 1.0,
+        })));
+        let mut device = Veml7700::new(SharedSim(sim.clone()));
+        device.set_configuration(AUTO_RANGE_START).unwrap();
+        let mut delay = CountingDelay::default();
+        device.periodic_process_auto_range(&mut delay).unwrap();
+        assert_eq!(delay.total_ns, 0);
+
+        // Steady light is sampled with the same settings, without waiting
+        for _ in 0..3 {
+            device.periodic_process_auto_range(&mut delay).unwrap();
+        }
+        assert_eq!(delay.total_ns, 0);
+        assert_eq!(device.get_outputs().gain, VemlGain::Gain1_8);
+
+        // Darkness raises the sensitivity from where it was
+        sim.borrow_mut().set_scene(VemlSimScene {
+            lux: 0.1,
+            white_ratio: 1.0,
+        });
+        device.periodic_process_auto_range(&mut delay).unwrap();
+        let outputs = device.get_outputs();
+        assert_eq!(outputs.gain, VemlGain::Gain2);
+        assert_eq!(outputs.integration_time, VemlIntegration::Int800);
+
+        // And light again lowers it only as far as needed
+        sim.borrow_mut().set_scene(VemlSimScene {
+            lux: 250.0,
+            white_ratio: 1.0,
+        });
+        let mut delay = CountingDelay::default();
+        device.periodic_process_auto_range(&mut delay).unwrap();
+        let outputs = device.get_outputs();
+        assert_eq!(outputs.gain, VemlGain::Gain2);
+        assert_eq!(outputs.integration_time, VemlIntegration::Int100);
+        assert!((outputs.lux - 250.0).abs() < 12.5, "{}", outputs.lux);
+        // Settling after each of the three changes: 2 x (400 + 200 + 100) ms
+        assert_eq!(delay.total_ns, 1_400_000_000);
+    }
+}